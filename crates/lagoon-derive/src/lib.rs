@@ -0,0 +1,48 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields};
+
+/// Implement `IntoValue` and `FromValue` for a struct with named fields, so that
+/// it can be passed to a script as a struct instance and read back again.
+///
+/// The Lagoon struct has the same name as the Rust struct, and every field is
+/// converted with its own `IntoValue` and `FromValue` implementations.
+#[proc_macro_derive(LagoonStruct)]
+pub fn derive_lagoon_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().map(|field| field.ident.clone().unwrap()).collect::<Vec<_>>(),
+            _ => return Error::new_spanned(&input.ident, "LagoonStruct can only be derived for structs with named fields").to_compile_error().into(),
+        },
+        _ => return Error::new_spanned(&input.ident, "LagoonStruct can only be derived for structs").to_compile_error().into(),
+    };
+
+    let ident = &input.ident;
+    let name = ident.to_string();
+    let names = fields.iter().map(|field| field.to_string()).collect::<Vec<_>>();
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let expanded = quote! {
+        impl #impl_generics ::lagoon_interpreter::IntoValue for #ident #type_generics #where_clause {
+            fn into_value(self) -> ::lagoon_interpreter::Value {
+                ::lagoon_interpreter::Value::instance(#name, vec![
+                    #( (#names, ::lagoon_interpreter::IntoValue::into_value(self.#fields)), )*
+                ])
+            }
+        }
+
+        impl #impl_generics ::lagoon_interpreter::FromValue for #ident #type_generics #where_clause {
+            fn from_value(value: ::lagoon_interpreter::Value) -> ::std::result::Result<Self, ::lagoon_interpreter::InterpreterResult> {
+                let fields = ::lagoon_interpreter::StructFields::of(value, #name)?;
+
+                Ok(Self {
+                    #( #fields: fields.get(#names)?, )*
+                })
+            }
+        }
+    };
+
+    expanded.into()
+}
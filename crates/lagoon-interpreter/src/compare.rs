@@ -0,0 +1,101 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use crate::environment::Value;
+use crate::interpreter::InterpreterResult;
+
+impl Value {
+    /// Loose equality, used by `==`, `!=`, `in` and `not in`. Numbers, strings
+    /// that hold a number and booleans (as `1` and `0`) are compared by their
+    /// numeric value, whilst `null` only equals `null`.
+    pub fn equals(&self, other: &Value) -> bool {
+        equal(self, other, false)
+    }
+
+    /// Strict equality, used by `===` and `!==`. Values of different types are
+    /// never equal.
+    pub fn strictly_equals(&self, other: &Value) -> bool {
+        equal(self, other, true)
+    }
+
+    /// Order two numbers or two strings, which are compared by their Unicode
+    /// code points. `None` means that the values are unordered, e.g. `NaN`.
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, InterpreterResult> {
+        match (self, other) {
+            (Value::Constant(l), r) => l.compare(r),
+            (l, Value::Constant(r)) => l.compare(r),
+            (Value::Number(l), Value::Number(r)) => Ok(l.partial_cmp(r)),
+            (Value::String(l), Value::String(r)) => Ok(Some(l.chars().cmp(r.chars()))),
+            (l, r) => Err(InterpreterResult::InvalidComparison(l.clone().typestring(), r.clone().typestring())),
+        }
+    }
+}
+
+/// Lists, maps and struct instances are equal when their contents are, using
+/// the same kind of equality. Anything else that can't be copied, such as a
+/// function or a host object, is only equal to itself.
+fn equal(left: &Value, right: &Value, strict: bool) -> bool {
+    match (left, right) {
+        (Value::Constant(l), r) => equal(l, r, strict),
+        (l, Value::Constant(r)) => equal(l, r, strict),
+        (Value::Null, Value::Null) => true,
+        (Value::Null, _) | (_, Value::Null) => false,
+        (Value::Number(l), Value::Number(r)) => l == r,
+        (Value::String(l), Value::String(r)) => l == r,
+        (Value::Bool(l), Value::Bool(r)) => l == r,
+        (Value::List(l), Value::List(r)) => Rc::ptr_eq(l, r) || {
+            let (l, r) = (l.borrow(), r.borrow());
+
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| equal(l, r, strict))
+        },
+        (Value::Map(l), Value::Map(r)) => Rc::ptr_eq(l, r) || {
+            let (l, r) = (l.borrow(), r.borrow());
+
+            l.len() == r.len() && l.iter().all(|(key, l)| r.get(key).map(|r| equal(l, r, strict)).unwrap_or(false))
+        },
+        (Value::StructInstance { environment: l, definition: d }, Value::StructInstance { environment: r, definition: e }) => match (&**d, &**e) {
+            (Value::Struct { fields, methods: d, .. }, Value::Struct { methods: e, .. }) if Rc::ptr_eq(d, e) => Rc::ptr_eq(l, r) || {
+                let (l, r) = (l.borrow(), r.borrow());
+
+                fields.iter().all(|field| match (l.get(field.name.clone()), r.get(field.name.clone())) {
+                    (Some(l), Some(r)) => equal(&l, &r, strict),
+                    (l, r) => l.is_none() && r.is_none(),
+                })
+            },
+            _ => false,
+        },
+        (Value::Struct { methods: l, .. }, Value::Struct { methods: r, .. }) => Rc::ptr_eq(l, r),
+        // Methods are the same function whichever value they were accessed on.
+        (Value::Function { body: l, .. }, Value::Function { body: r, .. }) => Rc::ptr_eq(l, r),
+        (Value::Closure { closure: l, .. }, Value::Closure { closure: r, .. }) => Rc::ptr_eq(l, r),
+        (Value::NativeFunction { callback: l, .. }, Value::NativeFunction { callback: r, .. }) => Rc::ptr_eq(l, r),
+        (Value::NativeMethod { name: l, context: c, .. }, Value::NativeMethod { name: r, context: d, .. }) => l == r && c.clone().typestring() == d.clone().typestring(),
+        (Value::Error(l), Value::Error(r)) => Rc::ptr_eq(l, r),
+        (Value::Object(l), Value::Object(r)) => Rc::ptr_eq(l, r),
+        (Value::Module(l), Value::Module(r)) => Rc::ptr_eq(l, r),
+        _ if strict => false,
+        (l, r) => match (number(l), number(r)) {
+            (Some(l), Some(r)) => l == r,
+            _ => false,
+        },
+    }
+}
+
+/// The number that a scalar stands for when it is loosely compared with a
+/// value of another type. Strings only count when they are written as a
+/// decimal number, such as `"42"`, `" -1.5 "` or `"1e3"`.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => Some(*n),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        Value::String(s) => {
+            let s = s.trim();
+
+            match s.chars().all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E')) {
+                true => s.parse().ok(),
+                false => None,
+            }
+        },
+        _ => None,
+    }
+}
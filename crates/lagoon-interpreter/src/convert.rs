@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::rc::Rc;
+use std::cell::RefCell;
+use hashbrown::HashMap as Methods;
+use indexmap::IndexMap;
+use lagoon_parser::Parameter;
+
+use crate::environment::{Environment, Value};
+use crate::interpreter::{Interpreter, InterpreterResult, instantiate};
+
+/// Convert a Rust value into a Lagoon value.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// Convert a Lagoon value into a Rust value, failing with
+/// `InterpreterResult::TypeMismatch` if the value has the wrong type.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult>;
+}
+
+/// The arguments for a call made from Rust, e.g. `()`, `(1.0, "two")` or a
+/// `Vec<Value>`.
+pub trait IntoArguments {
+    fn into_arguments(self) -> Vec<Value>;
+}
+
+fn mismatch<T>(expected: &str, value: Value) -> Result<T, InterpreterResult> {
+    Err(InterpreterResult::TypeMismatch(expected.to_string(), value.typestring()))
+}
+
+/// Constants are converted the same way as the value they hold.
+fn unwrap(value: Value) -> Value {
+    match value {
+        Value::Constant(v) => unwrap(*v),
+        _ => value,
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Null
+    }
+}
+
+impl FromValue for () {
+    fn from_value(_: Value) -> Result<Self, InterpreterResult> {
+        Ok(())
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        match unwrap(value) {
+            Value::Number(n) => Ok(n),
+            value => mismatch("number", value),
+        }
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Value {
+        Value::Number(self as f64)
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        f64::from_value(value).map(|n| n as f32)
+    }
+}
+
+// Lagoon only has one number type, so integers are checked to be whole and in
+// range rather than silently truncated. `MAX as f64` rounds up to the next power
+// of two for the 64-bit types, but adding one to it gives the exclusive bound for
+// every type.
+macro_rules! integer {
+    ($($type:ty),+) => {
+        $(
+            impl IntoValue for $type {
+                fn into_value(self) -> Value {
+                    Value::Number(self as f64)
+                }
+            }
+
+            impl FromValue for $type {
+                fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+                    match unwrap(value) {
+                        Value::Number(n) if n.fract() == 0.0 && n >= <$type>::MIN as f64 && n < <$type>::MAX as f64 + 1.0 => Ok(n as $type),
+                        value => mismatch(stringify!($type), value),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        match unwrap(value) {
+            Value::Bool(b) => Ok(b),
+            value => mismatch("bool", value),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        match unwrap(value) {
+            Value::String(s) => Ok(s),
+            value => mismatch("string", value),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        match unwrap(value) {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(Rc::new(RefCell::new(self.into_iter().map(IntoValue::into_value).collect())))
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        match unwrap(value) {
+            Value::List(items) => items.borrow().iter().cloned().map(T::from_value).collect(),
+            value => mismatch("list", value),
+        }
+    }
+}
+
+impl<T: IntoValue, S: BuildHasher> IntoValue for HashMap<String, T, S> {
+    fn into_value(self) -> Value {
+        Value::Map(Rc::new(RefCell::new(self.into_iter().map(|(key, value)| (key, value.into_value())).collect())))
+    }
+}
+
+impl<T: FromValue, S: BuildHasher + Default> FromValue for HashMap<String, T, S> {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        match unwrap(value) {
+            Value::Map(items) => items.borrow().iter()
+                .map(|(key, value)| Ok((key.clone(), T::from_value(value.clone())?)))
+                .collect(),
+            value => mismatch("map", value),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for IndexMap<String, T> {
+    fn into_value(self) -> Value {
+        Value::Map(Rc::new(RefCell::new(self.into_iter().map(|(key, value)| (key, value.into_value())).collect())))
+    }
+}
+
+impl<T: FromValue> FromValue for IndexMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+        match unwrap(value) {
+            Value::Map(items) => items.borrow().iter()
+                .map(|(key, value)| Ok((key.clone(), T::from_value(value.clone())?)))
+                .collect(),
+            value => mismatch("map", value),
+        }
+    }
+}
+
+// Tuples are represented as fixed-length lists.
+macro_rules! tuple {
+    ($length:literal => $($name:ident),+) => {
+        impl<$($name: IntoValue),+> IntoValue for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn into_value(self) -> Value {
+                let ($($name,)+) = self;
+
+                Value::List(Rc::new(RefCell::new(vec![$($name.into_value()),+])))
+            }
+        }
+
+        impl<$($name: FromValue),+> FromValue for ($($name,)+) {
+            fn from_value(value: Value) -> Result<Self, InterpreterResult> {
+                let items = match unwrap(value) {
+                    Value::List(items) if items.borrow().len() == $length => items.borrow().clone(),
+                    value => return mismatch(concat!("list of ", $length, " items"), value),
+                };
+
+                let mut items = items.into_iter();
+
+                Ok(($($name::from_value(items.next().unwrap())?,)+))
+            }
+        }
+    };
+}
+
+tuple!(1 => A);
+tuple!(2 => A, B);
+tuple!(3 => A, B, C);
+tuple!(4 => A, B, C, D);
+tuple!(5 => A, B, C, D, E);
+
+impl Value {
+    /// Create an instance of a struct that has no methods. This is how
+    /// `#[derive(LagoonStruct)]` converts a Rust struct into a Lagoon value.
+    ///
+    /// Once the instance reaches a script that declares a struct with the same
+    /// name, it becomes an instance of that struct instead.
+    pub fn instance(name: &str, fields: Vec<(&str, Value)>) -> Value {
+        let definition = Value::Struct {
+            name: name.to_string(),
+            fields: fields.iter().map(|(field, _)| Parameter::new(*field)).collect(),
+            methods: Rc::new(RefCell::new(Methods::new())),
+        };
+
+        instantiate(definition, fields.into_iter().map(|(field, value)| (field.to_string(), value)).collect())
+            .expect("every field is part of the definition")
+    }
+}
+
+impl Interpreter {
+    /// Move struct instances created by `Value::instance` over to the script's
+    /// struct of the same name, so that they have its methods and are equal to
+    /// the instances the script creates. Lists, maps and fields are searched too.
+    pub(crate) fn adopt(&self, value: Value) -> Value {
+        self.adopt_within(value, &mut HashMap::new())
+    }
+
+    /// Adopt the instances held by globals, after a struct has been declared.
+    pub(crate) fn adopt_globals(&mut self) {
+        let mut seen = HashMap::new();
+        let globals = self.globals.iter().map(|(name, value)| (name.clone(), value.clone())).collect::<Vec<_>>();
+
+        for (name, value) in globals {
+            let value = self.adopt_within(value, &mut seen);
+
+            self.globals.insert(name, value);
+        }
+    }
+
+    /// `seen` maps every list, map and instance that has already been visited
+    /// to its replacement, which keeps shared values shared and stops cycles.
+    fn adopt_within(&self, value: Value, seen: &mut HashMap<*const (), Value>) -> Value {
+        let key = match &value {
+            Value::List(items) => Rc::as_ptr(items) as *const (),
+            Value::Map(items) => Rc::as_ptr(items) as *const (),
+            Value::StructInstance { environment, .. } => Rc::as_ptr(environment) as *const (),
+            Value::Constant(v) => return Value::Constant(Box::new(self.adopt_within(*v.clone(), seen))),
+            _ => return value,
+        };
+
+        if let Some(adopted) = seen.get(&key) {
+            return adopted.clone();
+        }
+
+        seen.insert(key, value.clone());
+
+        let adopted = match &value {
+            Value::List(items) => {
+                let adopted = items.borrow().iter().map(|item| self.adopt_within(item.clone(), seen)).collect();
+
+                *items.borrow_mut() = adopted;
+                value
+            },
+            Value::Map(items) => {
+                let adopted = items.borrow().iter().map(|(key, item)| (key.clone(), self.adopt_within(item.clone(), seen))).collect();
+
+                *items.borrow_mut() = adopted;
+                value
+            },
+            Value::StructInstance { environment, definition } => {
+                let fields = environment.borrow().values().into_iter()
+                    .map(|(field, item)| (field, self.adopt_within(item, seen)))
+                    .collect::<Vec<_>>();
+
+                match (&**definition, self.globals.get(&value.clone().typestring())) {
+                    // A definition without any methods that isn't the script's
+                    // own struct comes from the host. Instances with fields the
+                    // script's struct doesn't have are left as they are.
+                    (Value::Struct { methods, .. }, Some(script @ Value::Struct { methods: script_methods, .. }))
+                        if methods.borrow().is_empty() && ! Rc::ptr_eq(methods, script_methods) => {
+                        instantiate(script.clone(), fields).unwrap_or(value)
+                    },
+                    _ => {
+                        for (field, item) in fields {
+                            environment.borrow_mut().set(field, item);
+                        }
+
+                        value
+                    },
+                }
+            },
+            _ => unreachable!(),
+        };
+
+        seen.insert(key, adopted.clone());
+        adopted
+    }
+}
+
+/// The fields of a struct instance, used by `#[derive(LagoonStruct)]` to
+/// convert a Lagoon value back into a Rust struct.
+pub struct StructFields {
+    name: String,
+    environment: Rc<RefCell<Environment>>,
+}
+
+impl StructFields {
+    /// Take the fields of an instance of the struct called `name`, failing if
+    /// the value is anything else.
+    pub fn of(value: Value, name: &str) -> Result<Self, InterpreterResult> {
+        match unwrap(value) {
+            Value::StructInstance { environment, definition } => match *definition {
+                Value::Struct { name: ref definition, .. } if definition == name => Ok(Self { name: name.to_string(), environment }),
+                definition => mismatch(name, Value::StructInstance { environment, definition: Box::new(definition) }),
+            },
+            value => mismatch(name, value),
+        }
+    }
+
+    pub fn get<T: FromValue>(&self, field: &str) -> Result<T, InterpreterResult> {
+        match self.environment.borrow().get(field) {
+            Some(value) => T::from_value(value),
+            None => Err(InterpreterResult::UndefinedField(self.name.clone(), field.to_string())),
+        }
+    }
+}
+
+impl IntoArguments for Vec<Value> {
+    fn into_arguments(self) -> Vec<Value> {
+        self
+    }
+}
+
+impl IntoArguments for () {
+    fn into_arguments(self) -> Vec<Value> {
+        Vec::new()
+    }
+}
+
+macro_rules! tuple_arguments {
+    ($($name:ident),+) => {
+        impl<$($name: IntoValue),+> IntoArguments for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn into_arguments(self) -> Vec<Value> {
+                let ($($name,)+) = self;
+
+                vec![$($name.into_value()),+]
+            }
+        }
+    };
+}
+
+tuple_arguments!(A);
+tuple_arguments!(A, B);
+tuple_arguments!(A, B, C);
+tuple_arguments!(A, B, C, D);
+tuple_arguments!(A, B, C, D, E);
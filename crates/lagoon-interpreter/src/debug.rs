@@ -0,0 +1,131 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use lagoon_parser::{Expression, Span, Statement, StatementKind};
+
+use crate::environment::Value;
+use crate::interpreter::{Engine, Frame, Interpreter, InterpreterResult};
+
+/// Hooks that let a debugger follow a program as it runs, e.g. to stop at a
+/// breakpoint. The interpreter waits for a hook to return before carrying on,
+/// so a debugger can inspect the call stack, scopes and expressions in the
+/// meantime. Returning an error stops the program.
+pub trait Debugger {
+    /// Called before every statement runs.
+    fn statement(&mut self, interpreter: &mut Interpreter, span: Span) -> Result<(), InterpreterResult>;
+
+    /// Called when a function is called, once its frame is on the call stack
+    /// but before its body runs.
+    fn call(&mut self, _interpreter: &mut Interpreter, _frame: &Frame) -> Result<(), InterpreterResult> {
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Hook(Rc<RefCell<dyn Debugger>>);
+
+impl Debug for Hook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Debugger")
+    }
+}
+
+impl Interpreter {
+    /// Attach a debugger. The virtual machine doesn't run statement by
+    /// statement, so the tree-walking engine is always used from now on.
+    pub fn set_debugger(&mut self, debugger: impl Debugger + 'static) {
+        self.debugger = Some(Hook(Rc::new(RefCell::new(debugger))));
+        self.set_engine(Engine::TreeWalker);
+    }
+
+    /// The hooks are taken out while they run, so that evaluating an expression
+    /// from inside of a hook doesn't call back into the debugger.
+    pub(crate) fn debug_statement(&mut self, span: Span) -> Result<(), InterpreterResult> {
+        match self.debugger.take() {
+            Some(hook) => {
+                let result = hook.0.borrow_mut().statement(self, span);
+
+                self.debugger = Some(hook);
+                result
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn debug_call(&mut self) -> Result<(), InterpreterResult> {
+        match (self.debugger.take(), self.frames().last().cloned()) {
+            (Some(hook), Some(frame)) => {
+                let result = hook.0.borrow_mut().call(self, &frame);
+
+                self.debugger = Some(hook);
+                result
+            },
+            (hook, _) => {
+                self.debugger = hook;
+                Ok(())
+            },
+        }
+    }
+
+    /// The file whose top-level code is running: a module while it's being
+    /// imported, otherwise the file the interpreter was created with.
+    pub fn current_path(&self) -> PathBuf {
+        self.loading.last().cloned().unwrap_or_else(|| self.path())
+    }
+
+    /// The variables in every scope that the running code can see, innermost
+    /// first. The last scope holds the top-level variables, followed by the
+    /// functions and structs that have been declared.
+    pub fn scopes(&self) -> Vec<Vec<(String, Value)>> {
+        let mut scopes = vec![self.env().values()];
+        let mut parent = self.env().parent();
+
+        while let Some(environment) = parent {
+            scopes.push(environment.borrow().values());
+            parent = environment.borrow().parent();
+        }
+
+        scopes.last_mut().unwrap().extend(self.definitions());
+        scopes
+    }
+
+    /// Evaluate an expression in the current scope, e.g. a watch expression
+    /// while the program is stopped at a breakpoint.
+    pub fn evaluate(&mut self, expression: Expression) -> Result<Value, InterpreterResult> {
+        let span = expression.span;
+        let statement = Statement::new(StatementKind::Return { value: expression }, span);
+
+        // Local variables can't be bound to slots from outside of the code that
+        // declared them, so every visible name is looked up like a global.
+        let names = self.scopes().into_iter().flatten().map(|(name, _)| name).chain(self.globals.keys().cloned()).collect::<Vec<String>>();
+
+        crate::resolver::resolve(std::slice::from_ref(&statement), names).map_err(InterpreterResult::from_errors)?;
+
+        match self.run_statement(statement) {
+            Err(InterpreterResult::Return(value)) => Ok(value),
+            Err(error) => Err(error),
+            Ok(()) => Ok(Value::Null),
+        }
+    }
+}
+
+impl Value {
+    /// The values inside of a list, map or struct instance, named by their
+    /// index, key or field, so that a debugger can show them as children.
+    pub fn children(&self) -> Vec<(String, Value)> {
+        match self {
+            Value::Constant(value) => value.children(),
+            Value::List(items) => items.borrow().iter().enumerate().map(|(index, item)| (index.to_string(), item.clone())).collect(),
+            Value::Map(items) => items.borrow().iter().map(|(key, value)| (key.clone(), value.clone())).collect(),
+            Value::StructInstance { environment, definition } => match &**definition {
+                Value::Struct { fields, .. } => fields.iter()
+                    .filter_map(|field| environment.borrow().get(field.name.clone()).map(|value| (field.name.clone(), value)))
+                    .collect(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+}
@@ -1,50 +1,128 @@
 use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::iter::Iterator;
-use lagoon_parser::{Block, Parameter, Expression};
+use lagoon_parser::{Block, Parameter, Span};
 use bigdecimal::{BigDecimal, FromPrimitive};
 
 use crate::interpreter::Interpreter;
 use crate::interpreter::InterpreterResult;
+use crate::vm::Closure;
+use crate::host::HostObjectRef;
+use crate::module::Module;
 
-pub type NativeFunctionCallback = fn (&mut Interpreter, Vec<Value>) -> Value;
+pub type NativeFunctionCallback = Rc<dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, InterpreterResult>>;
 pub type NativeMethodCallback = fn (&mut Interpreter, Value, Vec<Value>) -> Result<Value, InterpreterResult>;
 
-#[derive(Debug, Clone)]
+/// A single scope of variables. Every block, loop iteration and function call
+/// creates a new `Environment` whose `parent` points at the enclosing scope, so
+/// that lookups and assignments can walk outwards until they find a binding.
+///
+/// Variables keep the position they were first declared at, which is the slot
+/// that the resolver hands out for them.
+#[derive(Debug, Clone, Default)]
 pub struct Environment {
-    values: HashMap<String, Value>,
+    values: IndexMap<String, Value>,
+    parent: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
         Self {
-            values: HashMap::new(),
+            values: IndexMap::new(),
+            parent: None,
+        }
+    }
+
+    pub fn extend(parent: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: IndexMap::new(),
+            parent: Some(parent),
         }
     }
 
+    /// Create (or shadow) a binding in this scope.
     pub fn set(&mut self, name: impl Into<String>, value: Value) {
         self.values.insert(name.into(), value);
     }
 
+    /// Update the nearest existing binding, returning `false` if no scope in
+    /// the chain has a binding with the given name.
+    pub fn assign(&mut self, name: impl Into<String>, value: Value) -> bool {
+        let name = name.into();
+
+        if let Some(existing) = self.values.get_mut(&name) {
+            *existing = value;
+            return true;
+        }
+
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+
     pub fn get(&self, name: impl Into<String>) -> Option<Value> {
-        if let Some(value) = self.values.get(&name.into()) {
-            Some(value.clone())
-        } else {
-            None
+        let name = name.into();
+
+        match self.values.get(&name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.borrow().get(name)),
         }
     }
 
-    pub fn drop(&mut self, name: impl Into<String>) {
-        self.values.remove(&name.into());
+    /// Get the variable in `slot` of the scope `depth` levels outwards. The slot
+    /// is empty if the variable hasn't been declared yet.
+    pub fn get_slot(&self, depth: usize, slot: usize) -> Option<Value> {
+        match depth {
+            0 => self.values.get_index(slot).map(|(_, value)| value.clone()),
+            _ => self.parent.as_ref()?.borrow().get_slot(depth - 1, slot),
+        }
+    }
+
+    pub fn assign_slot(&mut self, depth: usize, slot: usize, value: Value) -> bool {
+        match depth {
+            0 => match self.values.get_index_mut(slot) {
+                Some((_, existing)) => {
+                    *existing = value;
+                    true
+                },
+                None => false,
+            },
+            _ => match &self.parent {
+                Some(parent) => parent.borrow_mut().assign_slot(depth - 1, slot, value),
+                None => false,
+            },
+        }
     }
 
-    pub fn dump(&self) {
-        dbg!(self.values.clone());
+    /// The names of the variables declared in this scope.
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.values.keys()
+    }
+
+    /// The variables declared in this scope, along with their values.
+    pub(crate) fn values(&self) -> Vec<(String, Value)> {
+        self.values.iter().map(|(name, value)| (name.clone(), value.clone())).collect()
+    }
+
+    pub(crate) fn parent(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.parent.clone()
     }
 }
 
+/// A catchable error. Built-in runtime errors are converted into one of these
+/// when they reach a `catch` block, and scripts can create their own with the
+/// `error()` function.
+#[derive(Debug, Clone)]
+pub struct ErrorValue {
+    pub kind: String,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
 #[derive(Clone)]
 pub enum Value {
     Number(f64),
@@ -61,12 +139,20 @@ pub enum Value {
         definition: Box<Value>,
     },
     List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<IndexMap<String, Value>>>),
     Function {
         name: String,
         params: Vec<Parameter>,
-        body: Block,
-        environment: Option<Environment>,
-        context: Option<Expression>,
+        /// Shared by every copy of the function, which is what makes two
+        /// functions the same one.
+        body: Rc<Block>,
+        environment: Option<Rc<RefCell<Environment>>>,
+        context: Option<Box<Value>>,
+    },
+    /// A function compiled to bytecode by the virtual machine.
+    Closure {
+        closure: Rc<Closure>,
+        context: Option<Box<Value>>,
     },
     NativeFunction {
         name: String,
@@ -75,9 +161,13 @@ pub enum Value {
     NativeMethod {
         name: String,
         callback: NativeMethodCallback,
-        context: Expression,
+        context: Box<Value>,
     },
     Constant(Box<Value>),
+    Error(Rc<ErrorValue>),
+    /// A value owned by the program that is embedding Lagoon.
+    Object(HostObjectRef),
+    Module(Rc<Module>),
 }
 
 impl Debug for Value {
@@ -87,8 +177,9 @@ impl Debug for Value {
             Value::Number(n) => n.to_string(),
             Value::String(s) => s.to_string(),
             Value::Null => "null".to_string(),
-            Value::NativeFunction { name, .. } => format!("<{}>", name),
-            Value::Function { name, params, .. } => format!("<{}>({})", name, params.into_iter().map(|p| p.name.clone()).collect::<Vec<String>>().join(", ")),
+            Value::NativeFunction { name, .. } | Value::NativeMethod { name, .. } => format!("<{}>", name),
+            Value::Function { name, params, .. } => format!("<{}>({})", name, params.iter().map(|p| p.name.clone()).collect::<Vec<String>>().join(", ")),
+            Value::Closure { closure, .. } => format!("<{}>({})", closure.name, closure.function.params.iter().map(|p| p.name.clone()).collect::<Vec<String>>().join(", ")),
             Value::StructInstance { definition, .. } => {
                 let name = match *definition.clone() {
                     Value::Struct { name, .. } => name,
@@ -99,8 +190,8 @@ impl Debug for Value {
             },
             Value::Struct { name, methods, fields, .. } => {
                 let name = format!("<struct:{}>", name);
-                let mut fields = fields.into_iter().map(|p| p.name.clone()).collect::<Vec<String>>();
-                let mut methods = methods.borrow().keys().into_iter().map(|p| {
+                let mut fields = fields.iter().map(|p| p.name.clone()).collect::<Vec<String>>();
+                let mut methods = methods.borrow().keys().map(|p| {
                     let mut p = p.clone();
                     p.push_str("()");
                     p
@@ -124,16 +215,26 @@ impl Debug for Value {
                     }
                 }
 
-                buffer.push_str("]");
+                buffer.push(']');
                 buffer
             },
+            Value::Map(items) => {
+                let items = items.borrow().iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.clone().to_string()))
+                    .collect::<Vec<String>>();
+
+                format!("{{{}}}", items.join(", "))
+            },
+            Value::Error(error) => format!("{}: {}", error.kind, error.message),
+            Value::Object(object) => format!("<{}>", object.borrow().type_name()),
+            Value::Module(module) => format!("<module {}>", module.name),
             Value::Bool(true) => "true".to_string(),
             Value::Bool(false) => "false".to_string(),
-            _ => todo!(),
         })
     }
 }
 
+#[allow(clippy::wrong_self_convention, clippy::inherent_to_string)]
 impl Value {
     pub fn to_vec(self) -> Rc<RefCell<Vec<Value>>> {
         match self {
@@ -143,23 +244,29 @@ impl Value {
         }
     }
 
+    pub fn to_map(self) -> Rc<RefCell<IndexMap<String, Value>>> {
+        match self {
+            Value::Map(map) => map,
+            Value::Constant(v) => v.to_map(),
+            _ => unreachable!()
+        }
+    }
+
     pub fn to_bigdecimal(self) -> BigDecimal {
         BigDecimal::from_f64(self.to_number()).unwrap()
     }
 
+    /// Values that don't stand for a number, such as lists, are `NaN`.
     pub fn to_number(self) -> f64 {
         match self {
             Value::Number(n) => n,
             Value::Bool(true) => 1.0,
             Value::Null | Value::Bool(false) => 0.0,
             Value::String(s) => {
-                match s.trim().parse::<f64>() {
-                    Ok(f) => f,
-                    Err(_) => 0.0
-                }
+                s.trim().parse::<f64>().unwrap_or(0.0)
             }
             Value::Constant(v) => v.to_number(),
-            _ => unreachable!(),
+            _ => f64::NAN,
         }
     }
 
@@ -169,15 +276,14 @@ impl Value {
             Value::Number(n) => n.to_string(),
             Value::Bool(_) => self.to_number().to_string(),
             Value::Null => "".to_string(),
-            v @ Value::Function { .. } | v @ Value::StructInstance { .. } | v @ Value::List(..) | v @ Value::Struct { .. } => format!("{:?}", v),
             Value::Constant(v) => v.to_string(),
-            _ => todo!(),
+            v => format!("{:?}", v),
         }
     }
 
     pub fn to_bool(self) -> bool {
         match self {
-            Value::Bool(true) | Value::Function { .. } => true,
+            Value::Bool(true) | Value::Function { .. } | Value::Closure { .. } | Value::Object(..) | Value::Module(..) => true,
             Value::String(s) => !s.is_empty(),
             Value::Number(n) => n > 0.0,
             Value::Constant(v) => v.to_bool(),
@@ -185,15 +291,40 @@ impl Value {
         }
     }
 
-    pub fn is(self, other: Value) -> bool {
-        match (self, other.clone()) {
-            (Value::String(l), r) => l == r.to_string(),
-            (Value::Number(n), r) => n == r.to_number(),
-            (Value::Bool(true), r) => r.to_bool() == true,
-            (Value::Bool(false), r) => r.to_bool() == false,
-            (Value::Null, Value::Null) => true,
-            (Value::Constant(v), _) => v.is(other),
-            _ => false,
+    /// Bind a method to the value it was accessed on, so that `this` refers to
+    /// that value when it is called. Any other value is returned untouched.
+    pub fn bind(self, context: Value) -> Value {
+        match self {
+            Value::Function { name, params, body, environment, .. } => Value::Function {
+                name, params, body, environment, context: Some(Box::new(context)),
+            },
+            Value::Closure { closure, .. } => Value::Closure { closure, context: Some(Box::new(context)) },
+            _ => self,
+        }
+    }
+
+    /// Remove the value that a method is bound to.
+    pub fn unbind(self) -> Value {
+        match self {
+            Value::Function { name, params, body, environment, .. } => Value::Function {
+                name, params, body, environment, context: None,
+            },
+            Value::Closure { closure, .. } => Value::Closure { closure, context: None },
+            _ => self,
+        }
+    }
+
+    /// Give a function a new name, returning `None` if the value isn't a function.
+    pub fn renamed(self, name: String) -> Option<Value> {
+        match self {
+            Value::Function { params, body, environment, context, .. } => Some(Value::Function {
+                name, params, body, environment, context,
+            }),
+            Value::Closure { closure, context } => Some(Value::Closure {
+                closure: Rc::new(Closure { name, ..(*closure).clone() }),
+                context,
+            }),
+            _ => None,
         }
     }
 
@@ -203,15 +334,18 @@ impl Value {
             Value::Number(..) => "number".into(),
             Value::Bool(..) => "bool".into(),
             Value::Null => "null".into(),
-            Value::Function { .. } | Value::NativeFunction { .. } => "function".into(),
+            Value::Function { .. } | Value::Closure { .. } | Value::NativeFunction { .. } | Value::NativeMethod { .. } => "function".into(),
             Value::StructInstance { definition, .. } => match *definition.clone() {
                 Value::Struct { name, .. } => name,
                 _ => unreachable!()
             },
             Value::Struct { .. } => "struct".into(),
             Value::List(..) => "list".into(),
+            Value::Map(..) => "map".into(),
+            Value::Error(..) => "error".into(),
+            Value::Object(object) => object.borrow().type_name().to_string(),
+            Value::Module(..) => "module".into(),
             Value::Constant(v) => v.typestring(),
-            _ => unreachable!()
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::environment::Value;
+use crate::interpreter::{Interpreter, InterpreterResult};
+
+/// A Rust value that scripts can use like an object, reading and writing its
+/// fields and calling its methods.
+///
+/// Methods receive the interpreter so that they can call back into the script,
+/// e.g. to run a callback that was passed in as an argument.
+pub trait HostObject {
+    /// The name reported by `type()` and in error messages.
+    fn type_name(&self) -> &str;
+
+    fn get(&self, _field: &str) -> Option<Value> {
+        None
+    }
+
+    fn set(&mut self, field: &str, _value: Value) -> Result<(), InterpreterResult> {
+        Err(InterpreterResult::UndefinedField(self.type_name().to_string(), field.to_string()))
+    }
+
+    fn has_method(&self, _method: &str) -> bool {
+        false
+    }
+
+    fn call(&mut self, _interpreter: &mut Interpreter, method: &str, _arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+        Err(InterpreterResult::UndefinedMethod(self.type_name().to_string(), method.to_string()))
+    }
+}
+
+pub type HostObjectRef = Rc<RefCell<dyn HostObject>>;
+
+impl Value {
+    /// Wrap a Rust value so that it can be passed to a script.
+    pub fn object(object: impl HostObject + 'static) -> Value {
+        Value::Object(Rc::new(RefCell::new(object)))
+    }
+}
+
+pub(crate) fn get_field(object: HostObjectRef, field: String) -> Result<Value, InterpreterResult> {
+    let borrowed = object.try_borrow().map_err(|_| busy(&field))?;
+
+    if let Some(value) = borrowed.get(&field) {
+        return Ok(value);
+    }
+
+    let name = format!("{}.{}", borrowed.type_name(), field);
+
+    if ! borrowed.has_method(&field) {
+        return Err(InterpreterResult::UndefinedField(borrowed.type_name().to_string(), field));
+    }
+
+    drop(borrowed);
+
+    // Methods are bound to the object they were accessed on, just like the
+    // methods of a struct instance.
+    Ok(Value::NativeFunction {
+        name,
+        callback: Rc::new(move |interpreter, arguments| {
+            let value = object.try_borrow_mut().map_err(|_| busy(&field))?.call(interpreter, &field, arguments)?;
+
+            Ok(interpreter.adopt(value))
+        }),
+    })
+}
+
+pub(crate) fn set_field(object: HostObjectRef, field: String, value: Value) -> Result<(), InterpreterResult> {
+    let mut borrowed = object.try_borrow_mut().map_err(|_| busy(&field))?;
+
+    borrowed.set(&field, value)
+}
+
+/// A method that calls back into the script can't use the object it was called
+/// on until it returns.
+fn busy(field: &str) -> InterpreterResult {
+    InterpreterResult::ObjectInUse(field.to_string())
+}
@@ -1,41 +1,83 @@
-use std::slice::Iter;
 use std::rc::Rc;
 use std::cell::{RefCell, Ref, RefMut};
 use std::path::PathBuf;
 use std::fs::canonicalize;
+use std::mem::size_of;
+use std::time::Duration;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use hashbrown::HashMap;
+use indexmap::IndexMap;
 use thiserror::Error;
-use colored::*;
 use lagoon_parser::*;
+use lagoon_parser::diagnostics::{colors_enabled, Diagnostic};
+use colored::Colorize;
 
 use crate::environment::*;
+use crate::convert::{IntoValue, FromValue, IntoArguments};
+use crate::limits::{Limits, Usage, STACK_RED_ZONE, STACK_SEGMENT_SIZE};
+use crate::permissions::{Capability, Permissions};
+use crate::lint::{Lints, Problem};
+use crate::debug::Hook;
 
 pub fn register_global_functions(interpreter: &mut Interpreter) {
-    interpreter.define_global_function("println", crate::stdlib::println);
-    interpreter.define_global_function("print", crate::stdlib::print);
-    interpreter.define_global_function("type", crate::stdlib::r#type);
-    interpreter.define_global_function("require", crate::stdlib::require);
+    interpreter.define_native("println", crate::stdlib::println);
+    interpreter.define_native("print", crate::stdlib::print);
+    interpreter.define_native("type", crate::stdlib::r#type);
+    interpreter.define_native("require", crate::stdlib::require);
+    interpreter.define_native("error", crate::stdlib::error);
 }
 
 pub fn interpret(ast: Program, path: PathBuf) -> Result<(), InterpreterResult> {
-    let mut interpreter = Interpreter::new(ast.iter(), canonicalize(path).unwrap());
+    interpret_with(ast, path, Engine::TreeWalker)
+}
+
+pub fn interpret_with(ast: Program, path: PathBuf, engine: Engine) -> Result<(), InterpreterResult> {
+    let mut interpreter = Interpreter::new(ast, canonicalize(path).unwrap());
+
+    interpreter.set_engine(engine);
 
     register_global_functions(&mut interpreter);
 
     interpreter.run()
 }
 
+/// The strategy used to execute a program. Both engines share the same values,
+/// standard library and error reporting, so a program should behave identically
+/// under either of them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Engine {
+    /// Walk the syntax tree directly.
+    #[default]
+    TreeWalker,
+    /// Compile the program to bytecode and run it on the virtual machine.
+    Bytecode,
+}
+
+
 #[derive(Error, Debug)]
 pub enum InterpreterResult {
     #[error("")]
     Return(Value),
 
+    #[error("Cannot use `break` outside of a loop.")]
+    Break(Span),
+
+    #[error("Cannot use `continue` outside of a loop.")]
+    Continue(Span),
+
     #[error("Undefined variable: {0}.")]
     UndefinedVariable(String),
 
     #[error("Undefined index: {0}.")]
     UndefinedIndex(usize),
 
+    #[error("Undefined key: {0:?}.")]
+    UndefinedKey(String),
+
+    #[error("Map keys must be strings, received {0}.")]
+    InvalidMapKey(String),
+
     #[error("Undefined field: {0}.{1}")]
     UndefinedField(String, String),
 
@@ -56,338 +98,773 @@ pub enum InterpreterResult {
 
     #[error("Cannot assign value to constant.")]
     CannotAssignValueToConstant,
+
+    #[error("Method {0} expected {1} arguments, received {2}.")]
+    ArgumentCountMismatch(String, usize, usize),
+
+    #[error("Method {0} expected an argument of type {1}, received {2}.")]
+    InvalidArgumentType(String, String, String),
+
+    #[error("Cannot find module: {0}.")]
+    ModuleNotFound(String),
+
+    #[error("Failed to load module {0}: {1}")]
+    InvalidModule(String, String),
+
+    #[error("Module {0} doesn't export {1}.")]
+    UndefinedExport(String, String),
+
+    #[error("Circular import: {0}.")]
+    CircularImport(String),
+
+    #[error("Invalid JSON: {0}.")]
+    InvalidJson(String),
+
+    #[error("Cannot compare {0} with {1}.")]
+    InvalidComparison(String, String),
+
+    #[error("Cannot apply `{0}` to {1} and {2}.")]
+    UnsupportedOperands(String, String, String),
+
+    #[error("Cannot apply `{0}` to {1}.")]
+    UnsupportedOperand(String, String),
+
+    #[error("Cannot access property {1} on value of type {0}.")]
+    InvalidPropertyAccess(String, String),
+
+    #[error("Invalid assignment target.")]
+    InvalidAssignmentTarget,
+
+    #[error("Cannot instantiate value of type {0}, it isn't a struct.")]
+    NotAStruct(String),
+
+    #[error("Cannot index into value of type {0}.")]
+    NotIndexable(String),
+
+    #[error("List indices must be numbers, received {0}.")]
+    InvalidIndex(String),
+
+    #[error("Cannot call value of type {0}.")]
+    NotCallable(String),
+
+    #[error("Expected a value of type {0}, received {1}.")]
+    TypeMismatch(String, String),
+
+    #[error("Cannot use {0} while one of its methods is running.")]
+    ObjectInUse(String),
+
+    #[error("Ran out of fuel after {0} steps.")]
+    OutOfFuel(u64),
+
+    #[error("Exceeded the maximum call depth of {0}.")]
+    CallDepthExceeded(usize),
+
+    #[error("Exceeded the memory limit of {0} bytes.")]
+    MemoryLimitExceeded(usize),
+
+    #[error("Timed out after {0:?}.")]
+    Timeout(Duration),
+
+    #[error("Permission denied: {0} access to {1}.")]
+    PermissionDenied(Capability, String),
+
+    #[error("Uncaught exception: {}", .0.clone().to_string())]
+    Thrown(Value),
+
+    #[error("The program was stopped by the debugger.")]
+    Stopped,
+
+    #[error("{0}")]
+    Located(Box<InterpreterResult>, Span),
+
+    #[error("{0}")]
+    Traced(Box<InterpreterResult>, Vec<Frame>),
+
+    /// Every error that was found before the program started to run, such as
+    /// each undefined variable that the resolver came across. There are always
+    /// at least two of them.
+    #[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("\n"))]
+    Multiple(Vec<InterpreterResult>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Function,
+    Method,
+    Closure,
+    Native,
+}
+
+/// A single entry in the call stack. The `span` points at the expression that
+/// called the function, not the function itself.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    pub kind: FrameKind,
+    pub span: Span,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self.kind {
+            FrameKind::Closure => write!(f, "<closure>"),
+            FrameKind::Native => write!(f, "{} (native)", self.name),
+            FrameKind::Function | FrameKind::Method => write!(f, "{}", self.name),
+        }
+    }
 }
 
 impl InterpreterResult {
-    pub fn print(self) {
-        eprintln!("{}", format!("{}", self).red().bold());
-        std::process::exit(1);
+    /// Attach a source location to the error, unless it already has one or
+    /// is only being used to unwind the stack (e.g. `return`).
+    pub fn located(self, span: Span) -> Self {
+        match self {
+            InterpreterResult::Traced(error, trace) => InterpreterResult::Traced(Box::new(error.located(span)), trace),
+            InterpreterResult::Return(..) | InterpreterResult::Break(..) |
+            InterpreterResult::Continue(..) | InterpreterResult::Located(..) | InterpreterResult::Multiple(..) => self,
+            _ => InterpreterResult::Located(Box::new(self), span),
+        }
+    }
+
+    /// Combine the errors found before a program runs into one.
+    pub(crate) fn from_errors(mut errors: Vec<InterpreterResult>) -> Self {
+        match errors.len() {
+            1 => errors.remove(0),
+            _ => InterpreterResult::Multiple(errors),
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            InterpreterResult::Located(_, span) | InterpreterResult::Break(span) |
+            InterpreterResult::Continue(span) => Some(*span),
+            InterpreterResult::Traced(error, _) => error.span(),
+            InterpreterResult::Multiple(errors) => errors[0].span(),
+            _ => None,
+        }
+    }
+
+    /// The call stack at the point the error was raised, innermost call first.
+    /// Errors raised outside of any function have an empty stack trace.
+    pub fn trace(&self) -> &[Frame] {
+        match self {
+            InterpreterResult::Traced(_, trace) => trace,
+            _ => &[],
+        }
+    }
+
+    /// The underlying error, without any location information. When there
+    /// are several errors, this is the first of them.
+    pub fn inner(&self) -> &InterpreterResult {
+        match self {
+            InterpreterResult::Located(error, _) | InterpreterResult::Traced(error, _) => error.inner(),
+            InterpreterResult::Multiple(errors) => errors[0].inner(),
+            _ => self,
+        }
+    }
+
+    /// Every error that this one stands for, which is only ever more than one
+    /// for the problems found before a program runs.
+    pub fn errors(&self) -> Vec<&InterpreterResult> {
+        match self {
+            InterpreterResult::Multiple(errors) => errors.iter().collect(),
+            _ => vec![self],
+        }
+    }
+
+    /// The name of the error, which is exposed to scripts as the `kind` of a
+    /// caught error.
+    pub fn kind(&self) -> &'static str {
+        match self.inner() {
+            InterpreterResult::Return(..) => "Return",
+            InterpreterResult::Break(..) => "Break",
+            InterpreterResult::Continue(..) => "Continue",
+            InterpreterResult::UndefinedVariable(..) => "UndefinedVariable",
+            InterpreterResult::UndefinedIndex(..) => "UndefinedIndex",
+            InterpreterResult::UndefinedKey(..) => "UndefinedKey",
+            InterpreterResult::InvalidMapKey(..) => "InvalidMapKey",
+            InterpreterResult::UndefinedField(..) => "UndefinedField",
+            InterpreterResult::UndefinedMethod(..) => "UndefinedMethod",
+            InterpreterResult::InvalidIterable(..) => "InvalidIterable",
+            InterpreterResult::TooFewArguments(..) => "TooFewArguments",
+            InterpreterResult::InvalidAppendTarget(..) => "InvalidAppendTarget",
+            InterpreterResult::InvalidMethodAssignmentTarget(..) => "InvalidMethodAssignmentTarget",
+            InterpreterResult::CannotAssignValueToConstant => "CannotAssignValueToConstant",
+            InterpreterResult::ArgumentCountMismatch(..) => "ArgumentCountMismatch",
+            InterpreterResult::InvalidArgumentType(..) => "InvalidArgumentType",
+            InterpreterResult::ModuleNotFound(..) => "ModuleNotFound",
+            InterpreterResult::InvalidModule(..) => "InvalidModule",
+            InterpreterResult::NotCallable(..) => "NotCallable",
+            InterpreterResult::TypeMismatch(..) => "TypeMismatch",
+            InterpreterResult::ObjectInUse(..) => "ObjectInUse",
+            InterpreterResult::OutOfFuel(..) => "OutOfFuel",
+            InterpreterResult::CallDepthExceeded(..) => "CallDepthExceeded",
+            InterpreterResult::MemoryLimitExceeded(..) => "MemoryLimitExceeded",
+            InterpreterResult::Timeout(..) => "Timeout",
+            InterpreterResult::PermissionDenied(..) => "PermissionDenied",
+            InterpreterResult::UndefinedExport(..) => "UndefinedExport",
+            InterpreterResult::CircularImport(..) => "CircularImport",
+            InterpreterResult::InvalidJson(..) => "InvalidJson",
+            InterpreterResult::InvalidComparison(..) => "InvalidComparison",
+            InterpreterResult::UnsupportedOperands(..) => "UnsupportedOperands",
+            InterpreterResult::UnsupportedOperand(..) => "UnsupportedOperand",
+            InterpreterResult::InvalidPropertyAccess(..) => "InvalidPropertyAccess",
+            InterpreterResult::InvalidAssignmentTarget => "InvalidAssignmentTarget",
+            InterpreterResult::NotAStruct(..) => "NotAStruct",
+            InterpreterResult::NotIndexable(..) => "NotIndexable",
+            InterpreterResult::InvalidIndex(..) => "InvalidIndex",
+            InterpreterResult::Thrown(..) => "Error",
+            InterpreterResult::Stopped => "Stopped",
+            InterpreterResult::Located(..) | InterpreterResult::Traced(..) | InterpreterResult::Multiple(..) => unreachable!(),
+        }
+    }
+
+    /// Whether a `catch` block is allowed to handle this error. Control flow
+    /// such as `return` and `break` must always pass straight through, and so
+    /// must exceeded limits and a debugger stopping the program so that they
+    /// always reach the host.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(self.inner(),
+            InterpreterResult::Return(..) | InterpreterResult::Break(..) | InterpreterResult::Continue(..) |
+            InterpreterResult::OutOfFuel(..) | InterpreterResult::CallDepthExceeded(..) |
+            InterpreterResult::MemoryLimitExceeded(..) | InterpreterResult::Timeout(..) | InterpreterResult::Stopped
+        )
+    }
+
+    /// Convert the error into the value that is bound to the variable of a
+    /// `catch` block.
+    pub fn into_value(self) -> Value {
+        let span = self.span();
+
+        match self.inner() {
+            InterpreterResult::Thrown(Value::Error(error)) if error.span.is_none() => Value::Error(Rc::new(ErrorValue {
+                span,
+                ..(**error).clone()
+            })),
+            InterpreterResult::Thrown(value) => value.clone(),
+            error => Value::Error(Rc::new(ErrorValue {
+                kind: error.kind().to_string(),
+                message: error.to_string(),
+                span,
+            })),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self.inner() {
+            InterpreterResult::Return(..) => "E0100",
+            InterpreterResult::UndefinedVariable(..) => "E0101",
+            InterpreterResult::UndefinedIndex(..) => "E0102",
+            InterpreterResult::UndefinedField(..) => "E0103",
+            InterpreterResult::UndefinedMethod(..) => "E0104",
+            InterpreterResult::InvalidIterable(..) => "E0105",
+            InterpreterResult::TooFewArguments(..) => "E0106",
+            InterpreterResult::InvalidAppendTarget(..) => "E0107",
+            InterpreterResult::InvalidMethodAssignmentTarget(..) => "E0108",
+            InterpreterResult::CannotAssignValueToConstant => "E0109",
+            InterpreterResult::Break(..) | InterpreterResult::Continue(..) => "E0110",
+            InterpreterResult::UndefinedKey(..) => "E0111",
+            InterpreterResult::InvalidMapKey(..) => "E0112",
+            InterpreterResult::Thrown(..) => "E0113",
+            InterpreterResult::ArgumentCountMismatch(..) => "E0114",
+            InterpreterResult::InvalidArgumentType(..) => "E0115",
+            InterpreterResult::ModuleNotFound(..) => "E0116",
+            InterpreterResult::InvalidModule(..) => "E0117",
+            InterpreterResult::NotCallable(..) => "E0118",
+            InterpreterResult::TypeMismatch(..) => "E0119",
+            InterpreterResult::ObjectInUse(..) => "E0120",
+            InterpreterResult::OutOfFuel(..) => "E0121",
+            InterpreterResult::CallDepthExceeded(..) => "E0122",
+            InterpreterResult::MemoryLimitExceeded(..) => "E0123",
+            InterpreterResult::Timeout(..) => "E0124",
+            InterpreterResult::PermissionDenied(..) => "E0125",
+            InterpreterResult::UndefinedExport(..) => "E0126",
+            InterpreterResult::CircularImport(..) => "E0127",
+            InterpreterResult::InvalidJson(..) => "E0128",
+            InterpreterResult::Stopped => "E0129",
+            InterpreterResult::InvalidComparison(..) => "E0130",
+            InterpreterResult::UnsupportedOperands(..) => "E0131",
+            InterpreterResult::UnsupportedOperand(..) => "E0132",
+            InterpreterResult::InvalidPropertyAccess(..) => "E0133",
+            InterpreterResult::InvalidAssignmentTarget => "E0134",
+            InterpreterResult::NotAStruct(..) => "E0135",
+            InterpreterResult::NotIndexable(..) => "E0136",
+            InterpreterResult::InvalidIndex(..) => "E0137",
+            InterpreterResult::Located(..) | InterpreterResult::Traced(..) | InterpreterResult::Multiple(..) => unreachable!(),
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let error = self.inner();
+        let message = match error {
+            InterpreterResult::Return(..) => "Cannot return from outside of a function.".to_string(),
+            _ => error.to_string(),
+        };
+
+        let mut diagnostic = Diagnostic::error(message).with_code(self.code());
+
+        if let Some(span) = self.span() {
+            diagnostic = diagnostic.with_label(span, match error {
+                InterpreterResult::UndefinedVariable(..) => "not found in this scope",
+                InterpreterResult::UndefinedIndex(..) => "index out of bounds",
+                InterpreterResult::UndefinedKey(..) => "key not found in map",
+                InterpreterResult::Thrown(..) => "thrown here",
+                InterpreterResult::UndefinedField(..) | InterpreterResult::UndefinedMethod(..) => "unknown property",
+                InterpreterResult::TooFewArguments(..) | InterpreterResult::ArgumentCountMismatch(..) |
+                InterpreterResult::InvalidArgumentType(..) | InterpreterResult::NotCallable(..) |
+                InterpreterResult::CallDepthExceeded(..) => "called here",
+                InterpreterResult::ModuleNotFound(..) | InterpreterResult::InvalidModule(..) |
+                InterpreterResult::CircularImport(..) => "required here",
+                InterpreterResult::UndefinedExport(..) => "not exported",
+                InterpreterResult::PermissionDenied(..) => "not allowed",
+                InterpreterResult::CannotAssignValueToConstant => "cannot assign twice to constant",
+                InterpreterResult::InvalidAssignmentTarget => "cannot assign to this expression",
+                InterpreterResult::UnsupportedOperands(..) | InterpreterResult::UnsupportedOperand(..) |
+                InterpreterResult::InvalidComparison(..) => "unsupported operation",
+                InterpreterResult::Break(..) | InterpreterResult::Continue(..) => "not inside of a `for` or `while` loop",
+                _ => "",
+            });
+        }
+
+        match error {
+            InterpreterResult::UndefinedVariable(name) => diagnostic
+                .with_help(format!("declare the variable before using it, e.g. `let {} = null`", name)),
+            InterpreterResult::TooFewArguments(_, _, expected) => diagnostic
+                .with_note(format!("the function expects exactly {} argument(s)", expected)),
+            InterpreterResult::CannotAssignValueToConstant => diagnostic
+                .with_help("use `let` instead of `const` if the value needs to change"),
+            InterpreterResult::Return(..) => diagnostic
+                .with_note("`return` can only be used inside of a function body"),
+            InterpreterResult::UndefinedKey(..) => diagnostic
+                .with_help("use `key in map` or `map.has(key)` to check whether a key exists"),
+            InterpreterResult::Thrown(..) => diagnostic
+                .with_help("wrap the code in a `try { ... } catch (e) { ... }` statement to handle the error"),
+            InterpreterResult::CallDepthExceeded(..) => diagnostic
+                .with_help("check for a recursive function that never returns"),
+            InterpreterResult::PermissionDenied(capability, _) => diagnostic
+                .with_help(format!("run the script with `{}` to grant access", capability.flag())),
+            InterpreterResult::UndefinedExport(_, name) => diagnostic
+                .with_help(format!("add `export` to the declaration of `{}`", name)),
+            InterpreterResult::CircularImport(..) => diagnostic
+                .with_help("move the declarations that both modules need into a module of their own"),
+            InterpreterResult::ModuleNotFound(path) if ! path.starts_with('.') && ! path.starts_with("std/") => diagnostic
+                .with_help("packages are loaded from the dependencies in `lagoon.toml`, `lagoon_modules`, `vendor` or a directory in `LAGOON_PATH`, use `./` for a relative path"),
+            _ => diagnostic,
+        }
+    }
+
+    pub fn print(&self, source: &str, path: &str) {
+        if let InterpreterResult::Multiple(errors) = self {
+            for error in errors {
+                error.print(source, path);
+                eprintln!();
+            }
+
+            let message = format!("aborting due to {} previous errors", errors.len());
+
+            return match colors_enabled() {
+                true => eprintln!("{}: {}", "error".red().bold(), message.bold()),
+                false => eprintln!("error: {}", message),
+            };
+        }
+
+        self.diagnostic().emit(source, path);
+
+        if ! self.trace().is_empty() {
+            eprint!("{}", self.render_trace(path));
+        }
+    }
+
+    /// Render the stack trace, one frame per line with the innermost call first.
+    pub fn render_trace(&self, path: &str) -> String {
+        let mut out = String::from("stack backtrace:\n");
+
+        for (i, frame) in self.trace().iter().enumerate() {
+            out.push_str(&format!("{:>4}: {}\n", i, frame));
+            out.push_str(&format!("          at {}:{}:{}\n", path, frame.span.line, frame.span.column));
+        }
+
+        out
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct Interpreter<'i> {
-    ast: Iter<'i, Statement>,
-    environment: Rc<RefCell<Environment>>,
+pub struct Interpreter {
+    ast: Program,
+    pub(crate) environment: Rc<RefCell<Environment>>,
     pub globals: HashMap<String, Value>,
     path: PathBuf,
+    frames: Vec<Frame>,
+    engine: Engine,
+    pub(crate) limits: Limits,
+    pub(crate) usage: Usage,
+    pub(crate) permissions: Permissions,
+    /// Modules that have been loaded, keyed by their canonical path. A module
+    /// that is still running has no value yet.
+    pub(crate) modules: HashMap<PathBuf, Option<Value>>,
+    /// The modules that are currently running, outermost first.
+    pub(crate) loading: Vec<PathBuf>,
+    /// The globals that every module starts with, such as native functions.
+    pub(crate) prelude: HashMap<String, Value>,
+    pub(crate) search_paths: Vec<PathBuf>,
+    /// The entry points of packages that have been added by name.
+    pub(crate) packages: HashMap<String, PathBuf>,
+    pub(crate) debugger: Option<Hook>,
 }
 
-impl<'i> Interpreter<'i> {
-    pub fn new(ast: Iter<'i, Statement>, path: PathBuf) -> Self {
+impl Interpreter {
+    /// Create an interpreter for a program. The standard library isn't loaded
+    /// until `register_global_functions()` is called.
+    pub fn new(ast: Program, path: PathBuf) -> Self {
         Self {
-            ast: ast,
+            ast,
             environment: Rc::new(RefCell::new(Environment::new())),
             globals: HashMap::new(),
-            path: path,
+            path,
+            frames: Vec::new(),
+            engine: Engine::default(),
+            limits: Limits::default(),
+            usage: Usage::default(),
+            permissions: Permissions::default(),
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            prelude: HashMap::new(),
+            search_paths: Vec::new(),
+            packages: HashMap::new(),
+            debugger: None,
         }
     }
 
-    fn run_statement(&mut self, statement: Statement) -> Result<(), InterpreterResult> {
-        Ok(match statement {
-            Statement::LetDeclaration { name, initial } => {
-                if initial.is_none() {
-                    self.env_mut().set(name, Value::Null)
-                } else {
-                    let initial = initial.unwrap();
-                    let value = self.run_expression(initial)?;
+    pub(crate) fn run_statement(&mut self, statement: Statement) -> Result<(), InterpreterResult> {
+        let span = statement.span;
 
-                    self.env_mut().set(name, value)
-                }
+        self.tick().map_err(|e| e.located(span))?;
+        self.debug_statement(span).map_err(|e| e.located(span))?;
+
+        self.run_statement_kind(statement.kind, span).map_err(|e| e.located(span))
+    }
+
+    /// Run the body of a `for` or `while` loop, returning `false` when a `break`
+    /// statement means the loop should stop.
+    fn run_loop_body(&mut self, block: &[Statement]) -> Result<bool, InterpreterResult> {
+        self.tick()?;
+
+        for statement in block {
+            match self.run_statement(statement.clone()) {
+                Err(InterpreterResult::Break(..)) => return Ok(false),
+                Err(InterpreterResult::Continue(..)) => return Ok(true),
+                Err(error) => return Err(error),
+                Ok(_) => (),
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn run_statement_kind(&mut self, statement: StatementKind, span: Span) -> Result<(), InterpreterResult> {
+        match statement {
+            StatementKind::LetDeclaration { name, initial } => {
+                let value = match initial {
+                    Some(initial) => self.run_expression(initial)?,
+                    None => Value::Null,
+                };
+
+                self.env_mut().set(name, value)
             },
-            Statement::ConstDeclaration { name, initial } => {
+            StatementKind::ConstDeclaration { name, initial } => {
                 let value = Value::Constant(Box::new(self.run_expression(initial)?));
 
                 self.env_mut().set(name, value)
             },
-            Statement::FunctionDeclaration { name, params, body } => {
+            StatementKind::FunctionDeclaration { name, params, body } => {
+                let environment = Some(Rc::clone(&self.environment));
+
                 self.globals.insert(name.clone(), Value::Function {
-                    name, params, body, environment: None, context: None,
+                    name, params, body: Rc::new(body), environment, context: None,
                 });
             },
-            Statement::StructDeclaration { name, fields } => {
+            StatementKind::StructDeclaration { name, fields } => {
                 self.globals.insert(name.clone(), Value::Struct {
                     name, fields, methods: Rc::new(RefCell::new(HashMap::new())),
                 });
+
+                self.adopt_globals();
             },
-            Statement::For { iterable, value, index, then } => {
+            StatementKind::For { iterable, value, index, then } => {
                 let iterable = self.run_expression(iterable)?;
 
-                let items = match iterable {
-                    Value::List(items) => items,
-                    _ => return Err(InterpreterResult::InvalidIterable(iterable.typestring())),
-                };
+                let items = iterate(iterable)?;
 
                 // If there aren't any items in the list, we can leave this execution
                 // cycle early.
-                if items.borrow().is_empty() {
+                if items.is_empty() {
                     return Ok(())
                 }
 
-                let set_index: bool = index.is_some();
+                // Each iteration gets a fresh scope, so closures created inside of
+                // the loop body capture the value from that particular iteration.
+                for (i, item) in items.into_iter() {
+                    let mut scope = Environment::extend(Rc::clone(&self.environment));
 
-                for (i, item) in items.borrow().iter().enumerate() {
-                    self.env_mut().set(value.clone(), item.clone());
+                    scope.set(value.clone(), item);
 
-                    if set_index {
-                        self.env_mut().set(index.clone().unwrap(), Value::Number(i as f64));
+                    if let Some(index) = &index {
+                        scope.set(index.clone(), i);
                     }
 
-                    for statement in then.clone() {
-                        self.run_statement(statement)?;
+                    if ! self.scoped(scope, |interpreter| interpreter.run_loop_body(&then))? {
+                        break;
                     }
                 }
+            },
+            StatementKind::While { condition, then } => {
+                while self.run_expression(condition.clone())?.to_bool() {
+                    let scope = Environment::extend(Rc::clone(&self.environment));
 
-                self.env_mut().drop(value);
+                    if ! self.scoped(scope, |interpreter| interpreter.run_loop_body(&then))? {
+                        break;
+                    }
+                }
+            },
+            StatementKind::Break => return Err(InterpreterResult::Break(span)),
+            StatementKind::Continue => return Err(InterpreterResult::Continue(span)),
+            StatementKind::Throw { value } => {
+                return Err(InterpreterResult::Thrown(self.run_expression(value)?));
+            },
+            StatementKind::Try { then, catch, finally } => {
+                let result = match (self.run_block(then), catch) {
+                    (Err(error), Some(Catch { name, then })) if error.is_catchable() => {
+                        let mut scope = Environment::extend(Rc::clone(&self.environment));
+
+                        scope.set(name.name, error.into_value());
+
+                        self.scoped(scope, |interpreter| interpreter.run_block(then))
+                    },
+                    (result, _) => result,
+                };
 
-                if set_index {
-                    self.env_mut().drop(index.unwrap());
+                // The `finally` block always runs, but an error inside of it takes
+                // priority over whatever happened in the `try` or `catch` blocks.
+                if let Some(finally) = finally {
+                    self.run_block(finally)?;
                 }
+
+                result?;
             },
-            Statement::If { condition, then, otherwise } => {
+            StatementKind::If { condition, then, otherwise } => {
                 let condition = self.run_expression(condition)?;
 
                 if condition.to_bool() {
-                    for statement in then {
-                        self.run_statement(statement)?;
-                    }
-                } else if otherwise.is_some() {
-                    for statement in otherwise.unwrap() {
-                        self.run_statement(statement)?;
+                    self.run_block(then)?;
+                } else {
+                    match otherwise {
+                        Some(Else::If(statement)) => self.run_statement(*statement)?,
+                        Some(Else::Block(otherwise)) => self.run_block(otherwise)?,
+                        None => (),
                     }
                 }
             },
-            Statement::Expression { expression } => {
+            StatementKind::Expression { expression } => {
                 self.run_expression(expression)?;
             },
-            Statement::Return { value } => {
+            StatementKind::Return { value } => {
                 return Err(InterpreterResult::Return(self.run_expression(value)?));
             },
-            _ => todo!("{:?}", statement),
-        })
+            StatementKind::Export { declaration } => self.run_statement(*declaration)?,
+            StatementKind::Import { names, path } => {
+                let module = self.load_module(&path)?;
+
+                for Parameter { name, .. } in names {
+                    let value = get_property(module.clone(), name.clone())?;
+
+                    self.env_mut().set(name, value);
+                }
+            },
+        };
+
+        Ok(())
     }
 
+    /// Call a function from native code, e.g. the callback passed to `List.map()`.
+    /// The call is attributed to the same location as the current stack frame.
     pub fn call(&mut self, callable: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-        Ok(match callable {
-            Value::Constant(v) => self.call(*v, arguments)?,
-            Value::NativeFunction { callback, .. } => callback(self, arguments),
-            Value::NativeMethod { callback, context, .. } => {
-                let context = self.run_expression(context)?;
+        let span = self.frames.last().map(|f| f.span).unwrap_or_default();
 
-                callback(self, context, arguments)?
-            },
-            Value::Function { name, params, body, environment, context } => {
-                if params.first() != Some(&Parameter { name: "this".to_string() }) && params.len() != arguments.len() {
-                    return Err(InterpreterResult::TooFewArguments(name.clone(), arguments.len(), params.len()));
-                }
+        self.call_at(callable, arguments, span)
+    }
 
-                let old_environment = Rc::clone(&self.environment);
-                let new_environment = if environment.is_some() { 
-                    Rc::new(RefCell::new(environment.unwrap()))
-                } else {
-                    Rc::new(RefCell::new(Environment::new()))
-                };
+    /// The current call stack, innermost call last.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
 
-                if context.is_some() && params.first() == Some(&Parameter { name: "this".to_string() }) {
-                    let context = self.run_expression(context.unwrap())?;
-                    new_environment.borrow_mut().set("this", context);
-                }
+    pub(crate) fn call_at(&mut self, callable: Value, arguments: Vec<Value>, span: Span) -> Result<Value, InterpreterResult> {
+        let (name, kind) = match &callable {
+            Value::Constant(v) => return self.call_at(*v.clone(), arguments, span),
+            Value::Function { name, .. } if name == "Closure" => (name.clone(), FrameKind::Closure),
+            Value::Function { name, params, .. } if params.first().map(|p| p.name == "this").unwrap_or(false) => (name.clone(), FrameKind::Method),
+            Value::Function { name, .. } => (name.clone(), FrameKind::Function),
+            Value::Closure { closure, .. } if closure.name == "Closure" => (closure.name.clone(), FrameKind::Closure),
+            Value::Closure { closure, .. } if closure.function.params.first().map(|p| p.name == "this").unwrap_or(false) => (closure.name.clone(), FrameKind::Method),
+            Value::Closure { closure, .. } => (closure.name.clone(), FrameKind::Function),
+            Value::NativeFunction { name, .. } | Value::NativeMethod { name, .. } => (name.clone(), FrameKind::Native),
+            _ => return Err(InterpreterResult::NotCallable(callable.typestring())),
+        };
+
+        self.check_call_depth()?;
+        self.frames.push(Frame { name, kind, span });
+
+        // Snapshot the call stack as soon as an error leaves the function that
+        // raised it. Outer calls will see that the error already has a trace.
+        let result = self.debug_call().and_then(|_| self.invoke_on_stack(callable, arguments)).map_err(|error| match error {
+            InterpreterResult::Traced(..) => error,
+            _ if ! error.is_catchable() => error,
+            _ => InterpreterResult::Traced(Box::new(error), self.frames.iter().rev().cloned().collect()),
+        });
 
-                for (Parameter { name, .. }, value) in params.into_iter().filter(|p| p.name != "this").zip(arguments) {
-                    new_environment.borrow_mut().set(name, value);
-                };
+        self.frames.pop();
+
+        result
+    }
+
+    /// Every call made by a script also uses the Rust stack, so make sure there
+    /// is room for it, moving onto a new stack when the current one runs low.
+    /// This lets the call depth limit work on a thread with a small stack.
+    fn invoke_on_stack(&mut self, callable: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || self.invoke(callable, arguments))
+    }
 
-                self.environment = new_environment;
+    fn invoke(&mut self, callable: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+        Ok(match callable {
+            Value::NativeFunction { callback, .. } => callback(self, arguments)?,
+            Value::NativeMethod { callback, context, .. } => callback(self, *context, arguments)?,
+            Value::Closure { closure, context } => self.run_closure(closure, context.map(|c| *c), arguments)?,
+            Value::Function { name, params, body, environment, context } => {
+                let has_this = params.first().map(|p| p.name == "this").unwrap_or(false);
+
+                if ! has_this && params.len() != arguments.len() {
+                    return Err(InterpreterResult::TooFewArguments(name, arguments.len(), params.len()));
+                }
 
-                let mut return_value: Option<Value> = None;
+                let mut scope = Environment::extend(environment.unwrap_or_default());
 
-                for statement in body {
-                    match self.run_statement(statement) {
-                        Err(InterpreterResult::Return(value)) => {
-                            return_value = Some(value);
-                            break;
-                        },
-                        _ => (),
+                // Every parameter needs a value, even if it wasn't passed in, so
+                // that the body's variables end up in the slots the resolver expects.
+                let mut arguments = arguments.into_iter();
+
+                for Parameter { name, .. } in params {
+                    let value = match name.as_str() {
+                        "this" if has_this => context.clone().map(|c| *c),
+                        _ => arguments.next(),
                     };
+
+                    scope.set(name, value.unwrap_or(Value::Null));
                 }
 
-                self.environment = old_environment;
+                let result = self.scoped(scope, |interpreter| {
+                    for statement in body.iter().cloned() {
+                        interpreter.run_statement(statement)?;
+                    }
+
+                    Ok(Value::Null)
+                });
 
-                if return_value.is_some() { return_value.unwrap() } else { Value::Null }
+                match result {
+                    Ok(value) | Err(InterpreterResult::Return(value)) => value,
+                    // A `break` or `continue` should never escape the function
+                    // body, even if the function is called inside of a loop.
+                    Err(error @ InterpreterResult::Break(..)) | Err(error @ InterpreterResult::Continue(..)) => {
+                        let span = error.span().unwrap_or_default();
+
+                        return Err(InterpreterResult::Located(Box::new(error), span));
+                    },
+                    Err(error) => return Err(error),
+                }
             },
-            _ => todo!(),
+            _ => unreachable!(),
         })
     }
 
     fn run_expression(&mut self, expression: Expression) -> Result<Value, InterpreterResult> {
+        let span = expression.span;
+
+        self.run_expression_kind(expression.kind, span).map_err(|e| e.located(span))
+    }
+
+    fn run_expression_kind(&mut self, expression: ExpressionKind, span: Span) -> Result<Value, InterpreterResult> {
         Ok(match expression {
-            Expression::Number(n) => Value::Number(n),
-            Expression::String(s) => Value::String(s),
-            Expression::Bool(b) => Value::Bool(b),
-            Expression::Identifier(n) => {
-                if self.globals.contains_key(&n) {
-                    self.globals[&n].clone()
-                } else {
-                    if let Some(v) = self.env().get(n.clone()) {
-                        v
-                    } else {
-                        return Err(InterpreterResult::UndefinedVariable(n));
-                    }
-                }
-            },
-            Expression::Index(target, index) => {
+            ExpressionKind::Number(n) => Value::Number(n),
+            ExpressionKind::String(s) => Value::String(s),
+            ExpressionKind::Bool(b) => Value::Bool(b),
+            ExpressionKind::Null => Value::Null,
+            ExpressionKind::Identifier(n, binding) => self.lookup_binding(n, binding.get())?,
+            ExpressionKind::Index(target, index) => {
                 let instance = self.run_expression(*target)?;
-                let index = self.run_expression(*index.expect("Expected index."))?.to_number() as usize;
+                // Reading `a[]` is an invalid index, just like `a[null]`.
+                let index = match index {
+                    Some(index) => self.run_expression(*index)?,
+                    None => Value::Null,
+                };
 
-                match instance {
-                    Value::List(items) => {
-                        match items.borrow().get(index) {
-                            Some(v) => v.clone(),
-                            None => return Err(InterpreterResult::UndefinedIndex(index))
-                        }
-                    },
-                    _ => unreachable!()
-                }
+                get_index(instance, index)?
             },
-            Expression::Get(target, field) => {
+            ExpressionKind::Get(target, field) => {
                 let instance = self.run_expression(*target.clone())?;
 
-                self.get_property(instance, field, *target)?
+                get_property(instance, field)?
             },
-            Expression::Infix(left, op, right) => {
+            ExpressionKind::Infix(left, op, right) => {
                 let left = self.run_expression(*left)?;
                 let right = self.run_expression(*right)?;
 
-                match (left, op, right) {
-                    (Value::Number(l), Op::Add, Value::Number(r)) => Value::Number(l + r),
-                    (Value::Number(l), Op::Multiply, Value::Number(r)) => Value::Number(l * r),
-                    (Value::Number(l), Op::Divide, Value::Number(r)) => Value::Number(l / r),
-                    (Value::Number(l), Op::Subtract, Value::Number(r)) => Value::Number(l - r),
-                    (Value::Number(l), Op::Add, Value::String(r)) => {
-                        let mut l = l.to_string();
-                        l.push_str(r.as_str());
-                        Value::String(l)
-                    },
-                    (Value::String(l), Op::Add, Value::Number(r)) => {
-                        let mut l = l;
-                        l.push_str(r.to_string().as_str());
-                        Value::String(l)
-                    },
-                    (Value::String(l), Op::Add, Value::String(r)) => {
-                        let mut l = l;
-                        l.push_str(r.as_str());
-                        Value::String(l)
-                    },
-                    (Value::String(l), Op::Equals, Value::String(r)) => Value::Bool(l == r),
-                    (Value::Number(l), Op::Equals, Value::Number(r)) => Value::Bool(l == r),
-                    (Value::Bool(l), Op::Equals, Value::Bool(r)) => Value::Bool(l == r),
-                    (Value::String(l), Op::NotEquals, Value::String(r)) => Value::Bool(l != r),
-                    (Value::Number(l), Op::NotEquals, Value::Number(r)) => Value::Bool(l != r),
-                    (Value::Bool(l), Op::NotEquals, Value::Bool(r)) => Value::Bool(l != r),
-                    (Value::Number(l), Op::LessThan, Value::Number(r)) => Value::Bool(l < r),
-                    (Value::Number(l), Op::GreaterThan, Value::Number(r)) => Value::Bool(l > r),
-                    (Value::Number(l), Op::LessThanOrEquals, Value::Number(r)) => Value::Bool(l <= r),
-                    (Value::Number(l), Op::GreaterThanOrEquals, Value::Number(r)) => Value::Bool(l >= r),
-                    (l, Op::And, r) => Value::Bool(l.to_bool() && r.to_bool()),
-                    (l, Op::Or, r) => Value::Bool(l.to_bool() || r.to_bool()),
-                    (Value::Number(l), Op::Pow, Value::Number(r)) => Value::Number(l.powf(r)),
-                    (l, Op::In, Value::List(r)) => {
-                        let filtered: Vec<Value> = r.borrow().clone()
-                            .into_iter()
-                            .filter(|v| {
-                                v.clone().is(l.clone())
-                            })
-                            .collect();
-
-                        Value::Bool(! filtered.is_empty())
-                    },
-                    (Value::String(l), Op::In, Value::String(r)) => {
-                        Value::Bool(r.contains(l.as_str()))
-                    },
-                    (l, Op::NotIn, Value::List(r)) => {
-                        let filtered: Vec<Value> = r.borrow().clone()
-                            .into_iter()
-                            .filter(|v| {
-                                v.clone().is(l.clone())
-                            })
-                            .collect();
-
-                        Value::Bool(filtered.is_empty())
-                    },
-                    (Value::String(l), Op::NotIn, Value::String(r)) => {
-                        Value::Bool(! r.contains(l.as_str()))
-                    },
-                    _ => todo!(),
-                }
+                self.allocate(infix(left, op, right)?)?
             },
-            Expression::List(items) => {
+            ExpressionKind::List(items) => {
                 let mut values: Vec<Value> = Vec::new();
 
                 for item in items.into_iter() {
                     values.push(self.run_expression(item)?);
                 }
 
-                Value::List(Rc::new(RefCell::new(values)))
+                self.allocate(Value::List(Rc::new(RefCell::new(values))))?
             },
-            Expression::Closure(params, body) => {
+            ExpressionKind::Map(items) => {
+                let mut values: IndexMap<String, Value> = IndexMap::new();
+
+                for (key, value) in items.into_iter() {
+                    let key = map_key(self.run_expression(key)?)?;
+
+                    values.insert(key, self.run_expression(value)?);
+                }
+
+                self.allocate(Value::Map(Rc::new(RefCell::new(values))))?
+            },
+            ExpressionKind::Closure(params, body) => {
                 Value::Function {
                     name: String::from("Closure"),
                     params,
-                    body,
-                    environment: Some(self.environment.borrow().clone()),
+                    body: Rc::new(body),
+                    environment: Some(Rc::clone(&self.environment)),
                     context: None,
                 }
             },
-            Expression::Struct(definition, fields) => {
+            ExpressionKind::Struct(definition, fields) => {
                 let definition = self.run_expression(*definition)?;
-
-                let (name, field_definitions, methods) = match definition.clone() {
-                    Value::Struct { name, fields, methods } => (name, fields, methods),
-                    _ => unreachable!()
-                };
-
-                let mut environment = Environment::new();
+                let mut values = Vec::new();
 
                 for (field, value) in fields {
-                    if ! field_definitions.contains(&Parameter { name: field.clone() }) {
-                        return Err(InterpreterResult::UndefinedField(name, field.clone()));
+                    if let Value::Struct { name, fields: definitions, .. } = &definition {
+                        if ! definitions.iter().any(|p| p.name == field) {
+                            return Err(InterpreterResult::UndefinedField(name.clone(), field));
+                        }
                     }
 
-                    let value = self.run_expression(value)?;
-
-                    environment.set(field, match value {
-                        Value::StructInstance { environment, definition } => {
-                            // This logic is needed to ensure that any nested structs
-                            // that receive modifications do not apply the same side-effect
-                            // to the original reference.
-                            let environment = environment.borrow().clone();
-
-                            Value::StructInstance { definition, environment: Rc::new(RefCell::new(environment)) }
-                        },
-                        _ => value,
-                    });
+                    values.push((field, self.run_expression(value)?));
                 }
 
-                let environment = Rc::new(RefCell::new(environment));
-
-                for (name, method) in methods.borrow().clone() {
-                    let method = match method {
-                        Value::Function { name, body, params, .. } => Value::Function {
-                            name,
-                            params,
-                            body,
-                            environment: None,
-                            context: None,
-                        },
-                        _ => unreachable!()
-                    };
-                    
-                    environment.borrow_mut().set(name, method);
-                }
-
-                Value::StructInstance { environment, definition: Box::new(definition) }
+                instantiate(definition, values)?
             },
-            Expression::Call(callable, arguments) => {
+            ExpressionKind::Call(callable, arguments) => {
                 let callable = self.run_expression(*callable)?;
                 let mut argument_values: Vec<Value> = Vec::new();
 
@@ -395,157 +872,546 @@ impl<'i> Interpreter<'i> {
                     argument_values.push(self.run_expression(argument)?);
                 }
 
-                self.call(callable, argument_values)?
+                self.call_at(callable, argument_values, span)?
             },
-            Expression::Prefix(op, right) => {
+            ExpressionKind::Prefix(op, right) => {
                 let right = self.run_expression(*right)?;
 
-                match op {
-                    Op::Bang => Value::Bool(! right.to_bool()),
-                    Op::Subtract => Value::Number(- right.to_number()),
-                    _ => unreachable!()
-                }
+                prefix(op, right)?
             },
-            Expression::Assign(target, value) => {
+            ExpressionKind::Assign(target, value) => {
                 let value = self.run_expression(*value)?;
 
-                fn assign_to_instance(instance: Value, field: String, value: Value) -> Result<(), InterpreterResult> {
-                    Ok(match instance.clone() {
-                        // TODO: Check if the field exists on the definition before
-                        // actually doing the assignment.
-                        Value::StructInstance { environment, .. } => {
-                            environment.borrow_mut().set(field, value.clone())
-                        },
-                        Value::Struct { methods, .. } => {
-                            if ! matches!(value.clone(), Value::Function { .. }) {
-                                return Err(InterpreterResult::InvalidMethodAssignmentTarget(instance.typestring()))
-                            } else {
-                                methods.borrow_mut().insert(field, value.clone());
-                            }
-                        },
-                        Value::Constant(v) => assign_to_instance(*v, field, value)?,
-                        _ => return Err(InterpreterResult::InvalidMethodAssignmentTarget(instance.typestring())),
-                    })
-                }
-
-                fn assign_to_list(interpreter: &mut Interpreter, instance: Value, index: Option<Box<Expression>>, value: Value) -> Result<(), InterpreterResult> {
-                    Ok(match instance {
-                        Value::List(items) => {
-                            match index {
-                                Some(i) => {
-                                    let index = interpreter.run_expression(*i)?.to_number();
-                                    items.borrow_mut()[index as usize] = value.clone();
-                                },
-                                None => {
-                                    items.borrow_mut().push(value.clone());
-                                }
-                            }
-                        },
-                        _ => return Err(InterpreterResult::InvalidAppendTarget(instance.typestring()))
-                    })
-                }
-
-                match *target.clone() {
-                    Expression::Index(instance, index) => {
+                match target.kind.clone() {
+                    ExpressionKind::Index(instance, index) => {
                         let instance = self.run_expression(*instance)?;
+                        let index = match index {
+                            Some(index) => Some(self.run_expression(*index)?),
+                            None => None,
+                        };
 
-                        assign_to_list(self, instance, index, value.clone())?;
+                        if index.is_none() {
+                            self.charge(size_of::<Value>())?;
+                        }
+
+                        assign_to_index(instance, index, value.clone())?;
                     },
-                    Expression::Get(instance, field) => {
+                    ExpressionKind::Get(instance, field) => {
                         let instance = self.run_expression(*instance)?;
 
-                        assign_to_instance(instance, field, value.clone())?;
+                        assign_to_field(instance, field, value.clone())?;
                     },
-                    _ => {
-                        match self.run_expression(*target.clone())? {
-                            Value::Constant(_) => return Err(InterpreterResult::CannotAssignValueToConstant),
-                            _ => (),
-                        };
+                    ExpressionKind::Identifier(i, binding) => {
+                        if let Value::Constant(_) = self.run_expression(*target.clone())? {
+                            return Err(InterpreterResult::CannotAssignValueToConstant);
+                        }
 
-                        match *target.clone() {
-                            Expression::Identifier(i) => {
-                                self.env_mut().set(i, value.clone());
+                        match binding.get() {
+                            Binding::Local { depth, slot } | Binding::Captured { depth, slot } => {
+                                self.env_mut().assign_slot(depth, slot, value.clone());
                             },
-                            _ => todo!()
+                            _ => self.assign(i, value.clone()),
                         }
-                    }
+                    },
+                    _ => return Err(InterpreterResult::InvalidAssignmentTarget),
                 };
 
                 value
             },
-            _ => todo!("{:?}", expression),
         })
     }
 
+    /// Find a variable using the slot that the resolver gave it, only falling back
+    /// to a lookup by name for globals.
+    fn lookup_binding(&self, name: String, binding: Binding) -> Result<Value, InterpreterResult> {
+        match binding {
+            Binding::Local { depth, slot } | Binding::Captured { depth, slot } => self.env().get_slot(depth, slot)
+                .ok_or(InterpreterResult::UndefinedVariable(name)),
+            Binding::Global | Binding::Unresolved => self.lookup(name),
+        }
+    }
+
+    /// Find a variable in the current scope, falling back to the globals.
+    pub(crate) fn lookup(&self, name: String) -> Result<Value, InterpreterResult> {
+        if let Some(v) = self.env().get(name.clone()) {
+            Ok(v)
+        } else if let Some(v) = self.globals.get(&name) {
+            Ok(v.clone())
+        } else {
+            Err(InterpreterResult::UndefinedVariable(name))
+        }
+    }
+
+    /// Update the nearest variable with the given name, or create a new one in
+    /// the current scope if it doesn't exist yet.
+    pub(crate) fn assign(&mut self, name: String, value: Value) {
+        if ! self.env_mut().assign(name.clone(), value.clone()) {
+            self.env_mut().set(name, value);
+        }
+    }
+
     pub fn path(&self) -> PathBuf {
         self.path.clone()
     }
 
-    fn define_global_function(&mut self, name: impl Into<String>, callback: NativeFunctionCallback) {
-        let name = name.into();
+    /// Parse `source` and create an interpreter for it with the standard library
+    /// already registered. Modules that the program imports are found relative
+    /// to `path`.
+    pub fn from_source(source: &str, path: impl Into<PathBuf>) -> Result<Self, ParseError> {
+        let mut interpreter = Self::new(parse(generate(source))?, path.into());
+
+        register_global_functions(&mut interpreter);
+
+        Ok(interpreter)
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    /// Register a native function. The callback is free to capture state from
+    /// the host, such as a database handle or a logger.
+    pub fn define_function(&mut self, name: impl Into<String>, callback: impl Fn(&mut Interpreter, Vec<Value>) -> Result<Value, InterpreterResult> + 'static) {
+        // Struct instances returned by the host need to be adopted by the
+        // script, see `Value::instance`.
+        self.define_native(name, move |interpreter, arguments| {
+            let value = callback(interpreter, arguments)?;
 
-        self.globals.insert(name.clone(), Value::NativeFunction {
-            name: name,
-            callback: callback,
+            Ok(interpreter.adopt(value))
         });
     }
 
-    fn env(&self) -> Ref<Environment> {
-        RefCell::borrow(&self.environment)
+    /// Register one of Lagoon's own functions, which never create struct
+    /// instances that need adopting.
+    pub(crate) fn define_native(&mut self, name: impl Into<String>, callback: impl Fn(&mut Interpreter, Vec<Value>) -> Result<Value, InterpreterResult> + 'static) {
+        let name = name.into();
+
+        self.globals.insert(name.clone(), Value::NativeFunction { name, callback: Rc::new(callback) });
     }
 
-    fn env_mut(&mut self) -> RefMut<Environment> {
-        RefCell::borrow_mut(&self.environment)
+    /// Define a global that scripts can read, e.g. a host object created with
+    /// `Value::object()`. Globals need to be set before the program runs so that
+    /// the resolver knows they exist.
+    pub fn set_global(&mut self, name: impl Into<String>, value: impl IntoValue) {
+        let value = self.adopt(value.into_value());
+
+        self.globals.insert(name.into(), value);
     }
 
-    fn get_property(&mut self, value: Value, field: String, target: Expression) -> Result<Value, InterpreterResult> {
-        Ok(match value {
-            Value::StructInstance { environment, definition, .. } => if let Some(value) = environment.borrow().get(field.clone()) {
-                match value {
-                    Value::Function { name, params, body, environment, .. } => Value::Function { name, params, body, environment, context: Some(target) },
-                    _ => value,
-                }
-            } else {
-                let name = match *definition {
-                    Value::Struct { name, .. } => name,
-                    _ => unreachable!()
-                };
+    /// Read a top-level variable or global, converting it into a Rust value.
+    pub fn get_global<T: FromValue>(&self, name: &str) -> Result<T, InterpreterResult> {
+        T::from_value(self.lookup(name.to_string())?)
+    }
 
-                return Err(InterpreterResult::UndefinedField(name, field))
-            },
-            Value::Struct { name, methods, .. } => if let Some(value) = methods.borrow().get(&field.clone()) {
-                value.clone()
-            } else {
-                return Err(InterpreterResult::UndefinedMethod(name, field))
-            },
-            Value::String(..) => Value::NativeMethod { name: field.clone(), callback: crate::stdlib::StringObject::get(field), context: target },
-            Value::Number(..) => Value::NativeMethod { name: field.clone(), callback: crate::stdlib::NumberObject::get(field), context: target },
-            Value::List(..) => Value::NativeMethod { name: field.clone(), callback: crate::stdlib::ListObject::get(field), context: target },
-            Value::Constant(v) => self.get_property(*v, field, target)?,
-            _ => todo!(),
+    /// Call a function defined by the script (or any other global function) by
+    /// name, converting the arguments and return value.
+    pub fn call_function<T: FromValue>(&mut self, name: &str, arguments: impl IntoArguments) -> Result<T, InterpreterResult> {
+        let callable = self.lookup(name.to_string())?;
+
+        let arguments = arguments.into_arguments().into_iter().map(|argument| self.adopt(argument)).collect();
+
+        self.start_clock();
+
+        T::from_value(self.call(callable, arguments)?)
+    }
+
+    /// Run `callback` with `environment` as the current scope, restoring the
+    /// previous scope afterwards (even if the callback fails).
+    fn scoped<T>(&mut self, environment: Environment, callback: impl FnOnce(&mut Self) -> Result<T, InterpreterResult>) -> Result<T, InterpreterResult> {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = callback(self);
+
+        self.environment = previous;
+
+        result
+    }
+
+    fn run_block(&mut self, block: Block) -> Result<(), InterpreterResult> {
+        let scope = Environment::extend(Rc::clone(&self.environment));
+
+        self.scoped(scope, |interpreter| {
+            for statement in block {
+                interpreter.run_statement(statement)?;
+            }
+
+            Ok(())
         })
     }
 
+    /// Check the program for undefined variables and assignments to constants,
+    /// binding each identifier to a slot.
+    fn resolve(&self, program: &[Statement]) -> Result<(), InterpreterResult> {
+        let globals = self.globals.keys().cloned().chain(self.env().names().cloned()).collect::<Vec<String>>();
+
+        crate::resolver::resolve(program, globals).map_err(InterpreterResult::from_errors)
+    }
+
+    /// Look for mistakes in the program that the interpreter was created with,
+    /// without running it.
+    pub fn check(&self, lints: &Lints) -> Vec<Problem> {
+        let globals = self.globals.keys().cloned().chain(self.env().names().cloned()).collect::<Vec<String>>();
+
+        crate::lint::check(&self.ast, globals, lints)
+    }
+
+    pub(crate) fn env(&self) -> Ref<'_, Environment> {
+        RefCell::borrow(&self.environment)
+    }
+
+    pub(crate) fn env_mut(&mut self) -> RefMut<'_, Environment> {
+        RefCell::borrow_mut(&self.environment)
+    }
+
     pub fn exec(&mut self, ast: Program) -> Result<(), InterpreterResult> {
-        let mut ast = ast.into_iter();
+        if self.prelude.is_empty() {
+            self.prelude = self.globals.clone();
+        }
+
+        self.resolve(&ast)?;
+
+        if self.engine == Engine::Bytecode {
+            return self.run_bytecode(&ast);
+        }
 
-        while let Some(statement) = ast.next() {
+        for statement in ast {
             self.run_statement(statement)?;
         }
 
         Ok(())
     }
 
-    fn run(&mut self) -> Result<(), InterpreterResult> {
-        while let Some(statement) = self.ast.next() {
-            self.run_statement(statement.clone())?;
+    /// Run more code in the same scope as everything that ran before it, like
+    /// `exec()`, returning the value of the last statement when it's an
+    /// expression.
+    pub fn eval(&mut self, mut ast: Program) -> Result<Option<Value>, InterpreterResult> {
+        let (expression, span) = match ast.pop() {
+            Some(Statement { kind: StatementKind::Expression { expression }, span }) => (expression, span),
+            Some(statement) => {
+                ast.push(statement);
+
+                return self.exec(ast).map(|_| None);
+            },
+            None => return Ok(None),
+        };
+
+        self.exec(ast)?;
+
+        // Returning from the top level hands the value back here, whichever
+        // engine is running.
+        match self.exec(vec![Statement::new(StatementKind::Return { value: expression }, span)]) {
+            Err(InterpreterResult::Return(value)) => Ok(Some(value)),
+            Err(error) => Err(error),
+            Ok(()) => Ok(None),
         }
+    }
+
+    /// The variables declared at the top level, followed by the functions and
+    /// structs that have been defined since the program started.
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        let environment = self.env();
+        let mut variables: Vec<(String, Value)> = environment.names()
+            .filter_map(|name| environment.get(name.clone()).map(|value| (name.clone(), value)))
+            .collect();
+
+        variables.extend(self.definitions());
+        variables
+    }
+
+    /// The functions and structs that have been defined since the program
+    /// started, sorted by name.
+    pub(crate) fn definitions(&self) -> Vec<(String, Value)> {
+        let mut definitions: Vec<(String, Value)> = self.globals.iter()
+            .filter(|(name, _)| ! self.prelude.contains_key(*name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
 
-        if ! ::std::env::args().filter(|a| a == "--debug").collect::<Vec<String>>().is_empty() {
-            self.env().dump();
-            dbg!(self.globals.clone());
+        definitions.sort_by(|a, b| a.0.cmp(&b.0));
+        definitions
+    }
+
+    /// Run the program that the interpreter was created with.
+    pub fn run(&mut self) -> Result<(), InterpreterResult> {
+        let ast = self.ast.clone();
+
+        self.prelude = self.globals.clone();
+        self.resolve(&ast)?;
+        self.start_clock();
+
+        match self.engine {
+            Engine::TreeWalker => for statement in ast {
+                self.run_statement(statement)?;
+            },
+            Engine::Bytecode => self.run_bytecode(&ast)?,
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Maps are keyed by strings, so any other type of value is rejected.
+pub(crate) fn map_key(value: Value) -> Result<String, InterpreterResult> {
+    match value {
+        Value::String(key) => Ok(key),
+        Value::Constant(v) => map_key(*v),
+        _ => Err(InterpreterResult::InvalidMapKey(value.typestring())),
+    }
+}
+
+/// Look up a method on one of the built-in scalar objects (e.g. `StringObject`).
+fn native_method(typestring: &str, field: String, context: Value, lookup: fn(String) -> Option<NativeMethodCallback>) -> Result<Value, InterpreterResult> {
+    match lookup(field.clone()) {
+        Some(callback) => Ok(Value::NativeMethod { name: field, callback, context: Box::new(context) }),
+        None => Err(InterpreterResult::UndefinedMethod(typestring.to_string(), field)),
+    }
+}
+
+/// Apply an infix operator to two values that have already been evaluated.
+pub(crate) fn infix(left: Value, op: Op, right: Value) -> Result<Value, InterpreterResult> {
+    Ok(match (left, op, right) {
+        // Constants behave exactly like the value they hold.
+        (Value::Constant(l), op, r) => return infix(*l, op, r),
+        (l, op, Value::Constant(r)) => return infix(l, op, *r),
+        (Value::Number(l), Op::Add, Value::Number(r)) => Value::Number(l + r),
+        (Value::Number(l), Op::Multiply, Value::Number(r)) => Value::Number(l * r),
+        (Value::Number(l), Op::Divide, Value::Number(r)) => Value::Number(l / r),
+        (Value::Number(l), Op::Subtract, Value::Number(r)) => Value::Number(l - r),
+        (Value::Number(l), Op::Add, Value::String(r)) => {
+            let mut l = l.to_string();
+            l.push_str(r.as_str());
+            Value::String(l)
+        },
+        (Value::String(l), Op::Add, Value::Number(r)) => {
+            let mut l = l;
+            l.push_str(r.to_string().as_str());
+            Value::String(l)
+        },
+        (Value::String(l), Op::Add, Value::String(r)) => {
+            let mut l = l;
+            l.push_str(r.as_str());
+            Value::String(l)
+        },
+        (l, Op::Equals, r) => Value::Bool(l.equals(&r)),
+        (l, Op::NotEquals, r) => Value::Bool(! l.equals(&r)),
+        (l, Op::StrictEquals, r) => Value::Bool(l.strictly_equals(&r)),
+        (l, Op::StrictNotEquals, r) => Value::Bool(! l.strictly_equals(&r)),
+        (l, Op::LessThan, r) => Value::Bool(l.compare(&r)? == Some(Ordering::Less)),
+        (l, Op::GreaterThan, r) => Value::Bool(l.compare(&r)? == Some(Ordering::Greater)),
+        (l, Op::LessThanOrEquals, r) => Value::Bool(matches!(l.compare(&r)?, Some(Ordering::Less | Ordering::Equal))),
+        (l, Op::GreaterThanOrEquals, r) => Value::Bool(matches!(l.compare(&r)?, Some(Ordering::Greater | Ordering::Equal))),
+        (l, Op::And, r) => Value::Bool(l.to_bool() && r.to_bool()),
+        (l, Op::Or, r) => Value::Bool(l.to_bool() || r.to_bool()),
+        (Value::Number(l), Op::Pow, Value::Number(r)) => Value::Number(l.powf(r)),
+        (l, Op::In, Value::List(r)) => {
+            Value::Bool(r.borrow().iter().any(|v| v.equals(&l)))
+        },
+        (Value::String(l), Op::In, Value::String(r)) => {
+            Value::Bool(r.contains(l.as_str()))
+        },
+        (Value::String(l), Op::In, Value::Map(r)) => {
+            Value::Bool(r.borrow().contains_key(&l))
+        },
+        (l, Op::NotIn, Value::List(r)) => {
+            Value::Bool(! r.borrow().iter().any(|v| v.equals(&l)))
+        },
+        (Value::String(l), Op::NotIn, Value::String(r)) => {
+            Value::Bool(! r.contains(l.as_str()))
+        },
+        (Value::String(l), Op::NotIn, Value::Map(r)) => {
+            Value::Bool(! r.borrow().contains_key(&l))
+        },
+        (l, op, r) => return Err(InterpreterResult::UnsupportedOperands(op.to_string(), l.typestring(), r.typestring())),
+    })
+}
+
+pub(crate) fn prefix(op: Op, right: Value) -> Result<Value, InterpreterResult> {
+    Ok(match (op, right) {
+        (op, Value::Constant(r)) => return prefix(op, *r),
+        (Op::Bang, r) => Value::Bool(! r.to_bool()),
+        (Op::Subtract, r @ (Value::Number(..) | Value::Bool(..) | Value::Null | Value::String(..))) => Value::Number(- r.to_number()),
+        (op, r) => return Err(InterpreterResult::UnsupportedOperand(op.to_string(), r.typestring())),
+    })
+}
+
+/// Lists can only be indexed by numbers.
+fn list_index(index: Value) -> Result<usize, InterpreterResult> {
+    match index {
+        Value::Number(n) => Ok(n as usize),
+        Value::Constant(v) => list_index(*v),
+        _ => Err(InterpreterResult::InvalidIndex(index.typestring())),
+    }
+}
+
+pub(crate) fn get_index(instance: Value, index: Value) -> Result<Value, InterpreterResult> {
+    match instance {
+        Value::List(items) => {
+            let index = list_index(index)?;
+
+            match items.borrow().get(index) {
+                Some(v) => Ok(v.clone()),
+                None => Err(InterpreterResult::UndefinedIndex(index))
+            }
+        },
+        Value::Map(items) => {
+            let key = map_key(index)?;
+
+            match items.borrow().get(&key) {
+                Some(v) => Ok(v.clone()),
+                None => Err(InterpreterResult::UndefinedKey(key))
+            }
+        },
+        Value::Constant(v) => get_index(*v, index),
+        _ => Err(InterpreterResult::NotIndexable(instance.typestring())),
+    }
+}
+
+/// Collect the `(index, value)` pairs of an iterable value. Lists are indexed by
+/// position, whilst maps expose each key as the index of the loop.
+///
+/// The items are copied so that the body of the loop is free to modify the
+/// original list.
+pub(crate) fn iterate(iterable: Value) -> Result<Vec<(Value, Value)>, InterpreterResult> {
+    Ok(match iterable {
+        Value::List(items) => items.borrow().iter().cloned().enumerate()
+            .map(|(i, item)| (Value::Number(i as f64), item))
+            .collect(),
+        Value::Map(items) => items.borrow().iter()
+            .map(|(key, item)| (Value::String(key.clone()), item.clone()))
+            .collect(),
+        _ => return Err(InterpreterResult::InvalidIterable(iterable.typestring())),
+    })
+}
+
+/// Create a new instance of a struct from a list of evaluated field values.
+pub(crate) fn instantiate(definition: Value, fields: Vec<(String, Value)>) -> Result<Value, InterpreterResult> {
+    let (name, field_definitions, methods) = match definition.clone() {
+        Value::Struct { name, fields, methods } => (name, fields, methods),
+        Value::Constant(v) => return instantiate(*v, fields),
+        _ => return Err(InterpreterResult::NotAStruct(definition.typestring())),
+    };
+
+    let mut environment = Environment::new();
+
+    for (field, value) in fields {
+        if ! field_definitions.iter().any(|p| p.name == field) {
+            return Err(InterpreterResult::UndefinedField(name, field));
+        }
+
+        environment.set(field, match value {
+            Value::StructInstance { environment, definition } => {
+                // This logic is needed to ensure that any nested structs
+                // that receive modifications do not apply the same side-effect
+                // to the original reference.
+                let environment = environment.borrow().clone();
+
+                Value::StructInstance { definition, environment: Rc::new(RefCell::new(environment)) }
+            },
+            _ => value,
+        });
+    }
+
+    let environment = Rc::new(RefCell::new(environment));
+
+    for (name, method) in methods.borrow().clone() {
+        environment.borrow_mut().set(name, method.unbind());
+    }
+
+    Ok(Value::StructInstance { environment, definition: Box::new(definition) })
+}
+
+pub(crate) fn assign_to_field(instance: Value, field: String, value: Value) -> Result<(), InterpreterResult> {
+    match instance.clone() {
+        // TODO: Check if the field exists on the definition before
+        // actually doing the assignment.
+        Value::StructInstance { environment, .. } => {
+            environment.borrow_mut().set(field, value)
+        },
+        Value::Struct { name: struct_name, methods, .. } => {
+            // Methods are named after the struct so that they can be
+            // told apart in stack traces.
+            let value = match value.renamed(format!("{}.{}", struct_name, field)) {
+                Some(value) => value,
+                None => return Err(InterpreterResult::InvalidMethodAssignmentTarget(instance.typestring())),
+            };
+
+            methods.borrow_mut().insert(field, value);
+        },
+        Value::Object(object) => crate::host::set_field(object, field, value)?,
+        Value::Module(..) => return Err(InterpreterResult::CannotAssignValueToConstant),
+        Value::Constant(v) => assign_to_field(*v, field, value)?,
+        _ => return Err(InterpreterResult::InvalidMethodAssignmentTarget(instance.typestring())),
+    };
+
+    Ok(())
+}
+
+pub(crate) fn assign_to_index(instance: Value, index: Option<Value>, value: Value) -> Result<(), InterpreterResult> {
+    match instance {
+        Value::List(items) => {
+            match index {
+                Some(index) => {
+                    let index = list_index(index)?;
+
+                    match items.borrow_mut().get_mut(index) {
+                        Some(item) => *item = value,
+                        None => return Err(InterpreterResult::UndefinedIndex(index)),
+                    }
+                },
+                None => {
+                    items.borrow_mut().push(value);
+                }
+            }
+        },
+        Value::Map(items) => {
+            match index {
+                Some(index) => {
+                    let key = map_key(index)?;
+                    items.borrow_mut().insert(key, value);
+                },
+                None => return Err(InterpreterResult::InvalidAppendTarget("map".into())),
+            }
+        },
+        _ => return Err(InterpreterResult::InvalidAppendTarget(instance.typestring()))
+    };
+
+    Ok(())
+}
+
+pub(crate) fn get_property(value: Value, field: String) -> Result<Value, InterpreterResult> {
+    Ok(match value {
+        Value::StructInstance { ref environment, ref definition } => {
+            let (name, methods) = match &**definition {
+                Value::Struct { name, methods, .. } => (name.clone(), methods),
+                _ => unreachable!()
+            };
+
+            // Methods added to the struct after the instance was created are
+            // only found on the definition.
+            let found = environment.borrow().get(field.clone()).or_else(|| methods.borrow().get(&field).cloned());
+
+            match found {
+                Some(method) => method.bind(value.clone()),
+                None => return Err(InterpreterResult::UndefinedField(name, field)),
+            }
+        },
+        Value::Struct { name, methods, .. } => if let Some(value) = methods.borrow().get(&field) {
+            value.clone()
+        } else {
+            return Err(InterpreterResult::UndefinedMethod(name, field))
+        },
+        Value::String(..) => native_method("string", field, value, crate::stdlib::StringObject::get)?,
+        Value::Number(..) => native_method("number", field, value, crate::stdlib::NumberObject::get)?,
+        Value::List(..) => native_method("list", field, value, crate::stdlib::ListObject::get)?,
+        Value::Map(..) => native_method("map", field, value, crate::stdlib::MapObject::get)?,
+        Value::Error(error) => match field.as_str() {
+            "message" => Value::String(error.message.clone()),
+            "kind" => Value::String(error.kind.clone()),
+            "line" => error.span.map(|s| Value::Number(s.line as f64)).unwrap_or(Value::Null),
+            "column" => error.span.map(|s| Value::Number(s.column as f64)).unwrap_or(Value::Null),
+            _ => return Err(InterpreterResult::UndefinedField("error".into(), field)),
+        },
+        Value::Object(object) => crate::host::get_field(object, field)?,
+        Value::Module(module) => module.get(&field)?,
+        Value::Constant(v) => get_property(*v, field)?,
+        _ => return Err(InterpreterResult::InvalidPropertyAccess(value.typestring(), field)),
+    })
+}
@@ -1,5 +1,26 @@
 mod interpreter;
 mod environment;
 mod stdlib;
+mod resolver;
+mod lint;
+mod convert;
+mod host;
+mod limits;
+mod permissions;
+mod module;
+mod debug;
+mod compare;
+mod vm;
 
-pub use interpreter::{InterpreterResult, Interpreter, interpret};
\ No newline at end of file
+pub use interpreter::{InterpreterResult, Interpreter, Engine, Frame, FrameKind, interpret, interpret_with, register_global_functions};
+pub use environment::{Value, ErrorValue, NativeFunctionCallback};
+pub use convert::{IntoValue, FromValue, IntoArguments, StructFields};
+pub use lagoon_derive::LagoonStruct;
+pub use host::{HostObject, HostObjectRef};
+pub use limits::{Limits, DEFAULT_MAX_CALL_DEPTH};
+pub use permissions::{Permissions, Access, Capability};
+pub use module::Module;
+pub use lint::{check, Lint, Level, Lints, Problem};
+pub use resolver::{resolve_with, Declaration, Visitor};
+pub use debug::Debugger;
+pub use stdlib::{StringObject, ListObject, NumberObject, MapObject};
@@ -0,0 +1,151 @@
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+use crate::environment::Value;
+use crate::interpreter::{Interpreter, InterpreterResult};
+
+/// The default maximum call depth.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// A call made with less than this much stack left moves onto a new stack of
+/// `STACK_SEGMENT_SIZE` bytes. The red zone needs to fit everything a single
+/// call does before it reaches the next one, in a debug build too.
+pub(crate) const STACK_RED_ZONE: usize = 256 * 1024;
+pub(crate) const STACK_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Limits on the resources that a script is allowed to use. A script that
+/// exceeds one of them stops with an error that `try` can't catch, so it's
+/// always handed back to the host.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// The number of steps the interpreter can take. The tree-walker counts
+    /// every statement and loop iteration, whilst the virtual machine counts
+    /// every instruction.
+    pub fuel: Option<u64>,
+    /// The number of calls that can be on the stack at once.
+    pub max_call_depth: Option<usize>,
+    /// An approximate number of bytes that can be allocated for strings, lists
+    /// and maps. Memory is never given back, so this caps the total allocated
+    /// over the lifetime of the interpreter.
+    pub max_memory: Option<usize>,
+    /// How long a call to `run()` or `call_function()` can take.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: None,
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+            max_memory: None,
+            timeout: None,
+        }
+    }
+}
+
+impl Limits {
+    /// No limits at all, not even on the call depth.
+    pub fn unlimited() -> Self {
+        Self { fuel: None, max_call_depth: None, max_memory: None, timeout: None }
+    }
+
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+
+    pub fn with_max_memory(mut self, bytes: usize) -> Self {
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// The resources used so far, checked against the `Limits`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Usage {
+    steps: u64,
+    memory: usize,
+    deadline: Option<Instant>,
+}
+
+impl Interpreter {
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Start the clock for the timeout. Nested calls back into the interpreter
+    /// (e.g. from a native function) share the deadline of the outermost call.
+    pub(crate) fn start_clock(&mut self) {
+        if self.frames().is_empty() {
+            self.usage.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        }
+    }
+
+    /// Take a single step, using up some fuel and checking the deadline.
+    pub(crate) fn tick(&mut self) -> Result<(), InterpreterResult> {
+        self.usage.steps += 1;
+
+        if let Some(fuel) = self.limits.fuel {
+            if self.usage.steps > fuel {
+                return Err(InterpreterResult::OutOfFuel(fuel));
+            }
+        }
+
+        if let Some(deadline) = self.usage.deadline {
+            if Instant::now() >= deadline {
+                return Err(InterpreterResult::Timeout(self.limits.timeout.unwrap_or_default()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make sure there is room on the stack for another call.
+    pub(crate) fn check_call_depth(&self) -> Result<(), InterpreterResult> {
+        match self.limits.max_call_depth {
+            Some(depth) if self.frames().len() >= depth => Err(InterpreterResult::CallDepthExceeded(depth)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Count a newly created string, list or map against the memory limit.
+    pub(crate) fn allocate(&mut self, value: Value) -> Result<Value, InterpreterResult> {
+        self.charge(size(&value))?;
+
+        Ok(value)
+    }
+
+    pub(crate) fn charge(&mut self, bytes: usize) -> Result<(), InterpreterResult> {
+        self.usage.memory += bytes;
+
+        match self.limits.max_memory {
+            Some(memory) if self.usage.memory > memory => Err(InterpreterResult::MemoryLimitExceeded(memory)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The approximate number of bytes a value owns, not including anything that
+/// it shares with other values.
+fn size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::List(items) => items.borrow().len() * size_of::<Value>(),
+        Value::Map(items) => items.borrow().iter().map(|(key, _)| key.len() + size_of::<Value>()).sum(),
+        _ => 0,
+    }
+}
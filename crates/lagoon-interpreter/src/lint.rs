@@ -0,0 +1,560 @@
+//! Static checks that find mistakes in a program without running it.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use hashbrown::{HashMap, HashSet};
+use lagoon_parser::*;
+use lagoon_parser::diagnostics::{Diagnostic, Severity};
+
+use crate::interpreter::InterpreterResult;
+
+/// Something that `check` looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UndefinedVariable,
+    ConstantAssignment,
+    ArgumentCount,
+    UnknownField,
+    UnusedVariable,
+    UnreachableCode,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 6] = [
+        Lint::UndefinedVariable,
+        Lint::ConstantAssignment,
+        Lint::ArgumentCount,
+        Lint::UnknownField,
+        Lint::UnusedVariable,
+        Lint::UnreachableCode,
+    ];
+
+    /// The name used to configure the lint.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UndefinedVariable => "undefined-variable",
+            Lint::ConstantAssignment => "constant-assignment",
+            Lint::ArgumentCount => "argument-count",
+            Lint::UnknownField => "unknown-field",
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnreachableCode => "unreachable-code",
+        }
+    }
+
+    /// Mistakes that would stop the program at runtime are errors, and the
+    /// rest are warnings.
+    pub fn default_level(&self) -> Level {
+        match self {
+            Lint::UnusedVariable | Lint::UnreachableCode => Level::Warn,
+            _ => Level::Deny,
+        }
+    }
+}
+
+impl FromStr for Lint {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Lint::ALL.iter()
+            .find(|lint| lint.name() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown lint `{}`", name))
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How seriously to take a lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Don't report it at all.
+    Allow,
+    /// Report it as a warning.
+    Warn,
+    /// Report it as an error, which makes the check fail.
+    Deny,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level {
+            "allow" => Ok(Level::Allow),
+            "warn" => Ok(Level::Warn),
+            "deny" => Ok(Level::Deny),
+            _ => Err(format!("unknown lint level `{}`, expected `allow`, `warn` or `deny`", level)),
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", match self {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Deny => "deny",
+        })
+    }
+}
+
+/// The level of every lint, starting from their defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lints {
+    levels: HashMap<Lint, Level>,
+}
+
+impl Lints {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or_else(|| lint.default_level())
+    }
+}
+
+/// A mistake found by `check`.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub lint: Lint,
+    pub level: Level,
+    pub span: Span,
+    diagnostic: Diagnostic,
+}
+
+impl Problem {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let note = match self.lint.default_level() == self.level {
+            true => format!("`{}` is set to `{}` by default", self.lint, self.level),
+            false => format!("`{}` is set to `{}`", self.lint, self.level),
+        };
+
+        self.diagnostic.clone().with_note(note)
+    }
+
+    pub fn print(&self, source: &str, path: &str) {
+        self.diagnostic().emit(source, path);
+    }
+}
+
+/// Check a program for mistakes, returning them in the order they appear.
+/// `globals` are the names that exist before the program runs.
+pub fn check(program: &[Statement], globals: impl IntoIterator<Item = String>, lints: &Lints) -> Vec<Problem> {
+    let (errors, unused) = crate::resolver::analyse(program, globals);
+    let mut checker = Checker { lints, problems: Vec::new(), functions: HashMap::new(), structs: HashMap::new() };
+
+    for error in errors {
+        let lint = match error.inner() {
+            InterpreterResult::CannotAssignValueToConstant => Lint::ConstantAssignment,
+            _ => Lint::UndefinedVariable,
+        };
+
+        checker.report(lint, error.span().unwrap_or_default(), error.diagnostic());
+    }
+
+    for (name, span) in unused {
+        let diagnostic = Diagnostic::error(format!("unused variable `{}`", name))
+            .with_label(span, "never read")
+            .with_help(format!("if this is intentional, prefix it with an underscore: `_{}`", name));
+
+        checker.report(Lint::UnusedVariable, span, diagnostic);
+    }
+
+    let mut declarations = Declarations::default();
+
+    declarations.block(program);
+
+    let Declarations { functions, structs, variables } = declarations;
+
+    // A name that is also used for a variable could hold anything by the time
+    // it's used, and one that's declared twice could be either declaration.
+    checker.functions = functions.into_iter()
+        .filter(|(name, _)| ! variables.contains(name))
+        .filter_map(|(name, arities)| match arities.as_slice() {
+            [arity] => Some((name, *arity)),
+            _ => None,
+        })
+        .collect();
+    checker.structs = structs.into_iter()
+        .filter(|(name, _)| ! variables.contains(name))
+        .filter_map(|(name, fields)| match fields.as_slice() {
+            [fields] => Some((name, fields.clone())),
+            _ => None,
+        })
+        .collect();
+
+    checker.block(program);
+
+    let mut problems = checker.problems;
+
+    problems.sort_by_key(|problem| problem.span.start);
+    problems
+}
+
+/// The functions and structs declared anywhere in a program, which are all
+/// global, and every other name that is declared and could hide one of them.
+#[derive(Default)]
+struct Declarations {
+    functions: HashMap<String, Vec<usize>>,
+    structs: HashMap<String, Vec<HashSet<String>>>,
+    variables: HashSet<String>,
+}
+
+impl Declarations {
+    fn block(&mut self, block: &[Statement]) {
+        let nodes = Nodes::of(block);
+
+        nodes.statements.into_iter().for_each(|statement| self.statement(statement));
+        nodes.expressions.into_iter().for_each(|expression| self.expression(expression));
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            // Functions that take `this` are methods, which are called with one
+            // argument fewer than they declare.
+            StatementKind::FunctionDeclaration { name, params, .. } => match params.first() {
+                Some(first) if first.name == "this" => { self.variables.insert(name.clone()); },
+                _ => self.functions.entry(name.clone()).or_default().push(params.len()),
+            },
+            StatementKind::StructDeclaration { name, fields } => {
+                self.structs.entry(name.clone()).or_default().push(fields.iter().map(|field| field.name.clone()).collect());
+            },
+            StatementKind::LetDeclaration { name, .. } | StatementKind::ConstDeclaration { name, .. } => {
+                self.variables.insert(name.clone());
+            },
+            StatementKind::For { value, index, .. } => {
+                self.variables.insert(value.clone());
+                self.variables.extend(index.clone());
+            },
+            StatementKind::Try { catch: Some(catch), .. } => {
+                self.variables.insert(catch.name.name.clone());
+            },
+            StatementKind::Import { names, .. } => {
+                self.variables.extend(names.iter().map(|name| name.name.clone()));
+            },
+            _ => (),
+        }
+
+        // Parameters can hide a function or struct inside of the body.
+        if let StatementKind::FunctionDeclaration { params, .. } = &statement.kind {
+            self.variables.extend(params.iter().map(|param| param.name.clone()));
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        if let ExpressionKind::Closure(params, _) = &expression.kind {
+            self.variables.extend(params.iter().map(|param| param.name.clone()));
+        }
+    }
+}
+
+struct Checker<'l> {
+    lints: &'l Lints,
+    problems: Vec<Problem>,
+    /// The number of parameters that each function takes.
+    functions: HashMap<String, usize>,
+    /// The fields of each struct.
+    structs: HashMap<String, HashSet<String>>,
+}
+
+impl<'l> Checker<'l> {
+    fn report(&mut self, lint: Lint, span: Span, mut diagnostic: Diagnostic) {
+        let level = self.lints.level(lint);
+
+        diagnostic.severity = match level {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+
+        diagnostic.code = Some(lint.name().to_string());
+
+        self.problems.push(Problem { lint, level, span, diagnostic });
+    }
+
+    fn block(&mut self, block: &[Statement]) {
+        let nodes = Nodes::of(block);
+
+        self.unreachable(block);
+
+        for statement in nodes.statements {
+            match &statement.kind {
+                StatementKind::FunctionDeclaration { body, .. } => self.unreachable(body),
+                StatementKind::If { then, otherwise, .. } => {
+                    self.unreachable(then);
+
+                    if let Some(Else::Block(otherwise)) = otherwise {
+                        self.unreachable(otherwise);
+                    }
+                },
+                StatementKind::For { then, .. } | StatementKind::While { then, .. } => self.unreachable(then),
+                StatementKind::Try { then, catch, finally } => {
+                    self.unreachable(then);
+
+                    if let Some(catch) = catch {
+                        self.unreachable(&catch.then);
+                    }
+
+                    if let Some(finally) = finally {
+                        self.unreachable(finally);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        for expression in nodes.expressions {
+            match &expression.kind {
+                ExpressionKind::Call(callee, arguments) => self.call(expression, callee, arguments),
+                ExpressionKind::Struct(definition, fields) => self.fields(definition, fields),
+                ExpressionKind::Closure(_, body) => self.unreachable(body),
+                _ => (),
+            }
+        }
+    }
+
+    fn call(&mut self, call: &Expression, callee: &Expression, arguments: &[Expression]) {
+        let (name, expected) = match &callee.kind {
+            ExpressionKind::Identifier(name, binding) if binding.get() == Binding::Global => match self.functions.get(name) {
+                Some(expected) => (name, *expected),
+                None => return,
+            },
+            _ => return,
+        };
+
+        if arguments.len() == expected {
+            return;
+        }
+
+        let diagnostic = Diagnostic::error(format!("`{}` expects {} argument{}, but {} {} given", name, expected, plural(expected), arguments.len(), if arguments.len() == 1 { "was" } else { "were" }))
+            .with_label(call.span, "called here");
+
+        self.report(Lint::ArgumentCount, call.span, diagnostic);
+    }
+
+    fn fields(&mut self, definition: &Expression, fields: &HashMap<Identifier, Expression>) {
+        let (name, declared) = match &definition.kind {
+            ExpressionKind::Identifier(name, binding) if binding.get() == Binding::Global => match self.structs.get(name) {
+                Some(declared) => (name, declared.clone()),
+                None => return,
+            },
+            _ => return,
+        };
+
+        let mut unknown: Vec<(&Identifier, &Expression)> = fields.iter()
+            .filter(|(field, _)| ! declared.contains(*field))
+            .collect();
+
+        unknown.sort_by_key(|(_, value)| value.span.start);
+
+        for (field, value) in unknown {
+            let mut expected: Vec<&String> = declared.iter().collect();
+
+            expected.sort();
+
+            let diagnostic = Diagnostic::error(format!("struct `{}` has no field named `{}`", name, field))
+                .with_label(value.span, "unknown field")
+                .with_note(match expected.len() {
+                    0 => format!("`{}` doesn't have any fields", name),
+                    _ => format!("the fields of `{}` are {}", name, expected.iter().map(|f| format!("`{}`", f)).collect::<Vec<String>>().join(", ")),
+                });
+
+            self.report(Lint::UnknownField, value.span, diagnostic);
+        }
+    }
+
+    /// Report the first statement in a block that comes after a statement that
+    /// always leaves it.
+    fn unreachable(&mut self, block: &[Statement]) {
+        let exit = match block.iter().position(exits) {
+            Some(exit) if exit + 1 < block.len() => exit,
+            _ => return,
+        };
+
+        let span = block[exit + 1].span;
+        let diagnostic = Diagnostic::error("unreachable statement")
+            .with_label(span, "unreachable statement")
+            .with_secondary_label(block[exit].span, "any code following this is unreachable");
+
+        self.report(Lint::UnreachableCode, span, diagnostic);
+    }
+}
+
+/// Whether a statement always leaves the block that it's in.
+fn exits(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return { .. } | StatementKind::Throw { .. } | StatementKind::Break | StatementKind::Continue => true,
+        StatementKind::If { then, otherwise: Some(otherwise), .. } => then.iter().any(exits) && match otherwise {
+            Else::If(statement) => exits(statement),
+            Else::Block(block) => block.iter().any(exits),
+        },
+        _ => false,
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+/// Every statement and expression in a block, however deeply nested.
+#[derive(Default)]
+struct Nodes<'a> {
+    statements: Vec<&'a Statement>,
+    expressions: Vec<&'a Expression>,
+}
+
+impl<'a> Nodes<'a> {
+    fn of(block: &'a [Statement]) -> Self {
+        let mut nodes = Nodes::default();
+
+        nodes.block(block);
+        nodes
+    }
+
+    fn block(&mut self, block: &'a [Statement]) {
+        for statement in block {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        self.statements.push(statement);
+
+        match &statement.kind {
+            StatementKind::LetDeclaration { initial: Some(value), .. } | StatementKind::ConstDeclaration { initial: value, .. } |
+            StatementKind::Return { value } | StatementKind::Throw { value } | StatementKind::Expression { expression: value } => self.expression(value),
+            StatementKind::FunctionDeclaration { body, .. } => self.block(body),
+            StatementKind::If { condition, then, otherwise } => {
+                self.expression(condition);
+                self.block(then);
+
+                match otherwise {
+                    Some(Else::If(statement)) => self.statement(statement),
+                    Some(Else::Block(block)) => self.block(block),
+                    None => (),
+                }
+            },
+            StatementKind::For { iterable: condition, then, .. } | StatementKind::While { condition, then } => {
+                self.expression(condition);
+                self.block(then);
+            },
+            StatementKind::Try { then, catch, finally } => {
+                self.block(then);
+
+                if let Some(catch) = catch {
+                    self.block(&catch.then);
+                }
+
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            },
+            StatementKind::Export { declaration } => self.statement(declaration),
+            StatementKind::LetDeclaration { initial: None, .. } | StatementKind::StructDeclaration { .. } |
+            StatementKind::Break | StatementKind::Continue | StatementKind::Import { .. } => (),
+        }
+    }
+
+    fn expression(&mut self, expression: &'a Expression) {
+        self.expressions.push(expression);
+
+        match &expression.kind {
+            ExpressionKind::Assign(left, right) | ExpressionKind::Infix(left, _, right) => {
+                self.expression(left);
+                self.expression(right);
+            },
+            ExpressionKind::Prefix(_, expression) | ExpressionKind::Get(expression, _) => self.expression(expression),
+            ExpressionKind::Call(callee, arguments) => {
+                self.expression(callee);
+
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            },
+            ExpressionKind::Struct(definition, fields) => {
+                self.expression(definition);
+
+                for value in fields.values() {
+                    self.expression(value);
+                }
+            },
+            ExpressionKind::Closure(_, body) => self.block(body),
+            ExpressionKind::Index(instance, index) => {
+                self.expression(instance);
+
+                if let Some(index) = index {
+                    self.expression(index);
+                }
+            },
+            ExpressionKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            },
+            ExpressionKind::Map(items) => {
+                for (key, value) in items {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            },
+            ExpressionKind::Number(..) | ExpressionKind::String(..) | ExpressionKind::Bool(..) |
+            ExpressionKind::Null | ExpressionKind::Identifier(..) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(source: &str, lints: &Lints) -> Vec<String> {
+        let program = parse(generate(source)).unwrap();
+
+        check(&program, vec!["println".to_string()], lints).iter()
+            .map(|problem| format!("{} {}", problem.lint, problem.span.line))
+            .collect()
+    }
+
+    #[test]
+    fn it_finds_mistakes() {
+        let lints = Lints::default();
+
+        assert_eq!(problems("println(a)\nconst b = 1\nb = 2", &lints), vec!["undefined-variable 1", "constant-assignment 3"]);
+        assert_eq!(problems("fn add(a, b) {\nreturn a + b\n}\nadd(1)\nadd(1, 2)", &lints), vec!["argument-count 4"]);
+        assert_eq!(problems("struct Point { x, y }\nPoint { x: 1, z: 2 }", &lints), vec!["unknown-field 2"]);
+        assert_eq!(problems("fn a(x, _y) {\nlet z = 1\nreturn 1\nprintln(z)\n}", &lints), vec!["unused-variable 1", "unreachable-code 4"]);
+        assert_eq!(problems("if true {\nreturn 1\n} else {\nthrow 2\n}\nprintln(1)", &lints), vec!["unreachable-code 6"]);
+    }
+
+    #[test]
+    fn it_leaves_names_that_could_be_anything_alone() {
+        let lints = Lints::default();
+
+        // A variable or parameter with the same name might not be the function.
+        assert!(problems("fn add(a, b) {\nreturn a + b\n}\nfn twice(add) {\nreturn add(1)\n}\ntwice(add)", &lints).is_empty());
+        assert!(problems("fn speak(this) {\nreturn 1\n}\nspeak()", &lints).is_empty());
+        assert!(problems("let a = 1\na = 2\nfn f(x) {\nx = 1\nreturn x\n}\nf(a)", &lints).is_empty());
+    }
+
+    #[test]
+    fn it_can_change_the_level_of_a_lint() {
+        let mut lints = Lints::default();
+
+        lints.set(Lint::UnusedVariable, Level::Allow);
+        lints.set(Lint::UnreachableCode, Level::Deny);
+
+        let program = parse(generate("fn a(x) {\nreturn 1\nreturn 2\n}")).unwrap();
+        let problems = check(&program, Vec::new(), &lints);
+
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].level, Level::Deny);
+        assert_eq!(problems[0].diagnostic().severity, Severity::Error);
+        assert_eq!("unused-variable".parse::<Lint>(), Ok(Lint::UnusedVariable));
+        assert!("warn".parse::<Level>().is_ok() && "loud".parse::<Level>().is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use indexmap::IndexMap;
+use lagoon_parser::{generate, parse, Program, Span, StatementKind};
+
+use crate::environment::{Environment, Value};
+use crate::interpreter::{Interpreter, InterpreterResult};
+
+/// A module loaded with `import` or `require()`. Every module runs in a scope of
+/// its own, and only the declarations that it exports can be used elsewhere.
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub exports: IndexMap<String, Value>,
+}
+
+impl Module {
+    pub fn get(&self, name: &str) -> Result<Value, InterpreterResult> {
+        self.exports.get(name).cloned()
+            .ok_or_else(|| InterpreterResult::UndefinedExport(self.name.clone(), name.to_string()))
+    }
+}
+
+impl Interpreter {
+    /// Set the directories that packages are searched for in, after any
+    /// `lagoon_modules` or `vendor` directory. Relative paths are resolved
+    /// against the directory of the script being run. Modules found this way
+    /// still need permission to be loaded.
+    pub fn set_search_paths(&mut self, paths: Vec<PathBuf>) {
+        self.search_paths = paths;
+    }
+
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+
+    /// Make a package available under `name`, ahead of any package found on
+    /// disk. `import "name"` loads `entry`, and `import "name/path"` loads a
+    /// module relative to the directory that `entry` is in.
+    pub fn add_package(&mut self, name: impl Into<String>, entry: PathBuf) {
+        self.packages.insert(name.into(), entry);
+    }
+
+    /// Load the module at `path`. Every module only runs once, after which the
+    /// same module value is handed out from the cache.
+    pub(crate) fn load_module(&mut self, path: &str) -> Result<Value, InterpreterResult> {
+        if let Some(name) = path.strip_prefix("std/") {
+            return self.load_builtin_module(path, name);
+        }
+
+        let module_path = self.find_module(path)?;
+
+        self.check_module(&module_path)?;
+
+        match self.modules.get(&module_path) {
+            Some(Some(module)) => return Ok(module.clone()),
+            // The module is still running, so one of its own imports must have
+            // led back to it.
+            Some(None) => return Err(InterpreterResult::CircularImport(self.import_chain(&module_path))),
+            None => (),
+        }
+
+        let display = module_path.to_string_lossy().to_string();
+        let contents = ::std::fs::read_to_string(&module_path)
+            .map_err(|e| InterpreterResult::InvalidModule(display.clone(), e.to_string()))?;
+
+        let ast = parse(generate(&contents))
+            .map_err(|e| InterpreterResult::InvalidModule(display.clone(), located_message(e.to_string(), e.span())))?;
+
+        self.modules.insert(module_path.clone(), None);
+        self.loading.push(module_path.clone());
+
+        let result = self.run_module(ast);
+
+        self.loading.pop();
+
+        let exports = match result {
+            Ok(exports) => exports,
+            Err(error) => {
+                self.modules.remove(&module_path);
+
+                // Errors that the script isn't allowed to catch, like exceeded
+                // limits, have to reach the host as they are. Circular imports
+                // are reported once, by the module that started the cycle.
+                return Err(match error.inner() {
+                    InterpreterResult::CircularImport(..) => error,
+                    _ if ! error.is_catchable() => error,
+                    inner => InterpreterResult::InvalidModule(display, located_message(inner.to_string(), error.span())),
+                });
+            },
+        };
+
+        let name = module_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let module = Value::Module(Rc::new(Module { name, path: module_path.clone(), exports }));
+
+        self.modules.insert(module_path, Some(module.clone()));
+
+        Ok(module)
+    }
+
+    /// Load one of the modules that are built into the interpreter. They don't
+    /// touch the filesystem, so they don't need permission to be loaded.
+    fn load_builtin_module(&mut self, path: &str, name: &str) -> Result<Value, InterpreterResult> {
+        let key = PathBuf::from(path);
+
+        if let Some(Some(module)) = self.modules.get(&key) {
+            return Ok(module.clone());
+        }
+
+        let exports = crate::stdlib::module(name)
+            .ok_or_else(|| InterpreterResult::ModuleNotFound(path.to_string()))?
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+
+        let module = Value::Module(Rc::new(Module { name: path.to_string(), path: key.clone(), exports }));
+
+        self.modules.insert(key, Some(module.clone()));
+
+        Ok(module)
+    }
+
+    /// Find the file for a module. Relative paths start from the directory of
+    /// the module doing the importing. Anything else is a package, which is
+    /// looked for in the `lagoon_modules` and `vendor` directories next to the
+    /// importer or any of its parents, and then in the search paths. Packages
+    /// that were added by name are checked before any of those.
+    ///
+    /// The `.lag` extension is optional, and a directory is loaded through the
+    /// `main.lag` file inside of it.
+    fn find_module(&self, path: &str) -> Result<PathBuf, InterpreterResult> {
+        let importer = self.loading.last().cloned().unwrap_or_else(|| self.path());
+        let directory = importer.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let (package, rest) = path.split_once('/').unwrap_or((path, ""));
+
+        let candidates: Vec<PathBuf> = if path.starts_with('.') {
+            vec![directory.join(path)]
+        } else if let Some(entry) = self.packages.get(package) {
+            if rest.is_empty() {
+                return entry.canonicalize().map_err(|_| InterpreterResult::ModuleNotFound(path.to_string()));
+            }
+
+            vec![entry.parent().map(Path::to_path_buf).unwrap_or_default().join(rest)]
+        } else {
+            let script = self.path().parent().map(Path::to_path_buf).unwrap_or_default();
+
+            directory.ancestors()
+                .flat_map(|parent| [parent.join("lagoon_modules"), parent.join("vendor")])
+                .chain(self.search_paths.iter().map(|search_path| script.join(search_path)))
+                .map(|base| base.join(path))
+                .collect()
+        };
+
+        candidates.into_iter()
+            .flat_map(|candidate| {
+                let file = match path.ends_with(".lag") {
+                    true => candidate.clone(),
+                    false => PathBuf::from(format!("{}.lag", candidate.display())),
+                };
+
+                [file, candidate.join("main.lag")]
+            })
+            .find(|file| file.is_file())
+            .and_then(|file| file.canonicalize().ok())
+            .ok_or_else(|| InterpreterResult::ModuleNotFound(path.to_string()))
+    }
+
+    /// Run a module with fresh globals and a fresh scope, returning its exports.
+    fn run_module(&mut self, ast: Program) -> Result<IndexMap<String, Value>, InterpreterResult> {
+        let exported = ast.iter().filter_map(|statement| match &statement.kind {
+            StatementKind::Export { declaration } => declaration.kind.declared_name().map(str::to_string),
+            _ => None,
+        }).collect::<Vec<String>>();
+
+        let globals = std::mem::replace(&mut self.globals, self.prelude.clone());
+        let environment = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(Environment::new())));
+
+        let result = self.exec(ast);
+
+        let module_globals = std::mem::replace(&mut self.globals, globals);
+        let module_environment = std::mem::replace(&mut self.environment, environment);
+
+        result?;
+
+        // Functions declared by the module are still looked up by name once the
+        // module has finished running, so they need to be found through the
+        // scope they captured rather than the globals of whoever calls them.
+        let mut scope = module_environment.borrow_mut();
+
+        for (name, value) in module_globals {
+            if scope.get(name.clone()).is_none() {
+                scope.set(name, value);
+            }
+        }
+
+        Ok(exported.into_iter().filter_map(|name| scope.get(name.clone()).map(|value| (name, value))).collect())
+    }
+
+    /// Describe the chain of imports that leads from `path` back to itself.
+    fn import_chain(&self, path: &Path) -> String {
+        let directory = self.path().parent().map(Path::to_path_buf).unwrap_or_default();
+        let start = self.loading.iter().position(|p| p == path).unwrap_or(0);
+
+        self.loading[start..].iter().map(PathBuf::as_path).chain(std::iter::once(path))
+            .map(|p| p.strip_prefix(&directory).unwrap_or(p).display().to_string())
+            .collect::<Vec<String>>()
+            .join(" -> ")
+    }
+}
+
+fn located_message(message: String, span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!("{} (at {})", message, span),
+        None => message,
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Component, Path, PathBuf};
+
+use crate::interpreter::{Interpreter, InterpreterResult};
+
+/// Something that a script has to be given permission to do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    Read,
+    Write,
+    Env,
+    Run,
+    Modules,
+}
+
+impl Capability {
+    /// The command-line flag that grants the capability.
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Capability::Read => "--allow-read",
+            Capability::Write => "--allow-write",
+            Capability::Env => "--allow-env",
+            Capability::Run => "--allow-run",
+            Capability::Modules => "--allow-modules",
+        }
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", match self {
+            Capability::Read => "read",
+            Capability::Write => "write",
+            Capability::Env => "environment",
+            Capability::Run => "run",
+            Capability::Modules => "module",
+        })
+    }
+}
+
+/// How much of a capability has been granted.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Access<T> {
+    #[default]
+    Denied,
+    Only(Vec<T>),
+    All,
+}
+
+impl<T> Access<T> {
+    /// Grant access to another item, on top of anything already granted.
+    pub fn allow(&mut self, item: T) {
+        match self {
+            Access::Denied => *self = Access::Only(vec![item]),
+            Access::Only(items) => items.push(item),
+            Access::All => (),
+        }
+    }
+
+    fn permits(&self, allowed: impl Fn(&T) -> bool) -> bool {
+        match self {
+            Access::Denied => false,
+            Access::Only(items) => items.iter().any(allowed),
+            Access::All => true,
+        }
+    }
+}
+
+/// The capabilities given to a script. Relative paths are resolved against
+/// the directory of the script being run.
+///
+/// By default a script can only load modules from its own directory, and
+/// everything else is denied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Permissions {
+    /// Directories (or files) that can be read from.
+    pub read: Access<PathBuf>,
+    /// Directories (or files) that can be written to.
+    pub write: Access<PathBuf>,
+    /// Environment variables that can be read.
+    pub env: Access<String>,
+    /// Programs that can be spawned.
+    pub run: Access<String>,
+    /// Directories that modules can be loaded from.
+    pub modules: Access<PathBuf>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            modules: Access::Only(vec![PathBuf::from(".")]),
+            ..Self::none()
+        }
+    }
+}
+
+impl Permissions {
+    /// Deny everything, including loading modules.
+    pub fn none() -> Self {
+        Self {
+            read: Access::Denied,
+            write: Access::Denied,
+            env: Access::Denied,
+            run: Access::Denied,
+            modules: Access::Denied,
+        }
+    }
+
+    /// Allow everything.
+    pub fn all() -> Self {
+        Self {
+            read: Access::All,
+            write: Access::All,
+            env: Access::All,
+            run: Access::All,
+            modules: Access::All,
+        }
+    }
+}
+
+impl Interpreter {
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    pub fn check_read(&self, path: &Path) -> Result<(), InterpreterResult> {
+        self.check_path(Capability::Read, &self.permissions.read, path)
+    }
+
+    pub fn check_write(&self, path: &Path) -> Result<(), InterpreterResult> {
+        self.check_path(Capability::Write, &self.permissions.write, path)
+    }
+
+    pub fn check_module(&self, path: &Path) -> Result<(), InterpreterResult> {
+        self.check_path(Capability::Modules, &self.permissions.modules, path)
+    }
+
+    pub fn check_env(&self, name: &str) -> Result<(), InterpreterResult> {
+        match self.permissions.env.permits(|allowed| allowed == name) {
+            true => Ok(()),
+            false => Err(InterpreterResult::PermissionDenied(Capability::Env, name.to_string())),
+        }
+    }
+
+    pub fn check_run(&self, program: &str) -> Result<(), InterpreterResult> {
+        match self.permissions.run.permits(|allowed| allowed == program) {
+            true => Ok(()),
+            false => Err(InterpreterResult::PermissionDenied(Capability::Run, program.to_string())),
+        }
+    }
+
+    /// Paths are compared once symlinks and `..` have been resolved, so that
+    /// a script can't escape an allowed directory.
+    fn check_path(&self, capability: Capability, access: &Access<PathBuf>, path: &Path) -> Result<(), InterpreterResult> {
+        let path = self.absolute(path);
+
+        match access.permits(|root| path.starts_with(self.absolute(root))) {
+            true => Ok(()),
+            false => Err(InterpreterResult::PermissionDenied(capability, path.display().to_string())),
+        }
+    }
+
+    fn absolute(&self, path: &Path) -> PathBuf {
+        let path = match self.path().parent() {
+            Some(directory) if path.is_relative() => directory.join(path),
+            _ => path.to_path_buf(),
+        };
+
+        canonical(&path)
+    }
+}
+
+/// Canonicalize as much of the path as exists, so that files which are about
+/// to be created can be checked too.
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    // The part that doesn't exist can't contain symlinks, but it might still
+    // contain `..` components that need to be taken into account.
+    let path = normalize(path);
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => canonical(parent).join(name),
+        _ => path,
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => { normalized.pop(); },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
@@ -0,0 +1,696 @@
+use std::cell::Cell;
+use hashbrown::{HashMap, HashSet};
+use lagoon_parser::*;
+
+use crate::interpreter::InterpreterResult;
+
+/// Resolve every identifier in `program` to the scope and slot that it will be
+/// stored in at runtime, reporting any names that can never be found and any
+/// assignments to constants.
+///
+/// `globals` are the names that already exist before the program runs, such as
+/// native functions. Every module is resolved on its own, since the only names
+/// it can see from other modules are the ones it imports.
+pub fn resolve(program: &[Statement], globals: impl IntoIterator<Item = String>) -> Result<(), Vec<InterpreterResult>> {
+    let (errors, _) = analyse(program, globals);
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+/// Resolve `program` like `resolve`, telling `visitor` about every declaration
+/// and every identifier that refers to one along the way.
+pub fn resolve_with<'a>(program: &'a [Statement], globals: impl IntoIterator<Item = String>, visitor: &mut dyn Visitor<'a>) -> Result<(), Vec<InterpreterResult>> {
+    let resolver = run(program, globals, Some(visitor));
+
+    match resolver.errors.is_empty() {
+        true => Ok(()),
+        false => Err(resolver.errors),
+    }
+}
+
+/// Resolve `program` like `resolve`, also returning the local variables and
+/// parameters that are never read, along with where they were declared.
+pub(crate) fn analyse(program: &[Statement], globals: impl IntoIterator<Item = String>) -> (Vec<InterpreterResult>, Vec<(String, Span)>) {
+    let resolver = run(program, globals, None);
+
+    (resolver.errors, resolver.unused)
+}
+
+fn run<'a, 'v>(program: &'a [Statement], globals: impl IntoIterator<Item = String>, visitor: Option<&'v mut dyn Visitor<'a>>) -> Resolver<'a, 'v> {
+    let mut resolver = Resolver { visitor, ..Resolver::default() };
+
+    resolver.globals.extend(globals);
+    resolver.collect(program);
+
+    for statement in program {
+        resolver.statement(statement);
+    }
+
+    let pending = std::mem::take(&mut resolver.pending);
+
+    for function in pending {
+        resolver.function(function);
+    }
+
+    resolver.errors.sort_by_key(|e| e.span().map(|s| s.start).unwrap_or_default());
+    resolver
+}
+
+/// Something that declares a name.
+#[derive(Debug, Clone, Copy)]
+pub enum Declaration<'a> {
+    /// A variable, constant, function or struct, and whether it's declared at
+    /// the top-level.
+    Statement(&'a Statement, bool),
+    /// One of the names brought in by an `import`.
+    Import(&'a Statement, &'a Parameter),
+    Parameter(&'a Parameter),
+    /// The value or index of a `for` loop.
+    Loop(&'a Statement, &'a str),
+    /// The error of a `catch` block.
+    Catch(&'a Parameter),
+}
+
+impl<'a> Declaration<'a> {
+    pub fn name(&self) -> &'a str {
+        match self {
+            Declaration::Statement(statement, _) => match &statement.kind {
+                StatementKind::LetDeclaration { name, .. } | StatementKind::ConstDeclaration { name, .. } |
+                StatementKind::FunctionDeclaration { name, .. } | StatementKind::StructDeclaration { name, .. } => name,
+                _ => unreachable!(),
+            },
+            Declaration::Import(_, name) | Declaration::Parameter(name) | Declaration::Catch(name) => &name.name,
+            Declaration::Loop(_, name) => name,
+        }
+    }
+
+    fn span(&self) -> Span {
+        match self {
+            Declaration::Statement(statement, _) | Declaration::Loop(statement, _) => statement.span,
+            Declaration::Import(_, name) | Declaration::Parameter(name) | Declaration::Catch(name) => name.span,
+        }
+    }
+}
+
+/// Follows the resolver through a program, for tools that need to know which
+/// declaration every identifier refers to. Declarations are numbered in the
+/// order they are passed to `declare`, starting from zero.
+pub trait Visitor<'a> {
+    /// A new declaration, which can be used until the offset `end` or from
+    /// anywhere if it's `None`.
+    fn declare(&mut self, declaration: Declaration<'a>, end: Option<usize>);
+
+    /// A name that can be used from anywhere being declared again, which shares
+    /// its value with the `existing` declaration.
+    fn redeclare(&mut self, declaration: Declaration<'a>, existing: usize);
+
+    /// An identifier at `span` that refers to `declaration`.
+    fn reference(&mut self, span: Span, declaration: usize);
+
+    /// A field of a struct literal, just before its value is resolved.
+    fn field(&mut self, _name: &'a str, _value: &'a Expression) {}
+}
+
+/// A block, loop iteration or function body. Variables are stored in the order
+/// they are first declared, and declaring the same name twice reuses its slot.
+#[derive(Default)]
+struct Scope<'a> {
+    names: Vec<Local>,
+    function: bool,
+    /// The offset that the scope ends at.
+    end: usize,
+    pending: Vec<Function<'a>>,
+}
+
+/// A function or closure, which is resolved once the scope it's declared in
+/// has ended.
+struct Function<'a> {
+    params: &'a [Parameter],
+    body: &'a [Statement],
+    end: usize,
+}
+
+struct Local {
+    name: String,
+    constant: bool,
+    /// Whether the variable is ever read, rather than only assigned to.
+    used: bool,
+    span: Span,
+    /// The number given to the declaration when it was passed to the visitor.
+    declaration: usize,
+}
+
+#[derive(Default)]
+struct Resolver<'a, 'v> {
+    scopes: Vec<Scope<'a>>,
+    /// Functions declared at the top-level, which are resolved at the very end.
+    pending: Vec<Function<'a>>,
+    /// Native functions, along with every function and struct in the program.
+    globals: HashSet<String>,
+    /// Top-level variables, mapped to whether every declaration is a constant.
+    variables: HashMap<String, bool>,
+    /// Top-level variables that have been declared so far.
+    declared: HashMap<String, bool>,
+    errors: Vec<InterpreterResult>,
+    /// Local variables that went out of scope without ever being read.
+    unused: Vec<(String, Span)>,
+    visitor: Option<&'v mut dyn Visitor<'a>>,
+    /// How many declarations have been passed to the visitor.
+    declarations: usize,
+    /// The first declaration of every name that can be used from anywhere.
+    first: HashMap<String, usize>,
+}
+
+impl<'a, 'v> Resolver<'a, 'v> {
+    /// Find the names that are visible from anywhere in the program.
+    fn collect(&mut self, program: &'a [Statement]) {
+        let mut collector = Collector::default();
+
+        for statement in program {
+            let statement = match &statement.kind {
+                StatementKind::Export { declaration } => declaration,
+                _ => statement,
+            };
+
+            match &statement.kind {
+                StatementKind::LetDeclaration { name, .. } => {
+                    self.variables.insert(name.clone(), false);
+                    collector.declarations.push(Declaration::Statement(statement, true));
+                },
+                StatementKind::ConstDeclaration { name, .. } => {
+                    self.variables.entry(name.clone()).or_insert(true);
+                    collector.declarations.push(Declaration::Statement(statement, true));
+                },
+                // Imported names can't be reassigned, just like constants.
+                StatementKind::Import { names, .. } => for name in names {
+                    self.variables.entry(name.name.clone()).or_insert(true);
+                    collector.declarations.push(Declaration::Import(statement, name));
+                },
+                _ => (),
+            }
+
+            collector.statement(statement);
+        }
+
+        for declaration in collector.declarations {
+            if let Declaration::Statement(Statement { kind: StatementKind::FunctionDeclaration { name, .. } | StatementKind::StructDeclaration { name, .. }, .. }, _) = declaration {
+                self.globals.insert(name.clone());
+            }
+
+            match self.first.get(declaration.name()) {
+                Some(existing) => {
+                    let existing = *existing;
+                    self.visit(|visitor| visitor.redeclare(declaration, existing));
+                },
+                None => {
+                    let number = self.announce(declaration, None);
+                    self.first.insert(declaration.name().to_string(), number);
+                },
+            }
+        }
+    }
+
+    fn visit(&mut self, callback: impl FnOnce(&mut dyn Visitor<'a>)) {
+        if let Some(visitor) = self.visitor.as_deref_mut() {
+            callback(visitor);
+        }
+    }
+
+    /// Pass a new declaration to the visitor, returning the number it's given.
+    fn announce(&mut self, declaration: Declaration<'a>, end: Option<usize>) -> usize {
+        self.visit(|visitor| visitor.declare(declaration, end));
+        self.declarations += 1;
+        self.declarations - 1
+    }
+
+    fn begin_scope(&mut self, function: bool, end: usize) {
+        self.scopes.push(Scope { function, end, ..Scope::default() });
+    }
+
+    /// Close the current scope. Functions declared inside of it are resolved
+    /// now, so that they can refer to variables declared after them.
+    fn end_scope(&mut self) {
+        let pending = std::mem::take(&mut self.scopes.last_mut().unwrap().pending);
+
+        for function in pending {
+            self.function(function);
+        }
+
+        for local in self.scopes.pop().unwrap().names {
+            self.report_unused(local);
+        }
+    }
+
+    fn declare(&mut self, declaration: Declaration<'a>, constant: bool) {
+        let name = declaration.name();
+
+        let end = match self.scopes.last() {
+            Some(scope) => scope.end,
+            None => {
+                self.declared.insert(name.to_string(), constant);
+                return;
+            },
+        };
+
+        let local = Local { name: name.to_string(), constant, used: false, span: declaration.span(), declaration: self.announce(declaration, Some(end)) };
+        let scope = self.scopes.last_mut().unwrap();
+
+        // Declaring a name again reuses its slot, but the old declaration can
+        // still have gone unused.
+        match scope.names.iter_mut().find(|l| l.name == name) {
+            Some(existing) => {
+                let previous = std::mem::replace(existing, local);
+                self.report_unused(previous);
+            },
+            None => scope.names.push(local),
+        }
+    }
+
+    /// Names that start with an underscore are unused on purpose, and `this`
+    /// is only there so that a function can be used as a method.
+    fn report_unused(&mut self, local: Local) {
+        if ! local.used && ! local.name.starts_with('_') && local.name != "this" {
+            self.unused.push((local.name, local.span));
+        }
+    }
+
+    /// Find a local variable, returning its binding and whether it's a constant.
+    fn local(&self, name: &str) -> Option<(Binding, bool)> {
+        let mut captured = false;
+
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(slot) = scope.names.iter().position(|l| l.name == name) {
+                let constant = scope.names[slot].constant;
+
+                return Some((match captured {
+                    true => Binding::Captured { depth, slot },
+                    false => Binding::Local { depth, slot },
+                }, constant));
+            }
+
+            captured = captured || scope.function;
+        }
+
+        None
+    }
+
+    fn in_function(&self) -> bool {
+        self.scopes.iter().any(|s| s.function)
+    }
+
+    /// Resolve a name that isn't a local variable, returning whether it's a
+    /// constant or `None` if it doesn't exist.
+    fn global(&self, name: &str) -> Option<bool> {
+        // Code inside of a function runs after the rest of the top-level has
+        // been declared, whereas top-level code runs from top to bottom.
+        let variables = match self.in_function() {
+            true => &self.variables,
+            false => &self.declared,
+        };
+
+        match variables.get(name) {
+            Some(constant) => Some(*constant && ! self.globals.contains(name)),
+            None if self.globals.contains(name) => Some(false),
+            None => None,
+        }
+    }
+
+    /// Resolve an identifier, returning whether it's a constant. `read` is false
+    /// when the identifier is only being assigned to.
+    fn identifier(&mut self, name: &str, binding: &Cell<Binding>, span: Span, read: bool) -> Option<bool> {
+        if let Some((local, constant)) = self.local(name) {
+            if let Binding::Local { depth, slot } | Binding::Captured { depth, slot } = local {
+                let index = self.scopes.len() - 1 - depth;
+                let found = &mut self.scopes[index].names[slot];
+
+                found.used = found.used || read;
+
+                let declaration = found.declaration;
+                self.visit(|visitor| visitor.reference(span, declaration));
+            }
+
+            binding.set(local);
+            return Some(constant);
+        }
+
+        binding.set(Binding::Global);
+
+        let global = self.global(name);
+
+        match (global, self.first.get(name)) {
+            (None, _) => self.error(InterpreterResult::UndefinedVariable(name.to_string()), span),
+            (Some(_), Some(declaration)) => {
+                let declaration = *declaration;
+                self.visit(|visitor| visitor.reference(span, declaration));
+            },
+            (Some(_), None) => (),
+        }
+
+        global
+    }
+
+    fn error(&mut self, error: InterpreterResult, span: Span) {
+        self.errors.push(InterpreterResult::Located(Box::new(error), span));
+    }
+
+    fn block(&mut self, block: &'a [Statement], end: usize) {
+        self.begin_scope(false, end);
+
+        for statement in block {
+            self.statement(statement);
+        }
+
+        self.end_scope();
+    }
+
+    fn defer(&mut self, params: &'a [Parameter], body: &'a [Statement], end: usize) {
+        let function = Function { params, body, end };
+
+        match self.scopes.last_mut() {
+            Some(scope) => scope.pending.push(function),
+            None => self.pending.push(function),
+        }
+    }
+
+    fn function(&mut self, function: Function<'a>) {
+        self.begin_scope(true, function.end);
+
+        for param in function.params {
+            self.declare(Declaration::Parameter(param), false);
+        }
+
+        for statement in function.body {
+            self.statement(statement);
+        }
+
+        self.end_scope();
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        let end = statement.span.end;
+
+        match &statement.kind {
+            StatementKind::LetDeclaration { initial, .. } => {
+                if let Some(initial) = initial {
+                    self.expression(initial);
+                }
+
+                self.declare(Declaration::Statement(statement, false), false);
+            },
+            StatementKind::ConstDeclaration { initial, .. } => {
+                self.expression(initial);
+                self.declare(Declaration::Statement(statement, false), true);
+            },
+            StatementKind::FunctionDeclaration { params, body, .. } => self.defer(params, body, end),
+            StatementKind::StructDeclaration { .. } | StatementKind::Break | StatementKind::Continue => (),
+            StatementKind::If { condition, then, otherwise } => {
+                self.expression(condition);
+                self.block(then, end);
+
+                match otherwise {
+                    Some(Else::If(statement)) => self.statement(statement),
+                    Some(Else::Block(block)) => self.block(block, end),
+                    None => (),
+                }
+            },
+            StatementKind::For { iterable, value, index, then } => {
+                self.expression(iterable);
+                self.begin_scope(false, end);
+                self.declare(Declaration::Loop(statement, value), false);
+
+                if let Some(index) = index {
+                    self.declare(Declaration::Loop(statement, index), false);
+                }
+
+                for statement in then {
+                    self.statement(statement);
+                }
+
+                self.end_scope();
+            },
+            StatementKind::While { condition, then } => {
+                self.expression(condition);
+                self.begin_scope(false, end);
+
+                for statement in then {
+                    self.statement(statement);
+                }
+
+                self.end_scope();
+            },
+            StatementKind::Try { then, catch, finally } => {
+                self.block(then, end);
+
+                if let Some(Catch { name, then }) = catch {
+                    self.begin_scope(false, end);
+                    self.declare(Declaration::Catch(name), false);
+                    self.block(then, end);
+                    self.end_scope();
+                }
+
+                if let Some(finally) = finally {
+                    self.block(finally, end);
+                }
+            },
+            StatementKind::Return { value } | StatementKind::Throw { value } => self.expression(value),
+            StatementKind::Expression { expression } => self.expression(expression),
+            StatementKind::Export { declaration } => self.statement(declaration),
+            StatementKind::Import { names, .. } => for name in names {
+                self.declare(Declaration::Import(statement, name), true);
+            },
+        }
+    }
+
+    fn expression(&mut self, expression: &'a Expression) {
+        match &expression.kind {
+            ExpressionKind::Number(..) | ExpressionKind::String(..) | ExpressionKind::Bool(..) | ExpressionKind::Null => (),
+            ExpressionKind::Identifier(name, binding) => {
+                self.identifier(name, binding, expression.span, true);
+            },
+            ExpressionKind::Assign(target, value) => {
+                self.expression(value);
+
+                match &target.kind {
+                    ExpressionKind::Identifier(name, binding) => {
+                        if let Some(true) = self.identifier(name, binding, target.span, false) {
+                            self.error(InterpreterResult::CannotAssignValueToConstant, expression.span);
+                        }
+                    },
+                    _ => self.expression(target),
+                }
+            },
+            ExpressionKind::Infix(left, _, right) => {
+                self.expression(left);
+                self.expression(right);
+            },
+            ExpressionKind::Prefix(_, right) => self.expression(right),
+            ExpressionKind::Call(callable, arguments) => {
+                self.expression(callable);
+
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            },
+            ExpressionKind::Struct(definition, fields) => {
+                self.expression(definition);
+
+                for (field, value) in fields.iter() {
+                    self.visit(|visitor| visitor.field(field, value));
+                    self.expression(value);
+                }
+            },
+            ExpressionKind::Closure(params, body) => self.defer(params, body, expression.span.end),
+            ExpressionKind::Get(instance, _) => self.expression(instance),
+            ExpressionKind::Index(instance, index) => {
+                self.expression(instance);
+
+                if let Some(index) = index {
+                    self.expression(index);
+                }
+            },
+            ExpressionKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            },
+            ExpressionKind::Map(items) => {
+                for (key, value) in items {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            },
+        }
+    }
+}
+
+/// Finds the functions and structs declared anywhere in a program.
+#[derive(Default)]
+struct Collector<'a> {
+    declarations: Vec<Declaration<'a>>,
+    /// How many blocks deep the statements being collected are.
+    depth: usize,
+}
+
+impl<'a> Collector<'a> {
+    fn block(&mut self, block: &'a [Statement]) {
+        self.depth += 1;
+
+        for statement in block {
+            self.statement(statement);
+        }
+
+        self.depth -= 1;
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match &statement.kind {
+            StatementKind::FunctionDeclaration { body, .. } => {
+                self.declarations.push(Declaration::Statement(statement, self.depth == 0));
+                self.block(body);
+            },
+            StatementKind::StructDeclaration { .. } => self.declarations.push(Declaration::Statement(statement, self.depth == 0)),
+            StatementKind::LetDeclaration { initial: Some(value), .. } | StatementKind::ConstDeclaration { initial: value, .. } |
+            StatementKind::Return { value } | StatementKind::Throw { value } | StatementKind::Expression { expression: value } => self.expression(value),
+            StatementKind::If { condition, then, otherwise } => {
+                self.expression(condition);
+                self.block(then);
+
+                match otherwise {
+                    Some(Else::If(statement)) => self.statement(statement),
+                    Some(Else::Block(block)) => self.block(block),
+                    None => (),
+                }
+            },
+            StatementKind::For { iterable: condition, then, .. } | StatementKind::While { condition, then } => {
+                self.expression(condition);
+                self.block(then);
+            },
+            StatementKind::Try { then, catch, finally } => {
+                self.block(then);
+
+                if let Some(catch) = catch {
+                    self.block(&catch.then);
+                }
+
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            },
+            StatementKind::Export { declaration } => self.statement(declaration),
+            StatementKind::LetDeclaration { initial: None, .. } | StatementKind::Break | StatementKind::Continue |
+            StatementKind::Import { .. } => (),
+        }
+    }
+
+    fn expression(&mut self, expression: &'a Expression) {
+        match &expression.kind {
+            ExpressionKind::Call(callable, arguments) => {
+                self.expression(callable);
+
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            },
+            ExpressionKind::Closure(_, body) => self.block(body),
+            ExpressionKind::Assign(left, right) | ExpressionKind::Infix(left, _, right) => {
+                self.expression(left);
+                self.expression(right);
+            },
+            ExpressionKind::Prefix(_, expression) | ExpressionKind::Get(expression, _) => self.expression(expression),
+            ExpressionKind::Index(instance, index) => {
+                self.expression(instance);
+
+                if let Some(index) = index {
+                    self.expression(index);
+                }
+            },
+            ExpressionKind::Struct(definition, fields) => {
+                self.expression(definition);
+
+                for value in fields.values() {
+                    self.expression(value);
+                }
+            },
+            ExpressionKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            },
+            ExpressionKind::Map(items) => {
+                for (key, value) in items {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            },
+            ExpressionKind::Number(..) | ExpressionKind::String(..) | ExpressionKind::Bool(..) |
+            ExpressionKind::Null | ExpressionKind::Identifier(..) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(source: &str) -> Vec<String> {
+        let program = parse(generate(source)).unwrap();
+
+        match resolve(&program, vec!["println".to_string()]) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(|e| format!("{} {}", e.inner(), e.span().unwrap().line)).collect(),
+        }
+    }
+
+    fn binding(program: &[Statement]) -> Binding {
+        match &program.last().unwrap().kind {
+            StatementKind::Expression { expression: Expression { kind: ExpressionKind::Identifier(_, binding), .. } } => binding.get(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn it_reports_undefined_variables_and_constant_assignments() {
+        assert_eq!(errors("println(name)\nlet name = 1"), vec!["Undefined variable: name. 1"]);
+        assert_eq!(errors("const name = 1\nfn rename() {\nname = 2\n}"), vec!["Cannot assign value to constant. 3"]);
+        assert_eq!(errors("if true {\nconst a = 1\na = 2\n}"), vec!["Cannot assign value to constant. 3"]);
+        assert_eq!(errors("if true {\nlet a = 1\n}\nprintln(a)"), vec!["Undefined variable: a. 4"]);
+        assert!(errors("let f = fn () {\nreturn later\n}\nlet later = 1").is_empty());
+        assert!(errors("fn a() {\nb()\n}\nfn b() {}").is_empty());
+    }
+
+    #[test]
+    fn it_binds_identifiers_to_slots() {
+        let program = parse(generate("let a = 1\na")).unwrap();
+        resolve(&program, Vec::new()).unwrap();
+        assert_eq!(binding(&program), Binding::Global);
+
+        let program = parse(generate("if true {\nlet a = 1\nlet b = 2\nif true {\nb\n}\n}")).unwrap();
+        resolve(&program, Vec::new()).unwrap();
+
+        let inner = match &program[0].kind {
+            StatementKind::If { then, .. } => match &then[2].kind {
+                StatementKind::If { then, .. } => then.clone(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        assert_eq!(binding(&inner), Binding::Local { depth: 1, slot: 1 });
+
+        let program = parse(generate("fn f(a) {\nreturn fn () {\na\n}\n}")).unwrap();
+        resolve(&program, Vec::new()).unwrap();
+
+        let body = match &program[0].kind {
+            StatementKind::FunctionDeclaration { body, .. } => match &body[0].kind {
+                StatementKind::Return { value: Expression { kind: ExpressionKind::Closure(_, body), .. } } => body.clone(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        assert_eq!(binding(&body), Binding::Captured { depth: 1, slot: 0 });
+    }
+}
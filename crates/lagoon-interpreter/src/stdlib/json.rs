@@ -0,0 +1,314 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::iter::Peekable;
+use std::str::Chars;
+use indexmap::IndexMap;
+
+use crate::environment::Value;
+use crate::interpreter::{Interpreter, InterpreterResult};
+
+pub fn stringify(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("json.stringify()", 1, &arguments)?;
+
+    let mut json = String::new();
+
+    write_value(&mut json, arguments[0].clone())?;
+
+    Ok(Value::String(json))
+}
+
+pub fn parse(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("json.parse()", 1, &arguments)?;
+
+    let source = match &arguments[0] {
+        Value::String(source) => source.clone(),
+        value => return Err(InterpreterResult::InvalidArgumentType("json.parse()".into(), "string".into(), value.clone().typestring())),
+    };
+
+    let mut parser = Parser { chars: source.chars().peekable() };
+    let value = parser.value()?;
+
+    parser.whitespace();
+
+    if let Some(c) = parser.chars.next() {
+        return Err(InterpreterResult::InvalidJson(format!("unexpected character {:?} after the value", c)));
+    }
+
+    interpreter.allocate(value)
+}
+
+fn write_value(json: &mut String, value: Value) -> Result<(), InterpreterResult> {
+    match value {
+        Value::Null => json.push_str("null"),
+        Value::Bool(b) => json.push_str(if b { "true" } else { "false" }),
+        // JSON has no way to represent `NaN` or infinity.
+        Value::Number(n) if ! n.is_finite() => json.push_str("null"),
+        Value::Number(n) => json.push_str(&n.to_string()),
+        Value::String(s) => write_string(json, &s),
+        Value::List(items) => {
+            json.push('[');
+
+            for (i, item) in items.borrow().iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+
+                write_value(json, item.clone())?;
+            }
+
+            json.push(']');
+        },
+        Value::Map(items) => write_object(json, items.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect())?,
+        // Only the fields of a struct are written, not its methods.
+        Value::StructInstance { environment, definition } => {
+            let fields = match *definition {
+                Value::Struct { fields, .. } => fields,
+                _ => unreachable!(),
+            };
+
+            let environment = environment.borrow();
+
+            write_object(json, fields.into_iter()
+                .map(|field| (field.name.clone(), environment.get(field.name).unwrap_or(Value::Null)))
+                .collect())?;
+        },
+        Value::Constant(v) => write_value(json, *v)?,
+        value => return Err(InterpreterResult::InvalidArgumentType("json.stringify()".into(), "null, bool, number, string, list, map or struct".into(), value.typestring())),
+    };
+
+    Ok(())
+}
+
+fn write_object(json: &mut String, items: Vec<(String, Value)>) -> Result<(), InterpreterResult> {
+    json.push('{');
+
+    for (i, (key, value)) in items.into_iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+
+        write_string(json, &key);
+        json.push(':');
+        write_value(json, value)?;
+    }
+
+    json.push('}');
+
+    Ok(())
+}
+
+fn write_string(json: &mut String, s: &str) {
+    json.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+}
+
+/// A recursive descent parser for JSON text. Objects become maps, so their keys
+/// keep the order they were written in.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Result<Value, InterpreterResult> {
+        self.whitespace();
+
+        Ok(match self.chars.peek() {
+            Some('{') => self.object()?,
+            Some('[') => self.list()?,
+            Some('"') => Value::String(self.string()?),
+            Some('t') => self.keyword("true", Value::Bool(true))?,
+            Some('f') => self.keyword("false", Value::Bool(false))?,
+            Some('n') => self.keyword("null", Value::Null)?,
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.number()?,
+            Some(c) => return Err(unexpected(*c)),
+            None => return Err(end()),
+        })
+    }
+
+    fn object(&mut self) -> Result<Value, InterpreterResult> {
+        let mut items = IndexMap::new();
+
+        self.chars.next();
+        self.whitespace();
+
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+        } else {
+            loop {
+                self.whitespace();
+
+                if self.chars.peek() != Some(&'"') {
+                    return Err(self.chars.next().map(unexpected).unwrap_or_else(end));
+                }
+
+                let key = self.string()?;
+
+                self.whitespace();
+                self.expect(':')?;
+
+                items.insert(key, self.value()?);
+
+                self.whitespace();
+
+                match self.chars.next() {
+                    Some(',') => continue,
+                    Some('}') => break,
+                    Some(c) => return Err(unexpected(c)),
+                    None => return Err(end()),
+                }
+            }
+        }
+
+        Ok(Value::Map(Rc::new(RefCell::new(items))))
+    }
+
+    fn list(&mut self) -> Result<Value, InterpreterResult> {
+        let mut items = Vec::new();
+
+        self.chars.next();
+        self.whitespace();
+
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+        } else {
+            loop {
+                items.push(self.value()?);
+
+                self.whitespace();
+
+                match self.chars.next() {
+                    Some(',') => continue,
+                    Some(']') => break,
+                    Some(c) => return Err(unexpected(c)),
+                    None => return Err(end()),
+                }
+            }
+        }
+
+        Ok(Value::List(Rc::new(RefCell::new(items))))
+    }
+
+    fn string(&mut self) -> Result<String, InterpreterResult> {
+        let mut s = String::new();
+
+        self.chars.next();
+
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => s.push(self.unicode()?),
+                    Some(c) => return Err(unexpected(c)),
+                    None => return Err(end()),
+                },
+                Some(c) if (c as u32) < 0x20 => return Err(unexpected(c)),
+                Some(c) => s.push(c),
+                None => return Err(end()),
+            }
+        }
+    }
+
+    /// Read a `\u` escape, combining surrogate pairs into a single character.
+    fn unicode(&mut self) -> Result<char, InterpreterResult> {
+        let high = self.hex()?;
+
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                return Err(InterpreterResult::InvalidJson("unpaired surrogate in string".into()));
+            }
+
+            let low = self.hex()?;
+
+            if ! (0xDC00..0xE000).contains(&low) {
+                return Err(InterpreterResult::InvalidJson("unpaired surrogate in string".into()));
+            }
+
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| InterpreterResult::InvalidJson("unpaired surrogate in string".into()))
+    }
+
+    fn hex(&mut self) -> Result<u32, InterpreterResult> {
+        let mut code = 0;
+
+        for _ in 0..4 {
+            let c = self.chars.next().ok_or_else(end)?;
+            let digit = c.to_digit(16).ok_or_else(|| unexpected(c))?;
+
+            code = code * 16 + digit;
+        }
+
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, InterpreterResult> {
+        let mut number = String::new();
+
+        while let Some(c) = self.chars.peek() {
+            if ! (c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                break;
+            }
+
+            number.push(*c);
+            self.chars.next();
+        }
+
+        number.parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| InterpreterResult::InvalidJson(format!("invalid number {:?}", number)))
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, InterpreterResult> {
+        for expected in keyword.chars() {
+            self.expect(expected)?;
+        }
+
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), InterpreterResult> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(unexpected(c)),
+            None => Err(end()),
+        }
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some(' ' | '\n' | '\r' | '\t')) {
+            self.chars.next();
+        }
+    }
+}
+
+fn unexpected(c: char) -> InterpreterResult {
+    InterpreterResult::InvalidJson(format!("unexpected character {:?}", c))
+}
+
+fn end() -> InterpreterResult {
+    InterpreterResult::InvalidJson("unexpected end of input".into())
+}
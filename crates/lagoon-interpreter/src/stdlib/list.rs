@@ -7,8 +7,11 @@ use crate::interpreter::{Interpreter, InterpreterResult};
 pub struct ListObject;
 
 impl ListObject {
-    pub fn get(name: String) -> NativeMethodCallback {
-        match name.as_str() {
+    /// The name of every method, for tools such as the language server.
+    pub const METHODS: &[&str] = &["isEmpty", "isNotEmpty", "reverse", "join", "filter", "each", "map", "first"];
+
+    pub fn get(name: String) -> Option<NativeMethodCallback> {
+        Some(match name.as_str() {
             "isEmpty" => list_is_empty,
             "isNotEmpty" => list_is_not_empty,
             "reverse" => list_reverse,
@@ -17,46 +20,46 @@ impl ListObject {
             "each" => list_each,
             "map" => list_map,
             "first" => list_first,
-            _ => panic!("Undefined method: {}", name),
-        }
+            _ => return None,
+        })
     }
 }
 
 fn list_is_empty(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("List.isEmpty()", 0, &arguments);
+    super::arity("List.isEmpty()", 0, &arguments)?;
 
     Ok(Value::Bool(context.to_vec().borrow().is_empty()))
 }
 
 fn list_is_not_empty(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("List.isNotEmpty()", 0, &arguments);
+    super::arity("List.isNotEmpty()", 0, &arguments)?;
 
     Ok(Value::Bool(! context.to_vec().borrow().is_empty()))
 }
 
-fn list_reverse(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("List.reverse()", 0, &arguments);
+fn list_reverse(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("List.reverse()", 0, &arguments)?;
 
     let mut list = context.to_vec().borrow().clone();
     list.reverse();
 
-    Ok(Value::List(Rc::new(RefCell::new(list))))
+    interpreter.allocate(Value::List(Rc::new(RefCell::new(list))))
 }
 
-fn list_join(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("List.join()", 1, &arguments);
+fn list_join(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("List.join()", 1, &arguments)?;
 
     let list = context.to_vec().borrow().clone();
-    let separator = arguments.get(0).unwrap().clone().to_string();
+    let separator = arguments.first().unwrap().clone().to_string();
     let result = list.into_iter().map(|a| a.to_string()).collect::<Vec<String>>().join(&separator);
     
-    Ok(Value::String(result))
+    interpreter.allocate(Value::String(result))
 }
 
 fn list_filter(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("List.filter()", 1, &arguments);
+    super::arity("List.filter()", 1, &arguments)?;
 
-    let callback = arguments.get(0).unwrap().clone();
+    let callback = arguments.first().unwrap().clone();
     let mut new_list: Vec<Value> = Vec::new();
 
     for item in context.to_vec().borrow().clone().into_iter() {
@@ -65,13 +68,13 @@ fn list_filter(interpreter: &mut Interpreter, context: Value, arguments: Vec<Val
         }
     }
 
-    Ok(Value::List(Rc::new(RefCell::new(new_list))))
+    interpreter.allocate(Value::List(Rc::new(RefCell::new(new_list))))
 }
 
 fn list_each(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("List.each()", 1, &arguments);
+    super::arity("List.each()", 1, &arguments)?;
 
-    let callback = arguments.get(0).unwrap().clone();
+    let callback = arguments.first().unwrap().clone();
 
     for v in context.clone().to_vec().borrow().iter() {
         interpreter.call(callback.clone(), vec![v.clone()])?.to_bool();   
@@ -81,9 +84,9 @@ fn list_each(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value
 }
 
 fn list_map(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("List.map()", 1, &arguments);
+    super::arity("List.map()", 1, &arguments)?;
 
-    let callback = arguments.get(0).unwrap().clone();
+    let callback = arguments.first().unwrap().clone();
     let mut list = context.clone().to_vec().borrow().clone();
 
     for (i, v) in list.clone().iter().enumerate() {
@@ -92,10 +95,14 @@ fn list_map(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>
         list[i] = result;
     }
 
-    Ok(Value::List(Rc::new(RefCell::new(list))))
+    interpreter.allocate(Value::List(Rc::new(RefCell::new(list))))
 }
 
 fn list_first(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    if arguments.len() > 1 {
+        return Err(InterpreterResult::ArgumentCountMismatch("List.first()".into(), 1, arguments.len()));
+    }
+
     let list = context.clone().to_vec().borrow().clone();
 
     if list.is_empty() {
@@ -103,7 +110,7 @@ fn list_first(interpreter: &mut Interpreter, context: Value, arguments: Vec<Valu
     }
 
     if arguments.len() == 1 {
-        let callback = arguments.get(0).unwrap().clone();
+        let callback = arguments.first().unwrap().clone();
 
         for v in list.iter() {
             let result = interpreter.call(callback.clone(), vec![v.clone()])?;
@@ -0,0 +1,75 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::environment::{Value, NativeMethodCallback};
+use crate::interpreter::{Interpreter, InterpreterResult, map_key};
+
+pub struct MapObject;
+
+impl MapObject {
+    /// The name of every method, for tools such as the language server.
+    pub const METHODS: &[&str] = &["keys", "values", "has", "remove", "merge"];
+
+    pub fn get(name: String) -> Option<NativeMethodCallback> {
+        Some(match name.as_str() {
+            "keys" => map_keys,
+            "values" => map_values,
+            "has" => map_has,
+            "remove" => map_remove,
+            "merge" => map_merge,
+            _ => return None,
+        })
+    }
+}
+
+fn map_keys(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("Map.keys()", 0, &arguments)?;
+
+    let keys = context.to_map().borrow().keys().map(|k| Value::String(k.clone())).collect();
+
+    interpreter.allocate(Value::List(Rc::new(RefCell::new(keys))))
+}
+
+fn map_values(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("Map.values()", 0, &arguments)?;
+
+    let values = context.to_map().borrow().values().cloned().collect();
+
+    interpreter.allocate(Value::List(Rc::new(RefCell::new(values))))
+}
+
+fn map_has(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("Map.has()", 1, &arguments)?;
+
+    let key = map_key(arguments.first().unwrap().clone())?;
+
+    Ok(Value::Bool(context.to_map().borrow().contains_key(&key)))
+}
+
+fn map_remove(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("Map.remove()", 1, &arguments)?;
+
+    let key = map_key(arguments.first().unwrap().clone())?;
+
+    // Use `shift_remove` so that the remaining keys keep their insertion order.
+    Ok(context.to_map().borrow_mut().shift_remove(&key).unwrap_or(Value::Null))
+}
+
+/// Create a new map containing the entries of both maps. Keys in the argument
+/// take precedence over keys in the original map.
+fn map_merge(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("Map.merge()", 1, &arguments)?;
+
+    let mut map = context.to_map().borrow().clone();
+    let other = match arguments.first().unwrap().clone() {
+        Value::Map(other) => other,
+        Value::Constant(other) if matches!(*other, Value::Map(..)) => other.to_map(),
+        other => return Err(InterpreterResult::InvalidArgumentType("Map.merge()".into(), "map".into(), other.typestring())),
+    };
+
+    for (key, value) in other.borrow().iter() {
+        map.insert(key.clone(), value.clone());
+    }
+
+    interpreter.allocate(Value::Map(Rc::new(RefCell::new(map))))
+}
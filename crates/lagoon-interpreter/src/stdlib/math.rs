@@ -0,0 +1,44 @@
+use crate::environment::Value;
+use crate::interpreter::{Interpreter, InterpreterResult};
+
+fn number(name: &str, arguments: &[Value]) -> Result<f64, InterpreterResult> {
+    super::arity(name, 1, arguments)?;
+
+    match &arguments[0] {
+        Value::Number(n) => Ok(*n),
+        Value::Constant(v) if matches!(**v, Value::Number(..)) => Ok(v.clone().to_number()),
+        value => Err(InterpreterResult::InvalidArgumentType(name.into(), "number".into(), value.clone().typestring())),
+    }
+}
+
+pub fn abs(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    Ok(Value::Number(number("math.abs()", &arguments)?.abs()))
+}
+
+pub fn floor(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    Ok(Value::Number(number("math.floor()", &arguments)?.floor()))
+}
+
+pub fn ceil(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    Ok(Value::Number(number("math.ceil()", &arguments)?.ceil()))
+}
+
+pub fn round(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    Ok(Value::Number(number("math.round()", &arguments)?.round()))
+}
+
+pub fn sqrt(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    Ok(Value::Number(number("math.sqrt()", &arguments)?.sqrt()))
+}
+
+pub fn min(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("math.min()", 2, &arguments)?;
+
+    Ok(Value::Number(arguments[0].clone().to_number().min(arguments[1].clone().to_number())))
+}
+
+pub fn max(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("math.max()", 2, &arguments)?;
+
+    Ok(Value::Number(arguments[0].clone().to_number().max(arguments[1].clone().to_number())))
+}
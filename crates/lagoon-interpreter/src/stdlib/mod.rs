@@ -1,78 +1,99 @@
+use std::rc::Rc;
+
 use crate::interpreter::{Interpreter, InterpreterResult};
-use crate::environment::Value;
-use lagoon_parser::{generate, parse};
+use crate::environment::{Value, ErrorValue, NativeFunctionCallback};
 
 mod string;
 mod number;
 mod list;
+mod map;
+mod json;
+mod math;
 
 pub use string::StringObject;
 pub use number::NumberObject;
 pub use list::ListObject;
+pub use map::MapObject;
 
-pub fn arity(name: &str, arity: usize, arguments: &Vec<Value>) {
+pub fn arity(name: &str, arity: usize, arguments: &[Value]) -> Result<(), InterpreterResult> {
     if arity != arguments.len() {
-        panic!("Method {} expected {} arguments, received {}.", name, arity, arguments.len());
+        return Err(InterpreterResult::ArgumentCountMismatch(name.to_string(), arity, arguments.len()));
     }
+
+    Ok(())
 }
 
-pub fn println(_: &mut Interpreter, args: Vec<Value>) -> Value {
-    let arg = args.get(0).unwrap().clone();
+type Function = fn(&mut Interpreter, Vec<Value>) -> Result<Value, InterpreterResult>;
+
+/// The modules that are built into the interpreter. Scripts import them with a
+/// `std/` prefix, e.g. `import { parse } from "std/json"`.
+pub fn module(name: &str) -> Option<Vec<(&'static str, Value)>> {
+    let functions: &[(&str, Function)] = match name {
+        "json" => &[("stringify", json::stringify), ("parse", json::parse)],
+        "math" => &[
+            ("abs", math::abs), ("floor", math::floor), ("ceil", math::ceil), ("round", math::round),
+            ("sqrt", math::sqrt), ("min", math::min), ("max", math::max),
+        ],
+        _ => return None,
+    };
+
+    let mut exports = functions.iter().map(|(function, callback)| {
+        let callback: NativeFunctionCallback = Rc::new(*callback);
+
+        (*function, Value::NativeFunction { name: format!("{}.{}", name, function), callback })
+    }).collect::<Vec<_>>();
+
+    if name == "math" {
+        exports.push(("PI", Value::Constant(Box::new(Value::Number(std::f64::consts::PI)))));
+    }
+
+    Some(exports)
+}
+
+pub fn println(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterResult> {
+    arity("println()", 1, &args)?;
+
+    let arg = args.first().cloned().unwrap();
 
     println!("{}", arg.to_string());
 
-    Value::Null
+    Ok(Value::Null)
 }
 
-pub fn print(_: &mut Interpreter, args: Vec<Value>) -> Value {
-    let arg = args.get(0).unwrap().clone();
+pub fn print(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterResult> {
+    arity("print()", 1, &args)?;
+
+    let arg = args.first().cloned().unwrap();
 
     print!("{}", arg.to_string());
 
-    Value::Null
+    Ok(Value::Null)
 }
 
-pub fn r#type(_: &mut Interpreter, args: Vec<Value>) -> Value {
-    if args.is_empty() || args.len() > 1 {
-        panic!("Function {} expects {} argument, received {}", "type", 1, args.len());
-    }
+pub fn r#type(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterResult> {
+    arity("type()", 1, &args)?;
 
     let arg = args.first().unwrap();
 
-    Value::String(arg.clone().typestring())
+    Ok(Value::String(arg.clone().typestring()))
 }
 
-pub fn require(interpreter: &mut Interpreter, args: Vec<Value>) -> Value {
-    arity("require", 1, &args);
-    
-    let path = args.first().unwrap().clone().to_string();
-    let directory = interpreter.path().parent().unwrap().to_path_buf();
-
-    // Handle relative paths.
-    if path.starts_with(".") {
-        let mut module_path = directory.clone();
-        if path.ends_with(".lag") {
-            module_path.push(path);
-        } else {
-            module_path.push(path + ".lag");
-        }
-
-        let module_path = module_path.canonicalize().unwrap();
-        let contents = ::std::fs::read_to_string(&module_path).unwrap();
-
-        let tokens = generate(&contents);
-        let ast = parse(tokens).unwrap(); // TODO: Handle errors here.
-
-        let value = match interpreter.exec(ast) {
-            Ok(_) => Value::Null,
-            Err(e) => {
-                e.print();
-                std::process::exit(1);
-            },
-        };
-
-        return value;
+pub fn error(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterResult> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(InterpreterResult::ArgumentCountMismatch("error()".into(), if args.is_empty() { 1 } else { 2 }, args.len()));
     }
 
-    panic!("Cannot find module.")
-}
\ No newline at end of file
+    let message = args[0].clone().to_string();
+    let kind = args.get(1).cloned().map(|k| k.to_string()).unwrap_or_else(|| "Error".to_string());
+
+    Ok(Value::Error(Rc::new(ErrorValue { kind, message, span: None })))
+}
+
+/// Load a module, returning an object whose fields are the module's exports.
+pub fn require(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, InterpreterResult> {
+    arity("require()", 1, &args)?;
+
+    let path = args.first().unwrap().clone().to_string();
+
+    interpreter.load_module(&path)
+}
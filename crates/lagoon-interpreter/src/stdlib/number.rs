@@ -1,4 +1,4 @@
-use bigdecimal::{ToPrimitive};
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
 
 use crate::environment::{Value, NativeMethodCallback};
 use crate::interpreter::{Interpreter, InterpreterResult};
@@ -6,18 +6,21 @@ use crate::interpreter::{Interpreter, InterpreterResult};
 pub struct NumberObject;
 
 impl NumberObject {
-    pub fn get(name: String) -> NativeMethodCallback {
-        match name.as_str() {
+    /// The name of every method, for tools such as the language server.
+    pub const METHODS: &[&str] = &["isInteger", "isFloat", "toFixed"];
+
+    pub fn get(name: String) -> Option<NativeMethodCallback> {
+        Some(match name.as_str() {
             "isInteger" => number_is_integer,
             "isFloat" => number_is_float,
             "toFixed" => number_to_fixed,
-            _ => panic!("Undefined method: {}", name),
-        }
+            _ => return None,
+        })
     }
 }
 
 fn number_is_integer(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("Number.isInteger", 0, &arguments);
+    super::arity("Number.isInteger()", 0, &arguments)?;
     
     let number = context.to_number(); 
     
@@ -25,7 +28,7 @@ fn number_is_integer(_: &mut Interpreter, context: Value, arguments: Vec<Value>)
 }
 
 fn number_is_float(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("Number.isFloat", 0, &arguments);
+    super::arity("Number.isFloat()", 0, &arguments)?;
     
     let number = context.to_number(); 
     
@@ -33,14 +36,24 @@ fn number_is_float(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -
 }
 
 fn number_to_fixed(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    let number = context.to_bigdecimal();
-    let precision = if arguments.is_empty() { 0 } else { arguments.get(0).unwrap().clone().to_number() as i64 };
+    if arguments.len() > 1 {
+        return Err(InterpreterResult::ArgumentCountMismatch("Number.toFixed()".into(), 1, arguments.len()));
+    }
+
+    let precision = if arguments.is_empty() { 0 } else { arguments.first().unwrap().clone().to_number() as i64 };
+
+    // `NaN` and infinite numbers can't be represented as a `BigDecimal`, so
+    // there is nothing to round.
+    let number = match BigDecimal::from_f64(context.clone().to_number()) {
+        Some(number) => number,
+        None => return Ok(Value::Number(context.to_number())),
+    };
 
-    if precision == 0 {
-        return Ok(Value::Number(number.to_f64().unwrap().trunc()))
+    if precision <= 0 {
+        return Ok(Value::Number(number.to_f64().unwrap_or(0.0).trunc()))
     }
 
-    let rounded: f64 = format!("{:.1$}", number.round(precision), precision as usize).parse().unwrap();
+    let rounded: f64 = format!("{:.1$}", number.round(precision), precision as usize).parse().unwrap_or(0.0);
 
     Ok(Value::Number(rounded))
 }
\ No newline at end of file
@@ -4,8 +4,11 @@ use crate::interpreter::{Interpreter, InterpreterResult};
 pub struct StringObject;
 
 impl StringObject {
-    pub fn get(name: String) -> NativeMethodCallback {
-        match name.as_str() {
+    /// The name of every method, for tools such as the language server.
+    pub const METHODS: &[&str] = &["contains", "startsWith", "endsWith", "finish", "append", "tap", "toUpper", "toLower"];
+
+    pub fn get(name: String) -> Option<NativeMethodCallback> {
+        Some(match name.as_str() {
             "contains" => string_contains,
             "startsWith" => string_starts_with,
             "endsWith" => string_ends_with,
@@ -14,13 +17,13 @@ impl StringObject {
             "tap" => string_tap,
             "toUpper" => string_to_upper,
             "toLower" => string_to_lower,
-            _ => panic!("Undefined method: {}", name),
-        }
+            _ => return None,
+        })
     }
 }
 
 fn string_contains(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("String.contains", 1, &arguments);
+    super::arity("String.contains()", 1, &arguments)?;
 
     let string = context.to_string();
 
@@ -34,7 +37,7 @@ fn string_contains(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -
 }
 
 fn string_starts_with(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("String.startsWith", 1, &arguments);
+    super::arity("String.startsWith()", 1, &arguments)?;
 
     let string = context.to_string();
 
@@ -48,7 +51,7 @@ fn string_starts_with(_: &mut Interpreter, context: Value, arguments: Vec<Value>
 }
 
 fn string_ends_with(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("String.endsWith", 1, &arguments);
+    super::arity("String.endsWith()", 1, &arguments)?;
 
     let string = context.to_string();
 
@@ -61,8 +64,8 @@ fn string_ends_with(_: &mut Interpreter, context: Value, arguments: Vec<Value>)
     Ok(Value::Bool(false))
 }
 
-fn string_finish(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("String.finish", 1, &arguments);
+fn string_finish(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("String.finish()", 1, &arguments)?;
 
     let mut string = context.to_string();
     let append = arguments[0].clone().to_string();
@@ -71,45 +74,40 @@ fn string_finish(_: &mut Interpreter, context: Value, arguments: Vec<Value>) ->
         string.push_str(append.as_str());
     }
 
-    Ok(Value::String(string))
+    interpreter.allocate(Value::String(string))
 }
 
-fn string_append(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("String.append", 1, &arguments);
+fn string_append(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("String.append()", 1, &arguments)?;
 
     let mut string = context.to_string();
     let append = arguments[0].clone().to_string();
 
     string.push_str(append.as_str());
 
-    Ok(Value::String(string))
+    interpreter.allocate(Value::String(string))
 }
 
 fn string_tap(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("String.tap", 1, &arguments);
+    super::arity("String.tap()", 1, &arguments)?;
 
     let string = context.clone();
 
-    // TODO: Add some better error handling here. Maybe check that
-    // the argument being passed is actually a function.
-    let callback = match arguments.get(0) {
-        Some(f) => f.clone(),
-        _ => unreachable!()
-    };
+    let callback = arguments.first().unwrap().clone();
 
     interpreter.call(callback, vec![string])?;
 
     Ok(context)
 }
 
-fn string_to_upper(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("String.toUpper", 0, &arguments);
+fn string_to_upper(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("String.toUpper()", 0, &arguments)?;
 
-    Ok(Value::String(context.to_string().to_uppercase()))
+    interpreter.allocate(Value::String(context.to_string().to_uppercase()))
 }
 
-fn string_to_lower(_: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
-    super::arity("String.toLower", 0, &arguments);
+fn string_to_lower(interpreter: &mut Interpreter, context: Value, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+    super::arity("String.toLower()", 0, &arguments)?;
 
-    Ok(Value::String(context.to_string().to_lowercase()))
-}
\ No newline at end of file
+    interpreter.allocate(Value::String(context.to_string().to_lowercase()))
+}
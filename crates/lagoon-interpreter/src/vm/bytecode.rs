@@ -0,0 +1,138 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::ops::Range;
+use lagoon_parser::{Op, Parameter, Span};
+
+use crate::environment::{Environment, Value};
+
+/// A single bytecode instruction. Instructions operate on a stack of values and
+/// a fixed number of local slots that the compiler resolves ahead of time.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Null,
+    Pop,
+    /// Wrap the value on top of the stack in a `Value::Constant`.
+    Constant,
+
+    GetLocal(usize),
+    SetLocal(usize),
+    /// Give a local slot a new variable that hasn't been initialised yet.
+    /// Closures that captured the old variable keep a reference to it.
+    DeclareLocal(usize),
+    /// Pop a value into the current variable of a local slot.
+    InitLocal(usize),
+    GetUpvalue(usize),
+    SetUpvalue(usize),
+
+    /// Variables that can't be resolved at compile time, such as top-level `let`
+    /// declarations and global functions, are looked up by name.
+    GetName(String),
+    SetName(String),
+    DefineName(String),
+    DefineGlobal(String),
+    DefineStruct(String, Vec<Parameter>),
+    /// Load a module and push the given exports, in order.
+    Import(String, Vec<String>),
+
+    Closure(usize),
+    List(usize),
+    Map(usize),
+    Instantiate(Vec<String>),
+    Infix(Op),
+    Prefix(Op),
+    Index,
+    SetIndex,
+    Append,
+    Get(String),
+    SetField(String),
+    Call(usize),
+
+    Jump(usize),
+    JumpIfFalse(usize),
+    Iterate,
+    Next { value: usize, index: Option<usize>, exit: usize },
+    EndIterate,
+
+    Return,
+    /// Signals used when a `break` or `continue` can't be compiled to a jump,
+    /// e.g. when it needs to run a `finally` block on its way out of the loop.
+    Break,
+    Continue,
+    Throw,
+    Try(Box<TryBlock>),
+    /// Assign to something that can't be assigned to, e.g. `1 = 2`, which is
+    /// an error once the value has been evaluated.
+    InvalidAssignment,
+}
+
+/// The layout of a `try` statement. The `body`, `catch` and `finally` ranges
+/// follow the `Try` instruction and execution resumes at `end` afterwards.
+#[derive(Debug, Clone)]
+pub struct TryBlock {
+    pub body: Range<usize>,
+    pub catch: Option<(usize, Range<usize>)>,
+    pub finally: Option<Range<usize>>,
+    pub end: usize,
+    /// Where to jump when a `break` or `continue` leaves the `try` statement,
+    /// or `None` if the signal needs to keep travelling outwards.
+    pub on_break: Option<usize>,
+    pub on_continue: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>,
+    pub functions: Vec<Rc<Function>>,
+}
+
+impl Chunk {
+    pub fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        self.code.push(instruction);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+}
+
+/// Where a closure finds one of the variables it captures when it is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+    Local(usize),
+    Upvalue(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    /// The name of the variable stored in each local slot.
+    pub locals: Vec<String>,
+    pub captures: Vec<(String, Capture)>,
+    pub chunk: Chunk,
+}
+
+/// A variable that can be shared between a function and the closures it creates.
+/// It is empty until its declaration has run.
+pub type Variable = Rc<RefCell<Option<Value>>>;
+
+/// A compiled function along with the variables it captured. Captured variables
+/// are shared with the scope that created them, whilst names that aren't captured
+/// are looked up in the `environment` of the module the closure was created in.
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub name: String,
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Variable>,
+    pub environment: Rc<RefCell<Environment>>,
+}
@@ -0,0 +1,571 @@
+use std::rc::Rc;
+use lagoon_parser::*;
+
+use super::bytecode::*;
+
+/// Compile a program into the bytecode for its top-level function.
+pub fn compile(program: &[Statement]) -> Function {
+    let mut compiler = Compiler { states: vec![State::new("main", Vec::new(), true)] };
+
+    for statement in program {
+        compiler.statement(statement);
+    }
+
+    compiler.states.pop().unwrap().finish()
+}
+
+enum Target {
+    Local(usize),
+    Upvalue(usize),
+    Name,
+}
+
+struct Loop {
+    continue_target: usize,
+    breaks: Vec<usize>,
+    tries: usize,
+}
+
+/// The compiler state for a single function.
+struct State {
+    name: String,
+    params: Vec<Parameter>,
+    chunk: Chunk,
+    scopes: Vec<Vec<(String, usize)>>,
+    locals: Vec<String>,
+    captures: Vec<(String, Capture)>,
+    loops: Vec<Loop>,
+    tries: usize,
+}
+
+impl State {
+    fn new(name: &str, params: Vec<Parameter>, main: bool) -> Self {
+        let mut state = Self {
+            name: name.to_string(),
+            params: params.clone(),
+            chunk: Chunk::default(),
+            scopes: Vec::new(),
+            locals: Vec::new(),
+            captures: Vec::new(),
+            loops: Vec::new(),
+            tries: 0,
+        };
+
+        // The top-level scope of the script isn't a block, so its variables are
+        // stored by name. Functions always have a scope for their parameters.
+        if ! main {
+            state.scopes.push(Vec::new());
+
+            for param in params {
+                state.declare(param.name);
+            }
+        }
+
+        state
+    }
+
+    fn declare(&mut self, name: String) -> usize {
+        let slot = self.locals.len();
+
+        self.locals.push(name.clone());
+        self.scopes.last_mut().unwrap().push((name, slot));
+
+        slot
+    }
+
+    /// Find the slot of a variable declared in the current scope.
+    fn slot(&self, name: &str) -> Option<usize> {
+        self.scopes.last()?.iter().rev().find(|(n, _)| n == name).map(|(_, slot)| *slot)
+    }
+
+    fn capture(&mut self, name: &str, capture: Capture) -> usize {
+        if let Some(index) = self.captures.iter().position(|(_, c)| *c == capture) {
+            return index;
+        }
+
+        self.captures.push((name.to_string(), capture));
+        self.captures.len() - 1
+    }
+
+    fn finish(self) -> Function {
+        Function {
+            name: self.name,
+            params: self.params,
+            locals: self.locals,
+            captures: self.captures,
+            chunk: self.chunk,
+        }
+    }
+}
+
+struct Compiler {
+    states: Vec<State>,
+}
+
+impl Compiler {
+    fn state(&mut self) -> &mut State {
+        self.states.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        self.state().chunk.emit(instruction, span)
+    }
+
+    fn here(&mut self) -> usize {
+        self.state().chunk.len()
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.state().chunk.code[at] {
+            Instruction::Jump(t) | Instruction::JumpIfFalse(t) => *t = target,
+            Instruction::Next { exit, .. } => *exit = target,
+            Instruction::Try(block) => block.on_break = Some(target),
+            _ => unreachable!(),
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.state().scopes.pop();
+    }
+
+    /// Turn the binding that the resolver gave an identifier into a slot. The
+    /// compiler's scopes mirror the resolver's, so the binding's depth says how
+    /// many scopes to walk outwards, even across function boundaries.
+    fn resolve(&mut self, name: &str, binding: Binding) -> Target {
+        let mut depth = match binding {
+            Binding::Local { depth, .. } | Binding::Captured { depth, .. } => depth,
+            Binding::Global | Binding::Unresolved => return Target::Name,
+        };
+
+        for state in (0..self.states.len()).rev() {
+            let scopes = &self.states[state].scopes;
+
+            if depth >= scopes.len() {
+                depth -= scopes.len();
+                continue;
+            }
+
+            let slot = scopes[scopes.len() - 1 - depth].iter().rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, slot)| *slot)
+                .expect("Resolved variable is missing from its scope.");
+
+            return self.capture(state, slot, name);
+        }
+
+        Target::Name
+    }
+
+    /// Thread a variable from the function that declared it through to the
+    /// function that is currently being compiled.
+    fn capture(&mut self, state: usize, slot: usize, name: &str) -> Target {
+        if state == self.states.len() - 1 {
+            return Target::Local(slot);
+        }
+
+        let mut capture = Capture::Local(slot);
+
+        for state in &mut self.states[state + 1..] {
+            capture = Capture::Upvalue(state.capture(name, capture));
+        }
+
+        match capture {
+            Capture::Upvalue(index) => Target::Upvalue(index),
+            Capture::Local(..) => unreachable!(),
+        }
+    }
+
+    /// Give every variable declared directly inside of `block` a slot in the
+    /// current scope, so that closures can refer to variables declared after
+    /// them. Returns the slots that were added.
+    fn reserve(&mut self, block: &[Statement]) -> Vec<usize> {
+        let mut slots = Vec::new();
+
+        for statement in block {
+            let name = match &statement.kind {
+                StatementKind::LetDeclaration { name, .. } | StatementKind::ConstDeclaration { name, .. } => name,
+                _ => continue,
+            };
+
+            if self.state().slot(name).is_none() {
+                slots.push(self.state().declare(name.clone()));
+            }
+        }
+
+        slots
+    }
+
+    /// Compile the statements of a block into the current scope, giving the
+    /// variables it declares fresh storage every time the block runs.
+    fn statements(&mut self, block: &[Statement], span: Span) {
+        for slot in self.reserve(block) {
+            self.emit(Instruction::DeclareLocal(slot), span);
+        }
+
+        for statement in block {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &[Statement], span: Span) {
+        self.begin_scope();
+        self.statements(block, span);
+        self.end_scope();
+    }
+
+    /// Store the value on top of the stack in a variable.
+    fn define(&mut self, name: &str, span: Span) {
+        match self.state().slot(name) {
+            Some(slot) => self.emit(Instruction::InitLocal(slot), span),
+            None => self.emit(Instruction::DefineName(name.to_string()), span),
+        };
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let span = statement.span;
+
+        match &statement.kind {
+            StatementKind::LetDeclaration { name, initial } => {
+                match initial {
+                    Some(initial) => self.expression(initial),
+                    None => { self.emit(Instruction::Null, span); },
+                }
+
+                self.define(name, span);
+            },
+            StatementKind::ConstDeclaration { name, initial } => {
+                self.expression(initial);
+                self.emit(Instruction::Constant, span);
+                self.define(name, span);
+            },
+            StatementKind::FunctionDeclaration { name, params, body } => {
+                self.function(name, params, body, span);
+                self.emit(Instruction::DefineGlobal(name.clone()), span);
+            },
+            StatementKind::StructDeclaration { name, fields } => {
+                self.emit(Instruction::DefineStruct(name.clone(), fields.clone()), span);
+            },
+            StatementKind::Export { declaration } => self.statement(declaration),
+            StatementKind::Import { names, path } => {
+                let names = names.iter().map(|p| p.name.clone()).collect::<Vec<String>>();
+
+                self.emit(Instruction::Import(path.clone(), names.clone()), span);
+
+                // The exports are pushed in order, so the last one is on top.
+                for name in names.iter().rev() {
+                    self.define(name, span);
+                }
+            },
+            StatementKind::If { condition, then, otherwise } => {
+                self.expression(condition);
+
+                let jump_to_else = self.emit(Instruction::JumpIfFalse(0), span);
+
+                self.block(then, span);
+
+                let jump_to_end = self.emit(Instruction::Jump(0), span);
+                let otherwise_start = self.here();
+
+                self.patch(jump_to_else, otherwise_start);
+
+                match otherwise {
+                    Some(Else::If(statement)) => self.statement(statement),
+                    Some(Else::Block(block)) => self.block(block, span),
+                    None => (),
+                }
+
+                let end = self.here();
+
+                self.patch(jump_to_end, end);
+            },
+            StatementKind::While { condition, then } => {
+                let start = self.here();
+
+                self.expression(condition);
+
+                let exit = self.emit(Instruction::JumpIfFalse(0), span);
+
+                self.begin_loop(start);
+                self.block(then, span);
+                self.emit(Instruction::Jump(start), span);
+
+                let end = self.here();
+
+                self.patch(exit, end);
+                self.end_loop(end);
+            },
+            StatementKind::For { iterable, value, index, then } => {
+                self.expression(iterable);
+                self.emit(Instruction::Iterate, span);
+                self.begin_scope();
+
+                let value = self.state().declare(value.clone());
+                let index = index.clone().map(|index| self.state().declare(index));
+                let next = self.emit(Instruction::Next { value, index, exit: 0 }, span);
+
+                // The body shares the scope of the loop variables.
+                self.begin_loop(next);
+                self.statements(then, span);
+                self.emit(Instruction::Jump(next), span);
+
+                let end = self.emit(Instruction::EndIterate, span);
+
+                self.patch(next, end);
+                self.end_loop(end);
+                self.end_scope();
+            },
+            StatementKind::Break => {
+                let state = self.state();
+
+                match state.loops.last() {
+                    Some(l) if l.tries == state.tries => {
+                        let jump = state.chunk.emit(Instruction::Jump(0), span);
+
+                        state.loops.last_mut().unwrap().breaks.push(jump);
+                    },
+                    _ => { state.chunk.emit(Instruction::Break, span); },
+                }
+            },
+            StatementKind::Continue => {
+                let state = self.state();
+
+                match state.loops.last() {
+                    Some(l) if l.tries == state.tries => {
+                        let target = l.continue_target;
+
+                        state.chunk.emit(Instruction::Jump(target), span);
+                    },
+                    _ => { state.chunk.emit(Instruction::Continue, span); },
+                }
+            },
+            StatementKind::Return { value } => {
+                self.expression(value);
+                self.emit(Instruction::Return, span);
+            },
+            StatementKind::Throw { value } => {
+                self.expression(value);
+                self.emit(Instruction::Throw, span);
+            },
+            StatementKind::Try { then, catch, finally } => self.r#try(then, catch, finally, span),
+            StatementKind::Expression { expression } => {
+                self.expression(expression);
+                self.emit(Instruction::Pop, span);
+            },
+        }
+    }
+
+    fn begin_loop(&mut self, continue_target: usize) {
+        let tries = self.state().tries;
+
+        self.state().loops.push(Loop { continue_target, breaks: Vec::new(), tries });
+    }
+
+    fn end_loop(&mut self, end: usize) {
+        let l = self.state().loops.pop().unwrap();
+
+        for at in l.breaks {
+            self.patch(at, end);
+        }
+    }
+
+    fn r#try(&mut self, then: &[Statement], catch: &Option<Catch>, finally: &Option<Block>, span: Span) {
+        let state = self.state();
+
+        // A `break` or `continue` that leaves the `try` statement can jump
+        // straight to its target if the loop is in the same part of the code.
+        let on_continue = match state.loops.last() {
+            Some(l) if l.tries == state.tries => Some(l.continue_target),
+            _ => None,
+        };
+
+        let at = self.emit(Instruction::Try(Box::new(TryBlock {
+            body: 0..0,
+            catch: None,
+            finally: None,
+            end: 0,
+            on_break: None,
+            on_continue,
+        })), span);
+
+        if on_continue.is_some() {
+            self.state().loops.last_mut().unwrap().breaks.push(at);
+        }
+
+        self.state().tries += 1;
+
+        let body_start = self.here();
+
+        self.block(then, span);
+
+        let body = body_start..self.here();
+
+        let catch = catch.as_ref().map(|Catch { name, then }| {
+            let start = self.here();
+
+            self.begin_scope();
+
+            let slot = self.state().declare(name.name.clone());
+
+            self.block(then, span);
+            self.end_scope();
+
+            (slot, start..self.here())
+        });
+
+        let finally = finally.as_ref().map(|block| {
+            let start = self.here();
+
+            self.block(block, span);
+
+            start..self.here()
+        });
+
+        self.state().tries -= 1;
+
+        let end = self.here();
+
+        if let Instruction::Try(block) = &mut self.state().chunk.code[at] {
+            block.body = body;
+            block.catch = catch;
+            block.finally = finally;
+            block.end = end;
+        }
+    }
+
+    fn function(&mut self, name: &str, params: &[Parameter], body: &[Statement], span: Span) {
+        self.states.push(State::new(name, params.to_vec(), false));
+        self.reserve(body);
+
+        for statement in body {
+            self.statement(statement);
+        }
+
+        self.emit(Instruction::Null, span);
+        self.emit(Instruction::Return, span);
+
+        let function = self.states.pop().unwrap().finish();
+        let chunk = &mut self.state().chunk;
+
+        chunk.functions.push(Rc::new(function));
+
+        let index = chunk.functions.len() - 1;
+
+        self.emit(Instruction::Closure(index), span);
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        let span = expression.span;
+
+        match &expression.kind {
+            ExpressionKind::Number(n) => { self.emit(Instruction::Number(*n), span); },
+            ExpressionKind::String(s) => { self.emit(Instruction::String(s.clone()), span); },
+            ExpressionKind::Bool(b) => { self.emit(Instruction::Bool(*b), span); },
+            ExpressionKind::Null => { self.emit(Instruction::Null, span); },
+            ExpressionKind::Identifier(name, binding) => {
+                let instruction = match self.resolve(name, binding.get()) {
+                    Target::Local(slot) => Instruction::GetLocal(slot),
+                    Target::Upvalue(index) => Instruction::GetUpvalue(index),
+                    Target::Name => Instruction::GetName(name.clone()),
+                };
+
+                self.emit(instruction, span);
+            },
+            // The value is evaluated before the target, just like the tree-walker.
+            ExpressionKind::Assign(target, value) => {
+                self.expression(value);
+
+                match &target.kind {
+                    ExpressionKind::Index(instance, Some(index)) => {
+                        self.expression(instance);
+                        self.expression(index);
+                        self.emit(Instruction::SetIndex, span);
+                    },
+                    ExpressionKind::Index(instance, None) => {
+                        self.expression(instance);
+                        self.emit(Instruction::Append, span);
+                    },
+                    ExpressionKind::Get(instance, field) => {
+                        self.expression(instance);
+                        self.emit(Instruction::SetField(field.clone()), span);
+                    },
+                    ExpressionKind::Identifier(name, binding) => {
+                        let instruction = match self.resolve(name, binding.get()) {
+                            Target::Local(slot) => Instruction::SetLocal(slot),
+                            Target::Upvalue(index) => Instruction::SetUpvalue(index),
+                            Target::Name => Instruction::SetName(name.clone()),
+                        };
+
+                        self.emit(instruction, span);
+                    },
+                    _ => {
+                        self.emit(Instruction::InvalidAssignment, span);
+                    },
+                }
+            },
+            ExpressionKind::Infix(left, op, right) => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Instruction::Infix(op.clone()), span);
+            },
+            ExpressionKind::Prefix(op, right) => {
+                self.expression(right);
+                self.emit(Instruction::Prefix(op.clone()), span);
+            },
+            ExpressionKind::Call(callable, arguments) => {
+                self.expression(callable);
+
+                for argument in arguments {
+                    self.expression(argument);
+                }
+
+                self.emit(Instruction::Call(arguments.len()), span);
+            },
+            ExpressionKind::Struct(definition, fields) => {
+                self.expression(definition);
+
+                let mut names = Vec::new();
+
+                for (name, value) in fields {
+                    self.expression(value);
+                    names.push(name.clone());
+                }
+
+                self.emit(Instruction::Instantiate(names), span);
+            },
+            ExpressionKind::Closure(params, body) => self.function("Closure", params, body, span),
+            ExpressionKind::Get(instance, field) => {
+                self.expression(instance);
+                self.emit(Instruction::Get(field.clone()), span);
+            },
+            ExpressionKind::Index(instance, index) => {
+                self.expression(instance);
+
+                match index {
+                    Some(index) => self.expression(index),
+                    None => { self.emit(Instruction::Null, span); },
+                }
+
+                self.emit(Instruction::Index, span);
+            },
+            ExpressionKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+
+                self.emit(Instruction::List(items.len()), span);
+            },
+            ExpressionKind::Map(items) => {
+                for (key, value) in items {
+                    self.expression(key);
+                    self.expression(value);
+                }
+
+                self.emit(Instruction::Map(items.len()), span);
+            },
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::mem::size_of;
+use hashbrown::HashMap;
+use indexmap::IndexMap;
+use lagoon_parser::{Parameter, Span, Statement};
+
+use crate::environment::Value;
+use crate::interpreter::*;
+use super::bytecode::*;
+use super::compiler::compile;
+
+/// How a range of bytecode finished running. Anything other than `Done` is
+/// passed outwards until it reaches a `try` statement, loop or function that
+/// knows how to handle it.
+enum Signal {
+    Done,
+    Return(Value),
+    Break(Span),
+    Continue(Span),
+}
+
+/// The state of a single call to a compiled function.
+struct Registers {
+    closure: Rc<Closure>,
+    locals: Vec<Variable>,
+    stack: Vec<Value>,
+    iterators: Vec<(Vec<(Value, Value)>, usize)>,
+}
+
+impl Registers {
+    fn new(closure: Rc<Closure>) -> Self {
+        let locals = closure.function.locals.iter().map(|_| Variable::default()).collect();
+
+        Self { closure, locals, stack: Vec::new(), iterators: Vec::new() }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Stack underflow.")
+    }
+
+    fn peek(&self) -> Value {
+        self.stack.last().cloned().expect("Stack underflow.")
+    }
+
+    fn pop_many(&mut self, n: usize) -> Vec<Value> {
+        let at = self.stack.len() - n;
+
+        self.stack.split_off(at)
+    }
+}
+
+fn variable(value: Value) -> Variable {
+    Rc::new(RefCell::new(Some(value)))
+}
+
+fn read_variable(variable: &Variable, name: &str) -> Result<Value, InterpreterResult> {
+    variable.borrow().clone().ok_or_else(|| InterpreterResult::UndefinedVariable(name.to_string()))
+}
+
+/// Assigning to a variable is only allowed once it has been declared, and only
+/// if it doesn't hold a constant.
+fn assign_to_variable(variable: &Variable, name: &str, value: Value) -> Result<(), InterpreterResult> {
+    if let Value::Constant(_) = read_variable(variable, name)? {
+        return Err(InterpreterResult::CannotAssignValueToConstant);
+    }
+
+    *variable.borrow_mut() = Some(value);
+
+    Ok(())
+}
+
+impl Interpreter {
+    /// Compile a program and run it as the top-level script.
+    pub(crate) fn run_bytecode(&mut self, program: &[Statement]) -> Result<(), InterpreterResult> {
+        let function = Rc::new(compile(program));
+        let environment = Rc::clone(&self.environment);
+        let closure = Rc::new(Closure { name: function.name.clone(), function, upvalues: Vec::new(), environment });
+        let mut registers = Registers::new(closure);
+        let end = registers.closure.function.chunk.len();
+
+        match self.execute(&mut registers, 0, end)? {
+            Signal::Done => Ok(()),
+            Signal::Return(value) => Err(InterpreterResult::Return(value)),
+            Signal::Break(span) => Err(InterpreterResult::Break(span)),
+            Signal::Continue(span) => Err(InterpreterResult::Continue(span)),
+        }
+    }
+
+    pub(crate) fn run_closure(&mut self, closure: Rc<Closure>, context: Option<Value>, arguments: Vec<Value>) -> Result<Value, InterpreterResult> {
+        let params = &closure.function.params;
+        let has_this = params.first().map(|p| p.name == "this").unwrap_or(false);
+
+        if ! has_this && params.len() != arguments.len() {
+            return Err(InterpreterResult::TooFewArguments(closure.name.clone(), arguments.len(), params.len()));
+        }
+
+        let mut registers = Registers::new(Rc::clone(&closure));
+        let mut slots = 0..params.len();
+
+        if has_this {
+            registers.locals[slots.next().unwrap()] = variable(context.unwrap_or(Value::Null));
+        }
+
+        for (slot, value) in slots.zip(arguments) {
+            registers.locals[slot] = variable(value);
+        }
+
+        let end = closure.function.chunk.len();
+        let previous = std::mem::replace(&mut self.environment, Rc::clone(&closure.environment));
+        let result = self.execute(&mut registers, 0, end);
+
+        self.environment = previous;
+
+        match result? {
+            Signal::Done => Ok(Value::Null),
+            Signal::Return(value) => Ok(value),
+            // A `break` or `continue` should never escape the function body,
+            // even if the function is called inside of a loop.
+            Signal::Break(span) => Err(InterpreterResult::Located(Box::new(InterpreterResult::Break(span)), span)),
+            Signal::Continue(span) => Err(InterpreterResult::Located(Box::new(InterpreterResult::Continue(span)), span)),
+        }
+    }
+
+    /// Run the instructions in `start..end`, stopping early if a signal needs to
+    /// be handled further out.
+    fn execute(&mut self, registers: &mut Registers, start: usize, end: usize) -> Result<Signal, InterpreterResult> {
+        let closure = Rc::clone(&registers.closure);
+        let chunk = &closure.function.chunk;
+        let mut ip = start;
+
+        while ip < end {
+            let span = chunk.spans[ip];
+
+            if let Err(error) = self.tick() {
+                return Err(error.located(span));
+            }
+
+            match self.step(registers, &chunk.code[ip], span) {
+                Ok(Step::Next) => ip += 1,
+                Ok(Step::Jump(target)) => ip = target,
+                Ok(Step::Signal(signal)) => return Ok(signal),
+                Err(error) => return Err(error.located(span)),
+            }
+        }
+
+        Ok(Signal::Done)
+    }
+
+    fn step(&mut self, registers: &mut Registers, instruction: &Instruction, span: Span) -> Result<Step, InterpreterResult> {
+        match instruction {
+            Instruction::Number(n) => registers.stack.push(Value::Number(*n)),
+            Instruction::String(s) => registers.stack.push(Value::String(s.clone())),
+            Instruction::Bool(b) => registers.stack.push(Value::Bool(*b)),
+            Instruction::Null => registers.stack.push(Value::Null),
+            Instruction::Pop => { registers.pop(); },
+            Instruction::Constant => {
+                let value = registers.pop();
+
+                registers.stack.push(Value::Constant(Box::new(value)));
+            },
+            Instruction::GetLocal(slot) => {
+                let value = read_variable(&registers.locals[*slot], &registers.closure.function.locals[*slot])?;
+
+                registers.stack.push(value);
+            },
+            Instruction::SetLocal(slot) => {
+                assign_to_variable(&registers.locals[*slot], &registers.closure.function.locals[*slot], registers.peek())?;
+            },
+            Instruction::DeclareLocal(slot) => registers.locals[*slot] = Variable::default(),
+            Instruction::InitLocal(slot) => *registers.locals[*slot].borrow_mut() = Some(registers.pop()),
+            Instruction::GetUpvalue(index) => {
+                let value = read_variable(&registers.closure.upvalues[*index], &registers.closure.function.captures[*index].0)?;
+
+                registers.stack.push(value);
+            },
+            Instruction::SetUpvalue(index) => {
+                assign_to_variable(&registers.closure.upvalues[*index], &registers.closure.function.captures[*index].0, registers.peek())?;
+            },
+            Instruction::GetName(name) => registers.stack.push(self.lookup(name.clone())?),
+            Instruction::SetName(name) => {
+                if let Value::Constant(_) = self.lookup(name.clone())? {
+                    return Err(InterpreterResult::CannotAssignValueToConstant);
+                }
+
+                self.assign(name.clone(), registers.peek());
+            },
+            Instruction::DefineName(name) => self.env_mut().set(name.clone(), registers.pop()),
+            Instruction::DefineGlobal(name) => { self.globals.insert(name.clone(), registers.pop()); },
+            Instruction::DefineStruct(name, fields) => self.define_struct(name, fields),
+            Instruction::Import(path, names) => {
+                let module = self.load_module(path)?;
+
+                for name in names {
+                    registers.stack.push(get_property(module.clone(), name.clone())?);
+                }
+            },
+            Instruction::Closure(index) => {
+                let function = Rc::clone(&registers.closure.function.chunk.functions[*index]);
+                let upvalues = function.captures.iter().map(|(_, capture)| match capture {
+                    Capture::Local(slot) => Rc::clone(&registers.locals[*slot]),
+                    Capture::Upvalue(index) => Rc::clone(&registers.closure.upvalues[*index]),
+                }).collect();
+
+                let environment = Rc::clone(&self.environment);
+                let closure = Closure { name: function.name.clone(), function, upvalues, environment };
+
+                registers.stack.push(Value::Closure { closure: Rc::new(closure), context: None });
+            },
+            Instruction::List(n) => {
+                let items = registers.pop_many(*n);
+
+                registers.stack.push(self.allocate(Value::List(Rc::new(RefCell::new(items))))?);
+            },
+            Instruction::Map(n) => {
+                let items = registers.pop_many(n * 2);
+                let mut values = IndexMap::new();
+
+                for pair in items.chunks(2) {
+                    values.insert(map_key(pair[0].clone())?, pair[1].clone());
+                }
+
+                registers.stack.push(self.allocate(Value::Map(Rc::new(RefCell::new(values))))?);
+            },
+            Instruction::Instantiate(names) => {
+                let values = registers.pop_many(names.len());
+                let definition = registers.pop();
+
+                registers.stack.push(instantiate(definition, names.iter().cloned().zip(values).collect())?);
+            },
+            Instruction::Infix(op) => {
+                let right = registers.pop();
+                let left = registers.pop();
+
+                registers.stack.push(self.allocate(infix(left, op.clone(), right)?)?);
+            },
+            Instruction::Prefix(op) => {
+                let right = registers.pop();
+
+                registers.stack.push(prefix(op.clone(), right)?);
+            },
+            Instruction::Index => {
+                let index = registers.pop();
+                let instance = registers.pop();
+
+                registers.stack.push(get_index(instance, index)?);
+            },
+            Instruction::SetIndex => {
+                let index = registers.pop();
+                let instance = registers.pop();
+
+                assign_to_index(instance, Some(index), registers.peek())?;
+            },
+            Instruction::Append => {
+                let instance = registers.pop();
+
+                self.charge(size_of::<Value>())?;
+
+                assign_to_index(instance, None, registers.peek())?;
+            },
+            Instruction::Get(field) => {
+                let instance = registers.pop();
+
+                registers.stack.push(get_property(instance, field.clone())?);
+            },
+            Instruction::SetField(field) => {
+                let instance = registers.pop();
+
+                assign_to_field(instance, field.clone(), registers.peek())?;
+            },
+            Instruction::Call(n) => {
+                let arguments = registers.pop_many(*n);
+                let callable = registers.pop();
+
+                registers.stack.push(self.call_at(callable, arguments, span)?);
+            },
+            Instruction::Jump(target) => return Ok(Step::Jump(*target)),
+            Instruction::JumpIfFalse(target) => if ! registers.pop().to_bool() {
+                return Ok(Step::Jump(*target));
+            },
+            Instruction::Iterate => {
+                let items = iterate(registers.pop())?;
+
+                registers.iterators.push((items, 0));
+            },
+            Instruction::Next { value, index, exit } => {
+                let (items, position) = registers.iterators.last_mut().unwrap();
+
+                let (i, item) = match items.get(*position) {
+                    Some(pair) => pair.clone(),
+                    None => return Ok(Step::Jump(*exit)),
+                };
+
+                *position += 1;
+
+                // Each iteration gets fresh variables, so closures created inside
+                // of the loop body capture the value from that particular iteration.
+                registers.locals[*value] = variable(item);
+
+                if let Some(index) = index {
+                    registers.locals[*index] = variable(i);
+                }
+            },
+            Instruction::EndIterate => { registers.iterators.pop(); },
+            Instruction::Return => return Ok(Step::Signal(Signal::Return(registers.pop()))),
+            Instruction::Break => return Ok(Step::Signal(Signal::Break(span))),
+            Instruction::Continue => return Ok(Step::Signal(Signal::Continue(span))),
+            Instruction::Throw => return Err(InterpreterResult::Thrown(registers.pop())),
+            Instruction::Try(block) => return self.run_try(registers, block),
+            Instruction::InvalidAssignment => return Err(InterpreterResult::InvalidAssignmentTarget),
+        };
+
+        Ok(Step::Next)
+    }
+
+    fn run_try(&mut self, registers: &mut Registers, block: &TryBlock) -> Result<Step, InterpreterResult> {
+        let stack = registers.stack.len();
+        let iterators = registers.iterators.len();
+
+        let result = match (self.execute(registers, block.body.start, block.body.end), &block.catch) {
+            (Err(error), Some((slot, catch))) if error.is_catchable() => {
+                registers.stack.truncate(stack);
+                registers.iterators.truncate(iterators);
+                registers.locals[*slot] = variable(error.into_value());
+
+                self.execute(registers, catch.start, catch.end)
+            },
+            (result, _) => result,
+        };
+
+        registers.stack.truncate(stack);
+        registers.iterators.truncate(iterators);
+
+        // The `finally` block always runs, but an error or signal inside of it
+        // takes priority over whatever happened in the `try` or `catch` blocks.
+        let signal = match &block.finally {
+            Some(finally) => match self.execute(registers, finally.start, finally.end)? {
+                Signal::Done => result?,
+                signal => signal,
+            },
+            None => result?,
+        };
+
+        Ok(match signal {
+            Signal::Done => Step::Jump(block.end),
+            Signal::Break(_) if block.on_break.is_some() => Step::Jump(block.on_break.unwrap()),
+            Signal::Continue(_) if block.on_continue.is_some() => Step::Jump(block.on_continue.unwrap()),
+            signal => Step::Signal(signal),
+        })
+    }
+
+    fn define_struct(&mut self, name: &str, fields: &[Parameter]) {
+        self.globals.insert(name.to_string(), Value::Struct {
+            name: name.to_string(), fields: fields.to_vec(), methods: Rc::new(RefCell::new(HashMap::new())),
+        });
+
+        self.adopt_globals();
+    }
+}
+
+enum Step {
+    Next,
+    Jump(usize),
+    Signal(Signal),
+}
@@ -0,0 +1,9 @@
+//! A bytecode compiler and stack-based virtual machine. Programs are compiled to
+//! a `Function` per Lagoon function, with variables resolved to numbered slots
+//! ahead of time rather than looked up by name in an `Environment`.
+
+mod bytecode;
+mod compiler;
+mod machine;
+
+pub(crate) use bytecode::Closure;
@@ -0,0 +1,40 @@
+//! Helpers shared by the tests that load modules from disk.
+
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A temporary directory that is removed when it is dropped.
+pub struct Directory(PathBuf);
+
+impl Deref for Directory {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Directory {
+    fn drop(&mut self) {
+        remove_dir_all(&self.0).ok();
+    }
+}
+
+/// Create a temporary directory that is unique to the test binary, containing
+/// `files` at the given paths.
+pub fn project(name: &str, files: &[(&str, &str)]) -> Directory {
+    let root = std::env::temp_dir().join(format!("lagoon-{}-{}", name, std::process::id()));
+
+    remove_dir_all(&root).ok();
+    create_dir_all(&root).unwrap();
+
+    for (path, source) in files {
+        let path = root.join(path);
+
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, source).unwrap();
+    }
+
+    Directory(root.canonicalize().unwrap())
+}
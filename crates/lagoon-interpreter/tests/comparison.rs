@@ -0,0 +1,58 @@
+use std::cmp::Ordering;
+use lagoon_interpreter::*;
+use lagoon_parser::{generate, parse};
+
+fn eval(source: &str, engine: Engine) -> Result<String, InterpreterResult> {
+    let mut interpreter = Interpreter::from_source("struct Point { x, y }\nstruct Pair { x, y }", "main.lag").unwrap();
+
+    interpreter.set_engine(engine);
+    interpreter.run().unwrap();
+
+    interpreter.eval(parse(generate(source)).unwrap()).map(|value| format!("{:?}", value.unwrap()))
+}
+
+#[test]
+fn it_compares_values_for_equality() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let cases = [
+            ("let a\na == null", "true"),
+            ("0 == null", "false"),
+            ("1 == \"1\"", "true"),
+            ("1 === \"1\"", "false"),
+            ("true == 1", "true"),
+            ("\"abc\" == 0", "false"),
+            ("[1, [2]] == [1, [\"2\"]]", "true"),
+            ("[1, [2]] !== [1, [\"2\"]]", "true"),
+            ("{\"a\": 1, \"b\": 2} == {\"b\": 2, \"a\": 1}", "true"),
+            ("let p = Point { x: 1, y: 2 }\np == Point { x: 1, y: 2 }", "true"),
+            ("let p = Point { x: 1, y: 2 }\np == Pair { x: 1, y: 2 }", "false"),
+            ("let f = fn () {}\nf == fn () {}", "false"),
+            ("[1] in [[1], [2]]", "true"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(eval(source, engine).unwrap(), expected, "{}", source);
+        }
+    }
+}
+
+#[test]
+fn it_only_orders_numbers_and_strings() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        assert_eq!(eval("\"apple\" < \"banana\"", engine).unwrap(), "true");
+        assert_eq!(eval("\"ab\" >= \"abc\"", engine).unwrap(), "false");
+        assert_eq!(eval("0 / 0 < 1", engine).unwrap(), "false");
+
+        assert!(matches!(eval("1 < \"2\"", engine).unwrap_err().inner(), InterpreterResult::InvalidComparison(l, r) if l == "number" && r == "string"));
+        assert!(matches!(eval("null >= 1", engine).unwrap_err().inner(), InterpreterResult::InvalidComparison(..)));
+        assert!(matches!(eval("[1] > [0]", engine).unwrap_err().inner(), InterpreterResult::InvalidComparison(..)));
+    }
+}
+
+#[test]
+fn it_compares_values_from_rust() {
+    assert!(Value::Number(1.0).equals(&Value::String("1".into())));
+    assert!(! Value::Number(1.0).strictly_equals(&Value::String("1".into())));
+    assert_eq!(Value::String("b".into()).compare(&Value::String("a".into())).unwrap(), Some(Ordering::Greater));
+    assert!(Value::Bool(true).compare(&Value::Bool(false)).is_err());
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use lagoon_interpreter::*;
+
+#[derive(Debug, PartialEq, LagoonStruct)]
+struct Person {
+    name: String,
+    age: u8,
+    email: Option<String>,
+}
+
+#[derive(Debug, PartialEq, LagoonStruct)]
+struct Team {
+    name: String,
+    members: Vec<Person>,
+}
+
+fn ryan() -> Person {
+    Person { name: "Ryan".into(), age: 30, email: None }
+}
+
+#[test]
+fn it_can_convert_primitives() {
+    assert_eq!(i32::from_value((-5i32).into_value()).unwrap(), -5);
+    assert_eq!(usize::from_value(10usize.into_value()).unwrap(), 10);
+    assert_eq!(f32::from_value(1.5f32.into_value()).unwrap(), 1.5);
+    assert!(bool::from_value(true.into_value()).unwrap());
+
+    assert!(matches!(u8::from_value(Value::Number(256.0)), Err(InterpreterResult::TypeMismatch(..))));
+    assert!(matches!(i64::from_value(Value::Number(1.5)), Err(InterpreterResult::TypeMismatch(..))));
+    assert!(matches!(u32::from_value(Value::Number(-1.0)), Err(InterpreterResult::TypeMismatch(..))));
+
+    assert_eq!(i64::from_value(Value::Number(-(2f64.powi(63)))).unwrap(), i64::MIN);
+    assert!(matches!(i64::from_value(Value::Number(2f64.powi(63))), Err(InterpreterResult::TypeMismatch(..))));
+    assert!(matches!(u64::from_value(Value::Number(2f64.powi(64))), Err(InterpreterResult::TypeMismatch(..))));
+    assert_eq!(u64::from_value(Value::Number(2f64.powi(63))).unwrap(), 1 << 63);
+    assert_eq!(i8::from_value(Value::Number(127.0)).unwrap(), 127);
+    assert!(matches!(i8::from_value(Value::Number(128.0)), Err(InterpreterResult::TypeMismatch(..))));
+}
+
+#[test]
+fn it_can_convert_collections() {
+    let list = vec![1.0, 2.0, 3.0].into_value();
+
+    assert_eq!(Vec::<f64>::from_value(list.clone()).unwrap(), vec![1.0, 2.0, 3.0]);
+    assert!(matches!(Vec::<String>::from_value(list), Err(InterpreterResult::TypeMismatch(..))));
+
+    assert_eq!(Option::<f64>::from_value(Value::Null).unwrap(), None);
+    assert_eq!(Option::<f64>::from_value(Some(1.0).into_value()).unwrap(), Some(1.0));
+
+    let mut map = HashMap::new();
+    map.insert("one".to_string(), 1u32);
+    map.insert("two".to_string(), 2u32);
+
+    assert_eq!(HashMap::<String, u32>::from_value(map.clone().into_value()).unwrap(), map);
+
+    let tuple = (1.0, "two", true).into_value();
+
+    assert_eq!(<(f64, String, bool)>::from_value(tuple.clone()).unwrap(), (1.0, "two".to_string(), true));
+    assert!(matches!(<(f64, String)>::from_value(tuple), Err(InterpreterResult::TypeMismatch(..))));
+}
+
+#[test]
+fn it_can_derive_struct_conversions() {
+    let team = Team { name: "Lagoon".into(), members: vec![ryan()] };
+
+    assert_eq!(Team::from_value(team.into_value()).unwrap().members, vec![ryan()]);
+    assert!(matches!(Person::from_value(Value::Null), Err(InterpreterResult::TypeMismatch(..))));
+}
+
+#[test]
+fn it_can_pass_derived_structs_to_scripts() {
+    let mut interpreter = Interpreter::from_source(r#"
+        struct Person {
+            name,
+            age,
+            email
+        }
+
+        let name = person.name
+        let older = Person { name: person.name, age: person.age + 1, email: "ryan@test.com" }
+        let invalid = Person { name: person.name, age: "thirty", email: null }
+    "#, "main.lag").unwrap();
+
+    interpreter.set_global("person", ryan());
+    interpreter.run().unwrap();
+
+    assert_eq!(interpreter.get_global::<String>("name").unwrap(), "Ryan");
+    assert_eq!(interpreter.get_global::<Person>("older").unwrap(), Person { name: "Ryan".into(), age: 31, email: Some("ryan@test.com".into()) });
+    assert!(matches!(interpreter.get_global::<Person>("invalid"), Err(InterpreterResult::TypeMismatch(..))));
+}
+
+#[test]
+fn it_treats_derived_structs_as_instances_of_the_scripts_struct() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::from_source(r#"
+            struct Person {
+                name,
+                age,
+                email
+            }
+
+            Person.greet = fn (this) {
+                return "Hi, " + this.name
+            }
+
+            let greeting = person.greet()
+            let ryan = Person { name: "Ryan", age: 30, email: null }
+            let same = person == ryan
+            let found = person in [ryan]
+
+            fn greet(other) {
+                return other.greet()
+            }
+
+            fn equals(other) {
+                return other == ryan
+            }
+
+            fn created() {
+                return make().greet()
+            }
+        "#, "main.lag").unwrap();
+
+        interpreter.set_engine(engine);
+        interpreter.set_global("person", ryan());
+        interpreter.define_function("make", |_, _| Ok(ryan().into_value()));
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_global::<String>("greeting").unwrap(), "Hi, Ryan");
+        assert!(interpreter.get_global::<bool>("same").unwrap());
+        assert!(interpreter.get_global::<bool>("found").unwrap());
+
+        assert_eq!(interpreter.call_function::<String>("greet", (ryan(),)).unwrap(), "Hi, Ryan");
+        assert!(interpreter.call_function::<bool>("equals", (ryan(),)).unwrap());
+        assert!(! interpreter.call_function::<bool>("equals", (Person { age: 31, ..ryan() },)).unwrap());
+        assert_eq!(interpreter.call_function::<String>("created", ()).unwrap(), "Hi, Ryan");
+    }
+}
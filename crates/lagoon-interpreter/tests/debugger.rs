@@ -0,0 +1,95 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use lagoon_interpreter::*;
+use lagoon_parser::{generate, parse, Span, StatementKind};
+
+/// Records every statement and call, evaluating an expression and listing the
+/// innermost scope whenever it reaches `line`.
+struct Recorder {
+    events: Rc<RefCell<Vec<String>>>,
+    line: usize,
+    watch: &'static str,
+    stop: bool,
+}
+
+impl Debugger for Recorder {
+    fn statement(&mut self, interpreter: &mut Interpreter, span: Span) -> Result<(), InterpreterResult> {
+        self.events.borrow_mut().push(format!("{} {}", span.line, interpreter.frames().len()));
+
+        if span.line != self.line {
+            return Ok(());
+        }
+
+        if self.stop {
+            return Err(InterpreterResult::Stopped);
+        }
+
+        let expression = match parse(generate(self.watch)).unwrap().remove(0).kind {
+            StatementKind::Expression { expression } => expression,
+            _ => unreachable!(),
+        };
+
+        let value = interpreter.evaluate(expression).map(|value| format!("{:?}", value)).unwrap_or_else(|error| error.to_string());
+        let scope = interpreter.scopes()[0].iter().map(|(name, value)| format!("{}={:?}", name, value)).collect::<Vec<String>>().join(" ");
+
+        self.events.borrow_mut().push(format!("{} = {}", self.watch, value));
+        self.events.borrow_mut().push(format!("scope {}", scope));
+
+        Ok(())
+    }
+
+    fn call(&mut self, interpreter: &mut Interpreter, frame: &Frame) -> Result<(), InterpreterResult> {
+        self.events.borrow_mut().push(format!("call {} {}", frame.name, interpreter.frames().len()));
+
+        Ok(())
+    }
+}
+
+const SOURCE: &str = "fn double(n) {
+    let result = n * 2
+    return result
+}
+
+let a = double(2)
+let b = a + 1";
+
+fn debug(line: usize, watch: &'static str, stop: bool) -> (Interpreter, Result<(), InterpreterResult>, Vec<String>) {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::from_source(SOURCE, "main.lag").unwrap();
+
+    interpreter.set_engine(Engine::Bytecode);
+    interpreter.set_debugger(Recorder { events: Rc::clone(&events), line, watch, stop });
+
+    let result = interpreter.run();
+    let events = events.borrow().clone();
+
+    (interpreter, result, events)
+}
+
+#[test]
+fn it_calls_the_debugger_before_every_statement_and_call() {
+    let (interpreter, result, events) = debug(3, "result + n", false);
+
+    result.unwrap();
+
+    assert_eq!(events, vec![
+        "1 0", "6 0", "call double 1", "2 1", "3 1",
+        "result + n = 6", "scope n=2 result=4",
+        "7 0",
+    ]);
+
+    assert_eq!(interpreter.get_global::<f64>("b").unwrap(), 5.0);
+}
+
+#[test]
+fn it_can_evaluate_with_errors_and_stop_the_program() {
+    let (_, _, events) = debug(3, "missing", false);
+
+    assert!(events.contains(&"missing = Undefined variable: missing.".to_string()));
+
+    let (interpreter, result, events) = debug(2, "", true);
+
+    assert!(matches!(result.unwrap_err().inner(), InterpreterResult::Stopped));
+    assert_eq!(events, vec!["1 0", "6 0", "call double 1", "2 1"]);
+    assert!(interpreter.get_global::<f64>("b").is_err());
+}
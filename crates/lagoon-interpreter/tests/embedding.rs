@@ -0,0 +1,152 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use lagoon_interpreter::*;
+use lagoon_parser::{generate, parse};
+
+struct Counter {
+    count: f64,
+    step: f64,
+}
+
+impl HostObject for Counter {
+    fn type_name(&self) -> &str {
+        "Counter"
+    }
+
+    fn get(&self, field: &str) -> Option<Value> {
+        match field {
+            "count" => Some(self.count.into_value()),
+            "step" => Some(self.step.into_value()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, field: &str, value: Value) -> Result<(), InterpreterResult> {
+        match field {
+            "step" => self.step = f64::from_value(value)?,
+            _ => return Err(InterpreterResult::UndefinedField(self.type_name().into(), field.into())),
+        }
+
+        Ok(())
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        method == "increment"
+    }
+
+    fn call(&mut self, _: &mut Interpreter, method: &str, _: Vec<Value>) -> Result<Value, InterpreterResult> {
+        match method {
+            "increment" => {
+                self.count += self.step;
+                Ok(self.count.into_value())
+            },
+            _ => Err(InterpreterResult::UndefinedMethod(self.type_name().into(), method.into())),
+        }
+    }
+}
+
+#[test]
+fn it_can_register_closures_with_captured_state() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let captured = Rc::clone(&log);
+
+    let mut interpreter = Interpreter::from_source(r#"
+        log("hello")
+        log("world")
+    "#, "main.lag").unwrap();
+
+    interpreter.define_function("log", move |_, arguments| {
+        captured.borrow_mut().push(String::from_value(arguments[0].clone())?);
+        Ok(Value::Null)
+    });
+
+    interpreter.run().unwrap();
+
+    assert_eq!(*log.borrow(), vec!["hello".to_string(), "world".to_string()]);
+}
+
+#[test]
+fn it_can_set_and_get_globals() {
+    let mut interpreter = Interpreter::from_source(r#"
+        let greeting = "Hello, " + name
+    "#, "main.lag").unwrap();
+
+    interpreter.set_global("name", "Ryan");
+    interpreter.run().unwrap();
+
+    assert_eq!(interpreter.get_global::<String>("greeting").unwrap(), "Hello, Ryan");
+    assert!(matches!(interpreter.get_global::<f64>("greeting"), Err(InterpreterResult::TypeMismatch(..))));
+    assert!(matches!(interpreter.get_global::<Value>("missing"), Err(InterpreterResult::UndefinedVariable(..))));
+}
+
+#[test]
+fn it_can_call_script_functions() {
+    let mut interpreter = Interpreter::from_source(r#"
+        fn add(a, b) {
+            return a + b
+        }
+
+        fn apply(callback) {
+            return callback(2)
+        }
+    "#, "main.lag").unwrap();
+
+    interpreter.define_function("double", |_, arguments| {
+        Ok((f64::from_value(arguments[0].clone())? * 2.0).into_value())
+    });
+
+    interpreter.run().unwrap();
+
+    assert_eq!(interpreter.call_function::<f64>("add", (1.0, 2.0)).unwrap(), 3.0);
+    assert_eq!(interpreter.call_function::<String>("add", ("a", "b")).unwrap(), "ab");
+
+    let double = interpreter.get_global::<Value>("double").unwrap();
+
+    assert_eq!(interpreter.call_function::<f64>("apply", (double,)).unwrap(), 4.0);
+}
+
+#[test]
+fn it_can_expose_host_objects() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::from_source(r#"
+            counter.step = 5
+            counter.increment()
+            let count = counter.increment()
+            let kind = type(counter)
+
+            try {
+                counter.missing
+            } catch (e) {
+                println(e.message)
+            }
+        "#, "main.lag").unwrap();
+
+        interpreter.set_engine(engine);
+        interpreter.set_global("counter", Value::object(Counter { count: 0.0, step: 1.0 }));
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_global::<f64>("count").unwrap(), 10.0);
+        assert_eq!(interpreter.get_global::<String>("kind").unwrap(), "Counter");
+    }
+}
+
+#[test]
+fn it_can_evaluate_code_one_piece_at_a_time() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::from_source("", "main.lag").unwrap();
+
+        interpreter.set_engine(engine);
+
+        let mut eval = |source: &str| interpreter.eval(parse(generate(source)).unwrap()).unwrap().map(|value| format!("{:?}", value));
+
+        assert_eq!(eval("let a = 1"), None);
+        assert_eq!(eval("fn double(n) { return n * 2 }"), None);
+        assert_eq!(eval("let b = double(a) + 1\nb"), Some("3".to_string()));
+        assert_eq!(eval("a = a + b"), Some("4".to_string()));
+        assert_eq!(eval("[a, b]"), Some("[4, 3]".to_string()));
+
+        let names: Vec<String> = interpreter.variables().into_iter().map(|(name, _)| name).collect();
+
+        assert_eq!(names, vec!["a", "b", "double"]);
+    }
+}
@@ -0,0 +1,83 @@
+use lagoon_interpreter::*;
+use lagoon_parser::{generate, parse};
+
+/// Run `source` inside of a `try` block, returning the kind of the error that
+/// was caught.
+fn caught(source: &str, engine: Engine) -> String {
+    let mut interpreter = Interpreter::from_source("let a = 1\nlet list = [1]", "main.lag").unwrap();
+
+    interpreter.set_engine(engine);
+    interpreter.run().unwrap();
+
+    let source = format!("let kind = null\ntry {{\n{}\n}} catch (e) {{\nkind = e.kind\n}}\nkind", source);
+
+    format!("{:?}", interpreter.eval(parse(generate(&source)).unwrap()).unwrap().unwrap())
+}
+
+#[test]
+fn it_raises_catchable_errors_for_invalid_operations() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let cases = [
+            ("println(1 - \"a\")", "UnsupportedOperands"),
+            ("[1] + 1", "UnsupportedOperands"),
+            ("1 in 5", "UnsupportedOperands"),
+            ("-[1]", "UnsupportedOperand"),
+            ("true.foo", "InvalidPropertyAccess"),
+            ("null.foo", "InvalidPropertyAccess"),
+            ("1 = 2", "InvalidAssignmentTarget"),
+            ("a { x: 1 }", "NotAStruct"),
+            ("list[[0]]", "InvalidIndex"),
+            ("let x = list[]", "InvalidIndex"),
+            ("list[\"0\"] = 1", "InvalidIndex"),
+            ("list[5] = 1", "UndefinedIndex"),
+            ("a[0]", "NotIndexable"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(caught(source, engine), expected, "{}", source);
+        }
+    }
+}
+
+#[test]
+fn it_raises_catchable_errors_from_native_functions() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let cases = [
+            ("type()", "ArgumentCountMismatch"),
+            ("println()", "ArgumentCountMismatch"),
+            ("print(1, 2)", "ArgumentCountMismatch"),
+            ("\"a\".contains()", "ArgumentCountMismatch"),
+            ("list.first(1, 2)", "ArgumentCountMismatch"),
+            ("{\"a\": 1}.keys(1)", "ArgumentCountMismatch"),
+            ("\"a\".nope()", "UndefinedMethod"),
+            ("list.nope()", "UndefinedMethod"),
+            ("a.nope()", "UndefinedMethod"),
+            ("{\"a\": 1}.nope()", "UndefinedMethod"),
+            ("{\"a\": 1}.merge(1)", "InvalidArgumentType"),
+            ("require(\"std/math\").abs(\"a\")", "InvalidArgumentType"),
+            ("require(\"std/json\").parse(1)", "InvalidArgumentType"),
+            ("require(\"./missing\")", "ModuleNotFound"),
+            ("require(\"std/missing\")", "ModuleNotFound"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(caught(source, engine), expected, "{}", source);
+        }
+    }
+}
+
+#[test]
+fn it_reports_every_error_found_before_running() {
+    let mut interpreter = Interpreter::from_source("const a = 1\na = 2\nprintln(b)", "main.lag").unwrap();
+    let error = interpreter.run().unwrap_err();
+    let errors: Vec<(&str, usize)> = error.errors().iter().map(|e| (e.kind(), e.span().unwrap().line)).collect();
+
+    assert_eq!(errors, vec![("CannotAssignValueToConstant", 2), ("UndefinedVariable", 3)]);
+    assert_eq!(error.kind(), "CannotAssignValueToConstant");
+
+    // A single error is returned on its own.
+    let error = Interpreter::from_source("println(b)", "main.lag").unwrap().run().unwrap_err();
+
+    assert!(matches!(error.inner(), InterpreterResult::UndefinedVariable(..)));
+    assert_eq!(error.errors().len(), 1);
+}
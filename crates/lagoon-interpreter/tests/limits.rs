@@ -0,0 +1,93 @@
+use std::time::Duration;
+use lagoon_interpreter::*;
+
+fn run(source: &str, limits: Limits) -> Vec<InterpreterResult> {
+    [Engine::TreeWalker, Engine::Bytecode].iter().map(|engine| {
+        let mut interpreter = Interpreter::from_source(source, "main.lag").unwrap();
+
+        interpreter.set_engine(*engine);
+        interpreter.set_limits(limits);
+        interpreter.run().unwrap_err()
+    }).collect()
+}
+
+#[test]
+fn it_stops_when_it_runs_out_of_fuel() {
+    for error in run("while true {}", Limits::default().with_fuel(1000)) {
+        assert!(matches!(error.inner(), InterpreterResult::OutOfFuel(1000)));
+    }
+}
+
+#[test]
+fn it_limits_the_call_depth() {
+    let source = r#"
+        fn recurse(n) {
+            return recurse(n + 1)
+        }
+
+        try {
+            recurse(0)
+        } catch (e) {
+            println("caught")
+        }
+    "#;
+
+    for error in run(source, Limits::default().with_max_call_depth(16)) {
+        assert!(matches!(error.inner(), InterpreterResult::CallDepthExceeded(16)));
+        assert!(! error.is_catchable());
+    }
+}
+
+#[test]
+fn it_limits_the_call_depth_on_a_small_stack() {
+    // Tests already run on their own thread, but spawning one makes sure that
+    // it has the default stack size, whatever `RUST_MIN_STACK` says.
+    let errors = std::thread::spawn(|| {
+        run("fn f(n) {\n    return f(n + 1)\n}\nf(0)", Limits::default())
+            .into_iter()
+            .map(|error| error.inner().to_string())
+            .collect::<Vec<_>>()
+    }).join().unwrap();
+
+    for error in errors {
+        assert_eq!(error, InterpreterResult::CallDepthExceeded(DEFAULT_MAX_CALL_DEPTH).to_string());
+    }
+}
+
+#[test]
+fn it_limits_memory() {
+    let source = r#"
+        let s = "ab"
+
+        while true {
+            s = s + s
+        }
+    "#;
+
+    for error in run(source, Limits::default().with_max_memory(1024 * 1024)) {
+        assert!(matches!(error.inner(), InterpreterResult::MemoryLimitExceeded(..)));
+    }
+
+    for error in run("let items = []\nwhile true { items[] = 1 }", Limits::default().with_max_memory(1024)) {
+        assert!(matches!(error.inner(), InterpreterResult::MemoryLimitExceeded(..)));
+    }
+}
+
+#[test]
+fn it_times_out() {
+    for error in run("while true {}", Limits::default().with_timeout(Duration::from_millis(50))) {
+        assert!(matches!(error.inner(), InterpreterResult::Timeout(..)));
+    }
+}
+
+#[test]
+fn it_applies_the_timeout_to_calls_from_the_host() {
+    let mut interpreter = Interpreter::from_source("fn spin() { while true {} }", "main.lag").unwrap();
+
+    interpreter.set_limits(Limits::default().with_timeout(Duration::from_millis(50)));
+    interpreter.run().unwrap();
+
+    let error = interpreter.call_function::<()>("spin", ()).unwrap_err();
+
+    assert!(matches!(error.inner(), InterpreterResult::Timeout(..)));
+}
@@ -0,0 +1,180 @@
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use lagoon_interpreter::*;
+
+mod common;
+
+use common::project;
+
+fn run(root: &Path, source: &str, engine: Engine) -> (Interpreter, Result<(), InterpreterResult>) {
+    let mut interpreter = Interpreter::from_source(source, root.join("main.lag")).unwrap();
+
+    interpreter.set_engine(engine);
+
+    let result = interpreter.run();
+
+    (interpreter, result)
+}
+
+#[test]
+fn it_runs_each_module_once() {
+    let root = project("modules-cache", &[
+        ("counter.lag", "loaded()\nlet count = 0\nexport fn increment() { count = count + 1\nreturn count }"),
+    ]);
+
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let loads = Rc::new(Cell::new(0));
+        let mut interpreter = Interpreter::from_source(r#"
+            import { increment } from "./counter"
+
+            increment()
+
+            let count = require("./counter.lag").increment()
+        "#, root.join("main.lag")).unwrap();
+
+        let counter = Rc::clone(&loads);
+
+        interpreter.set_engine(engine);
+        interpreter.define_function("loaded", move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(Value::Null)
+        });
+
+        interpreter.run().unwrap();
+
+        assert_eq!(loads.get(), 1);
+        assert_eq!(interpreter.get_global::<f64>("count").unwrap(), 2.0);
+    }
+}
+
+#[test]
+fn it_detects_circular_imports() {
+    let root = project("modules-cycle", &[
+        ("a.lag", "import { b } from \"./b\"\nexport fn a() {}"),
+        ("b.lag", "import { a } from \"./a\"\nexport fn b() {}"),
+    ]);
+
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let (_, result) = run(&root, "import { a } from \"./a\"", engine);
+
+        match result.unwrap_err().inner() {
+            InterpreterResult::CircularImport(chain) => assert_eq!(chain, "a.lag -> b.lag -> a.lag"),
+            error => panic!("unexpected error: {:?}", error),
+        }
+    }
+}
+
+#[test]
+fn it_only_exposes_exported_declarations() {
+    let root = project("modules-exports", &[
+        ("lib.lag", "let hidden = 1\nfn helper() { return hidden }\nexport fn visible() { return helper() }"),
+    ]);
+
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let (interpreter, result) = run(&root, "import { visible } from \"./lib\"\nlet value = visible()", engine);
+
+        result.unwrap();
+
+        assert_eq!(interpreter.get_global::<f64>("value").unwrap(), 1.0);
+        assert!(interpreter.get_global::<f64>("hidden").is_err());
+
+        let (_, result) = run(&root, "import { hidden } from \"./lib\"", engine);
+
+        assert!(matches!(result.unwrap_err().inner(), InterpreterResult::UndefinedExport(module, name) if module == "lib" && name == "hidden"));
+    }
+}
+
+#[test]
+fn modules_cannot_see_the_variables_of_the_importer() {
+    let root = project("modules-scope", &[
+        ("peek.lag", "export fn peek() { return secret }"),
+    ]);
+
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let (_, result) = run(&root, "let secret = 1\nimport { peek } from \"./peek\"", engine);
+
+        assert!(matches!(result.unwrap_err().inner(), InterpreterResult::InvalidModule(..)));
+    }
+}
+
+#[test]
+fn it_finds_packages_in_project_directories_and_search_paths() {
+    let root = project("modules-packages", &[
+        ("lagoon_modules/greet/main.lag", "export fn greet(name) { return \"Hello, \" + name }"),
+        ("vendor/version.lag", "export const VERSION = \"1.0\""),
+        ("shared/loud.lag", "export fn shout(s) { return s.toUpper() }"),
+    ]);
+
+    let source = r#"
+        import { greet } from "greet"
+        import { VERSION } from "version"
+        import { shout } from "loud"
+
+        let message = shout(greet("Ryan")) + " " + VERSION
+    "#;
+
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::from_source(source, root.join("main.lag")).unwrap();
+
+        interpreter.set_engine(engine);
+        interpreter.set_search_paths(vec![PathBuf::from("shared")]);
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_global::<String>("message").unwrap(), "HELLO, RYAN 1.0");
+
+        let (_, result) = run(&root, "import { shout } from \"loud\"", engine);
+
+        assert!(matches!(result.unwrap_err().inner(), InterpreterResult::ModuleNotFound(path) if path == "loud"));
+    }
+}
+
+#[test]
+fn it_can_import_the_standard_library() {
+    let mut interpreter = Interpreter::from_source(r#"
+        import { parse, stringify } from "std/json"
+        import { sqrt } from "std/math"
+
+        let value = parse(text)
+        let json = stringify(value)
+        let root = sqrt(value["b"][0])
+
+        let kind = null
+
+        try {
+            parse("[1,")
+        } catch (e) {
+            kind = e.kind
+        }
+    "#, "main.lag").unwrap();
+
+    interpreter.set_global("text", r#" {"a": "é\n🌊", "b": [16, {"c": null}], "d": -1.5e2, "e": "🌊"} "#);
+    interpreter.run().unwrap();
+
+    assert_eq!(interpreter.get_global::<String>("json").unwrap(), r#"{"a":"é\n🌊","b":[16,{"c":null}],"d":-150,"e":"🌊"}"#);
+    assert_eq!(interpreter.get_global::<f64>("root").unwrap(), 4.0);
+    assert_eq!(interpreter.get_global::<String>("kind").unwrap(), "InvalidJson");
+}
+
+#[test]
+fn it_loads_packages_that_were_added_by_name() {
+    let root = project("modules-named", &[
+        ("deps/text/src/lib.lag", "export fn wrap(s) { return \"[\" + s + \"]\" }"),
+        ("deps/text/src/case.lag", "export fn upper(s) { return s.toUpper() }"),
+    ]);
+
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::from_source(r#"
+            import { wrap } from "text"
+            import { upper } from "text/case"
+
+            let value = wrap(upper("lagoon"))
+        "#, root.join("main.lag")).unwrap();
+
+        interpreter.set_engine(engine);
+        interpreter.add_package("text", root.join("deps/text/src/lib.lag"));
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_global::<String>("value").unwrap(), "[LAGOON]");
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+use lagoon_interpreter::*;
+
+mod common;
+
+use common::Directory;
+
+/// Create a project with a script in `app/` and a module in `lib/`.
+fn project(name: &str) -> Directory {
+    common::project(&format!("permissions-{}", name), &[
+        ("app/local.lag", "let local = 1"),
+        ("lib/shared.lag", "export const shared = 2"),
+    ])
+}
+
+fn interpreter(root: &Path, source: &str, permissions: Permissions) -> Interpreter {
+    let mut interpreter = Interpreter::from_source(source, root.join("app/main.lag")).unwrap();
+
+    interpreter.set_permissions(permissions);
+    interpreter
+}
+
+#[test]
+fn it_only_loads_modules_from_the_scripts_directory_by_default() {
+    let root = project("default");
+
+    let mut allowed = interpreter(&root, r#"require("./local")"#, Permissions::default());
+
+    assert!(allowed.run().is_ok());
+
+    let mut denied = interpreter(&root, r#"require("../lib/shared")"#, Permissions::default());
+
+    assert!(matches!(denied.run().unwrap_err().inner(), InterpreterResult::PermissionDenied(Capability::Modules, _)));
+}
+
+#[test]
+fn it_can_allow_modules_from_other_directories() {
+    let root = project("modules");
+    let mut permissions = Permissions::default();
+
+    permissions.modules.allow(PathBuf::from("../lib"));
+
+    let mut interpreter = interpreter(&root, r#"
+        let value = require("../lib/shared").shared
+    "#, permissions);
+
+    interpreter.run().unwrap();
+
+    assert_eq!(interpreter.get_global::<f64>("value").unwrap(), 2.0);
+}
+
+#[test]
+fn scripts_can_catch_denied_permissions() {
+    let root = project("catch");
+
+    let mut interpreter = interpreter(&root, r#"
+        let kind = null
+
+        try {
+            require("./local")
+        } catch (e) {
+            kind = e.kind
+        }
+    "#, Permissions::none());
+
+    interpreter.run().unwrap();
+
+    assert_eq!(interpreter.get_global::<String>("kind").unwrap(), "PermissionDenied");
+}
+
+#[test]
+fn it_checks_paths_after_resolving_them() {
+    let root = project("paths");
+    let mut permissions = Permissions::none();
+
+    permissions.read.allow(root.join("lib"));
+    permissions.write.allow(PathBuf::from("../lib"));
+
+    let interpreter = interpreter(&root, "", permissions);
+
+    assert!(interpreter.check_read(&root.join("lib/shared.lag")).is_ok());
+    assert!(interpreter.check_read(&root.join("lib/../app/local.lag")).is_err());
+    assert!(interpreter.check_read(&root.join("lib/missing/../../app/new.lag")).is_err());
+    assert!(interpreter.check_write(&root.join("lib/new.lag")).is_ok());
+    assert!(interpreter.check_write(Path::new("../app/new.lag")).is_err());
+}
+
+#[test]
+fn it_checks_environment_variables_and_programs() {
+    let root = project("env");
+    let mut permissions = Permissions::none();
+
+    permissions.env.allow("HOME".to_string());
+    permissions.run = Access::All;
+
+    let interpreter = interpreter(&root, "", permissions);
+
+    assert!(interpreter.check_env("HOME").is_ok());
+    assert!(matches!(interpreter.check_env("SECRET"), Err(InterpreterResult::PermissionDenied(Capability::Env, _))));
+    assert!(interpreter.check_run("git").is_ok());
+}
@@ -0,0 +1,102 @@
+use lagoon_interpreter::*;
+use lagoon_parser::{generate, parse};
+
+/// Run a program with both engines, returning the value of its last line.
+fn run(source: &str) -> Vec<String> {
+    [Engine::TreeWalker, Engine::Bytecode].iter().map(|engine| {
+        let mut interpreter = Interpreter::from_source("", "main.lag").unwrap();
+
+        interpreter.set_engine(*engine);
+
+        format!("{:?}", interpreter.eval(parse(generate(source)).unwrap()).unwrap().unwrap())
+    }).collect()
+}
+
+fn assert_runs(source: &str, expected: &str) {
+    assert_eq!(run(source), vec![expected, expected], "{}", source);
+}
+
+#[test]
+fn closures_capture_variables_by_reference() {
+    assert_runs("
+fn counter() {
+    let count = 0
+
+    return fn () {
+        count = count + 1
+        return count
+    }
+}
+
+let a = counter()
+let b = counter()
+a()
+a()
+b()
+
+let counts = [a(), b()]
+counts", "[3, 2]");
+
+    // Changes made after the closure was created are visible inside of it.
+    assert_runs("
+let name = \"before\"
+let get = fn () { return name }
+name = \"after\"
+get()", "after");
+}
+
+#[test]
+fn blocks_shadow_outer_variables() {
+    assert_runs("
+let a = 1
+let seen = []
+
+if true {
+    let a = 2
+    seen[] = a
+}
+
+for item in [3] {
+    let a = item
+    seen[] = a
+}
+
+seen[] = a
+seen", "[2, 3, 1]");
+
+    // Assigning without `let` updates the outer variable instead.
+    assert_runs("
+let total = 0
+
+for n in [1, 2, 3] {
+    total = total + n
+}
+
+total", "6");
+}
+
+#[test]
+fn loop_variables_do_not_leak_or_overwrite_outer_variables() {
+    assert_runs("
+let item = \"outer\"
+
+for item in [1, 2] {
+    item
+}
+
+item", "outer");
+}
+
+#[test]
+fn each_iteration_gets_its_own_variables() {
+    assert_runs("
+let callbacks = []
+
+for i in [1, 2, 3] {
+    let doubled = i * 2
+    callbacks[] = fn () { return [i, doubled] }
+}
+
+let results = [callbacks[0](), callbacks[2]()]
+results", "[[1, 2], [3, 6]]");
+}
@@ -0,0 +1,64 @@
+use lagoon_interpreter::*;
+
+const SOURCE: &str = "struct Greeter {
+    name
+}
+
+Greeter.greet = fn (this) {
+    return [1].map(fn (n) {
+        return fail(n)
+    })
+}
+
+fn fail(n) {
+    return n.missing
+}
+
+let greeter = Greeter { name: \"Ryan\" }
+
+greeter.greet()
+";
+
+#[test]
+fn it_records_every_frame_of_an_uncaught_error() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::from_source(SOURCE, "main.lag").unwrap();
+
+        interpreter.set_engine(engine);
+
+        let error = interpreter.run().unwrap_err();
+        let frames: Vec<(String, FrameKind, usize, usize)> = error.trace().iter()
+            .map(|frame| (frame.name.clone(), frame.kind, frame.span.line, frame.span.column))
+            .collect();
+
+        assert!(matches!(error.inner(), InterpreterResult::UndefinedMethod(..)));
+        assert_eq!((error.span().unwrap().line, error.span().unwrap().column), (12, 12));
+        assert_eq!(frames, vec![
+            ("fail".to_string(), FrameKind::Function, 7, 16),
+            ("Closure".to_string(), FrameKind::Closure, 6, 12),
+            ("map".to_string(), FrameKind::Native, 6, 12),
+            ("Greeter.greet".to_string(), FrameKind::Method, 17, 1),
+        ], "{:?}", engine);
+
+        assert_eq!(error.render_trace("main.lag"), [
+            "stack backtrace:",
+            "   0: fail",
+            "          at main.lag:7:16",
+            "   1: <closure>",
+            "          at main.lag:6:12",
+            "   2: map (native)",
+            "          at main.lag:6:12",
+            "   3: Greeter.greet",
+            "          at main.lag:17:1",
+            "",
+        ].join("\n"));
+    }
+}
+
+#[test]
+fn errors_outside_of_functions_have_no_trace() {
+    let mut interpreter = Interpreter::from_source("let a = []\na[1]", "main.lag").unwrap();
+    let error = interpreter.run().unwrap_err();
+
+    assert!(error.trace().is_empty());
+}
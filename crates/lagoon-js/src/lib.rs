@@ -1,8 +1,9 @@
+use std::ops::{Deref, DerefMut};
 use lagoon_parser::*;
 use thiserror::Error;
-use colored::*;
+use lagoon_parser::diagnostics::Diagnostic;
 
-const POLYFILL: &'static [u8] = include_bytes!("./polyfill.js");
+const POLYFILL: &[u8] = include_bytes!("./polyfill.js");
 
 #[derive(Error, Debug)]
 pub enum TranspilerError {
@@ -10,67 +11,196 @@ pub enum TranspilerError {
     FailedToWriteFile,
 
     #[error("Unable to transpile statement: {0:?}")]
-    NotImplementedStatement(Statement),
+    NotImplementedStatement(Box<Statement>),
 
     #[error("Unable to transpile expression: {0:?}")]
-    NotImplementedExpression(Expression),
+    NotImplementedExpression(Box<Expression>),
 
     #[error("Unable to transpile operator: {0:?}")]
     NotImplementedOperator(Op),
 }
 
 impl TranspilerError {
-    pub fn print(self) {
-        eprintln!("{}", format!("{}", self).red().bold());
+    pub fn code(&self) -> &'static str {
+        match self {
+            TranspilerError::FailedToWriteFile => "E0200",
+            TranspilerError::NotImplementedStatement(..) => "E0201",
+            TranspilerError::NotImplementedExpression(..) => "E0202",
+            TranspilerError::NotImplementedOperator(..) => "E0203",
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.to_string()).with_code(self.code());
+
+        match self {
+            TranspilerError::NotImplementedStatement(statement) => diagnostic
+                .with_label(statement.span, "not supported by the JavaScript target"),
+            TranspilerError::NotImplementedExpression(expression) => diagnostic
+                .with_label(expression.span, "not supported by the JavaScript target"),
+            _ => diagnostic,
+        }
+    }
+
+    pub fn print(&self, source: &str, path: &str) {
+        self.diagnostic().emit(source, path);
+    }
+}
+
+/// A transpiled program, along with the paths of the modules it imports (as
+/// they were written in the source).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transpiled {
+    pub code: String,
+    pub imports: Vec<String>,
+}
+
+/// The JavaScript generated so far. Modules loaded with `require()` can appear
+/// anywhere, so they are collected here and imported at the top of the file.
+#[derive(Default)]
+struct Output {
+    code: String,
+    imports: Vec<String>,
+    required: Vec<String>,
+}
+
+impl Output {
+    /// The name of the namespace that a required module is imported as.
+    fn require(&mut self, path: String) -> String {
+        let index = match self.required.iter().position(|p| *p == path) {
+            Some(index) => index,
+            None => {
+                self.import(path.clone());
+                self.required.push(path);
+                self.required.len() - 1
+            },
+        };
+
+        format!("__lagoon_module_{}", index)
+    }
+
+    fn import(&mut self, path: String) {
+        if ! self.imports.contains(&path) {
+            self.imports.push(path);
+        }
+    }
+}
+
+impl Deref for Output {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.code
+    }
+}
+
+impl DerefMut for Output {
+    fn deref_mut(&mut self) -> &mut String {
+        &mut self.code
     }
 }
 
 pub fn transpile(ast: Program) -> Result<String, TranspilerError> {
-    let mut js = String::new();
+    transpile_module(ast).map(|transpiled| transpiled.code)
+}
 
-    js.push_str(
-        std::str::from_utf8(POLYFILL).unwrap()
-    );
+/// Transpile a program to an ES module. Any module it imports needs to be
+/// transpiled separately, to the path given by `module_path()`.
+pub fn transpile_module(ast: Program) -> Result<Transpiled, TranspilerError> {
+    let mut js = Output::default();
 
     for statement in ast {
         transpile_statement(&mut js, statement)?;
     }
 
-    Ok(js)
+    let mut code = String::from(std::str::from_utf8(POLYFILL).unwrap());
+
+    code.push('\n');
+
+    for (i, path) in js.required.iter().enumerate() {
+        code.push_str(&format!("import * as __lagoon_module_{} from \"{}\";\n", i, module_path(path)));
+    }
+
+    code.push_str(&js.code);
+
+    Ok(Transpiled { code, imports: js.imports })
 }
 
-fn transpile_statement(js: &mut String, statement: Statement) -> Result<(), TranspilerError> {
-    match statement {
-        Statement::For { iterable, value, index, then } => {
-            js.push_str("__lagoon_for_in(");
-            transpile_expression(js, iterable)?;
-            js.push_str(", (");
-            js.push_str(&value);
+/// The path that the JavaScript for a Lagoon module is imported from, e.g.
+/// `./lib` becomes `./lib.mjs`.
+pub fn module_path(path: &str) -> String {
+    format!("{}.mjs", path.strip_suffix(".lag").unwrap_or(path))
+}
 
-            if index.is_some() {
-                js.push_str(", ");
-                js.push_str(&index.unwrap());
+fn transpile_statement(js: &mut Output, statement: Statement) -> Result<(), TranspilerError> {
+    match statement.kind {
+        StatementKind::For { iterable, value, index, then } => {
+            js.push_str("for (let ");
+
+            match index {
+                Some(index) => {
+                    js.push('[');
+                    js.push_str(&index);
+                    js.push_str(", ");
+                    js.push_str(&value);
+                    js.push_str("] of (");
+                    transpile_expression(js, iterable)?;
+                    js.push_str(").entries()");
+                },
+                None => {
+                    js.push_str(&value);
+                    js.push_str(" of __lagoon_values(");
+                    transpile_expression(js, iterable)?;
+                    js.push(')');
+                },
             }
 
-            js.push_str(") =>");
+            js.push(')');
+            transpile_block(js, then)?;
+        },
+        StatementKind::While { condition, then } => {
+            js.push_str("while (");
+            transpile_expression(js, condition)?;
+            js.push(')');
             transpile_block(js, then)?;
-            js.push_str(")");
         },
-        Statement::LetDeclaration { name, initial } => {
+        StatementKind::Break => js.push_str("break"),
+        StatementKind::Continue => js.push_str("continue"),
+        StatementKind::Throw { value } => {
+            js.push_str("throw ");
+            transpile_expression(js, value)?;
+        },
+        StatementKind::Try { then, catch, finally } => {
+            js.push_str("try ");
+            transpile_block(js, then)?;
+
+            if let Some(Catch { name, then }) = catch {
+                js.push_str(" catch (");
+                js.push_str(&name.name);
+                js.push_str(") ");
+                transpile_block(js, then)?;
+            }
+
+            if let Some(finally) = finally {
+                js.push_str(" finally ");
+                transpile_block(js, finally)?;
+            }
+        },
+        StatementKind::LetDeclaration { name, initial } => {
             js.push_str("let ");
             js.push_str(&name);
             js.push_str(" = ");
-            transpile_expression(js, initial.unwrap_or(Expression::Null))?;
-            js.push_str(";");
+            transpile_expression(js, initial.unwrap_or_else(|| ExpressionKind::Null.into()))?;
+            js.push(';');
         },
-        Statement::ConstDeclaration { name, initial } => {
+        StatementKind::ConstDeclaration { name, initial } => {
             js.push_str("const ");
             js.push_str(&name);
             js.push_str(" = ");
             transpile_expression(js, initial)?;
-            js.push_str(";");
+            js.push(';');
         },
-        Statement::StructDeclaration { name, fields } => {
+        StatementKind::StructDeclaration { name, fields } => {
             js.push_str("class ");
             js.push_str(&name);
             js.push_str(" extends __lagoon_struct {\n");
@@ -84,7 +214,7 @@ fn transpile_statement(js: &mut String, statement: Statement) -> Result<(), Tran
             
             js.push_str("\n}");
         },
-        Statement::FunctionDeclaration { name, params, body } => {
+        StatementKind::FunctionDeclaration { name, params, body } => {
             js.push_str("function ");
             js.push_str(&name);
             js.push('(');
@@ -92,25 +222,33 @@ fn transpile_statement(js: &mut String, statement: Statement) -> Result<(), Tran
             js.push(')');
             transpile_block(js, body)?;
         },
-        Statement::If { condition, then, otherwise } => {
-            js.push_str("if (");
-            transpile_expression(js, condition)?;
-            js.push_str(")");
-            transpile_block(js, then)?;
-            
-            if otherwise.is_some() {
-                js.push_str(" else ");
-                transpile_block(js, otherwise.unwrap())?;
-            }
+        StatementKind::If { condition, then, otherwise } => {
+            transpile_if(js, condition, then, otherwise)?;
         },
-        Statement::Return { value } => {
+        StatementKind::Return { value } => {
             js.push_str("return ");
             transpile_expression(js, value)?;
         },
-        Statement::Expression { expression } => {
+        StatementKind::Expression { expression } => {
             transpile_expression(js, expression)?
         },
-        _ => return Err(TranspilerError::NotImplementedStatement(statement)),
+        StatementKind::Export { declaration } => {
+            js.push_str("export ");
+
+            return transpile_statement(js, *declaration);
+        },
+        // Packages and the built-in modules only exist for the interpreter.
+        StatementKind::Import { names, path } if ! path.starts_with('.') => {
+            return Err(TranspilerError::NotImplementedStatement(Box::new(Statement::new(StatementKind::Import { names, path }, statement.span))));
+        },
+        StatementKind::Import { names, path } => {
+            js.push_str("import { ");
+            js.push_str(&names.into_iter().map(|p| p.name).collect::<Vec<String>>().join(", "));
+            js.push_str(" } from \"");
+            js.push_str(&module_path(&path));
+            js.push('"');
+            js.import(path);
+        },
     };
 
     js.push_str(";\n");
@@ -118,7 +256,32 @@ fn transpile_statement(js: &mut String, statement: Statement) -> Result<(), Tran
     Ok(())
 }
 
-fn struct_constructor(js: &mut String, method: &str, parameters: &[String]) -> Result<(), TranspilerError> {
+fn transpile_if(js: &mut Output, condition: Expression, then: Block, otherwise: Option<Else>) -> Result<(), TranspilerError> {
+    js.push_str("if (");
+    transpile_expression(js, condition)?;
+    js.push(')');
+    transpile_block(js, then)?;
+
+    match otherwise {
+        Some(Else::If(statement)) => {
+            js.push_str(" else ");
+
+            match statement.kind {
+                StatementKind::If { condition, then, otherwise } => transpile_if(js, condition, then, otherwise)?,
+                _ => return Err(TranspilerError::NotImplementedStatement(statement)),
+            }
+        },
+        Some(Else::Block(otherwise)) => {
+            js.push_str(" else ");
+            transpile_block(js, otherwise)?;
+        },
+        None => (),
+    }
+
+    Ok(())
+}
+
+fn struct_constructor(js: &mut Output, method: &str, parameters: &[String]) -> Result<(), TranspilerError> {
     if parameters.is_empty() {
         return Ok(())
     }
@@ -136,17 +299,17 @@ fn struct_constructor(js: &mut String, method: &str, parameters: &[String]) -> R
     for parameter in parameters {
         js.push_str("this.");
         js.push_str(parameter);
-        js.push_str("=");
+        js.push('=');
         js.push_str(parameter);
         js.push_str(";\n");
     }
 
-    js.push_str("}");
+    js.push('}');
 
     Ok(())
 }
 
-fn transpile_block(js: &mut String, block: Block) -> Result<(), TranspilerError> {
+fn transpile_block(js: &mut Output, block: Block) -> Result<(), TranspilerError> {
     js.push_str("{ \n");
     for statement in block {
         transpile_statement(js, statement)?;
@@ -155,25 +318,25 @@ fn transpile_block(js: &mut String, block: Block) -> Result<(), TranspilerError>
     Ok(())
 }
 
-fn transpile_expression(js: &mut String, expression: Expression) -> Result<(), TranspilerError> {
-    match expression {
-        Expression::String(s) => {
+fn transpile_expression(js: &mut Output, expression: Expression) -> Result<(), TranspilerError> {
+    match expression.kind {
+        ExpressionKind::String(s) => {
             js.push('"');
             js.push_str(&s);
             js.push('"');
         },
-        Expression::Number(n) => {
+        ExpressionKind::Number(n) => {
             js.push_str(&n.to_string());
         },
-        Expression::Bool(b) => {
+        ExpressionKind::Bool(b) => {
             js.push_str(if b { "true" } else { "false" });
         },
-        Expression::Null => js.push_str("null"),
-        Expression::Identifier(i) => {
+        ExpressionKind::Null => js.push_str("null"),
+        ExpressionKind::Identifier(i, _) => {
             js.push_str(&i)
         },
-        Expression::List(items) => {
-            js.push_str("[");
+        ExpressionKind::List(items) => {
+            js.push('[');
             for (i, item) in items.clone().into_iter().enumerate() {
                 transpile_expression(js, item)?;
 
@@ -181,9 +344,35 @@ fn transpile_expression(js: &mut String, expression: Expression) -> Result<(), T
                     js.push_str(", ");
                 }
             }
-            js.push_str("]");
+            js.push(']');
+        },
+        ExpressionKind::Map(items) => {
+            js.push_str("new Map([");
+            for (i, (key, value)) in items.clone().into_iter().enumerate() {
+                js.push('[');
+                transpile_expression(js, key)?;
+                js.push_str(", ");
+                transpile_expression(js, value)?;
+                js.push(']');
+
+                if i != items.len() - 1 {
+                    js.push_str(", ");
+                }
+            }
+            js.push_str("])");
         },
-        Expression::Call(identifier, arguments) => {
+        ExpressionKind::Call(identifier, arguments) if matches!(&identifier.kind, ExpressionKind::Identifier(name, _) if name == "require") => {
+            // Modules are imported statically, so the path has to be known.
+            match arguments.first().map(|argument| &argument.kind) {
+                Some(ExpressionKind::String(path)) if arguments.len() == 1 && path.starts_with('.') => {
+                    let alias = js.require(path.clone());
+
+                    js.push_str(&alias);
+                },
+                _ => return Err(TranspilerError::NotImplementedExpression(Box::new(Expression::new(ExpressionKind::Call(identifier, arguments), expression.span)))),
+            }
+        },
+        ExpressionKind::Call(identifier, arguments) => {
             transpile_expression(js, *identifier)?;
             js.push('(');
             for (i, argument) in arguments.iter().enumerate() {
@@ -195,7 +384,7 @@ fn transpile_expression(js: &mut String, expression: Expression) -> Result<(), T
             }
             js.push(')');
         },
-        Expression::Infix(left, op, right) => {
+        ExpressionKind::Infix(left, op, right) => {
             if is_native_op(&op) {
                 transpile_expression(js, *left)?;
                 js.push_str(op_to_string(op)?);
@@ -207,7 +396,7 @@ fn transpile_expression(js: &mut String, expression: Expression) -> Result<(), T
                         transpile_expression(js, *left)?;
                         js.push_str(", ");
                         transpile_expression(js, *right)?;
-                        js.push_str(")");
+                        js.push(')');
                     },
                     Op::NotIn => {
                         js.push_str("! ");
@@ -215,13 +404,44 @@ fn transpile_expression(js: &mut String, expression: Expression) -> Result<(), T
                         transpile_expression(js, *left)?;
                         js.push_str(", ");
                         transpile_expression(js, *right)?;
-                        js.push_str(")");
+                        js.push(')');
+                    },
+                    // Equality and ordering follow Lagoon's rules rather than
+                    // JavaScript's, so they are left to the polyfill.
+                    Op::Equals | Op::NotEquals | Op::StrictEquals | Op::StrictNotEquals => {
+                        if matches!(op, Op::NotEquals | Op::StrictNotEquals) {
+                            js.push_str("! ");
+                        }
+
+                        let strict = matches!(op, Op::StrictEquals | Op::StrictNotEquals);
+
+                        js.push_str("__lagoon_equals(");
+                        transpile_expression(js, *left)?;
+                        js.push_str(", ");
+                        transpile_expression(js, *right)?;
+                        js.push_str(if strict { ", true)" } else { ", false)" });
+                    },
+                    Op::LessThan | Op::LessThanOrEquals | Op::GreaterThan | Op::GreaterThanOrEquals => {
+                        let comparison = match op {
+                            Op::LessThan => "<",
+                            Op::LessThanOrEquals => "<=",
+                            Op::GreaterThan => ">",
+                            _ => ">=",
+                        };
+
+                        js.push_str("(__lagoon_compare(");
+                        transpile_expression(js, *left)?;
+                        js.push_str(", ");
+                        transpile_expression(js, *right)?;
+                        js.push_str(") ");
+                        js.push_str(comparison);
+                        js.push_str(" 0)");
                     },
                     _ => unreachable!(),
                 }
             }
         },
-        Expression::Struct(target, fields) => {
+        ExpressionKind::Struct(target, fields) => {
             js.push_str("new ");
             transpile_expression(js, *target)?;
             js.push_str("({\n");
@@ -235,30 +455,30 @@ fn transpile_expression(js: &mut String, expression: Expression) -> Result<(), T
 
             js.push_str("\n})");
         },
-        Expression::Closure(params, body) => {
-            js.push_str("(");
+        ExpressionKind::Closure(params, body) => {
+            js.push('(');
             js.push_str(&params.into_iter().map(|p| p.name).collect::<Vec<String>>().join(", "));
             js.push_str(") => ");
             transpile_block(js, body)?;
         },
-        Expression::Assign(target, value) => {
-            match *target {
-                Expression::Get(target, field) if matches!(*value, Expression::Closure(..)) => {
+        ExpressionKind::Assign(target, value) => {
+            match target.kind {
+                ExpressionKind::Get(target, field) if matches!(value.kind, ExpressionKind::Closure(..)) => {
                     js.push_str("__lagoon_register_method(");
                     
                     transpile_expression(js, *target)?;
                     js.push_str(", ");
                     
-                    transpile_expression(js, Expression::String(field))?;
+                    transpile_expression(js, ExpressionKind::String(field).into())?;
                     js.push_str(", ");
                     
-                    let instance = match *value {
-                        Expression::Closure(ref params, ..) => !params.is_empty() && params.first().unwrap().name == "this",
+                    let instance = match value.kind {
+                        ExpressionKind::Closure(ref params, ..) => !params.is_empty() && params.first().unwrap().name == "this",
                         _ => false,
                     };
 
-                    match *value {
-                        Expression::Closure(p, body) => {
+                    match value.kind {
+                        ExpressionKind::Closure(p, body) => {
                             let mut p = p;
                             if instance {
                                 p.remove(0);
@@ -266,7 +486,7 @@ fn transpile_expression(js: &mut String, expression: Expression) -> Result<(), T
                             
                             js.push_str("function (");
                             js.push_str(&p.into_iter().map(|p| p.name).collect::<Vec<String>>().join(", "));
-                            js.push_str(")");
+                            js.push(')');
 
                             transpile_block(js, body)?;
                         },
@@ -275,44 +495,61 @@ fn transpile_expression(js: &mut String, expression: Expression) -> Result<(), T
 
                     js.push_str(", ");
 
-                    transpile_expression(js, Expression::Bool(instance))?;
+                    transpile_expression(js, ExpressionKind::Bool(instance).into())?;
 
-                    js.push_str(")");
+                    js.push(')');
                 },
-                _ => {
+                ExpressionKind::Index(target, Some(index)) => {
+                    js.push_str("__lagoon_set(");
                     transpile_expression(js, *target)?;
+                    js.push_str(", ");
+                    transpile_expression(js, *index)?;
+                    js.push_str(", ");
+                    transpile_expression(js, *value)?;
+                    js.push(')');
+                },
+                kind => {
+                    transpile_expression(js, Expression::new(kind, target.span))?;
                     js.push_str(op_to_string(Op::Assign)?);
                     transpile_expression(js, *value)?;
                 }
             };
         },
-        Expression::Get(instance, field) => {
+        ExpressionKind::Get(instance, field) => {
             transpile_expression(js, *instance)?;
-            js.push_str(".");
+            js.push('.');
             js.push_str(&field);
         },
-        Expression::Index(target, index) => {
+        ExpressionKind::Index(target, Some(index)) => {
+            js.push_str("__lagoon_get(");
+            transpile_expression(js, *target)?;
+            js.push_str(", ");
+            transpile_expression(js, *index)?;
+            js.push(')');
+        },
+        ExpressionKind::Index(target, index) => {
             transpile_expression(js, *target.clone())?;
-            js.push_str("[");
-            if index.is_none() {
-                transpile_expression(js, *target)?;
-                js.push_str(".length");
-            } else {
-                transpile_expression(js, *index.unwrap())?;
+            js.push('[');
+            match index {
+                Some(index) => transpile_expression(js, *index)?,
+                None => {
+                    transpile_expression(js, *target)?;
+                    js.push_str(".length");
+                },
             }
-            js.push_str("]");
+            js.push(']');
         },
-        _ => return Err(TranspilerError::NotImplementedExpression(expression))
+        _ => return Err(TranspilerError::NotImplementedExpression(Box::new(expression)))
     };
 
     Ok(())
 }
 
 fn is_native_op(op: &Op) -> bool {
-    match op {
-        Op::In | Op::NotIn => false,
-        _ => true,
-    }
+    !matches!(op,
+        Op::In | Op::NotIn | Op::Equals | Op::NotEquals | Op::StrictEquals | Op::StrictNotEquals |
+        Op::LessThan | Op::LessThanOrEquals | Op::GreaterThan | Op::GreaterThanOrEquals
+    )
 }
 
 fn op_to_string(op: Op) -> Result<&'static str, TranspilerError> {
@@ -321,15 +558,9 @@ fn op_to_string(op: Op) -> Result<&'static str, TranspilerError> {
         Op::Subtract => "-",
         Op::Multiply => "*",
         Op::Divide => "/",
-        Op::LessThan => "<",
-        Op::LessThanOrEquals => "<=",
-        Op::GreaterThan => ">",
-        Op::GreaterThanOrEquals => ">=",
         Op::Modulo => "%",
         Op::Pow => "**",
         Op::Bang => "!",
-        Op::Equals => "==",
-        Op::NotEquals => "!=",
         Op::Assign => "=",
         Op::And => "&&",
         Op::Or => "||",
@@ -1,6 +1,6 @@
 function println(...args) {
     args.forEach(arg => {
-        console.log(arg);
+        console.log(arg instanceof Error ? `${arg.kind}: ${arg.message}` : arg);
     });
 };
 const print = println;
@@ -10,23 +10,110 @@ class __lagoon_struct {
     }
 }
 function type(value) {
+    if (value === null || value === undefined) return "null";
     if (Array.isArray(value)) return "list";
+    if (value instanceof Map) return "map";
+    if (value instanceof Error) return "error";
     if (value.structName !== undefined) return "struct";
     return {
         "boolean": "bool",
     }[typeof value] || typeof value;
 }
+function error(message, kind = "Error") {
+    const e = new Error(message)
+    e.name = kind
+    return e
+}
+Object.defineProperty(Error.prototype, "kind", {
+    configurable: true,
+    get() {
+        return this.name
+    }
+})
 function __lagoon_in(left, right) {
     if (typeof left === 'string' && typeof right === 'string') {
-        return left.includes(right)
+        return right.includes(left)
     }
     
     if (Array.isArray(right)) {
-        return right.includes(left)
+        return right.some(item => __lagoon_equals(item, left, false))
+    }
+
+    if (right instanceof Map) {
+        return right.has(left)
+    }
+}
+function __lagoon_number(value) {
+    if (typeof value === 'number') return value
+    if (typeof value === 'boolean') return value ? 1 : 0
+    if (typeof value === 'string' && /^[0-9+\-.eE]+$/.test(value.trim())) return Number(value.trim())
+    return NaN
+}
+function __lagoon_equals(left, right, strict) {
+    if (left === undefined) left = null
+    if (right === undefined) right = null
+    if (left === right) return true
+    if (left === null || right === null) return false
+
+    if (Array.isArray(left) && Array.isArray(right)) {
+        return left.length === right.length && left.every((item, i) => __lagoon_equals(item, right[i], strict))
+    }
+
+    if (left instanceof Map && right instanceof Map) {
+        return left.size === right.size && [...left].every(([key, value]) => right.has(key) && __lagoon_equals(value, right.get(key), strict))
+    }
+
+    if (left instanceof __lagoon_struct && right instanceof __lagoon_struct) {
+        return left.constructor === right.constructor && Object.keys(left).every(key => __lagoon_equals(left[key], right[key], strict))
+    }
+
+    if (strict || typeof left === typeof right || typeof left === 'object' || typeof right === 'object' || typeof left === 'function' || typeof right === 'function') {
+        return false
+    }
+
+    return __lagoon_number(left) === __lagoon_number(right)
+}
+function __lagoon_compare(left, right) {
+    if (typeof left === 'number' && typeof right === 'number') {
+        return left < right ? -1 : left > right ? 1 : left === right ? 0 : NaN
+    }
+
+    if (typeof left === 'string' && typeof right === 'string') {
+        const l = [...left], r = [...right]
+
+        for (let i = 0; i < l.length && i < r.length; i++) {
+            const difference = l[i].codePointAt(0) - r[i].codePointAt(0)
+
+            if (difference !== 0) return Math.sign(difference)
+        }
+
+        return Math.sign(l.length - r.length)
     }
+
+    throw error(`Cannot compare ${type(left)} with ${type(right)}.`, "InvalidComparison")
+}
+function __lagoon_get(target, key) {
+    if (target instanceof Map) {
+        if (! target.has(key)) {
+            throw error(`Undefined key: "${key}".`, "UndefinedKey")
+        }
+
+        return target.get(key)
+    }
+
+    return target[key]
 }
-function __lagoon_for_in(target, callback) {
-    target.forEach(callback)
+function __lagoon_set(target, key, value) {
+    if (target instanceof Map) {
+        target.set(key, value)
+    } else {
+        target[key] = value
+    }
+
+    return value
+}
+function __lagoon_values(iterable) {
+    return iterable instanceof Map ? iterable.values() : iterable
 }
 function __lagoon_register_method(target, name, callback, instance = false) {
     if (instance) {
@@ -49,6 +136,22 @@ const __lagoon_og_array_reverse = Array.prototype.reverse
 Array.prototype.reverse = function () {
     return __lagoon_og_array_reverse.call([...this])
 }
+const __lagoon_og_map_keys = Map.prototype.keys
+const __lagoon_og_map_values = Map.prototype.values
+Map.prototype.keys = function () {
+    return [...__lagoon_og_map_keys.call(this)]
+}
+Map.prototype.values = function () {
+    return [...__lagoon_og_map_values.call(this)]
+}
+Map.prototype.remove = function (key) {
+    const value = this.has(key) ? this.get(key) : null
+    this.delete(key)
+    return value
+}
+Map.prototype.merge = function (other) {
+    return new Map([...this, ...other])
+}
 String.prototype.contains = function (needle) {
     return this.includes(needle)
 }
@@ -1,13 +1,93 @@
+use std::cell::Cell;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use hashbrown::HashMap;
 
 use crate::token::Token;
+use crate::span::Span;
 
 pub type Program = Vec<Statement>;
 pub type Block = Vec<Statement>;
 pub type Identifier = String;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// A copy of the statement with every span reset, so that statements
+    /// parsed from differently formatted code can be compared.
+    pub fn without_spans(&self) -> Self {
+        let kind = match &self.kind {
+            StatementKind::Return { value } => StatementKind::Return { value: value.without_spans() },
+            StatementKind::FunctionDeclaration { name, params, body } => StatementKind::FunctionDeclaration {
+                name: name.clone(), params: parameters_without_spans(params), body: block_without_spans(body),
+            },
+            StatementKind::StructDeclaration { name, fields } => StatementKind::StructDeclaration {
+                name: name.clone(), fields: parameters_without_spans(fields),
+            },
+            StatementKind::LetDeclaration { name, initial } => StatementKind::LetDeclaration {
+                name: name.clone(), initial: initial.as_ref().map(Expression::without_spans),
+            },
+            StatementKind::ConstDeclaration { name, initial } => StatementKind::ConstDeclaration {
+                name: name.clone(), initial: initial.without_spans(),
+            },
+            StatementKind::If { condition, then, otherwise } => StatementKind::If {
+                condition: condition.without_spans(),
+                then: block_without_spans(then),
+                otherwise: otherwise.as_ref().map(|otherwise| match otherwise {
+                    Else::If(statement) => Else::If(Box::new(statement.without_spans())),
+                    Else::Block(block) => Else::Block(block_without_spans(block)),
+                }),
+            },
+            StatementKind::For { iterable, value, index, then } => StatementKind::For {
+                iterable: iterable.without_spans(), value: value.clone(), index: index.clone(), then: block_without_spans(then),
+            },
+            StatementKind::While { condition, then } => StatementKind::While {
+                condition: condition.without_spans(), then: block_without_spans(then),
+            },
+            StatementKind::Throw { value } => StatementKind::Throw { value: value.without_spans() },
+            StatementKind::Try { then, catch, finally } => StatementKind::Try {
+                then: block_without_spans(then),
+                catch: catch.as_ref().map(|catch| Catch { name: Parameter::new(catch.name.name.clone()), then: block_without_spans(&catch.then) }),
+                finally: finally.as_ref().map(|finally| block_without_spans(finally)),
+            },
+            StatementKind::Expression { expression } => StatementKind::Expression { expression: expression.without_spans() },
+            StatementKind::Export { declaration } => StatementKind::Export { declaration: Box::new(declaration.without_spans()) },
+            StatementKind::Import { names, path } => StatementKind::Import { names: parameters_without_spans(names), path: path.clone() },
+            kind => kind.clone(),
+        };
+
+        kind.into()
+    }
+}
+
+/// A copy of a program with every span reset, see `Statement::without_spans`.
+pub fn without_spans(program: &[Statement]) -> Program {
+    block_without_spans(program)
+}
+
+fn block_without_spans(block: &[Statement]) -> Block {
+    block.iter().map(Statement::without_spans).collect()
+}
+
+fn parameters_without_spans(parameters: &[Parameter]) -> Vec<Parameter> {
+    parameters.iter().map(|parameter| Parameter::new(parameter.name.clone())).collect()
+}
+
+impl From<StatementKind> for Statement {
+    fn from(kind: StatementKind) -> Self {
+        Self::new(kind, Span::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
     Return {
         value: Expression,
     },
@@ -31,7 +111,7 @@ pub enum Statement {
     If {
         condition: Expression,
         then: Block,
-        otherwise: Option<Block>
+        otherwise: Option<Else>,
     },
     For {
         iterable: Expression,
@@ -39,23 +119,86 @@ pub enum Statement {
         index: Option<Identifier>,
         then: Block,
     },
+    While {
+        condition: Expression,
+        then: Block,
+    },
+    Break,
+    Continue,
+    Throw {
+        value: Expression,
+    },
+    Try {
+        then: Block,
+        catch: Option<Catch>,
+        finally: Option<Block>,
+    },
     Expression {
         expression: Expression,
+    },
+    /// `export fn`, `export struct` or `export const`, which makes the
+    /// declaration available to other modules.
+    Export {
+        declaration: Box<Statement>,
+    },
+    /// `import { a, b } from "./path"`.
+    Import {
+        names: Vec<Parameter>,
+        path: String,
+    },
+}
+
+impl StatementKind {
+    /// The name of a function, struct, variable or constant declaration.
+    pub fn declared_name(&self) -> Option<&str> {
+        match self {
+            StatementKind::FunctionDeclaration { name, .. } | StatementKind::StructDeclaration { name, .. } |
+            StatementKind::LetDeclaration { name, .. } | StatementKind::ConstDeclaration { name, .. } => Some(name),
+            _ => None,
+        }
     }
 }
 
+/// The `else` branch of an `if` statement. An `else if` is stored as a nested
+/// `if` statement so that chains of conditions stay flat.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Else {
+    If(Box<Statement>),
+    Block(Block),
+}
+
+/// The `catch (name) { ... }` branch of a `try` statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catch {
+    pub name: Parameter,
+    pub then: Block,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parameter {
     pub name: String,
+    pub span: Span,
+}
+
+impl Parameter {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), span: Span::default() }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expression {
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionKind {
     Number(f64),
     String(String),
     Bool(bool),
     Null,
-    Identifier(Identifier),
+    Identifier(Identifier, Cell<Binding>),
     Assign(Box<Expression>, Box<Expression>),
     Infix(Box<Expression>, Op, Box<Expression>),
     Prefix(Op, Box<Expression>),
@@ -65,9 +208,14 @@ pub enum Expression {
     Get(Box<Expression>, Identifier),
     Index(Box<Expression>, Option<Box<Expression>>),
     List(Vec<Expression>),
+    Map(Vec<(Expression, Expression)>),
 }
 
 impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
     pub fn some(self) -> Option<Self> {
         Some(self)
     }
@@ -75,6 +223,58 @@ impl Expression {
     pub fn boxed(self) -> Box<Self> {
         Box::new(self)
     }
+
+    /// A copy of the expression with every span reset.
+    pub fn without_spans(&self) -> Self {
+        let boxed = |expression: &Expression| Box::new(expression.without_spans());
+
+        let kind = match &self.kind {
+            ExpressionKind::Assign(target, value) => ExpressionKind::Assign(boxed(target), boxed(value)),
+            ExpressionKind::Infix(left, op, right) => ExpressionKind::Infix(boxed(left), op.clone(), boxed(right)),
+            ExpressionKind::Prefix(op, right) => ExpressionKind::Prefix(op.clone(), boxed(right)),
+            ExpressionKind::Call(callable, arguments) => ExpressionKind::Call(boxed(callable), arguments.iter().map(Expression::without_spans).collect()),
+            ExpressionKind::Struct(definition, fields) => ExpressionKind::Struct(
+                boxed(definition),
+                fields.iter().map(|(name, value)| (name.clone(), value.without_spans())).collect(),
+            ),
+            ExpressionKind::Closure(params, body) => ExpressionKind::Closure(parameters_without_spans(params), block_without_spans(body)),
+            ExpressionKind::Get(target, field) => ExpressionKind::Get(boxed(target), field.clone()),
+            ExpressionKind::Index(target, index) => ExpressionKind::Index(boxed(target), index.as_deref().map(boxed)),
+            ExpressionKind::List(items) => ExpressionKind::List(items.iter().map(Expression::without_spans).collect()),
+            ExpressionKind::Map(items) => ExpressionKind::Map(items.iter().map(|(key, value)| (key.without_spans(), value.without_spans())).collect()),
+            kind => kind.clone(),
+        };
+
+        kind.into()
+    }
+}
+
+impl ExpressionKind {
+    pub fn identifier(name: impl Into<Identifier>) -> Self {
+        Self::Identifier(name.into(), Cell::default())
+    }
+}
+
+/// Where the value of an identifier lives. Identifiers are parsed as `Unresolved`
+/// and the interpreter's resolver fills in the rest before the program runs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Binding {
+    #[default]
+    Unresolved,
+    /// A variable declared in an enclosing block or function. `depth` is the
+    /// number of scopes to walk outwards and `slot` is the position of the
+    /// variable inside of that scope.
+    Local { depth: usize, slot: usize },
+    /// A local variable that belongs to an enclosing function.
+    Captured { depth: usize, slot: usize },
+    /// A top-level variable, function or struct, which is looked up by name.
+    Global,
+}
+
+impl From<ExpressionKind> for Expression {
+    fn from(kind: ExpressionKind) -> Self {
+        Self::new(kind, Span::default())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -87,6 +287,8 @@ pub enum Op {
     Bang,
     Equals,
     NotEquals,
+    StrictEquals,
+    StrictNotEquals,
     Assign,
     LessThan,
     GreaterThan,
@@ -110,6 +312,8 @@ impl Op {
             Token::Percent => Self::Modulo,
             Token::Equals => Self::Equals,
             Token::NotEquals => Self::NotEquals,
+            Token::StrictEquals => Self::StrictEquals,
+            Token::StrictNotEquals => Self::StrictNotEquals,
             Token::Assign => Self::Assign,
             Token::LessThan => Self::LessThan,
             Token::GreaterThan => Self::GreaterThan,
@@ -123,4 +327,31 @@ impl Op {
             _ => unreachable!("{:?}", token)
         }
     }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", match self {
+            Op::Add => "+",
+            Op::Subtract => "-",
+            Op::Multiply => "*",
+            Op::Divide => "/",
+            Op::Modulo => "%",
+            Op::Bang => "!",
+            Op::Equals => "==",
+            Op::NotEquals => "!=",
+            Op::StrictEquals => "===",
+            Op::StrictNotEquals => "!==",
+            Op::Assign => "=",
+            Op::LessThan => "<",
+            Op::GreaterThan => ">",
+            Op::LessThanOrEquals => "<=",
+            Op::GreaterThanOrEquals => ">=",
+            Op::And => "&&",
+            Op::Or => "||",
+            Op::Pow => "**",
+            Op::In => "in",
+            Op::NotIn => "not in",
+        })
+    }
 }
\ No newline at end of file
@@ -0,0 +1,283 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::IsTerminal;
+use colored::*;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// A structured report about a problem in a Lagoon script.
+///
+/// Diagnostics are built up with the `with_*` methods and turned into text with
+/// `Diagnostic::render`, which never writes to the terminal itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<String>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: true });
+        self
+    }
+
+    pub fn with_secondary_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// The span of the first primary label, if there is one.
+    pub fn span(&self) -> Option<Span> {
+        self.labels.iter().find(|l| l.primary).map(|l| l.span)
+    }
+
+    /// Render the diagnostic in the same style as `rustc`, quoting the lines of
+    /// `source` that the labels point at.
+    pub fn render(&self, source: &str, path: &str, colors: bool) -> String {
+        let paint = Painter { colors };
+        let mut out = String::new();
+
+        let header = match &self.code {
+            Some(code) => format!("{}[{}]", self.severity, code),
+            None => self.severity.to_string(),
+        };
+
+        out.push_str(&paint.severity(self.severity, &header));
+        out.push_str(&paint.bold(&format!(": {}", self.message)));
+        out.push('\n');
+
+        let mut labels: Vec<&Label> = self.labels.iter()
+            .filter(|l| l.span.end <= source.len() && l.span.line > 0)
+            .collect();
+
+        labels.sort_by_key(|l| (l.span.line, l.span.column));
+
+        let gutter_width = labels.iter().map(|l| l.span.line.to_string().len()).max().unwrap_or(1);
+        let gutter = " ".repeat(gutter_width);
+
+        match self.span().filter(|s| s.end <= source.len() && s.line > 0) {
+            Some(span) => out.push_str(&format!("{}{} {}:{}:{}\n", gutter, paint.blue("-->"), path, span.line, span.column)),
+            None => out.push_str(&format!("{}{} {}\n", gutter, paint.blue("-->"), path)),
+        }
+
+        if ! labels.is_empty() {
+            let lines: Vec<&str> = source.lines().collect();
+
+            out.push_str(&format!("{} {}\n", gutter, paint.blue("|")));
+
+            let mut previous_line: Option<usize> = None;
+
+            for label in labels {
+                let line = label.span.line;
+                let text = lines.get(line - 1).copied().unwrap_or("");
+
+                if previous_line != Some(line) {
+                    if let Some(previous) = previous_line {
+                        if line > previous + 1 {
+                            out.push_str(&format!("{}\n", paint.blue("...")));
+                        }
+                    }
+
+                    out.push_str(&format!("{} {} {}\n", paint.blue(&format!("{:>width$}", line, width = gutter_width)), paint.blue("|"), text));
+                }
+
+                let available = text.chars().count().saturating_sub(label.span.column - 1);
+                let length = source.get(label.span.range())
+                    .map(|s| s.lines().next().unwrap_or("").chars().count())
+                    .unwrap_or(1)
+                    .min(available)
+                    .max(1);
+
+                let marker = if label.primary { "^" } else { "-" }.repeat(length);
+                let mut underline = format!("{}{}", " ".repeat(label.span.column - 1), marker);
+
+                if ! label.message.is_empty() {
+                    underline.push(' ');
+                    underline.push_str(&label.message);
+                }
+
+                let underline = if label.primary { paint.severity(self.severity, &underline) } else { paint.blue(&underline) };
+
+                out.push_str(&format!("{} {} {}\n", gutter, paint.blue("|"), underline));
+
+                previous_line = Some(line);
+            }
+        }
+
+        if ! self.notes.is_empty() || ! self.help.is_empty() {
+            out.push_str(&format!("{} {}\n", gutter, paint.blue("|")));
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("{} {} {}\n", gutter, paint.blue("="), paint.bold(&format!("note: {}", note))));
+        }
+
+        for help in &self.help {
+            out.push_str(&format!("{} {} {}\n", gutter, paint.blue("="), paint.bold(&format!("help: {}", help))));
+        }
+
+        out
+    }
+
+    /// Render the diagnostic and write it to `stderr`, using colours when the
+    /// terminal supports them.
+    pub fn emit(&self, source: &str, path: &str) {
+        eprint!("{}", self.render(source, path, colors_enabled()));
+    }
+}
+
+/// Decide whether diagnostics written to `stderr` should be coloured.
+///
+/// `NO_COLOR` always disables colours and `CLICOLOR_FORCE` always enables them,
+/// otherwise colours are only used when `stderr` is a terminal.
+pub fn colors_enabled() -> bool {
+    if std::env::var_os("NO_COLOR").map(|v| ! v.is_empty()).unwrap_or(false) {
+        return false;
+    }
+
+    if std::env::var_os("CLICOLOR_FORCE").map(|v| v != "0").unwrap_or(false) {
+        return true;
+    }
+
+    std::io::stderr().is_terminal()
+}
+
+struct Painter {
+    colors: bool,
+}
+
+impl Painter {
+    fn severity(&self, severity: Severity, text: &str) -> String {
+        if ! self.colors {
+            return text.to_string();
+        }
+
+        match severity {
+            Severity::Error => text.red().bold().to_string(),
+            Severity::Warning => text.yellow().bold().to_string(),
+            Severity::Note => text.green().bold().to_string(),
+        }
+    }
+
+    fn blue(&self, text: &str) -> String {
+        if self.colors { text.blue().bold().to_string() } else { text.to_string() }
+    }
+
+    fn bold(&self, text: &str) -> String {
+        if self.colors { text.bold().to_string() } else { text.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_can_render_a_diagnostic_with_a_label() {
+        let source = "let name = \"Ryan\"\nprintln(nme)";
+        let diagnostic = Diagnostic::error("Undefined variable: nme.")
+            .with_code("E0101")
+            .with_label(Span::new(26, 29, 2, 9), "not found in this scope")
+            .with_help("did you mean `name`?");
+
+        assert_eq!(diagnostic.render(source, "main.lag", false), [
+            "error[E0101]: Undefined variable: nme.",
+            " --> main.lag:2:9",
+            "  |",
+            "2 | println(nme)",
+            "  |         ^^^ not found in this scope",
+            "  |",
+            "  = help: did you mean `name`?",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn it_can_render_primary_and_secondary_labels() {
+        let source = "const name = 1\n\nname = 2";
+        let diagnostic = Diagnostic::error("Cannot assign value to constant.")
+            .with_label(Span::new(16, 24, 3, 1), "")
+            .with_secondary_label(Span::new(6, 10, 1, 7), "declared here");
+
+        assert_eq!(diagnostic.render(source, "main.lag", false), [
+            "error: Cannot assign value to constant.",
+            " --> main.lag:3:1",
+            "  |",
+            "1 | const name = 1",
+            "  |       ---- declared here",
+            "...",
+            "3 | name = 2",
+            "  | ^^^^^^^^",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn it_skips_the_snippet_when_the_span_is_outside_of_the_source() {
+        let diagnostic = Diagnostic::error("Unexpected token Eof.")
+            .with_label(Span::new(100, 101, 9, 1), "");
+
+        assert_eq!(diagnostic.render("", "main.lag", false), "error: Unexpected token Eof.\n --> main.lag\n");
+    }
+}
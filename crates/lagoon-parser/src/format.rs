@@ -0,0 +1,488 @@
+//! Pretty-prints Lagoon source code.
+//!
+//! The formatter works on the token stream, comments included, so nothing the
+//! author wrote is lost: number literals keep their spelling and struct fields
+//! keep their order. Line breaks are kept where they were, apart from inside of
+//! literals and calls that span several lines, which always get one item per
+//! line. Everything
+//! else (indentation, spacing, blank lines and trailing commas) is decided here.
+//! The syntax tree is only used to tell the different kinds of braces apart.
+
+use std::collections::HashSet;
+
+use crate::ast::{Else, Expression, ExpressionKind, Statement, StatementKind};
+use crate::parser::{parse_recovering, ParseError};
+use crate::token::{generate, generate_with_comments, Token};
+
+const INDENT: &str = "    ";
+
+/// Format a Lagoon program. Code that doesn't parse is returned as a list of
+/// errors instead, since there's no telling what it was meant to look like.
+pub fn format(source: &str) -> Result<String, Vec<ParseError>> {
+    let (program, errors) = parse_recovering(generate(source));
+
+    if ! errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut layout = Layout::default();
+
+    program.iter().for_each(|statement| layout.statement(statement));
+
+    let pieces = layout.literals(pieces(source));
+
+    Ok(layout.print(&pieces))
+}
+
+/// A token and the text it was written as.
+#[derive(Debug, Clone)]
+struct Piece<'s> {
+    token: Token,
+    text: &'s str,
+    start: usize,
+    end: usize,
+    line: usize,
+    /// The line that the token finishes on, which is only different from
+    /// `line` for strings that span multiple lines.
+    end_line: usize,
+}
+
+fn pieces(source: &str) -> Vec<Piece<'_>> {
+    generate_with_comments(source).into_iter().map(|token| {
+        let text = &source[token.span.range()];
+        let text = match token.token {
+            Token::Comment(_) => text.trim_end(),
+            _ => text,
+        };
+
+        Piece {
+            token: token.token,
+            text,
+            start: token.span.start,
+            end: token.span.end,
+            line: token.span.line,
+            end_line: token.span.line + text.matches('\n').count(),
+        }
+    }).collect()
+}
+
+/// What the syntax tree says about the source code.
+#[derive(Default)]
+struct Layout {
+    /// Where every statement starts, which tells blocks apart from other braces
+    /// and lines that continue a statement from lines that start a new one.
+    statements: HashSet<usize>,
+    /// Where every list, map and struct literal and every call ends.
+    literals: HashSet<usize>,
+}
+
+/// A bracket that hasn't been closed yet.
+struct Group {
+    /// The indentation of the line that the bracket was opened on.
+    indent: usize,
+    /// Whether the bracket starts a block of statements.
+    block: bool,
+}
+
+impl Layout {
+    fn statement(&mut self, statement: &Statement) {
+        self.statements.insert(statement.span.start);
+
+        match &statement.kind {
+            StatementKind::Return { value } | StatementKind::Throw { value } => self.expression(value),
+            StatementKind::Expression { expression } => self.expression(expression),
+            StatementKind::ConstDeclaration { initial, .. } => self.expression(initial),
+            StatementKind::LetDeclaration { initial, .. } => initial.iter().for_each(|initial| self.expression(initial)),
+            StatementKind::FunctionDeclaration { body, .. } => self.block(body),
+            StatementKind::If { condition, then, otherwise } => {
+                self.expression(condition);
+                self.block(then);
+
+                match otherwise {
+                    Some(Else::If(statement)) => self.statement(statement),
+                    Some(Else::Block(block)) => self.block(block),
+                    None => (),
+                }
+            },
+            StatementKind::For { iterable, then, .. } => {
+                self.expression(iterable);
+                self.block(then);
+            },
+            StatementKind::While { condition, then } => {
+                self.expression(condition);
+                self.block(then);
+            },
+            StatementKind::Try { then, catch, finally } => {
+                self.block(then);
+
+                if let Some(catch) = catch {
+                    self.block(&catch.then);
+                }
+
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            },
+            StatementKind::Export { declaration } => self.statement(declaration),
+            StatementKind::StructDeclaration { .. } | StatementKind::Import { .. } |
+            StatementKind::Break | StatementKind::Continue => (),
+        }
+    }
+
+    fn block(&mut self, block: &[Statement]) {
+        block.iter().for_each(|statement| self.statement(statement));
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::List(items) => {
+                self.literals.insert(expression.span.end);
+                items.iter().for_each(|item| self.expression(item));
+            },
+            ExpressionKind::Map(items) => {
+                self.literals.insert(expression.span.end);
+
+                for (key, value) in items {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            },
+            ExpressionKind::Struct(target, fields) => {
+                self.literals.insert(expression.span.end);
+                self.expression(target);
+                fields.values().for_each(|field| self.expression(field));
+            },
+            ExpressionKind::Assign(left, right) | ExpressionKind::Infix(left, _, right) => {
+                self.expression(left);
+                self.expression(right);
+            },
+            ExpressionKind::Prefix(_, right) => self.expression(right),
+            ExpressionKind::Call(target, args) => {
+                self.literals.insert(expression.span.end);
+                self.expression(target);
+                args.iter().for_each(|arg| self.expression(arg));
+            },
+            ExpressionKind::Closure(_, body) => self.block(body),
+            ExpressionKind::Get(target, _) => self.expression(target),
+            ExpressionKind::Index(target, index) => {
+                self.expression(target);
+                index.iter().for_each(|index| self.expression(index));
+            },
+            ExpressionKind::Number(_) | ExpressionKind::String(_) | ExpressionKind::Bool(_) |
+            ExpressionKind::Null | ExpressionKind::Identifier(..) => (),
+        }
+    }
+
+    /// Put every item of a list, map or struct literal, or every argument of a
+    /// call, that is spread over several lines on a line of its own, with the
+    /// brackets on their own lines and a trailing comma. Line breaks inside of
+    /// an item don't count, so a closure can still be the last argument of a
+    /// call. Literals and calls that fit on one line lose their trailing comma
+    /// instead.
+    fn literals<'s>(&self, pieces: Vec<Piece<'s>>) -> Vec<Piece<'s>> {
+        let closers = closers(&pieces);
+        let mut insert = HashSet::new();
+        let mut remove = HashSet::new();
+        let mut breaks = HashSet::new();
+
+        for (open, close) in closers.iter().enumerate() {
+            let close = match close {
+                Some(close) if self.literals.contains(&pieces[*close].end) => *close,
+                _ => continue,
+            };
+
+            let last = match (open + 1..close).rev().find(|i| ! is_comment(&pieces[*i])) {
+                Some(last) => last,
+                None => continue,
+            };
+
+            // The pieces that belong to this literal rather than to one of its
+            // items, along with the brackets of the items.
+            let mut own = vec![open];
+            let mut i = open + 1;
+
+            while i <= close {
+                own.push(i);
+
+                i = match closers[i] {
+                    Some(nested) if i < close => {
+                        own.push(nested);
+                        nested + 1
+                    },
+                    _ => i + 1,
+                };
+            }
+
+            let multiline = own.windows(2).any(|pair| closers[pair[0]] != Some(pair[1]) && pieces[pair[1]].line > pieces[pair[0]].end_line);
+
+            match pieces[last].token {
+                Token::Comma if ! multiline => { remove.insert(last); },
+                Token::Comma => (),
+                _ if multiline => { insert.insert(last); },
+                _ => (),
+            }
+
+            if ! multiline {
+                continue;
+            }
+
+            // Break the line after the opening bracket, after every comma that
+            // separates two items and before the closing bracket. Comments stay
+            // on the line they were written on.
+            let mut after = vec![open, last];
+            let mut i = open + 1;
+
+            while i < close {
+                match closers[i] {
+                    Some(nested) => i = nested + 1,
+                    None => {
+                        if pieces[i].token == Token::Comma {
+                            after.push(i);
+                        }
+
+                        i += 1;
+                    },
+                }
+            }
+
+            for mut i in after {
+                while i + 1 < close && is_comment(&pieces[i + 1]) && pieces[i + 1].line == pieces[i].end_line {
+                    i += 1;
+                }
+
+                breaks.insert(i + 1);
+            }
+        }
+
+        let mut result = Vec::with_capacity(pieces.len() + insert.len());
+        let mut shift = 0;
+        let mut end_line = 0;
+
+        for (i, mut piece) in pieces.into_iter().enumerate() {
+            if breaks.contains(&i) && piece.line == end_line {
+                shift += 1;
+            }
+
+            end_line = piece.end_line;
+
+            if remove.contains(&i) {
+                continue;
+            }
+
+            piece.line += shift;
+            piece.end_line += shift;
+
+            let comma = Piece { token: Token::Comma, text: ",", start: piece.end, end: piece.end, line: piece.end_line, end_line: piece.end_line };
+
+            result.push(piece);
+
+            if insert.contains(&i) {
+                result.push(comma);
+            }
+        }
+
+        result
+    }
+
+    fn print(&self, pieces: &[Piece]) -> String {
+        let closers = closers(pieces);
+        let mut output = String::new();
+        let mut groups: Vec<Group> = Vec::new();
+        let mut indent = 0;
+        let mut prefix = false;
+
+        for (i, piece) in pieces.iter().enumerate() {
+            let previous = if i > 0 { Some(&pieces[i - 1]) } else { None };
+            let starts_line = match previous {
+                None => true,
+                // `else`, `catch` and `finally` always follow the closing brace.
+                Some(previous) if previous.token == Token::RightBrace && matches!(piece.token, Token::Else | Token::Catch | Token::Finally) => false,
+                Some(previous) => piece.line > previous.end_line,
+            };
+
+            if is_closer(&piece.token) {
+                indent = match groups.pop() {
+                    Some(group) if starts_line => group.indent,
+                    _ => indent,
+                };
+            } else if starts_line {
+                indent = match groups.last() {
+                    Some(group) if ! group.block => group.indent + 1,
+                    Some(group) => group.indent + 1 + self.continues(piece) as usize,
+                    None => self.continues(piece) as usize,
+                };
+            }
+
+            match previous {
+                Some(previous) if starts_line => {
+                    output.push('\n');
+
+                    if piece.line > previous.end_line + 1 && ! is_opener(&previous.token) && ! is_closer(&piece.token) {
+                        output.push('\n');
+                    }
+
+                    output.push_str(&INDENT.repeat(indent));
+                },
+                Some(previous) if spaced(previous, piece, prefix) => output.push(' '),
+                _ => (),
+            }
+
+            output.push_str(piece.text);
+
+            // A `!` keeps its space, as in `if ! done`, but a negative number
+            // doesn't.
+            prefix = piece.token == Token::Minus && (starts_line || ! previous.map(|previous| ends_value(&previous.token)).unwrap_or(false));
+
+            if is_opener(&piece.token) {
+                let block = piece.token == Token::LeftBrace && match closers[i] {
+                    Some(close) if self.literals.contains(&pieces[close].end) => false,
+                    Some(close) => pieces[i + 1..=close].iter()
+                        .find(|piece| ! is_comment(piece))
+                        .map(|next| next.start == pieces[close].start || self.statements.contains(&next.start))
+                        .unwrap_or(true),
+                    None => false,
+                };
+
+                groups.push(Group { indent, block });
+            }
+        }
+
+        if ! output.is_empty() {
+            output.push('\n');
+        }
+
+        output
+    }
+
+    /// Whether a line that starts with `piece` carries on from the statement
+    /// on the line before it, and so should be indented a little further.
+    fn continues(&self, piece: &Piece) -> bool {
+        ! is_comment(piece) && ! self.statements.contains(&piece.start) && ! matches!(piece.token, Token::Else | Token::Catch | Token::Finally)
+    }
+}
+
+/// The index of the matching closing bracket for every opening bracket.
+fn closers(pieces: &[Piece]) -> Vec<Option<usize>> {
+    let mut closers = vec![None; pieces.len()];
+    let mut open = Vec::new();
+
+    for (i, piece) in pieces.iter().enumerate() {
+        if is_opener(&piece.token) {
+            open.push(i);
+        } else if is_closer(&piece.token) {
+            if let Some(opener) = open.pop() {
+                closers[opener] = Some(i);
+            }
+        }
+    }
+
+    closers
+}
+
+/// Whether there should be a space between two tokens on the same line.
+/// `prefix` is set when `previous` is a `-` that negates what comes after it.
+fn spaced(previous: &Piece, next: &Piece, prefix: bool) -> bool {
+    match (&previous.token, &next.token) {
+        (_, Token::Comment(_)) => true,
+        (Token::LeftParen | Token::LeftBracket, _) => false,
+        (Token::LeftBrace, Token::RightBrace) => false,
+        (Token::LeftBrace, _) => true,
+        (_, Token::RightParen | Token::RightBracket | Token::Comma | Token::Colon | Token::Dot) => false,
+        (_, Token::RightBrace) => true,
+        (Token::Dot, _) => false,
+        // Two minus signs in a row would start a comment.
+        (Token::Minus, Token::Minus) => true,
+        _ if prefix => false,
+        (previous, Token::LeftParen | Token::LeftBracket) => ! ends_value(previous),
+        _ => true,
+    }
+}
+
+/// Whether a token can be the last token of an expression, which makes the
+/// bracket after it a call or an index and the `-` after it a subtraction.
+fn ends_value(token: &Token) -> bool {
+    matches!(token,
+        Token::Identifier(_) | Token::Number(_) | Token::String(_) | Token::True | Token::False | Token::Null |
+        Token::RightParen | Token::RightBracket | Token::RightBrace)
+}
+
+fn is_opener(token: &Token) -> bool {
+    matches!(token, Token::LeftParen | Token::LeftBracket | Token::LeftBrace)
+}
+
+fn is_closer(token: &Token) -> bool {
+    matches!(token, Token::RightParen | Token::RightBracket | Token::RightBrace)
+}
+
+fn is_comment(piece: &Piece) -> bool {
+    matches!(piece.token, Token::Comment(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_can_format_code() {
+        assert_eq!(
+            format("let   a=[1,2,3,]\nif a[0]==-1{\nprintln( a )\n}\n\n\n\nelse {\n-- Comment\n  return {\"a\":1}\n}").unwrap(),
+            "let a = [1, 2, 3]\nif a[0] == -1 {\n    println(a)\n} else {\n    -- Comment\n    return { \"a\": 1 }\n}\n"
+        );
+    }
+
+    #[test]
+    fn it_adds_trailing_commas_to_literals_over_several_lines() {
+        assert_eq!(
+            format("let p = Point {\nx: 1, -- The x\ny: [\n1,\n2]\n}\nfoo(1,\n2)").unwrap(),
+            "let p = Point {\n    x: 1, -- The x\n    y: [\n        1,\n        2,\n    ],\n}\nfoo(\n    1,\n    2,\n)\n"
+        );
+    }
+
+    #[test]
+    fn it_puts_every_item_of_a_literal_or_call_over_several_lines_on_its_own_line() {
+        let cases = [
+            ("let q = [1,\n2]", "let q = [\n    1,\n    2,\n]\n"),
+            ("let q = [\n1,\n2\n]", "let q = [\n    1,\n    2,\n]\n"),
+            ("let q = [1, 2,\n3]", "let q = [\n    1,\n    2,\n    3,\n]\n"),
+            ("let l = [1, -- one\n2]", "let l = [\n    1, -- one\n    2,\n]\n"),
+            ("let l = [ -- items\n1, 2 -- two\n]", "let l = [ -- items\n    1,\n    2, -- two\n]\n"),
+            ("let m = {\"a\": [1, 2],\n\"b\": 2}", "let m = {\n    \"a\": [1, 2],\n    \"b\": 2,\n}\n"),
+            ("foo(1,\n2)", "foo(\n    1,\n    2,\n)\n"),
+            ("foo(\n1, bar(2,\n3)\n)", "foo(\n    1,\n    bar(\n        2,\n        3,\n    ),\n)\n"),
+            ("foo(1, 2,)", "foo(1, 2)\n"),
+            ("items.map(fn (x) {\nreturn x\n})", "items.map(fn (x) {\n    return x\n})\n"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(format(source).unwrap(), expected, "{}", source);
+            assert_eq!(format(expected).unwrap(), expected, "{}", expected);
+        }
+    }
+
+    #[test]
+    fn it_keeps_branches_after_a_comment_at_the_same_indentation() {
+        let cases = [
+            (
+                "if a {\nprintln(1)\n} -- after if\nelse {\nprintln(2)\n}\nprintln(3)",
+                "if a {\n    println(1)\n} -- after if\nelse {\n    println(2)\n}\nprintln(3)\n",
+            ),
+            (
+                "fn a() {\ntry {\nb()\n}\n-- Only errors from b\ncatch (e) {\nc()\n}\n}",
+                "fn a() {\n    try {\n        b()\n    }\n    -- Only errors from b\n    catch (e) {\n        c()\n    }\n}\n",
+            ),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(format(source).unwrap(), expected, "{}", source);
+            assert_eq!(format(expected).unwrap(), expected, "{}", expected);
+        }
+    }
+
+    #[test]
+    fn it_indents_statements_that_continue_onto_the_next_line() {
+        assert_eq!(
+            format("fn a() {\nreturn 1 +\n2\n}").unwrap(),
+            "fn a() {\n    return 1 +\n        2\n}\n"
+        );
+    }
+}
@@ -1,7 +1,12 @@
 mod parser;
 mod token;
 mod ast;
+mod span;
+mod format;
+pub mod diagnostics;
 
 pub use ast::*;
-pub use parser::parse;
-pub use token::generate;
\ No newline at end of file
+pub use parser::{parse, parse_recovering, ParseError};
+pub use token::{generate, generate_with_comments, Token, SpannedToken};
+pub use format::format;
+pub use span::Span;
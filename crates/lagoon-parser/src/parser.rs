@@ -1,12 +1,26 @@
 use std::slice::Iter;
 use thiserror::Error;
 use hashbrown::HashMap;
-use colored::*;
 
-use crate::token::Token;
+use crate::token::{Token, SpannedToken};
+use crate::span::Span;
+use crate::diagnostics::Diagnostic;
 use crate::ast::*;
 
-pub fn parse(tokens: Vec<Token>) -> Result<Program, ParseError> {
+pub fn parse(tokens: Vec<SpannedToken>) -> Result<Program, ParseError> {
+    let (program, errors) = parse_recovering(tokens);
+
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(program),
+    }
+}
+
+/// Parse as much of the token stream as possible, skipping over any invalid
+/// statements and collecting every error along the way.
+///
+/// The returned `Program` only contains the statements that parsed successfully.
+pub fn parse_recovering(tokens: Vec<SpannedToken>) -> (Program, Vec<ParseError>) {
     let mut parser = Parser::new(tokens.iter());
 
     parser.read();
@@ -14,11 +28,11 @@ pub fn parse(tokens: Vec<Token>) -> Result<Program, ParseError> {
 
     let mut program: Program = Vec::new();
 
-    while let Some(statement) = parser.next()? {
+    while let Some(statement) = parser.next() {
         program.push(statement);
     }
 
-    Ok(program)
+    (program, parser.errors)
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -43,10 +57,9 @@ impl Precedence {
             Token::Plus | Token::Minus => Self::Sum,
             Token::LeftParen | Token::Dot | Token::LeftBracket => Self::Call,
             Token::LessThan | Token::GreaterThan | Token::LessThanOrEquals | Token::GreaterThanOrEquals => Self::LessThanGreaterThan,
-            Token::Equals | Token::NotEquals => Self::Equals,
+            Token::Equals | Token::NotEquals | Token::StrictEquals | Token::StrictNotEquals => Self::Equals,
             Token::And | Token::Or | Token::In | Token::NotIn => Self::AndOr,
             Token::Assign => Self::Assign,
-            Token::LeftBrace => Self::Statement,
             Token::Pow => Self::Pow,
             _ => Self::Lowest,
         }
@@ -54,43 +67,80 @@ impl Precedence {
 }
 
 struct Parser<'p> {
-    tokens: Iter<'p, Token>,
+    tokens: Iter<'p, SpannedToken>,
     current: Token,
+    current_span: Span,
     peek: Token,
+    peek_span: Span,
+    previous_span: Span,
+    errors: Vec<ParseError>,
+    /// How many blocks deep the parser is. Imports and exports are only
+    /// allowed at the top-level of a module.
+    depth: usize,
+    /// Whether a `{` after an expression starts a struct literal. It doesn't in
+    /// the condition of an `if` or `while`, or the list a `for` loops over,
+    /// where it starts the block instead.
+    struct_literals: bool,
 }
 
 impl<'p> Parser<'p> {
 
-    fn new(tokens: Iter<'p, Token>) -> Self {
+    fn new(tokens: Iter<'p, SpannedToken>) -> Self {
         Self {
             current: Token::Eof,
+            current_span: Span::default(),
             peek: Token::Eof,
-            tokens: tokens,
+            peek_span: Span::default(),
+            previous_span: Span::default(),
+            errors: Vec::new(),
+            depth: 0,
+            struct_literals: true,
+            tokens,
         }
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
-        match self.current {
-            Token::Fn => self.parse_fn(true),
-            Token::Struct => self.parse_struct(),
-            Token::Let => self.parse_let(),
-            Token::Const => self.parse_const(),
-            Token::If => self.parse_if(),
-            Token::For => self.parse_for(),
+        let start = self.current_span;
+
+        let kind = match self.current {
+            Token::Fn => self.parse_fn(true)?,
+            Token::Struct => self.parse_struct()?,
+            Token::Let => self.parse_let()?,
+            Token::Const => self.parse_const()?,
+            Token::If => self.parse_if()?,
+            Token::For => self.parse_for()?,
+            Token::While => self.parse_while()?,
+            Token::Break => {
+                self.expect_token_and_read(Token::Break)?;
+                StatementKind::Break
+            },
+            Token::Continue => {
+                self.expect_token_and_read(Token::Continue)?;
+                StatementKind::Continue
+            },
+            Token::Throw => {
+                self.expect_token_and_read(Token::Throw)?;
+                StatementKind::Throw { value: self.parse_expression(Precedence::Lowest)? }
+            },
+            Token::Try => self.parse_try()?,
+            Token::Import if self.depth == 0 => self.parse_import()?,
+            Token::Export if self.depth == 0 => self.parse_export()?,
             Token::Return => {
                 self.expect_token_and_read(Token::Return)?;
 
                 if let Ok(expression) = self.parse_expression(Precedence::Lowest) {
-                    Ok(Statement::Return { value: expression })
+                    StatementKind::Return { value: expression }
                 } else {
-                    Ok(Statement::Return { value: Expression::Null })
+                    StatementKind::Return { value: Expression::new(ExpressionKind::Null, start) }
                 }
             },
-            _ => Ok(Statement::Expression{ expression: self.parse_expression(Precedence::Lowest)? }),
-        }
+            _ => StatementKind::Expression { expression: self.parse_expression(Precedence::Lowest)? },
+        };
+
+        Ok(Statement::new(kind, self.span_from(start)))
     }
 
-    fn parse_for(&mut self) -> Result<Statement, ParseError> {
+    fn parse_for(&mut self) -> Result<StatementKind, ParseError> {
         self.expect_token_and_read(Token::For)?;
 
         let (index, value) = if self.current_is(Token::LeftParen) {
@@ -109,47 +159,76 @@ impl<'p> Parser<'p> {
         let iterable = self.parse_expression(Precedence::Statement)?;
         let then = self.parse_block()?;
 
-        Ok(Statement::For { index, value, iterable, then })
+        Ok(StatementKind::For { index, value, iterable, then })
+    }
+
+    fn parse_while(&mut self) -> Result<StatementKind, ParseError> {
+        self.expect_token_and_read(Token::While)?;
+
+        let condition = self.parse_expression(Precedence::Statement)?;
+        let then = self.parse_block()?;
+
+        Ok(StatementKind::While { condition, then })
     }
 
+    /// Conditions are parsed with `Precedence::Statement`. Anything parsed with
+    /// `Precedence::Lowest`, such as an argument or list item, can contain
+    /// struct literals again.
     fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression, ParseError> {
-        let mut left = match self.current.clone() {
+        let struct_literals = self.struct_literals;
+
+        match precedence {
+            Precedence::Lowest => self.struct_literals = true,
+            Precedence::Statement => self.struct_literals = false,
+            _ => (),
+        }
+
+        let expression = self.parse_expression_with(precedence);
+
+        self.struct_literals = struct_literals;
+        expression
+    }
+
+    fn parse_expression_with(&mut self, precedence: Precedence) -> Result<Expression, ParseError> {
+        let start = self.current_span;
+
+        let kind = match self.current.clone() {
             Token::String(s) => {
                 self.expect_token_and_read(Token::String("".to_string()))?;
-                Expression::String(s.to_string())
+                ExpressionKind::String(s)
             },
             Token::Null => {
                 self.expect_token_and_read(Token::Null)?;
-                Expression::Null
+                ExpressionKind::Null
             },
             Token::Number(n) => {
                 self.expect_token_and_read(Token::Number(0.0))?;
-                Expression::Number(n)
+                ExpressionKind::Number(n)
             },
             Token::True => {
                 self.expect_token_and_read(Token::True)?;
-                Expression::Bool(true)
+                ExpressionKind::Bool(true)
             },
             Token::False => {
                 self.expect_token_and_read(Token::False)?;
-                Expression::Bool(false)
+                ExpressionKind::Bool(false)
             },
             Token::Identifier(s) => {
                 self.expect_identifier_and_read()?;
-                Expression::Identifier(s)
+                ExpressionKind::identifier(s)
             },
             Token::Fn => {
                 let (params, body) = match self.parse_fn(false)? {
-                    Statement::FunctionDeclaration { params, body, .. } => (params, body),
+                    StatementKind::FunctionDeclaration { params, body, .. } => (params, body),
                     _ => return Err(ParseError::Unreachable)
                 };
-                
-                Expression::Closure(params, body)
+
+                ExpressionKind::Closure(params, body)
             },
             t @ Token::Minus | t @ Token::Bang => {
                 self.expect_token_and_read(t.clone())?;
 
-                Expression::Prefix(Op::token(t), self.parse_expression(Precedence::Prefix)?.boxed())
+                ExpressionKind::Prefix(Op::token(t), self.parse_expression(Precedence::Prefix)?.boxed())
             },
             Token::LeftBracket => {
                 self.expect_token_and_read(Token::LeftBracket)?;
@@ -166,12 +245,41 @@ impl<'p> Parser<'p> {
 
                 self.expect_token_and_read(Token::RightBracket)?;
 
-                Expression::List(items)
+                ExpressionKind::List(items)
             },
-            _ => return Err(ParseError::UnexpectedToken(self.current.clone())),
+            // A `{` can only start a map literal in prefix position, so it never
+            // clashes with the postfix `Name { ... }` struct syntax.
+            Token::LeftBrace => {
+                self.expect_token_and_read(Token::LeftBrace)?;
+
+                let mut items: Vec<(Expression, Expression)> = Vec::new();
+
+                while ! self.current_is(Token::RightBrace) {
+                    let key = self.parse_expression(Precedence::Lowest)?;
+
+                    self.expect_token_and_read(Token::Colon)?;
+
+                    let value = self.parse_expression(Precedence::Lowest)?;
+
+                    items.push((key, value));
+
+                    if self.current_is(Token::Comma) {
+                        self.expect_token_and_read(Token::Comma)?;
+                    } else {
+                        break;
+                    }
+                }
+
+                self.expect_token_and_read(Token::RightBrace)?;
+
+                ExpressionKind::Map(items)
+            },
+            _ => return Err(self.unexpected()),
         };
 
-        while !self.current_is(Token::Eof) && precedence < Precedence::token(self.current.clone()) {
+        let mut left = Expression::new(kind, self.span_from(start));
+
+        while !self.current_is(Token::Eof) && precedence < self.precedence() {
             if let Some(expression) = self.parse_postfix_expression(left.clone())? {
                 left = expression;
             } else if let Some(expression) = self.parse_infix_expression(left.clone())? {
@@ -184,14 +292,27 @@ impl<'p> Parser<'p> {
         Ok(left)
     }
 
+    /// The precedence of the current token when it follows an expression. A
+    /// struct literal binds as tightly as a call, so `a == P { x: 1 }` compares
+    /// `a` with a new `P`.
+    fn precedence(&self) -> Precedence {
+        match self.current {
+            Token::LeftBrace if self.struct_literals => Precedence::Call,
+            Token::LeftBrace => Precedence::Lowest,
+            _ => Precedence::token(self.current.clone()),
+        }
+    }
+
     fn parse_postfix_expression(&mut self, left: Expression) -> Result<Option<Expression>, ParseError> {
-        Ok(match self.current {
+        let start = left.span;
+
+        let kind = match self.current {
             Token::Dot => {
                 self.expect_token_and_read(Token::Dot)?;
 
                 let field = self.expect_identifier_and_read()?.into();
 
-                Some(Expression::Get(Box::new(left), field))
+                ExpressionKind::Get(Box::new(left), field)
             },
             Token::LeftBracket => {
                 self.expect_token_and_read(Token::LeftBracket)?;
@@ -204,7 +325,7 @@ impl<'p> Parser<'p> {
 
                 self.expect_token_and_read(Token::RightBracket)?;
 
-                Some(Expression::Index(left.boxed(), index))
+                ExpressionKind::Index(left.boxed(), index)
             },
             Token::LeftBrace => {
                 self.expect_token_and_read(Token::LeftBrace)?;
@@ -212,13 +333,14 @@ impl<'p> Parser<'p> {
                 let mut fields: HashMap<Identifier, Expression> = HashMap::new();
 
                 while ! self.current_is(Token::RightBrace) {
+                    let field_span = self.current_span;
                     let field = self.expect_identifier_and_read()?;
 
                     let value = if self.current_is(Token::Colon) {
                         self.expect_token_and_read(Token::Colon)?;
                         self.parse_expression(Precedence::Lowest)?
                     } else {
-                        Expression::Identifier(field.clone().into())
+                        Expression::new(ExpressionKind::identifier(field.clone()), field_span)
                     };
 
                     fields.insert(field.into(), value);
@@ -230,7 +352,7 @@ impl<'p> Parser<'p> {
 
                 self.expect_token_and_read(Token::RightBrace)?;
 
-                Some(Expression::Struct(left.boxed(), fields))
+                ExpressionKind::Struct(left.boxed(), fields)
             },
             Token::LeftParen => {
                 self.expect_token_and_read(Token::LeftParen)?;
@@ -247,16 +369,21 @@ impl<'p> Parser<'p> {
 
                 self.expect_token_and_read(Token::RightParen)?;
 
-                Some(Expression::Call(Box::new(left), args))
+                ExpressionKind::Call(Box::new(left), args)
             },
-            _ => None
-        })
+            _ => return Ok(None)
+        };
+
+        Ok(Some(Expression::new(kind, self.span_from(start))))
     }
 
     fn parse_infix_expression(&mut self, left: Expression) -> Result<Option<Expression>, ParseError> {
-        Ok(match self.current {
+        let start = left.span;
+
+        let kind = match self.current {
             Token::Plus | Token::Minus | Token::Asterisk | Token::Slash |
-            Token::Equals | Token::NotEquals | Token::LessThanOrEquals | Token::LessThan |
+            Token::Equals | Token::NotEquals | Token::StrictEquals | Token::StrictNotEquals |
+            Token::LessThanOrEquals | Token::LessThan |
             Token::GreaterThan | Token::GreaterThanOrEquals | Token::And | Token::Or |
             Token::Pow | Token::In | Token::NotIn => {
                 let token = self.current.clone();
@@ -265,35 +392,125 @@ impl<'p> Parser<'p> {
 
                 let right = self.parse_expression(Precedence::token(token.clone()))?;
 
-                Some(Expression::Infix(Box::new(left), Op::token(token), Box::new(right)))
+                ExpressionKind::Infix(Box::new(left), Op::token(token), Box::new(right))
             },
             Token::Assign => {
                 self.read();
-                
+
                 let right = self.parse_expression(Precedence::Lowest)?;
 
-                Some(Expression::Assign(Box::new(left), Box::new(right)))
+                ExpressionKind::Assign(Box::new(left), Box::new(right))
             },
-            _ => None
-        })
+            _ => return Ok(None)
+        };
+
+        Ok(Some(Expression::new(kind, self.span_from(start))))
     }
 
-    fn parse_if(&mut self) -> Result<Statement, ParseError> {
+    fn parse_if(&mut self) -> Result<StatementKind, ParseError> {
         self.expect_token_and_read(Token::If)?;
 
         let condition = self.parse_expression(Precedence::Statement)?;
         let then = self.parse_block()?;
         let otherwise = if self.current_is(Token::Else) {
             self.expect_token_and_read(Token::Else)?;
+
+            if self.current_is(Token::If) {
+                let start = self.current_span;
+                let kind = self.parse_if()?;
+
+                Some(Else::If(Box::new(Statement::new(kind, self.span_from(start)))))
+            } else {
+                Some(Else::Block(self.parse_block()?))
+            }
+        } else {
+            None
+        };
+
+        Ok(StatementKind::If { condition, then, otherwise })
+    }
+
+    fn parse_try(&mut self) -> Result<StatementKind, ParseError> {
+        self.expect_token_and_read(Token::Try)?;
+
+        let then = self.parse_block()?;
+
+        let catch = if self.current_is(Token::Catch) {
+            self.expect_token_and_read(Token::Catch)?;
+            self.expect_token_and_read(Token::LeftParen)?;
+
+            let name = self.parse_parameter()?;
+
+            self.expect_token_and_read(Token::RightParen)?;
+
+            Some(Catch { name, then: self.parse_block()? })
+        } else {
+            None
+        };
+
+        let finally = if self.current_is(Token::Finally) {
+            self.expect_token_and_read(Token::Finally)?;
+
             Some(self.parse_block()?)
         } else {
             None
         };
 
-        Ok(Statement::If { condition, then, otherwise })
+        // A `try` on its own doesn't do anything, so at least one of the other
+        // branches is required.
+        if catch.is_none() && finally.is_none() {
+            return Err(self.unexpected());
+        }
+
+        Ok(StatementKind::Try { then, catch, finally })
+    }
+
+    fn parse_import(&mut self) -> Result<StatementKind, ParseError> {
+        self.expect_token_and_read(Token::Import)?;
+        self.expect_token_and_read(Token::LeftBrace)?;
+
+        let mut names = Vec::new();
+
+        while ! self.current_is(Token::RightBrace) {
+            if self.current_is(Token::Comma) {
+                self.expect_token_and_read(Token::Comma)?;
+            }
+
+            names.push(self.parse_parameter()?);
+        }
+
+        self.expect_token_and_read(Token::RightBrace)?;
+
+        // `from` isn't a keyword, so it can still be used as a variable name.
+        match &self.current {
+            Token::Identifier(from) if from == "from" => self.read(),
+            _ => return Err(self.unexpected()),
+        }
+
+        let path = match self.expect_token_and_read(Token::String("".to_string()))? {
+            Token::String(path) => path,
+            _ => unreachable!(),
+        };
+
+        Ok(StatementKind::Import { names, path })
+    }
+
+    fn parse_export(&mut self) -> Result<StatementKind, ParseError> {
+        self.expect_token_and_read(Token::Export)?;
+
+        let start = self.current_span;
+
+        let kind = match self.current {
+            Token::Fn => self.parse_fn(true)?,
+            Token::Struct => self.parse_struct()?,
+            Token::Const => self.parse_const()?,
+            _ => return Err(self.unexpected()),
+        };
+
+        Ok(StatementKind::Export { declaration: Box::new(Statement::new(kind, self.span_from(start))) })
     }
 
-    fn parse_const(&mut self) -> Result<Statement, ParseError> {
+    fn parse_const(&mut self) -> Result<StatementKind, ParseError> {
         self.expect_token_and_read(Token::Const)?;
 
         let name: Identifier = self.expect_identifier_and_read()?.into();
@@ -301,13 +518,10 @@ impl<'p> Parser<'p> {
 
         let initial = self.parse_expression(Precedence::Lowest)?;
 
-        Ok(Statement::ConstDeclaration {
-            name: name,
-            initial: initial,
-        })
+        Ok(StatementKind::ConstDeclaration { name, initial })
     }
 
-    fn parse_let(&mut self) -> Result<Statement, ParseError> {
+    fn parse_let(&mut self) -> Result<StatementKind, ParseError> {
         self.expect_token_and_read(Token::Let)?;
 
         let name: Identifier = self.expect_identifier_and_read()?.into();
@@ -319,13 +533,10 @@ impl<'p> Parser<'p> {
             None
         };
 
-        Ok(Statement::LetDeclaration {
-            name: name,
-            initial: initial,
-        })
+        Ok(StatementKind::LetDeclaration { name, initial })
     }
 
-    fn parse_struct(&mut self) -> Result<Statement, ParseError> {
+    fn parse_struct(&mut self) -> Result<StatementKind, ParseError> {
         self.expect_token_and_read(Token::Struct)?;
 
         let name: Identifier = self.expect_identifier_and_read()?.into();
@@ -339,17 +550,15 @@ impl<'p> Parser<'p> {
                 self.expect_token_and_read(Token::Comma)?;
             }
 
-            let field: String = self.expect_identifier_and_read()?.into();
-            
-            fields.push(Parameter { name: field })
+            fields.push(self.parse_parameter()?);
         }
 
         self.expect_token_and_read(Token::RightBrace)?;
 
-        Ok(Statement::StructDeclaration { name: name, fields: fields })
+        Ok(StatementKind::StructDeclaration { name, fields })
     }
 
-    fn parse_fn(&mut self, with_identifier: bool) -> Result<Statement, ParseError> {
+    fn parse_fn(&mut self, with_identifier: bool) -> Result<StatementKind, ParseError> {
         self.expect_token_and_read(Token::Fn)?;
 
         let name: Identifier = if with_identifier {
@@ -367,20 +576,21 @@ impl<'p> Parser<'p> {
                 self.expect_token_and_read(Token::Comma)?;
             }
 
-            let param: String = self.expect_identifier_and_read()?.into();
-            
-            params.push(Parameter { name: param })
+            params.push(self.parse_parameter()?);
         }
 
         self.expect_token_and_read(Token::RightParen)?;
-        
+
         let body: Vec<Statement> = self.parse_block()?;
 
-        Ok(Statement::FunctionDeclaration {
-            name: name,
-            params: params,
-            body: body,
-        })
+        Ok(StatementKind::FunctionDeclaration { name, params, body })
+    }
+
+    fn parse_parameter(&mut self) -> Result<Parameter, ParseError> {
+        let span = self.current_span;
+        let name: String = self.expect_identifier_and_read()?.into();
+
+        Ok(Parameter { name, span })
     }
 
     fn parse_block(&mut self) -> Result<Block, ParseError> {
@@ -388,10 +598,16 @@ impl<'p> Parser<'p> {
 
         let mut block = Vec::new();
 
-        while ! self.current_is(Token::RightBrace) {
-            block.push(self.parse_statement()?);
+        self.depth += 1;
+
+        while ! self.current_is(Token::RightBrace) && ! self.current_is(Token::Eof) {
+            if let Some(statement) = self.parse_statement_or_recover() {
+                block.push(statement);
+            }
         }
 
+        self.depth -= 1;
+
         self.expect_token_and_read(Token::RightBrace)?;
 
         Ok(block)
@@ -401,7 +617,7 @@ impl<'p> Parser<'p> {
         if self.current_is(token) {
             Ok(self.current.clone())
         } else {
-            Err(ParseError::UnexpectedToken(self.current.clone()))
+            Err(self.unexpected())
         }
     }
 
@@ -421,31 +637,125 @@ impl<'p> Parser<'p> {
         std::mem::discriminant(&self.current) == std::mem::discriminant(&token)
     }
 
+    fn unexpected(&self) -> ParseError {
+        ParseError::UnexpectedToken(self.current.clone(), self.current_span)
+    }
+
+    /// Create a span that covers everything from `start` up to the end of the
+    /// most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span)
+    }
+
     fn read(&mut self) {
+        self.previous_span = self.current_span;
         self.current = self.peek.clone();
-        self.peek = if let Some(token) = self.tokens.next() { token.clone() } else { Token::Eof };
+        self.current_span = self.peek_span;
+
+        if let Some(SpannedToken { token, span }) = self.tokens.next() {
+            self.peek = token.clone();
+            self.peek_span = *span;
+        } else {
+            // Point the end-of-file token at the very end of the last real token
+            // so that errors about unexpected EOFs still have a sensible location.
+            let end = self.current_span.end.max(self.previous_span.end);
+
+            self.peek = Token::Eof;
+            self.peek_span = Span { start: end, end, ..self.current_span };
+        }
     }
 
-    fn next(&mut self) -> Result<Option<Statement>, ParseError> {
-        if self.current == Token::Eof {
-            return Ok(None)
+    /// Parse the next statement. If it is invalid, the error is recorded and
+    /// the parser skips ahead to the start of the next statement.
+    fn parse_statement_or_recover(&mut self) -> Option<Statement> {
+        let start = self.current_span.start;
+
+        match self.parse_statement() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                let line = match &error {
+                    ParseError::UnexpectedToken(_, span) => span.line,
+                    _ => self.current_span.line,
+                };
+
+                self.errors.push(error);
+
+                // Always make some progress, otherwise a token that can't start
+                // a statement would be reported over and over again.
+                if self.current_span.start == start && ! self.current_is(Token::Eof) {
+                    self.read();
+                }
+
+                self.synchronize(line);
+
+                None
+            }
         }
+    }
 
-        Ok(Some(self.parse_statement()?))
+    /// Skip tokens until we reach something that looks like the start of a new
+    /// statement: a keyword, the end of the current block or a new line.
+    fn synchronize(&mut self, line: usize) {
+        loop {
+            match self.current {
+                Token::Eof | Token::RightBrace | Token::Fn | Token::Let | Token::Const |
+                Token::Struct | Token::If | Token::For | Token::While | Token::Return |
+                Token::Break | Token::Continue | Token::Throw | Token::Try |
+                Token::Import | Token::Export => break,
+                _ if self.current_span.line > line => break,
+                _ => self.read(),
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<Statement> {
+        while self.current != Token::Eof {
+            if let Some(statement) = self.parse_statement_or_recover() {
+                return Some(statement);
+            }
+        }
+
+        None
     }
 }
 
 #[derive(Debug, Error)]
 pub enum ParseError {
     #[error("Unexpected token {0:?}.")]
-    UnexpectedToken(Token),
+    UnexpectedToken(Token, Span),
     #[error("Entered unreachable code.")]
     Unreachable,
 }
 
 impl ParseError {
-    pub fn print(self) {
-        eprintln!("{}", format!("{}", self).red().bold());
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParseError::UnexpectedToken(_, span) => Some(*span),
+            ParseError::Unreachable => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken(..) => "E0001",
+            ParseError::Unreachable => "E0002",
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.to_string()).with_code(self.code());
+
+        match self {
+            ParseError::UnexpectedToken(Token::Eof, span) => diagnostic
+                .with_label(*span, "unexpected end of file")
+                .with_help("check for an unclosed `{`, `(` or `[`"),
+            ParseError::UnexpectedToken(_, span) => diagnostic.with_label(*span, "unexpected token"),
+            ParseError::Unreachable => diagnostic.with_note("this is a bug in the Lagoon parser"),
+        }
+    }
+
+    pub fn print(&self, source: &str, path: &str) {
+        self.diagnostic().emit(source, path);
     }
 }
 
@@ -456,7 +766,15 @@ mod tests {
 
     fn lex_and_parse(input: &str) -> Program {
         let tokens = token::generate(input);
-        parse(tokens).unwrap()
+        without_spans(&parse(tokens).unwrap())
+    }
+
+    fn statement(kind: StatementKind) -> Statement {
+        kind.into()
+    }
+
+    fn expression(kind: ExpressionKind) -> Expression {
+        kind.into()
     }
 
     #[test]
@@ -464,26 +782,26 @@ mod tests {
         assert_eq!(
             lex_and_parse("fn name() {}"),
             vec![
-                Statement::FunctionDeclaration { name: String::from("name"), body: vec![], params: vec![] }
+                statement(StatementKind::FunctionDeclaration { name: String::from("name"), body: vec![], params: vec![] })
             ]
         );
 
         assert_eq!(
             lex_and_parse("fn name(person) {}"),
             vec![
-                Statement::FunctionDeclaration { name: String::from("name"), body: vec![], params: vec![
-                    Parameter { name: String::from("person") }
-                ] }
+                statement(StatementKind::FunctionDeclaration { name: String::from("name"), body: vec![], params: vec![
+                    Parameter::new("person")
+                ] })
             ]
         );
 
         assert_eq!(
             lex_and_parse("fn say_hello(name, separator) {}"),
             vec![
-                Statement::FunctionDeclaration { name: String::from("say_hello"), body: vec![], params: vec![
-                    Parameter { name: String::from("name") },
-                    Parameter { name: String::from("separator") }
-                ] }
+                statement(StatementKind::FunctionDeclaration { name: String::from("say_hello"), body: vec![], params: vec![
+                    Parameter::new("name"),
+                    Parameter::new("separator")
+                ] })
             ]
         );
 
@@ -494,9 +812,9 @@ mod tests {
                 }
             "),
             vec![
-                Statement::FunctionDeclaration { name: String::from("say_hello"), body: vec![
-                    Statement::LetDeclaration { name: String::from("name"), initial: Expression::Bool(true).some() }
-                ], params: vec![] }
+                statement(StatementKind::FunctionDeclaration { name: String::from("say_hello"), body: vec![
+                    statement(StatementKind::LetDeclaration { name: String::from("name"), initial: expression(ExpressionKind::Bool(true)).some() })
+                ], params: vec![] })
             ]
         )
     }
@@ -506,14 +824,14 @@ mod tests {
         assert_eq!(
             lex_and_parse("let name"),
             vec![
-                Statement::LetDeclaration { name: String::from("name"), initial: None }
+                statement(StatementKind::LetDeclaration { name: String::from("name"), initial: None })
             ]
         );
 
         assert_eq!(
             lex_and_parse("let name = true"),
             vec![
-                Statement::LetDeclaration { name: String::from("name"), initial: Expression::Bool(true).some() }
+                statement(StatementKind::LetDeclaration { name: String::from("name"), initial: expression(ExpressionKind::Bool(true)).some() })
             ]
         );
     }
@@ -523,11 +841,11 @@ mod tests {
         assert_eq!(
             lex_and_parse(r##"123 "testing" true false 123.456"##),
             vec![
-                Statement::Expression { expression: Expression::Number(123.0) },
-                Statement::Expression { expression: Expression::String("testing".to_owned()) },
-                Statement::Expression { expression: Expression::Bool(true) },
-                Statement::Expression { expression: Expression::Bool(false) },
-                Statement::Expression { expression: Expression::Number(123.456) },
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Number(123.0)) }),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::String("testing".to_owned())) }),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Bool(true)) }),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Bool(false)) }),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Number(123.456)) }),
             ]
         );
     }
@@ -537,79 +855,79 @@ mod tests {
         assert_eq!(
             lex_and_parse("1 + 2"),
             vec![
-                Statement::Expression { expression: Expression::Infix(
-                    Box::new(Expression::Number(1.0)),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Infix(
+                    Box::new(expression(ExpressionKind::Number(1.0))),
                     Op::Add,
-                    Box::new(Expression::Number(2.0))
-                ) }
+                    Box::new(expression(ExpressionKind::Number(2.0)))
+                )) })
             ]
         );
 
         assert_eq!(
             lex_and_parse("1 - 2"),
             vec![
-                Statement::Expression { expression: Expression::Infix(
-                    Box::new(Expression::Number(1.0)),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Infix(
+                    Box::new(expression(ExpressionKind::Number(1.0))),
                     Op::Subtract,
-                    Box::new(Expression::Number(2.0))
-                ) }
+                    Box::new(expression(ExpressionKind::Number(2.0)))
+                )) })
             ]
         );
 
         assert_eq!(
             lex_and_parse("1 * 2"),
             vec![
-                Statement::Expression { expression: Expression::Infix(
-                    Box::new(Expression::Number(1.0)),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Infix(
+                    Box::new(expression(ExpressionKind::Number(1.0))),
                     Op::Multiply,
-                    Box::new(Expression::Number(2.0))
-                ) }
+                    Box::new(expression(ExpressionKind::Number(2.0)))
+                )) })
             ]
         );
 
         assert_eq!(
             lex_and_parse("1 / 2"),
             vec![
-                Statement::Expression { expression: Expression::Infix(
-                    Box::new(Expression::Number(1.0)),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Infix(
+                    Box::new(expression(ExpressionKind::Number(1.0))),
                     Op::Divide,
-                    Box::new(Expression::Number(2.0))
-                ) }
+                    Box::new(expression(ExpressionKind::Number(2.0)))
+                )) })
             ]
         );
 
         assert_eq!(
             lex_and_parse("1 + 2 * 3"),
             vec![
-                Statement::Expression { expression: Expression::Infix(
-                    Box::new(Expression::Number(1.0)),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Infix(
+                    Box::new(expression(ExpressionKind::Number(1.0))),
                     Op::Add,
-                    Box::new(Expression::Infix(
-                        Box::new(Expression::Number(2.0)),
+                    Box::new(expression(ExpressionKind::Infix(
+                        Box::new(expression(ExpressionKind::Number(2.0))),
                         Op::Multiply,
-                        Box::new(Expression::Number(3.0)),
-                    ))
-                ) }
+                        Box::new(expression(ExpressionKind::Number(3.0))),
+                    )))
+                )) })
             ]
         );
 
         assert_eq!(
             lex_and_parse("1 + 2 * 3 / 3"),
             vec![
-                Statement::Expression { expression: Expression::Infix(
-                    Box::new(Expression::Number(1.0)),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Infix(
+                    Box::new(expression(ExpressionKind::Number(1.0))),
                     Op::Add,
-                    Box::new(Expression::Infix(
-                        Box::new(Expression::Infix(
-                            Box::new(Expression::Number(2.0)),
+                    Box::new(expression(ExpressionKind::Infix(
+                        Box::new(expression(ExpressionKind::Infix(
+                            Box::new(expression(ExpressionKind::Number(2.0))),
                             Op::Multiply,
-                            Box::new(Expression::Number(3.0)),
-                        )),
+                            Box::new(expression(ExpressionKind::Number(3.0))),
+                        ))),
                         Op::Divide,
-                        Box::new(Expression::Number(3.0)),
-                        ),
+                        Box::new(expression(ExpressionKind::Number(3.0))),
+                        )),
                     )
-                ) }
+                )) })
             ]
         );
     }
@@ -619,35 +937,35 @@ mod tests {
         assert_eq!(
             lex_and_parse("hello()"),
             vec![
-                Statement::Expression { expression: Expression::Call(
-                    Box::new(Expression::Identifier("hello".to_owned())),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Call(
+                    Box::new(expression(ExpressionKind::identifier("hello".to_owned()))),
                     vec![]
-                ) }
+                )) })
             ]
         );
 
         assert_eq!(
             lex_and_parse("hello(true)"),
             vec![
-                Statement::Expression { expression: Expression::Call(
-                    Box::new(Expression::Identifier("hello".to_owned())),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Call(
+                    Box::new(expression(ExpressionKind::identifier("hello".to_owned()))),
                     vec![
-                       Expression::Bool(true)
+                       expression(ExpressionKind::Bool(true))
                     ]
-                )}
+                ))})
             ]
         );
 
         assert_eq!(
             lex_and_parse("hello(true, 1234)"),
             vec![
-                Statement::Expression { expression: Expression::Call(
-                    Box::new(Expression::Identifier("hello".to_owned())),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Call(
+                    Box::new(expression(ExpressionKind::identifier("hello".to_owned()))),
                     vec![
-                        Expression::Bool(true),
-                        Expression::Number(1234.0)
+                        expression(ExpressionKind::Bool(true)),
+                        expression(ExpressionKind::Number(1234.0))
                     ]
-                )}
+                ))})
             ]
         );
     }
@@ -657,11 +975,11 @@ mod tests {
         assert_eq!(
             lex_and_parse("if true {}"),
             vec![
-                Statement::If {
-                    condition: Expression::Bool(true),
+                statement(StatementKind::If {
+                    condition: expression(ExpressionKind::Bool(true)),
                     then: vec![],
                     otherwise: None
-                }
+                })
             ]
         );
 
@@ -670,13 +988,13 @@ mod tests {
                 let number = 1
             }"),
             vec![
-                Statement::If {
-                    condition: Expression::Bool(true),
+                statement(StatementKind::If {
+                    condition: expression(ExpressionKind::Bool(true)),
                     then: vec![
-                        Statement::LetDeclaration { name: String::from("number"), initial: Some(Expression::Number(1.0)) },
+                        statement(StatementKind::LetDeclaration { name: String::from("number"), initial: Some(expression(ExpressionKind::Number(1.0))) }),
                     ],
                     otherwise: None
-                }
+                })
             ]
         );
 
@@ -687,16 +1005,281 @@ mod tests {
                 let number = 2
             }"),
             vec![
-                Statement::If {
-                    condition: Expression::Bool(false),
+                statement(StatementKind::If {
+                    condition: expression(ExpressionKind::Bool(false)),
                     then: vec![
-                        Statement::LetDeclaration { name: String::from("number"), initial: Some(Expression::Number(1.0)) },
+                        statement(StatementKind::LetDeclaration { name: String::from("number"), initial: Some(expression(ExpressionKind::Number(1.0))) }),
                     ],
-                    otherwise: Some(vec![
-                        Statement::LetDeclaration { name: String::from("number"), initial: Some(Expression::Number(2.0)) },
-                    ])
-                }
+                    otherwise: Some(Else::Block(vec![
+                        statement(StatementKind::LetDeclaration { name: String::from("number"), initial: Some(expression(ExpressionKind::Number(2.0))) }),
+                    ]))
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn it_can_parse_struct_literals_inside_of_expressions() {
+        let point = |x: f64| {
+            let mut fields = HashMap::new();
+
+            fields.insert(String::from("x"), expression(ExpressionKind::Number(x)));
+            expression(ExpressionKind::Struct(expression(ExpressionKind::identifier("P")).boxed(), fields))
+        };
+
+        assert_eq!(
+            lex_and_parse("p == P { x: 1 }"),
+            vec![
+                statement(StatementKind::Expression {
+                    expression: expression(ExpressionKind::Infix(
+                        expression(ExpressionKind::identifier("p")).boxed(),
+                        Op::Equals,
+                        point(1.0).boxed(),
+                    ))
+                })
+            ]
+        );
+
+        // The brace after a condition starts the block, unless it's inside of
+        // brackets.
+        assert_eq!(
+            lex_and_parse("if p == [P { x: 1 }] {}"),
+            vec![
+                statement(StatementKind::If {
+                    condition: expression(ExpressionKind::Infix(
+                        expression(ExpressionKind::identifier("p")).boxed(),
+                        Op::Equals,
+                        expression(ExpressionKind::List(vec![point(1.0)])).boxed(),
+                    )),
+                    then: vec![],
+                    otherwise: None
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn it_attaches_spans_to_statements_and_expressions() {
+        let program = parse(token::generate("let name = true\nprintln(name)")).unwrap();
+
+        assert_eq!((program[0].span.start, program[0].span.end), (0, 15));
+        assert_eq!((program[1].span.line, program[1].span.column), (2, 1));
+
+        match &program[1].kind {
+            StatementKind::Expression { expression } => match &expression.kind {
+                ExpressionKind::Call(callable, arguments) => {
+                    assert_eq!((callable.span.start, callable.span.end), (16, 23));
+                    assert_eq!((arguments[0].span.line, arguments[0].span.column), (2, 9));
+                    assert_eq!((expression.span.start, expression.span.end), (16, 29));
+                },
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+
+        // Spans take part in equality, unless they are stripped first.
+        let moved = parse(token::generate("\nlet name = true\nprintln(name)")).unwrap();
+
+        assert_ne!(program, moved);
+        assert_eq!(without_spans(&program), without_spans(&moved));
+    }
+
+    #[test]
+    fn it_reports_the_location_of_unexpected_tokens() {
+        let error = parse(token::generate("fn name() {\n    let = 1\n}")).unwrap_err();
+
+        assert!(matches!(error, ParseError::UnexpectedToken(Token::Assign, _)));
+
+        let span = error.span().unwrap();
+
+        assert_eq!((span.line, span.column), (2, 9));
+    }
+
+    #[test]
+    fn it_can_recover_from_errors_and_report_all_of_them() {
+        let (program, errors) = parse_recovering(token::generate("
+            let = 1
+            let name = \"Ryan\"
+            fn hello( {
+                println(name)
+            }
+            println(name))
+            let age = 1
+        "));
+
+        // The stray `}` on line 6 is reported too, since the block it closes was never opened.
+        assert_eq!(errors.iter().map(|e| e.span().unwrap().line).collect::<Vec<usize>>(), vec![2, 4, 6, 7]);
+
+        assert_eq!(
+            without_spans(&program),
+            vec![
+                statement(StatementKind::LetDeclaration { name: String::from("name"), initial: Some(expression(ExpressionKind::String("Ryan".to_owned()))) }),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Call(
+                    Box::new(expression(ExpressionKind::identifier("println".to_owned()))),
+                    vec![expression(ExpressionKind::identifier("name".to_owned()))],
+                )) }),
+                statement(StatementKind::Expression { expression: expression(ExpressionKind::Call(
+                    Box::new(expression(ExpressionKind::identifier("println".to_owned()))),
+                    vec![expression(ExpressionKind::identifier("name".to_owned()))],
+                )) }),
+                statement(StatementKind::LetDeclaration { name: String::from("age"), initial: Some(expression(ExpressionKind::Number(1.0))) }),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_can_recover_inside_of_blocks() {
+        let (program, errors) = parse_recovering(token::generate("
+            fn hello() {
+                let = 1
+                return 2
+            }
+        "));
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            without_spans(&program),
+            vec![
+                statement(StatementKind::FunctionDeclaration { name: String::from("hello"), params: vec![], body: vec![
+                    statement(StatementKind::Return { value: expression(ExpressionKind::Number(2.0)) }),
+                ] }),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_unclosed_blocks() {
+        let (_, errors) = parse_recovering(token::generate("fn hello() {\n    println(1)"));
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], ParseError::UnexpectedToken(Token::Eof, _)));
+    }
+
+    #[test]
+    fn it_can_parse_while_statements() {
+        assert_eq!(
+            lex_and_parse("while true {
+                break
+                continue
+            }"),
+            vec![
+                statement(StatementKind::While {
+                    condition: expression(ExpressionKind::Bool(true)),
+                    then: vec![
+                        statement(StatementKind::Break),
+                        statement(StatementKind::Continue),
+                    ],
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn it_can_parse_else_if_chains() {
+        assert_eq!(
+            lex_and_parse("if a {
+                1
+            } else if b {
+                2
+            } else if c {
+                3
+            } else {
+                4
+            }"),
+            vec![
+                statement(StatementKind::If {
+                    condition: expression(ExpressionKind::identifier("a".to_owned())),
+                    then: vec![statement(StatementKind::Expression { expression: expression(ExpressionKind::Number(1.0)) })],
+                    otherwise: Some(Else::If(Box::new(statement(StatementKind::If {
+                        condition: expression(ExpressionKind::identifier("b".to_owned())),
+                        then: vec![statement(StatementKind::Expression { expression: expression(ExpressionKind::Number(2.0)) })],
+                        otherwise: Some(Else::If(Box::new(statement(StatementKind::If {
+                            condition: expression(ExpressionKind::identifier("c".to_owned())),
+                            then: vec![statement(StatementKind::Expression { expression: expression(ExpressionKind::Number(3.0)) })],
+                            otherwise: Some(Else::Block(vec![
+                                statement(StatementKind::Expression { expression: expression(ExpressionKind::Number(4.0)) }),
+                            ])),
+                        })))),
+                    })))),
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn it_can_parse_map_literals() {
+        assert_eq!(
+            lex_and_parse("let map = {}\nlet map = {\"name\": \"Ryan\", key: 1,}"),
+            vec![
+                statement(StatementKind::LetDeclaration {
+                    name: "map".to_string(),
+                    initial: Some(expression(ExpressionKind::Map(vec![]))),
+                }),
+                statement(StatementKind::LetDeclaration {
+                    name: "map".to_string(),
+                    initial: Some(expression(ExpressionKind::Map(vec![
+                        (expression(ExpressionKind::String("name".to_string())), expression(ExpressionKind::String("Ryan".to_string()))),
+                        (expression(ExpressionKind::identifier("key".to_string())), expression(ExpressionKind::Number(1.0))),
+                    ]))),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_can_parse_try_statements() {
+        assert_eq!(
+            lex_and_parse("try {
+                throw \"oops\"
+            } catch (e) {
+                println(e)
+            } finally {
+                break
+            }"),
+            vec![
+                statement(StatementKind::Try {
+                    then: vec![
+                        statement(StatementKind::Throw { value: expression(ExpressionKind::String("oops".to_string())) }),
+                    ],
+                    catch: Some(Catch {
+                        name: Parameter::new("e"),
+                        then: vec![
+                            statement(StatementKind::Expression {
+                                expression: expression(ExpressionKind::Call(
+                                    expression(ExpressionKind::identifier("println".to_string())).boxed(),
+                                    vec![expression(ExpressionKind::identifier("e".to_string()))],
+                                )),
+                            }),
+                        ],
+                    }),
+                    finally: Some(vec![statement(StatementKind::Break)]),
+                })
+            ]
+        );
+
+        assert!(parse(token::generate("try {}")).is_err());
+    }
+
+    #[test]
+    fn it_can_parse_imports_and_exports() {
+        assert_eq!(
+            lex_and_parse("import { add, Math } from \"./math\"\nexport const from = 1"),
+            vec![
+                statement(StatementKind::Import {
+                    names: vec![Parameter::new("add"), Parameter::new("Math")],
+                    path: "./math".to_string(),
+                }),
+                statement(StatementKind::Export {
+                    declaration: Box::new(statement(StatementKind::ConstDeclaration {
+                        name: "from".to_string(),
+                        initial: expression(ExpressionKind::Number(1.0)),
+                    })),
+                }),
             ]
         );
+
+        assert!(parse(token::generate("export let a = 1")).is_err());
+        assert!(parse(token::generate("fn a() {\nexport fn b() {}\n}")).is_err());
+        assert!(parse(token::generate("if true {\nimport { a } from \"./a\"\n}")).is_err());
     }
-}
\ No newline at end of file
+}
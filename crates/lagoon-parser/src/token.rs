@@ -1,17 +1,56 @@
 use logos::{Lexer, Logos};
 
-pub fn generate(input: &str) -> Vec<Token> {
-    Token::lexer(input).collect()
+use crate::span::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// Turn source code into the tokens that the parser reads. Comments are left
+/// out; use `generate_with_comments` to keep them.
+pub fn generate(input: &str) -> Vec<SpannedToken> {
+    generate_with_comments(input)
+        .into_iter()
+        .filter(|token| ! matches!(token.token, Token::Comment(_)))
+        .collect()
+}
+
+/// Turn source code into tokens, including a `Token::Comment` for every comment
+/// so that tools like the formatter can put them back.
+pub fn generate_with_comments(input: &str) -> Vec<SpannedToken> {
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut cursor = 0;
+
+    Token::lexer(input)
+        .spanned()
+        .map(|(token, range)| {
+            for (i, c) in input[cursor..range.start].char_indices() {
+                if c == '\n' {
+                    line += 1;
+                    line_start = cursor + i + 1;
+                }
+            }
+
+            cursor = range.start;
+
+            let column = input[line_start..range.start].chars().count() + 1;
+
+            SpannedToken { token, span: Span::new(range.start, range.end, line, column) }
+        })
+        .collect()
 }
 
 fn to_string(lex: &mut Lexer<Token>) -> Option<String> {
     let mut string: String = lex.slice().to_string();
 
-    if string.starts_with("$") {
+    if string.starts_with('$') {
         string.remove(0);
     }
 
-    if string.starts_with("\"") {
+    if string.starts_with('"') {
         string.remove(0);
     }
 
@@ -23,7 +62,7 @@ fn to_string(lex: &mut Lexer<Token>) -> Option<String> {
 }
 
 fn to_float(lex: &mut Lexer<Token>) -> Option<f64> {
-    Some(lex.slice().parse().ok()?)
+    lex.slice().parse().ok()
 }
 
 #[derive(Debug, Clone, Logos, PartialEq)]
@@ -44,12 +83,28 @@ pub enum Token {
     While,
     #[token("return")]
     Return,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
+    #[token("throw")]
+    Throw,
+    #[token("try")]
+    Try,
+    #[token("catch")]
+    Catch,
+    #[token("finally")]
+    Finally,
     #[token("for")]
     For,
     #[token("in")]
     In,
     #[token("not in")]
     NotIn,
+    #[token("import")]
+    Import,
+    #[token("export")]
+    Export,
 
     #[regex(r"[a-zA-Z_?]+", to_string)]
     Identifier(String),
@@ -91,6 +146,10 @@ pub enum Token {
     Equals,
     #[token("!=")]
     NotEquals,
+    #[token("===")]
+    StrictEquals,
+    #[token("!==")]
+    StrictNotEquals,
     #[token("<")]
     LessThan,
     #[token(">")]
@@ -121,17 +180,19 @@ pub enum Token {
     #[token(".")]
     Dot,
 
+    #[regex(r"--[^\n]*", |lex| lex.slice()[2..].to_string())]
+    Comment(String),
+
     Eof,
 
     #[error]
-    #[regex(r"--[^\n]*", logos::skip)]
     #[regex(r"[ \t\n\f]+", logos::skip)]
     Error,
 }
 
-impl Into<String> for Token {
-    fn into(self) -> String {
-        match self {
+impl From<Token> for String {
+    fn from(token: Token) -> Self {
+        match token {
             Token::Identifier(s) => s,
             Token::String(s) => s,
             _ => unreachable!()
@@ -147,12 +208,15 @@ mod tests {
     fn it_can_skip_comments() {
         let mut lexer = Token::lexer("-- foo");
 
+        assert_eq!(lexer.next(), Some(Token::Comment(" foo".to_string())));
         assert_eq!(lexer.next(), None);
+
+        assert_eq!(generate("-- foo\n1 -- bar").into_iter().map(|token| token.token).collect::<Vec<Token>>(), vec![Token::Number(1.0)]);
     }
 
     #[test]
     fn it_can_recognise_reserved_keywords() {
-        let mut lexer = Token::lexer("fn let true false if else while struct");
+        let mut lexer = Token::lexer("fn let true false if else while struct break continue throw try catch finally");
 
         assert_eq!(lexer.next(), Some(Token::Fn));
         assert_eq!(lexer.next(), Some(Token::Let));
@@ -162,6 +226,12 @@ mod tests {
         assert_eq!(lexer.next(), Some(Token::Else));
         assert_eq!(lexer.next(), Some(Token::While));
         assert_eq!(lexer.next(), Some(Token::Struct));
+        assert_eq!(lexer.next(), Some(Token::Break));
+        assert_eq!(lexer.next(), Some(Token::Continue));
+        assert_eq!(lexer.next(), Some(Token::Throw));
+        assert_eq!(lexer.next(), Some(Token::Try));
+        assert_eq!(lexer.next(), Some(Token::Catch));
+        assert_eq!(lexer.next(), Some(Token::Finally));
     }
 
     #[test]
@@ -201,6 +271,25 @@ mod tests {
         assert_eq!(lexer.next(), Some(Token::Number(6789.01)));
     }
 
+    #[test]
+    fn it_can_track_token_spans() {
+        let tokens = generate("let name = true\n\nfn  hello() {}");
+
+        assert_eq!(tokens[0].token, Token::Let);
+        assert_eq!((tokens[0].span.start, tokens[0].span.end), (0, 3));
+        assert_eq!((tokens[0].span.line, tokens[0].span.column), (1, 1));
+
+        assert_eq!(tokens[3].token, Token::True);
+        assert_eq!((tokens[3].span.line, tokens[3].span.column), (1, 12));
+
+        assert_eq!(tokens[4].token, Token::Fn);
+        assert_eq!((tokens[4].span.line, tokens[4].span.column), (3, 1));
+
+        assert_eq!(tokens[5].token, Token::Identifier("hello".to_owned()));
+        assert_eq!((tokens[5].span.start, tokens[5].span.end), (21, 26));
+        assert_eq!((tokens[5].span.line, tokens[5].span.column), (3, 5));
+    }
+
     #[test]
     fn it_can_recognise_strings() {
         let mut lexer = Token::lexer(r##""testing" "testing with \"" "testing \n""##);
@@ -0,0 +1,46 @@
+//! Formats every example program, checking that nothing about its meaning
+//! changes and that formatting it a second time leaves it alone.
+
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+use lagoon_parser::{format, generate, generate_with_comments, parse, without_spans, Token};
+
+fn examples(directory: &Path, files: &mut Vec<PathBuf>) {
+    for entry in read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+
+        if path.is_dir() {
+            examples(&path, files);
+        } else if path.extension().map(|e| e == "lag").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn it_formats_the_examples_idempotently() {
+    let mut files = Vec::new();
+
+    examples(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples"), &mut files);
+
+    assert!(! files.is_empty());
+
+    for file in files {
+        let source = read_to_string(&file).unwrap();
+        let formatted = format(&source).unwrap_or_else(|errors| panic!("{}: {:?}", file.display(), errors));
+
+        assert_eq!(without_spans(&parse(generate(&formatted)).unwrap()), without_spans(&parse(generate(&source)).unwrap()), "{}", file.display());
+        assert_eq!(format(&formatted).unwrap(), formatted, "{}", file.display());
+        assert_eq!(comments(&formatted), comments(&source), "{}", file.display());
+    }
+}
+
+fn comments(source: &str) -> Vec<String> {
+    generate_with_comments(source).into_iter()
+        .filter_map(|token| match token.token {
+            Token::Comment(comment) => Some(comment.trim_end().to_string()),
+            _ => None,
+        })
+        .collect()
+}
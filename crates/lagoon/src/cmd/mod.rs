@@ -2,11 +2,12 @@ use lagoon_parser::Program;
 use lagoon_js::TranspilerError;
 use std::fs::write;
 
-pub fn js(ast: Program, output: &str) -> Result<(), TranspilerError> {
-    let js = lagoon_js::transpile(ast)?;
+/// Transpile a program to `output`, returning the modules that it imports.
+pub fn js(ast: Program, output: &str) -> Result<Vec<String>, TranspilerError> {
+    let js = lagoon_js::transpile_module(ast)?;
 
-    match write(output, js) {
-        Err(_) => return Err(TranspilerError::FailedToWriteFile),
-        _ => Ok(())
+    match write(output, js.code) {
+        Err(_) => Err(TranspilerError::FailedToWriteFile),
+        _ => Ok(js.imports)
     }
-}
\ No newline at end of file
+}
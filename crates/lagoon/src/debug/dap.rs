@@ -0,0 +1,404 @@
+//! `lagoon debug`, a debug adapter that talks to an editor over stdin and
+//! stdout using the debug adapter protocol. Requests are read on a thread of
+//! their own, so that `pause` can reach the program while it is running.
+
+use std::cell::Cell;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use lagoon_interpreter::{Interpreter, InterpreterResult, Value};
+
+use super::{breakpoint_line, display, evaluate, stack, Controller, Frontend, Location, Reason, Session, Step};
+use serde_json::{json, Value as Json};
+
+use crate::json;
+
+/// There is only ever one thread, which runs the program.
+const THREAD: usize = 1;
+
+pub fn start(mut interpreter: Interpreter, source: &str, name: &str) {
+    let (sender, requests) = channel();
+
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+
+        while let Some(body) = json::read_message(&mut input) {
+            if let Ok(message) = serde_json::from_str(&body) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let client = Rc::new(Client { requests, seq: Cell::new(0), disconnected: Cell::new(false) });
+    let mut controller = Controller::default();
+
+    // The editor sends the breakpoints once the adapter has been initialized,
+    // and says when it is done so that the program can start.
+    loop {
+        let request = match client.requests.recv() {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+
+        if client.configure(&request, &mut controller) {
+            continue;
+        }
+
+        match command(&request) {
+            "initialize" => {
+                client.respond(&request, capabilities());
+                client.event("initialized", json!({}));
+            },
+            "launch" => {
+                if request["arguments"]["stopOnEntry"] == true {
+                    controller.stop_on_entry();
+                }
+
+                client.respond(&request, Json::Null);
+            },
+            "configurationDone" => {
+                client.respond(&request, Json::Null);
+                break;
+            },
+            "disconnect" | "terminate" => {
+                client.respond(&request, Json::Null);
+                return;
+            },
+            _ => client.fail(&request, "The program hasn't started yet."),
+        }
+    }
+
+    // Anything the program prints would get mixed up with the messages on
+    // stdout, so it is sent to the editor as output instead.
+    for (function, newline) in &[("println", "\n"), ("print", "")] {
+        let client = Rc::clone(&client);
+
+        interpreter.define_function(*function, move |_, args| {
+            if args.len() != 1 {
+                return Err(InterpreterResult::ArgumentCountMismatch(format!("{}()", function), 1, args.len()));
+            }
+
+            let arg = args[0].clone();
+
+            client.event("output", json!({ "category": "stdout", "output": format!("{}{}", arg.to_string(), newline) }));
+
+            Ok(Value::Null)
+        });
+    }
+
+    interpreter.set_debugger(Session::new(controller, Adapter { client: Rc::clone(&client), references: Vec::new() }));
+
+    let result = interpreter.run();
+
+    if client.disconnected.get() {
+        return;
+    }
+
+    if let Err(e) = &result {
+        if ! matches!(e.inner(), InterpreterResult::Stopped) {
+            let mut output = String::new();
+
+            for error in e.errors() {
+                output.push_str(&error.diagnostic().render(source, name, false));
+
+                if ! error.trace().is_empty() {
+                    output.push_str(&error.render_trace(name));
+                }
+            }
+
+            client.event("output", json!({ "category": "stderr", "output": output }));
+        }
+    }
+
+    client.event("exited", json!({ "exitCode": if result.is_ok() { 0 } else { 1 } }));
+    client.event("terminated", json!({}));
+
+    while let Ok(request) = client.requests.recv() {
+        if client.configure(&request, &mut Controller::default()) {
+            continue;
+        }
+
+        match command(&request) {
+            "disconnect" | "terminate" => {
+                client.respond(&request, Json::Null);
+                return;
+            },
+            _ => client.fail(&request, "The program has finished."),
+        }
+    }
+}
+
+fn capabilities() -> Json {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsFunctionBreakpoints": true,
+        "supportsEvaluateForHovers": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+fn command(request: &Json) -> &str {
+    request["command"].as_str().unwrap_or_default()
+}
+
+struct Client {
+    requests: Receiver<Json>,
+    seq: Cell<usize>,
+    disconnected: Cell<bool>,
+}
+
+impl Client {
+    fn send(&self, message: Vec<(&str, Json)>) {
+        self.seq.set(self.seq.get() + 1);
+
+        let seq = ("seq".to_string(), self.seq.get().into());
+        let message = std::iter::once(seq).chain(message.into_iter().map(|(key, value)| (key.to_string(), value))).collect();
+
+        let _ = json::write_message(&mut io::stdout(), &Json::Object(message));
+    }
+
+    fn respond(&self, request: &Json, body: Json) {
+        self.send(vec![
+            ("type", "response".into()),
+            ("request_seq", request["seq"].clone()),
+            ("success", true.into()),
+            ("command", command(request).into()),
+            ("body", body),
+        ]);
+    }
+
+    fn fail(&self, request: &Json, message: &str) {
+        self.send(vec![
+            ("type", "response".into()),
+            ("request_seq", request["seq"].clone()),
+            ("success", false.into()),
+            ("command", command(request).into()),
+            ("message", message.into()),
+        ]);
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send(vec![("type", "event".into()), ("event", event.into()), ("body", body)]);
+    }
+
+    /// Handle the requests that can be made at any time, returning whether the
+    /// request was one of them.
+    fn configure(&self, request: &Json, controller: &mut Controller) -> bool {
+        let arguments = &request["arguments"];
+
+        match command(request) {
+            "setBreakpoints" => {
+                let path = arguments["source"]["path"].as_str().unwrap_or_default();
+                let path = Path::new(path).canonicalize().unwrap_or_else(|_| PathBuf::from(path));
+                let mut lines = Vec::new();
+
+                let breakpoints = arguments["breakpoints"].as_array().into_iter().flatten().map(|breakpoint| {
+                    let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+
+                    match breakpoint_line(&path, line) {
+                        Some(line) => {
+                            lines.push(line);
+                            json!({ "verified": true, "line": line })
+                        },
+                        None => json!({ "verified": false, "line": line, "message": "There is no code on or after this line." }),
+                    }
+                }).collect::<Vec<Json>>();
+
+                controller.set_breakpoints(path, lines);
+                self.respond(request, json!({ "breakpoints": breakpoints }));
+            },
+            "setFunctionBreakpoints" => {
+                let names = arguments["breakpoints"].as_array().into_iter().flatten()
+                    .filter_map(|breakpoint| breakpoint["name"].as_str().map(String::from))
+                    .collect::<Vec<String>>();
+
+                let breakpoints = names.iter().map(|_| json!({ "verified": true })).collect::<Vec<Json>>();
+
+                controller.set_function_breakpoints(names);
+                self.respond(request, json!({ "breakpoints": breakpoints }));
+            },
+            "threads" => {
+                self.respond(request, json!({ "threads": [{ "id": THREAD, "name": "main" }] }));
+            },
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+struct Adapter {
+    client: Rc<Client>,
+    /// The variables behind each `variablesReference` handed out since the
+    /// program stopped, which are only valid until it carries on.
+    references: Vec<Vec<(String, Value)>>,
+}
+
+impl Adapter {
+    fn reference(&mut self, variables: Vec<(String, Value)>) -> usize {
+        self.references.push(variables);
+        self.references.len()
+    }
+
+    /// Lists, maps and struct instances can be expanded to show what's inside.
+    fn children(&mut self, value: &Value) -> usize {
+        match value.children() {
+            children if children.is_empty() => 0,
+            children => self.reference(children),
+        }
+    }
+
+    fn variable(&mut self, name: String, value: Value) -> Json {
+        json!({
+            "name": name,
+            "value": display(&value),
+            "type": value.clone().typestring(),
+            "variablesReference": self.children(&value),
+        })
+    }
+
+    fn scope(&mut self, name: &str, variables: Vec<(String, Value)>) -> Json {
+        json!({
+            "name": name,
+            "variablesReference": self.reference(variables),
+            "expensive": false,
+        })
+    }
+
+    fn disconnect(&mut self, request: &Json) -> InterpreterResult {
+        self.client.respond(request, Json::Null);
+        self.client.disconnected.set(true);
+
+        InterpreterResult::Stopped
+    }
+}
+
+impl Frontend for Adapter {
+    fn poll(&mut self, controller: &mut Controller) -> Result<(), InterpreterResult> {
+        while let Ok(request) = self.client.requests.try_recv() {
+            if self.client.configure(&request, controller) {
+                continue;
+            }
+
+            match command(&request) {
+                "pause" => {
+                    controller.pause();
+                    self.client.respond(&request, Json::Null);
+                },
+                "disconnect" | "terminate" => return Err(self.disconnect(&request)),
+                _ => self.client.fail(&request, "The program is running."),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stopped(&mut self, interpreter: &mut Interpreter, reason: Reason, location: &Location, controller: &mut Controller) -> Result<Step, InterpreterResult> {
+        self.references.clear();
+        self.client.event("stopped", json!({
+            "reason": reason.as_str(),
+            "threadId": THREAD,
+            "allThreadsStopped": true,
+        }));
+
+        loop {
+            let request = match self.client.requests.recv() {
+                Ok(request) => request,
+                Err(_) => return Err(InterpreterResult::Stopped),
+            };
+
+            if self.client.configure(&request, controller) {
+                continue;
+            }
+
+            let arguments = &request["arguments"];
+
+            let body = match command(&request) {
+                "stackTrace" => {
+                    let frames = stack(interpreter, location).into_iter().enumerate().map(|(i, frame)| {
+                        json!({
+                            "id": i + 1,
+                            "name": frame.name,
+                            "source": {
+                                "name": frame.path.file_name().unwrap_or_default().to_string_lossy(),
+                                "path": frame.path.display().to_string(),
+                            },
+                            "line": frame.span.line,
+                            "column": frame.span.column,
+                        })
+                    }).collect::<Vec<Json>>();
+
+                    json!({ "totalFrames": frames.len(), "stackFrames": frames })
+                },
+                "scopes" => {
+                    let mut scopes = interpreter.scopes();
+                    let globals = scopes.pop().unwrap_or_default();
+                    let mut list = Vec::new();
+
+                    // Only the variables of the innermost call can be seen, and
+                    // inner variables shadow the outer ones with the same name.
+                    if arguments["frameId"].as_u64() == Some(1) && ! scopes.is_empty() {
+                        let mut locals: Vec<(String, Value)> = Vec::new();
+
+                        for (name, value) in scopes.into_iter().flatten() {
+                            if ! locals.iter().any(|(local, _)| *local == name) {
+                                locals.push((name, value));
+                            }
+                        }
+
+                        list.push(self.scope("Locals", locals));
+                    }
+
+                    list.push(self.scope("Globals", globals));
+
+                    json!({ "scopes": list })
+                },
+                "variables" => {
+                    let reference = arguments["variablesReference"].as_u64().unwrap_or_default() as usize;
+                    let variables = self.references.get(reference.wrapping_sub(1)).cloned().unwrap_or_default();
+                    let variables = variables.into_iter().map(|(name, value)| self.variable(name, value)).collect::<Vec<Json>>();
+
+                    json!({ "variables": variables })
+                },
+                "evaluate" => match evaluate(interpreter, arguments["expression"].as_str().unwrap_or_default()) {
+                    Ok(value) => json!({
+                        "result": display(&value),
+                        "type": value.clone().typestring(),
+                        "variablesReference": self.children(&value),
+                    }),
+                    Err(message) => {
+                        self.client.fail(&request, &message);
+                        continue;
+                    },
+                },
+                "continue" => {
+                    self.client.respond(&request, json!({ "allThreadsContinued": true }));
+                    return Ok(Step::Continue);
+                },
+                "next" | "stepIn" | "stepOut" => {
+                    self.client.respond(&request, Json::Null);
+
+                    return Ok(match command(&request) {
+                        "next" => Step::Over,
+                        "stepIn" => Step::In,
+                        _ => Step::Out,
+                    });
+                },
+                "pause" => Json::Null,
+                "disconnect" | "terminate" => return Err(self.disconnect(&request)),
+                other => {
+                    self.client.fail(&request, &format!("Unknown request `{}`.", other));
+                    continue;
+                },
+            };
+
+            self.client.respond(&request, body);
+        }
+    }
+}
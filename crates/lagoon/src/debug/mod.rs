@@ -0,0 +1,345 @@
+//! A debugger for `lagoon debug`. The `Controller` decides where the program
+//! should stop, based on the breakpoints and the last step that was asked for,
+//! and a `Frontend` talks to the user whenever it does: either a debug adapter
+//! that an editor drives, or an interactive prompt in the terminal.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use lagoon_interpreter::{Debugger, Frame, FrameKind, Interpreter, InterpreterResult, Value};
+use lagoon_parser::{generate, parse, Else, Expression, ExpressionKind, Program, Span, Statement, StatementKind};
+
+pub mod dap;
+pub mod terminal;
+
+/// How the program carries on after it has stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Continue,
+    /// Stop at the next statement, including the first one inside of a call.
+    In,
+    /// Stop at the next line, without stopping inside of calls.
+    Over,
+    /// Stop once the current function has returned.
+    Out,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    Entry,
+    Breakpoint,
+    FunctionBreakpoint,
+    Step,
+    Pause,
+}
+
+impl Reason {
+    /// The reason as it's named in the debug adapter protocol.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reason::Entry => "entry",
+            Reason::Breakpoint => "breakpoint",
+            Reason::FunctionBreakpoint => "function breakpoint",
+            Reason::Step => "step",
+            Reason::Pause => "pause",
+        }
+    }
+}
+
+/// Where a statement is about to run. The depth is the number of calls on the
+/// stack, which tells steps whether a statement is inside of a call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub span: Span,
+    pub depth: usize,
+}
+
+impl Location {
+    fn new(interpreter: &Interpreter, span: Span) -> Self {
+        Self { path: interpreter.current_path(), span, depth: interpreter.frames().len() }
+    }
+
+    fn same_line(&self, other: &Location) -> bool {
+        self.path == other.path && self.span.line == other.span.line
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Controller {
+    breakpoints: HashMap<PathBuf, BTreeSet<usize>>,
+    functions: HashSet<String>,
+    step: Option<(Step, Location)>,
+    last: Option<Location>,
+    entry: bool,
+    pause: bool,
+    called: bool,
+}
+
+impl Controller {
+    /// Stop before the first statement runs.
+    pub fn stop_on_entry(&mut self) {
+        self.entry = true;
+    }
+
+    pub fn set_breakpoints(&mut self, path: PathBuf, lines: impl IntoIterator<Item = usize>) {
+        self.breakpoints.insert(path, lines.into_iter().collect());
+    }
+
+    pub fn add_breakpoint(&mut self, path: PathBuf, line: usize) {
+        self.breakpoints.entry(path).or_default().insert(line);
+    }
+
+    pub fn remove_breakpoint(&mut self, path: &Path, line: usize) -> bool {
+        self.breakpoints.get_mut(path).map(|lines| lines.remove(&line)).unwrap_or(false)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (&PathBuf, usize)> {
+        self.breakpoints.iter().flat_map(|(path, lines)| lines.iter().map(move |line| (path, *line)))
+    }
+
+    /// Stop whenever a function with one of these names is called.
+    pub fn set_function_breakpoints(&mut self, names: impl IntoIterator<Item = String>) {
+        self.functions = names.into_iter().collect();
+    }
+
+    pub fn add_function_breakpoint(&mut self, name: String) {
+        self.functions.insert(name);
+    }
+
+    pub fn remove_function_breakpoint(&mut self, name: &str) -> bool {
+        self.functions.remove(name)
+    }
+
+    pub fn function_breakpoints(&self) -> impl Iterator<Item = &String> {
+        self.functions.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.functions.clear();
+    }
+
+    /// Stop at the next statement that runs.
+    pub fn pause(&mut self) {
+        self.pause = true;
+    }
+
+    /// Decide whether the program should stop before the statement at
+    /// `location` runs. A breakpoint only stops the program once each time a
+    /// line is reached, even when the line holds more than one statement.
+    fn check(&mut self, location: &Location) -> Option<Reason> {
+        let moved = self.last.as_ref().map(|last| ! last.same_line(location)).unwrap_or(true);
+
+        if moved {
+            self.last = None;
+        }
+
+        if std::mem::take(&mut self.entry) {
+            return Some(Reason::Entry);
+        }
+
+        if std::mem::take(&mut self.pause) {
+            return Some(Reason::Pause);
+        }
+
+        if std::mem::take(&mut self.called) {
+            return Some(Reason::FunctionBreakpoint);
+        }
+
+        if moved && self.breakpoints.get(&location.path).map(|lines| lines.contains(&location.span.line)).unwrap_or(false) {
+            return Some(Reason::Breakpoint);
+        }
+
+        let stepped = match &self.step {
+            Some((Step::In, origin)) => ! origin.same_line(location) || location.depth != origin.depth,
+            Some((Step::Over, origin)) => location.depth < origin.depth || (location.depth == origin.depth && ! origin.same_line(location)),
+            Some((Step::Out, origin)) => location.depth < origin.depth,
+            Some((Step::Continue, _)) | None => false,
+        };
+
+        match stepped {
+            true => Some(Reason::Step),
+            false => None,
+        }
+    }
+
+    fn resume(&mut self, step: Step, location: Location) {
+        self.step = Some((step, location.clone()));
+        self.last = Some(location);
+    }
+}
+
+/// Talks to the user on behalf of a debugging session.
+pub trait Frontend {
+    /// Called before every statement, e.g. to handle requests that arrive
+    /// while the program is running.
+    fn poll(&mut self, _controller: &mut Controller) -> Result<(), InterpreterResult> {
+        Ok(())
+    }
+
+    /// Called when the program has stopped. The program is paused until this
+    /// returns, and carries on with the step that it returns.
+    fn stopped(&mut self, interpreter: &mut Interpreter, reason: Reason, location: &Location, controller: &mut Controller) -> Result<Step, InterpreterResult>;
+}
+
+pub struct Session<F: Frontend> {
+    controller: Controller,
+    frontend: F,
+}
+
+impl<F: Frontend> Session<F> {
+    pub fn new(controller: Controller, frontend: F) -> Self {
+        Self { controller, frontend }
+    }
+}
+
+impl<F: Frontend> Debugger for Session<F> {
+    fn statement(&mut self, interpreter: &mut Interpreter, span: Span) -> Result<(), InterpreterResult> {
+        self.frontend.poll(&mut self.controller)?;
+
+        let location = Location::new(interpreter, span);
+
+        if let Some(reason) = self.controller.check(&location) {
+            let step = self.frontend.stopped(interpreter, reason, &location, &mut self.controller)?;
+
+            self.controller.resume(step, location);
+        }
+
+        Ok(())
+    }
+
+    fn call(&mut self, _interpreter: &mut Interpreter, frame: &Frame) -> Result<(), InterpreterResult> {
+        if frame.kind != FrameKind::Native && self.controller.functions.contains(&frame.name) {
+            self.controller.called = true;
+        }
+
+        Ok(())
+    }
+}
+
+/// A call on the stack and the statement it is running.
+pub struct StackFrame {
+    pub name: String,
+    pub path: PathBuf,
+    pub span: Span,
+}
+
+/// The call stack, innermost call first. Each frame remembers where it was
+/// called from, which is where the frame outside of it is up to.
+pub fn stack(interpreter: &Interpreter, location: &Location) -> Vec<StackFrame> {
+    let mut stack = Vec::new();
+    let mut span = location.span;
+
+    for frame in interpreter.frames().iter().rev() {
+        stack.push(StackFrame { name: frame.to_string(), path: location.path.clone(), span });
+        span = frame.span;
+    }
+
+    stack.push(StackFrame { name: "<main>".to_string(), path: location.path.clone(), span });
+    stack
+}
+
+/// Evaluate an expression written by the user where the program has stopped.
+pub fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<Value, String> {
+    let expression = match parse(generate(source)) {
+        Ok(mut program) if program.len() == 1 => match program.remove(0).kind {
+            StatementKind::Expression { expression } => expression,
+            _ => return Err("Only expressions can be evaluated.".to_string()),
+        },
+        Ok(_) => return Err("Expected a single expression.".to_string()),
+        Err(error) => return Err(error.to_string()),
+    };
+
+    interpreter.evaluate(expression).map_err(|error| error.inner().to_string())
+}
+
+/// Show a value the way it would be written in a script, so that strings can
+/// be told apart from other values.
+pub fn display(value: &Value) -> String {
+    match value {
+        Value::Constant(value) => display(value),
+        Value::String(string) => format!("{:?}", string),
+        value => format!("{:?}", value),
+    }
+}
+
+/// Where a breakpoint at `line` of a file will stop: the first line on or
+/// after it that a statement starts on.
+pub fn breakpoint_line(path: &Path, line: usize) -> Option<usize> {
+    let source = read_to_string(path).ok()?;
+    let program = parse(generate(&source)).ok()?;
+
+    statement_lines(&program).range(line..).next().copied()
+}
+
+fn statement_lines(program: &Program) -> BTreeSet<usize> {
+    let mut lines = BTreeSet::new();
+
+    walk_statements(program, &mut lines);
+    lines
+}
+
+fn walk_statements(block: &[Statement], lines: &mut BTreeSet<usize>) {
+    for statement in block {
+        lines.insert(statement.span.line);
+
+        match &statement.kind {
+            StatementKind::FunctionDeclaration { body, .. } => walk_statements(body, lines),
+            StatementKind::If { condition, then, otherwise } => {
+                walk_expression(condition, lines);
+                walk_statements(then, lines);
+
+                match otherwise {
+                    Some(Else::If(statement)) => walk_statements(std::slice::from_ref(statement), lines),
+                    Some(Else::Block(block)) => walk_statements(block, lines),
+                    None => {},
+                }
+            },
+            StatementKind::For { iterable: value, then, .. } | StatementKind::While { condition: value, then } => {
+                walk_expression(value, lines);
+                walk_statements(then, lines);
+            },
+            StatementKind::Try { then, catch, finally } => {
+                walk_statements(then, lines);
+                catch.iter().for_each(|catch| walk_statements(&catch.then, lines));
+                finally.iter().for_each(|finally| walk_statements(finally, lines));
+            },
+            StatementKind::Export { declaration } => walk_statements(std::slice::from_ref(declaration), lines),
+            StatementKind::Return { value } | StatementKind::Throw { value } | StatementKind::Expression { expression: value } |
+            StatementKind::ConstDeclaration { initial: value, .. } | StatementKind::LetDeclaration { initial: Some(value), .. } => walk_expression(value, lines),
+            _ => {},
+        }
+    }
+}
+
+/// Closures hold statements of their own, wherever they are written.
+fn walk_expression(expression: &Expression, lines: &mut BTreeSet<usize>) {
+    let mut children: Vec<&Expression> = Vec::new();
+
+    match &expression.kind {
+        ExpressionKind::Closure(_, body) => walk_statements(body, lines),
+        ExpressionKind::Assign(left, right) | ExpressionKind::Infix(left, _, right) => children.extend([&**left, &**right]),
+        ExpressionKind::Prefix(_, value) | ExpressionKind::Get(value, _) => children.push(value),
+        ExpressionKind::Call(callee, arguments) => {
+            children.push(callee);
+            children.extend(arguments);
+        },
+        ExpressionKind::Struct(definition, fields) => {
+            children.push(definition);
+            children.extend(fields.values());
+        },
+        ExpressionKind::Index(value, index) => {
+            children.push(value);
+            children.extend(index.as_deref());
+        },
+        ExpressionKind::List(items) => children.extend(items),
+        ExpressionKind::Map(items) => items.iter().for_each(|(key, value)| children.extend([key, value])),
+        _ => {},
+    }
+
+    for child in children {
+        walk_expression(child, lines);
+    }
+}
@@ -0,0 +1,242 @@
+//! `lagoon debug --terminal`, which stops before the first statement and reads
+//! commands from stdin whenever the program is stopped.
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use std::process::exit;
+
+use lagoon_interpreter::{Interpreter, InterpreterResult};
+
+use super::{breakpoint_line, display, evaluate, stack, Controller, Frontend, Location, Reason, Session, Step};
+use crate::manifest;
+
+const HELP: &str = "\
+break, b <line|file:line|function>  Stop at a line or whenever a function is called
+delete <line|file:line|function>    Remove a breakpoint, or every breakpoint without an argument
+breakpoints                         Show every breakpoint
+continue, c                         Run until the next breakpoint
+step, s                             Run the next statement, stopping inside of calls
+next, n                             Run the next line, stepping over calls
+out, o                              Run until the current function returns
+backtrace, bt                       Show the call stack
+vars, v                             Show the variables that are in scope
+print, p <expression>               Evaluate an expression
+watch, w [expression]               Evaluate an expression whenever the program stops
+unwatch <number>                    Stop watching an expression
+list, l                             Show the code around the current line
+quit, q                             Stop the program";
+
+pub fn start(mut interpreter: Interpreter, source: &str, name: &str) {
+    let mut controller = Controller::default();
+
+    controller.stop_on_entry();
+    interpreter.set_debugger(Session::new(controller, Terminal::default()));
+
+    match interpreter.run() {
+        Err(e) if matches!(e.inner(), InterpreterResult::Stopped) => {},
+        Err(e) => {
+            e.print(source, name);
+            exit(1);
+        },
+        Ok(()) => {},
+    }
+}
+
+#[derive(Default)]
+struct Terminal {
+    sources: HashMap<PathBuf, Vec<String>>,
+    watches: Vec<String>,
+    previous: String,
+}
+
+impl Frontend for Terminal {
+    fn stopped(&mut self, interpreter: &mut Interpreter, reason: Reason, location: &Location, controller: &mut Controller) -> Result<Step, InterpreterResult> {
+        let line = location.span.line;
+
+        match reason {
+            Reason::Entry => println!("Stopped at the start of {}.", name(&location.path)),
+            Reason::Breakpoint | Reason::FunctionBreakpoint => println!("Stopped at a breakpoint in {}:{}.", name(&location.path), line),
+            Reason::Step | Reason::Pause => {},
+        }
+
+        self.show_lines(&location.path, line..=line, line);
+        self.show_watches(interpreter);
+
+        loop {
+            print!("(debug) ");
+            io::stdout().flush().unwrap();
+
+            let mut input = String::new();
+
+            // The program carries on by itself once there's nothing left to read.
+            if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+                println!();
+                controller.clear();
+
+                return Ok(Step::Continue);
+            }
+
+            let input = match input.trim() {
+                "" => self.previous.clone(),
+                input => input.to_string(),
+            };
+
+            let (command, argument) = match input.split_once(' ') {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (input.as_str(), ""),
+            };
+
+            self.previous = input.clone();
+
+            match command {
+                "break" | "b" => self.add_breakpoint(argument, location, controller),
+                "delete" | "clear" => self.remove_breakpoint(argument, location, controller),
+                "breakpoints" => {
+                    let mut functions = controller.function_breakpoints().collect::<Vec<&String>>();
+
+                    functions.sort();
+
+                    for (path, line) in controller.breakpoints() {
+                        println!("{}:{}", name(path), line);
+                    }
+
+                    for function in functions {
+                        println!("{}", function);
+                    }
+                },
+                "continue" | "c" => return Ok(Step::Continue),
+                "step" | "s" => return Ok(Step::In),
+                "next" | "n" => return Ok(Step::Over),
+                "out" | "finish" | "o" => return Ok(Step::Out),
+                "backtrace" | "bt" => {
+                    for (i, frame) in stack(interpreter, location).iter().enumerate() {
+                        println!("#{} {} at {}:{}", i, frame.name, name(&frame.path), frame.span.line);
+                    }
+                },
+                "vars" | "v" => {
+                    let scopes = interpreter.scopes();
+
+                    for (i, scope) in scopes.iter().enumerate() {
+                        println!("{}:", if i == scopes.len() - 1 { "Globals" } else { "Locals" });
+
+                        for (name, value) in scope {
+                            println!("    {} = {}", name, display(value));
+                        }
+                    }
+                },
+                "print" | "p" => match evaluate(interpreter, argument) {
+                    Ok(value) => println!("{}", display(&value)),
+                    Err(message) => println!("error: {}", message),
+                },
+                "watch" | "w" => {
+                    if ! argument.is_empty() {
+                        self.watches.push(argument.to_string());
+                    }
+
+                    self.show_watches(interpreter);
+                },
+                "unwatch" => match argument.parse::<usize>() {
+                    Ok(number) if number >= 1 && number <= self.watches.len() => {
+                        self.watches.remove(number - 1);
+                    },
+                    _ => println!("error: there is no watch expression numbered `{}`", argument),
+                },
+                "list" | "l" => self.show_lines(&location.path, line.saturating_sub(5).max(1)..=line + 5, line),
+                "help" | "h" | "?" => println!("{}", HELP),
+                "quit" | "q" => return Err(InterpreterResult::Stopped),
+                _ => println!("error: unknown command `{}`, type `help` to see every command", input),
+            }
+        }
+    }
+}
+
+impl Terminal {
+    /// Show some lines of a file, pointing at the current one.
+    fn show_lines(&mut self, path: &Path, range: RangeInclusive<usize>, current: usize) {
+        let lines = self.sources.entry(path.to_path_buf())
+            .or_insert_with(|| read_to_string(path).unwrap_or_default().lines().map(String::from).collect());
+
+        for number in *range.start()..=(*range.end()).min(lines.len()) {
+            let marker = if number == current { ">" } else { " " };
+
+            println!("{} {:>4} | {}", marker, number, lines[number - 1]);
+        }
+    }
+
+    fn show_watches(&mut self, interpreter: &mut Interpreter) {
+        for (i, watch) in self.watches.iter().enumerate() {
+            match evaluate(interpreter, watch) {
+                Ok(value) => println!("{}: {} = {}", i + 1, watch, display(&value)),
+                Err(message) => println!("{}: {} = <error: {}>", i + 1, watch, message),
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, target: &str, location: &Location, controller: &mut Controller) {
+        match breakpoint(target, location) {
+            Some(Target::Line(path, line)) => {
+                match breakpoint_line(&path, line) {
+                    Some(line) => {
+                        println!("Breakpoint set at {}:{}.", name(&path), line);
+                        controller.add_breakpoint(path, line);
+                    },
+                    None => println!("error: there is no code on or after line {} of {}", line, name(&path)),
+                }
+            },
+            Some(Target::Function(function)) => {
+                println!("Breakpoint set on `{}`.", function);
+                controller.add_function_breakpoint(function);
+            },
+            None => println!("error: expected a line, `file:line` or the name of a function"),
+        }
+    }
+
+    fn remove_breakpoint(&mut self, target: &str, location: &Location, controller: &mut Controller) {
+        if target.is_empty() {
+            controller.clear();
+            println!("Deleted every breakpoint.");
+            return;
+        }
+
+        let removed = match breakpoint(target, location) {
+            Some(Target::Line(path, line)) => controller.remove_breakpoint(&path, line),
+            Some(Target::Function(function)) => controller.remove_function_breakpoint(&function),
+            None => false,
+        };
+
+        if ! removed {
+            println!("error: there is no breakpoint at `{}`", target);
+        }
+    }
+}
+
+enum Target {
+    Line(PathBuf, usize),
+    Function(String),
+}
+
+/// Work out where a breakpoint goes. Lines on their own are in the file that
+/// is running, and files are relative to the current directory.
+fn breakpoint(target: &str, location: &Location) -> Option<Target> {
+    if let Ok(line) = target.parse() {
+        return Some(Target::Line(location.path.clone(), line));
+    }
+
+    match target.rsplit_once(':') {
+        Some((file, line)) => {
+            let path = Path::new(file).canonicalize().ok()?;
+
+            Some(Target::Line(path, line.parse().ok()?))
+        },
+        None if ! target.is_empty() && target.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '?') => Some(Target::Function(target.to_string())),
+        None => None,
+    }
+}
+
+fn name(path: &Path) -> String {
+    manifest::slashes(&manifest::relative(&std::env::current_dir().unwrap(), path))
+}
@@ -0,0 +1,61 @@
+//! The `Content-Length` framing that the language server and debug adapter
+//! protocols wrap their JSON messages in.
+
+use std::io::{self, BufRead};
+
+use serde_json::Value;
+
+/// Read the body of the next message, which comes after a `Content-Length`
+/// header and a blank line.
+pub fn read_message(input: &mut impl BufRead) -> Option<String> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        let header = header.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length?];
+
+    input.read_exact(&mut body).ok()?;
+
+    String::from_utf8(body).ok()
+}
+
+pub fn write_message(output: &mut impl io::Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_and_writes_messages() {
+        let mut output = Vec::new();
+
+        write_message(&mut output, &serde_json::json!({ "id": 1, "text": "🌴" })).unwrap();
+
+        assert_eq!(String::from_utf8(output.clone()).unwrap(), "Content-Length: 22\r\n\r\n{\"id\":1,\"text\":\"🌴\"}");
+        assert_eq!(read_message(&mut output.as_slice()).as_deref(), Some("{\"id\":1,\"text\":\"🌴\"}"));
+        assert_eq!(read_message(&mut "Content-Length: 10\r\n\r\n{}".as_bytes()), None);
+    }
+}
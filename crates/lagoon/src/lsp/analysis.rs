@@ -0,0 +1,437 @@
+//! What the language server knows about a document: where every function,
+//! struct and variable is declared and which declaration each identifier refers
+//! to. Names are looked up by the interpreter's resolver, so functions and
+//! structs are visible everywhere and function bodies can see the variables
+//! that are declared after them.
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use lagoon_parser::{generate, parse_recovering, Expression, ExpressionKind, Parameter, Program, Span, StatementKind, Token};
+use lagoon_parser::diagnostics::Diagnostic;
+use lagoon_interpreter::{register_global_functions, resolve_with, Declaration, Interpreter, Lints, ListObject, MapObject, NumberObject, StringObject, Visitor};
+
+pub const KEYWORDS: &[&str] = &[
+    "fn", "let", "const", "if", "else", "struct", "while", "for", "in", "return", "break", "continue",
+    "throw", "try", "catch", "finally", "import", "export", "true", "false", "null",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Variable,
+    Constant,
+    Parameter,
+}
+
+/// The kinds of value that have built-in methods.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    String,
+    List,
+    Number,
+    Map,
+}
+
+impl Type {
+    pub const ALL: &'static [Type] = &[Type::String, Type::List, Type::Number, Type::Map];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::String => "String",
+            Type::List => "List",
+            Type::Number => "Number",
+            Type::Map => "Map",
+        }
+    }
+
+    pub fn methods(&self) -> &'static [&'static str] {
+        match self {
+            Type::String => StringObject::METHODS,
+            Type::List => ListObject::METHODS,
+            Type::Number => NumberObject::METHODS,
+            Type::Map => MapObject::METHODS,
+        }
+    }
+
+    fn of(expression: &Expression) -> Option<Self> {
+        match expression.kind {
+            ExpressionKind::String(..) => Some(Type::String),
+            ExpressionKind::List(..) => Some(Type::List),
+            ExpressionKind::Number(..) => Some(Type::Number),
+            ExpressionKind::Map(..) => Some(Type::Map),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where the name itself is written.
+    pub span: Span,
+    /// The whole declaration.
+    pub range: Span,
+    /// How the declaration is shown when hovering over it.
+    pub detail: String,
+    pub fields: Vec<Parameter>,
+    /// Whether the symbol is declared at the top-level of the document.
+    pub top_level: bool,
+    /// The offsets that the name can be used from.
+    pub visible: Range<usize>,
+    /// The kind of value the variable was initialised with, if it's obvious.
+    pub value: Option<Type>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub span: Span,
+    pub symbol: usize,
+    /// Whether the reference is a struct field written as `Point { x }`, which
+    /// uses the variable of the same name.
+    pub shorthand: bool,
+}
+
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    pub fn new(source: &str, path: PathBuf, lints: &Lints) -> Self {
+        let (program, errors) = parse_recovering(generate(source));
+        let mut indexer = Indexer::new(source);
+
+        // Names that can't be found are reported by the check below.
+        let _ = resolve_with(&program, builtins(), &mut indexer);
+
+        let Indexer { symbols, references, .. } = indexer;
+
+        // Only a program that parsed can be checked, otherwise the statements
+        // the parser skipped would show up as undefined variables.
+        let diagnostics = match errors.is_empty() {
+            true => check(program, path, lints),
+            false => errors.iter().map(|error| error.diagnostic()).collect(),
+        };
+
+        Self { symbols, references, diagnostics }
+    }
+
+    /// Find the symbol that is declared or used at `offset`.
+    pub fn symbol_at(&self, offset: usize) -> Option<usize> {
+        self.symbols.iter().position(|symbol| symbol.span.contains(offset))
+            .or_else(|| self.references.iter().find(|reference| reference.span.contains(offset)).map(|reference| reference.symbol))
+    }
+
+    /// Every place that `symbol` is written, starting with its declaration.
+    pub fn occurrences(&self, symbol: usize) -> Vec<(Span, bool)> {
+        let mut occurrences = vec![(self.symbols[symbol].span, false)];
+
+        occurrences.extend(self.references.iter().filter(|reference| reference.symbol == symbol).map(|reference| (reference.span, reference.shorthand)));
+        occurrences
+    }
+
+    /// The symbols that can be used at `offset`, leaving out the ones that are
+    /// shadowed by a closer declaration.
+    pub fn visible(&self, offset: usize) -> Vec<&Symbol> {
+        let mut visible: Vec<&Symbol> = Vec::new();
+
+        for symbol in self.symbols.iter().filter(|symbol| symbol.visible.contains(&offset)) {
+            match visible.iter().position(|other| other.name == symbol.name) {
+                Some(index) if symbol.visible.start >= visible[index].visible.start => visible[index] = symbol,
+                Some(_) => (),
+                None => visible.push(symbol),
+            }
+        }
+
+        visible
+    }
+
+    /// When `offset` is just after a `.`, or in the middle of a name that comes
+    /// after one, find the type of the value before the dot. `Some(None)` means
+    /// there is a dot but the type isn't known.
+    pub fn receiver(&self, source: &str, offset: usize) -> Option<Option<Type>> {
+        let before = source.get(..offset)?.trim_end_matches(is_identifier);
+        let before = before.strip_suffix('.')?;
+
+        Some(match before.chars().last() {
+            Some('"') => Some(Type::String),
+            Some(']') => Some(Type::List),
+            Some(c) if c.is_ascii_digit() => Some(Type::Number),
+            Some(c) if is_identifier(c) => {
+                let name = &before[before.trim_end_matches(is_identifier).len()..];
+
+                self.visible(offset).into_iter().find(|symbol| symbol.name == name).and_then(|symbol| symbol.value)
+            },
+            _ => None,
+        })
+    }
+}
+
+pub fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '?'
+}
+
+/// The functions that every script can call without declaring them.
+pub fn builtins() -> Vec<String> {
+    let mut interpreter = Interpreter::new(Vec::new(), PathBuf::new());
+
+    register_global_functions(&mut interpreter);
+
+    let mut names: Vec<String> = interpreter.globals.keys().cloned().collect();
+
+    names.sort();
+    names
+}
+
+fn check(program: Program, path: PathBuf, lints: &Lints) -> Vec<Diagnostic> {
+    let mut interpreter = Interpreter::new(program, path);
+
+    register_global_functions(&mut interpreter);
+
+    interpreter.check(lints).iter().map(|problem| problem.diagnostic()).collect()
+}
+
+/// Converts between byte offsets and the line and UTF-16 character positions
+/// that editors use.
+pub struct Lines<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).collect();
+
+        Self { source, starts }
+    }
+
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = self.starts.partition_point(|start| *start <= offset) - 1;
+        let start = self.starts[line];
+
+        (line, self.source.get(start..offset).map(|text| text.encode_utf16().count()).unwrap_or_default())
+    }
+
+    pub fn offset(&self, line: usize, character: usize) -> usize {
+        let start = match self.starts.get(line) {
+            Some(start) => *start,
+            None => return self.source.len(),
+        };
+
+        let mut units = 0;
+
+        for (i, c) in self.source[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return start + i;
+            }
+
+            units += c.len_utf16();
+        }
+
+        self.source.len()
+    }
+}
+
+/// Turns what the resolver finds into symbols and references.
+struct Indexer<'a> {
+    source: &'a str,
+    /// Every identifier token, in order, used to find where a declaration's
+    /// name is written.
+    identifiers: Vec<(String, Span)>,
+    symbols: Vec<Symbol>,
+    references: Vec<Reference>,
+    /// Where the values of struct fields written as `Point { x }` start.
+    shorthands: HashSet<usize>,
+}
+
+impl<'a> Indexer<'a> {
+    fn new(source: &'a str) -> Self {
+        let identifiers = generate(source).into_iter()
+            .filter_map(|token| match token.token {
+                Token::Identifier(name) => Some((name, token.span)),
+                _ => None,
+            })
+            .collect();
+
+        Self { source, identifiers, symbols: Vec::new(), references: Vec::new(), shorthands: HashSet::new() }
+    }
+
+    /// The span of the first identifier called `name` inside of `within`.
+    fn name_span(&self, name: &str, within: Span) -> Span {
+        let first = self.identifiers.partition_point(|(_, span)| span.start < within.start);
+
+        self.identifiers[first..].iter()
+            .take_while(|(_, span)| span.end <= within.end)
+            .find(|(identifier, _)| identifier == name)
+            .map(|(_, span)| *span)
+            .unwrap_or(within)
+    }
+
+    /// The symbol for a declaration, along with the offset it can be used from
+    /// when it's declared inside of a block.
+    fn symbol(&self, declaration: Declaration) -> (Symbol, usize) {
+        let name = declaration.name();
+        let symbol = |kind, span: Span, range, detail| {
+            Symbol { name: name.to_string(), kind, span, range, detail, fields: Vec::new(), top_level: false, visible: 0..0, value: None }
+        };
+
+        match declaration {
+            Declaration::Statement(statement, top_level) => {
+                let span = self.name_span(name, statement.span);
+
+                let mut found = match &statement.kind {
+                    StatementKind::FunctionDeclaration { params, .. } => {
+                        let detail = format!("fn {}({})", name, params.iter().map(|p| p.name.as_str()).collect::<Vec<&str>>().join(", "));
+
+                        symbol(SymbolKind::Function, span, statement.span, detail)
+                    },
+                    StatementKind::StructDeclaration { fields, .. } => {
+                        let detail = match fields.is_empty() {
+                            true => format!("struct {} {{}}", name),
+                            false => format!("struct {} {{\n    {}\n}}", name, fields.iter().map(|f| f.name.as_str()).collect::<Vec<&str>>().join(",\n    ")),
+                        };
+
+                        Symbol { fields: fields.clone(), ..symbol(SymbolKind::Struct, span, statement.span, detail) }
+                    },
+                    StatementKind::ConstDeclaration { initial, .. } => {
+                        Symbol { value: Type::of(initial), ..symbol(SymbolKind::Constant, span, statement.span, format!("const {}", name)) }
+                    },
+                    StatementKind::LetDeclaration { initial, .. } => {
+                        Symbol { value: initial.as_ref().and_then(Type::of), ..symbol(SymbolKind::Variable, span, statement.span, format!("let {}", name)) }
+                    },
+                    _ => unreachable!(),
+                };
+
+                found.top_level = top_level;
+
+                (found, statement.span.end)
+            },
+            Declaration::Import(statement, imported) => {
+                let detail = match &statement.kind {
+                    StatementKind::Import { path, .. } => format!("import {{ {} }} from \"{}\"", name, path),
+                    _ => unreachable!(),
+                };
+
+                (symbol(SymbolKind::Constant, imported.span, statement.span, detail), statement.span.end)
+            },
+            Declaration::Parameter(param) => (symbol(SymbolKind::Parameter, param.span, param.span, format!("(parameter) {}", name)), param.span.start),
+            // The loop variables are written before the iterable.
+            Declaration::Loop(statement, _) => {
+                let within = match &statement.kind {
+                    StatementKind::For { iterable, .. } => Span { end: iterable.span.start, ..statement.span },
+                    _ => statement.span,
+                };
+
+                let span = self.name_span(name, within);
+
+                (symbol(SymbolKind::Variable, span, statement.span, format!("let {}", name)), span.end)
+            },
+            Declaration::Catch(error) => (symbol(SymbolKind::Variable, error.span, error.span, format!("let {}", name)), error.span.end),
+        }
+    }
+}
+
+impl<'a> Visitor<'_> for Indexer<'a> {
+    fn declare(&mut self, declaration: Declaration, end: Option<usize>) {
+        let (mut symbol, from) = self.symbol(declaration);
+
+        symbol.visible = match end {
+            Some(end) => from..end,
+            None => 0..self.source.len() + 1,
+        };
+
+        self.symbols.push(symbol);
+    }
+
+    fn redeclare(&mut self, declaration: Declaration, existing: usize) {
+        let (symbol, _) = self.symbol(declaration);
+
+        self.references.push(Reference { span: symbol.span, symbol: existing, shorthand: false });
+    }
+
+    fn reference(&mut self, span: Span, declaration: usize) {
+        self.references.push(Reference { span, symbol: declaration, shorthand: self.shorthands.contains(&span.start) });
+    }
+
+    fn field(&mut self, name: &str, value: &Expression) {
+        if let ExpressionKind::Identifier(identifier, _) = &value.kind {
+            if identifier == name && ! self.source[..value.span.start].trim_end().ends_with(':') {
+                self.shorthands.insert(value.span.start);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyse(source: &str) -> Analysis {
+        Analysis::new(source, PathBuf::from("test.lag"), &Lints::default())
+    }
+
+    fn definition(source: &str, at: &str) -> Option<String> {
+        let analysis = analyse(source);
+        let offset = source.find(at).unwrap();
+
+        analysis.symbol_at(offset).map(|symbol| {
+            let symbol = &analysis.symbols[symbol];
+            format!("{} {}", symbol.detail.lines().next().unwrap(), symbol.span.line)
+        })
+    }
+
+    #[test]
+    fn it_finds_definitions_through_scopes() {
+        let source = "fn add(a, b) {\nreturn a + b\n}\nlet a = add(1, 2)\nif true {\nlet a = 3\nprintln(a * 2)\n}\nprintln(a / 2)";
+
+        assert_eq!(definition(source, "add(1").as_deref(), Some("fn add(a, b) 1"));
+        assert_eq!(definition(source, "a + b").as_deref(), Some("(parameter) a 1"));
+        assert_eq!(definition(source, "a * 2").as_deref(), Some("let a 6"));
+        assert_eq!(definition(source, "a / 2").as_deref(), Some("let a 4"));
+        assert_eq!(definition(source, "println"), None);
+
+        // Function bodies can use variables that are declared after them.
+        let source = "fn show() {\nprintln(later)\n}\nlet later = 1";
+        assert_eq!(definition(source, "later)").as_deref(), Some("let later 4"));
+    }
+
+    #[test]
+    fn it_finds_loop_variables_and_shorthand_fields() {
+        let source = "struct Point { x }\nfor x in [1] {\nprintln(Point { x })\n}";
+        let analysis = analyse(source);
+        let symbol = analysis.symbol_at(source.find("x })").unwrap()).unwrap();
+
+        assert_eq!(definition(source, "x })").as_deref(), Some("let x 2"));
+        assert_eq!(analysis.occurrences(symbol).iter().map(|(_, shorthand)| *shorthand).collect::<Vec<bool>>(), vec![false, true]);
+        assert!(builtins().contains(&"println".to_string()));
+    }
+
+    #[test]
+    fn it_knows_what_is_visible() {
+        let source = "let name = \"Lagoon\"\nfn greet(greeting) {\nlet loud = greeting\n\n}\nname.";
+        let analysis = analyse(source);
+        let names = |offset| analysis.visible(offset).iter().map(|symbol| symbol.name.clone()).collect::<Vec<String>>();
+
+        assert_eq!(names(source.find("\n\n").unwrap() + 1), vec!["name", "greet", "greeting", "loud"]);
+        assert_eq!(names(source.len()), vec!["name", "greet"]);
+        assert_eq!(analysis.receiver(source, source.len()), Some(Some(Type::String)));
+        assert_eq!(analysis.receiver(source, source.find("greet(").unwrap() + 3), None);
+    }
+
+    #[test]
+    fn it_converts_positions() {
+        let lines = Lines::new("let a = \"🌴\"\nlet b = 1");
+
+        assert_eq!(lines.position(19), (1, 4));
+        assert_eq!(lines.offset(1, 4), 19);
+        assert_eq!(lines.position(13), (0, 11));
+        assert_eq!(lines.offset(0, 11), 13);
+        assert_eq!(lines.offset(0, 99), 14);
+    }
+}
@@ -0,0 +1,397 @@
+//! A language server, which editors start with `lagoon lsp` and talk to with
+//! JSON-RPC messages over stdin and stdout.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use lagoon_parser::Span;
+use lagoon_parser::diagnostics::{Diagnostic, Severity};
+
+use serde_json::{json, Value as Json};
+
+use crate::json;
+use crate::manifest::Project;
+use analysis::{builtins, is_identifier, Analysis, Lines, SymbolKind, Type, KEYWORDS};
+
+mod analysis;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_FAILED: i64 = -32803;
+
+pub fn start() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut server = Server { output: io::stdout(), documents: HashMap::new(), builtins: builtins(), shutdown: false };
+
+    while let Some(body) = json::read_message(&mut input) {
+        let message: Json = match serde_json::from_str(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                server.error(Json::Null, -32700, &format!("Invalid JSON: {}", error));
+                continue;
+            },
+        };
+
+        if message["method"] == "exit" {
+            break;
+        }
+
+        server.handle(&message);
+    }
+
+    // Leaving without being asked to shut down first means something went wrong.
+    std::process::exit(if server.shutdown { 0 } else { 1 });
+}
+
+struct Document {
+    source: String,
+    analysis: Analysis,
+}
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>,
+    builtins: Vec<String>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Json) {
+        let _ = json::write_message(&mut self.output, &message);
+    }
+
+    fn respond(&mut self, id: Json, result: Json) {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+    }
+
+    fn error(&mut self, id: Json, code: i64, message: &str) {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }));
+    }
+
+    fn notify(&mut self, method: &str, params: Json) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn handle(&mut self, message: &Json) {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = message["id"].clone();
+
+        let result = match method {
+            "initialize" => Ok(initialize()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            },
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                self.open(document["uri"].as_str().unwrap_or_default(), document["text"].as_str().unwrap_or_default());
+                return;
+            },
+            "textDocument/didChange" => {
+                // Documents are always synced in full, so the last change has
+                // the whole text.
+                if let Some(text) = params["contentChanges"].as_array().and_then(|changes| changes.last()).and_then(|change| change["text"].as_str()) {
+                    self.open(params["textDocument"]["uri"].as_str().unwrap_or_default(), text);
+                }
+
+                return;
+            },
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+
+                self.documents.remove(&uri);
+                self.notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }));
+                return;
+            },
+            "textDocument/definition" => self.at(params).map(|(uri, document, offset)| definition(uri, document, offset)),
+            "textDocument/hover" => self.at(params).map(|(_, document, offset)| hover(document, offset, &self.builtins)),
+            "textDocument/completion" => self.at(params).map(|(_, document, offset)| completion(document, offset, &self.builtins)),
+            "textDocument/documentSymbol" => match self.documents.get(params["textDocument"]["uri"].as_str().unwrap_or_default()) {
+                Some(document) => Ok(document_symbols(document)),
+                None => Err((INVALID_PARAMS, "The document isn't open.".to_string())),
+            },
+            "textDocument/rename" => {
+                let name = params["newName"].as_str().unwrap_or_default().to_string();
+
+                self.at(params).and_then(|(uri, document, offset)| rename(uri, document, offset, &name))
+            },
+            _ if id.is_null() => return,
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method `{}`.", method))),
+        };
+
+        match result {
+            Ok(result) => self.respond(id, result),
+            Err((code, message)) => self.error(id, code, &message),
+        }
+    }
+
+    /// Find the document and the offset that a request's position points to.
+    fn at<'s>(&'s self, params: &'s Json) -> Result<(&'s str, &'s Document, usize), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let document = self.documents.get(uri).ok_or((INVALID_PARAMS, "The document isn't open.".to_string()))?;
+        let line = params["position"]["line"].as_u64().unwrap_or_default() as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or_default() as usize;
+
+        Ok((uri, document, Lines::new(&document.source).offset(line, character)))
+    }
+
+    fn open(&mut self, uri: &str, text: &str) {
+        let path = path(uri);
+        // A language server has to carry on when the manifest can't be read, so
+        // the default lints are used instead.
+        let lints = Project::find(&crate::directory_of(&path)).ok().flatten().map(|project| project.manifest.lints).unwrap_or_default();
+        let document = Document { source: text.to_string(), analysis: Analysis::new(text, path, &lints) };
+
+        let lines = Lines::new(&document.source);
+        let diagnostics = document.analysis.diagnostics.iter().map(|diagnostic| self::diagnostic(&lines, diagnostic)).collect::<Vec<Json>>();
+
+        self.notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics }));
+        self.documents.insert(uri.to_string(), document);
+    }
+}
+
+fn initialize() -> Json {
+    json!({
+        "capabilities": {
+            "textDocumentSync": 1,
+            "definitionProvider": true,
+            "hoverProvider": true,
+            "completionProvider": { "triggerCharacters": ["."] },
+            "documentSymbolProvider": true,
+            "renameProvider": true,
+        },
+        "serverInfo": { "name": "lagoon", "version": crate::VERSION },
+    })
+}
+
+/// Turn a `file://` URI into a path, decoding any escaped characters.
+fn path(uri: &str) -> PathBuf {
+    let encoded = uri.strip_prefix("file://").unwrap_or(uri).as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+
+    while i < encoded.len() {
+        let escaped = match encoded[i] {
+            b'%' => encoded.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()),
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            },
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            },
+        }
+    }
+
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn position(lines: &Lines, offset: usize) -> Json {
+    let (line, character) = lines.position(offset);
+
+    json!({ "line": line, "character": character })
+}
+
+fn range(lines: &Lines, span: Span) -> Json {
+    json!({ "start": position(lines, span.start), "end": position(lines, span.end) })
+}
+
+fn diagnostic(lines: &Lines, diagnostic: &Diagnostic) -> Json {
+    let span = diagnostic.labels.iter().find(|label| label.primary).map(|label| label.span).unwrap_or_default();
+
+    let mut message = diagnostic.message.clone();
+
+    for note in &diagnostic.notes {
+        message.push_str(&format!("\nnote: {}", note));
+    }
+
+    for help in &diagnostic.help {
+        message.push_str(&format!("\nhelp: {}", help));
+    }
+
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+
+    let mut entry = json!({ "range": range(lines, span), "severity": severity, "source": "lagoon", "message": message });
+
+    if let Some(code) = &diagnostic.code {
+        entry["code"] = code.as_str().into();
+    }
+
+    entry
+}
+
+/// The name that `offset` is in, along with where it starts.
+fn word_at(source: &str, offset: usize) -> Option<(&str, usize)> {
+    let start = source[..offset].trim_end_matches(is_identifier).len();
+    let end = offset + source[offset..].len() - source[offset..].trim_start_matches(is_identifier).len();
+
+    match start < end {
+        true => Some((&source[start..end], start)),
+        false => None,
+    }
+}
+
+fn definition(uri: &str, document: &Document, offset: usize) -> Json {
+    let analysis = &document.analysis;
+
+    match analysis.symbol_at(offset) {
+        Some(symbol) => json!({
+            "uri": uri,
+            "range": range(&Lines::new(&document.source), analysis.symbols[symbol].span),
+        }),
+        None => Json::Null,
+    }
+}
+
+fn hover(document: &Document, offset: usize, builtins: &[String]) -> Json {
+    let (word, start) = match word_at(&document.source, offset) {
+        Some(word) => word,
+        None => return Json::Null,
+    };
+
+    let method = document.source[..start].ends_with('.');
+
+    let contents = match document.analysis.symbol_at(offset) {
+        Some(symbol) if ! method => document.analysis.symbols[symbol].detail.clone(),
+        None if method => {
+            let types = match document.analysis.receiver(&document.source, start) {
+                Some(Some(receiver)) => vec![receiver],
+                _ => Type::ALL.to_vec(),
+            };
+
+            let owners: Vec<String> = types.into_iter()
+                .filter(|receiver| receiver.methods().contains(&word))
+                .map(|receiver| format!("{}.{}()", receiver.name(), word))
+                .collect();
+
+            if owners.is_empty() {
+                return Json::Null;
+            }
+
+            format!("(method) {}", owners.join(" | "))
+        },
+        None if builtins.iter().any(|builtin| builtin == word) => format!("(builtin) fn {}", word),
+        _ => return Json::Null,
+    };
+
+    let lines = Lines::new(&document.source);
+
+    json!({
+        "contents": { "kind": "markdown", "value": format!("```lagoon\n{}\n```", contents) },
+        "range": range(&lines, Span::new(start, start + word.len(), 0, 0)),
+    })
+}
+
+fn completion_item(label: &str, kind: usize, detail: &str) -> Json {
+    json!({ "label": label, "kind": kind, "detail": detail })
+}
+
+fn completion(document: &Document, offset: usize, builtins: &[String]) -> Json {
+    let analysis = &document.analysis;
+
+    // After a `.`, only the methods of the value before it make sense.
+    if let Some(receiver) = analysis.receiver(&document.source, offset) {
+        let types = match receiver {
+            Some(receiver) => vec![receiver],
+            None => Type::ALL.to_vec(),
+        };
+
+        let mut items: Vec<Json> = Vec::new();
+        let mut seen: Vec<&str> = Vec::new();
+
+        for receiver in types {
+            for method in receiver.methods() {
+                if ! seen.contains(method) {
+                    seen.push(method);
+                    items.push(completion_item(method, 2, &format!("{} method", receiver.name())));
+                }
+            }
+        }
+
+        return Json::Array(items);
+    }
+
+    let mut items: Vec<Json> = analysis.visible(offset).into_iter()
+        .map(|symbol| {
+            let kind = match symbol.kind {
+                SymbolKind::Function => 3,
+                SymbolKind::Struct => 22,
+                SymbolKind::Constant => 21,
+                SymbolKind::Variable | SymbolKind::Parameter => 6,
+            };
+
+            completion_item(&symbol.name, kind, symbol.detail.lines().next().unwrap_or_default())
+        })
+        .collect();
+
+    items.extend(builtins.iter().map(|name| completion_item(name, 3, "builtin")));
+    items.extend(KEYWORDS.iter().map(|keyword| completion_item(keyword, 14, "keyword")));
+
+    Json::Array(items)
+}
+
+fn document_symbols(document: &Document) -> Json {
+    let lines = Lines::new(&document.source);
+    let symbol = |name: &str, kind: usize, detail: &str, full: Span, selection: Span, children: Vec<Json>| json!({
+        "name": name,
+        "detail": detail,
+        "kind": kind,
+        "range": range(&lines, full),
+        "selectionRange": range(&lines, selection),
+        "children": children,
+    });
+
+    let symbols = document.analysis.symbols.iter()
+        .filter(|found| found.top_level)
+        .map(|found| {
+            let kind = match found.kind {
+                SymbolKind::Function => 12,
+                SymbolKind::Struct => 23,
+                SymbolKind::Constant => 14,
+                SymbolKind::Variable | SymbolKind::Parameter => 13,
+            };
+
+            let fields = found.fields.iter().map(|field| symbol(&field.name, 8, "", field.span, field.span, Vec::new())).collect();
+
+            symbol(&found.name, kind, found.detail.lines().next().unwrap_or_default(), found.range, found.span, fields)
+        })
+        .collect();
+
+    Json::Array(symbols)
+}
+
+fn rename(uri: &str, document: &Document, offset: usize, name: &str) -> Result<Json, (i64, String)> {
+    if name.is_empty() || ! name.chars().all(is_identifier) || KEYWORDS.contains(&name) {
+        return Err((REQUEST_FAILED, format!("`{}` is not a valid name.", name)));
+    }
+
+    let symbol = document.analysis.symbol_at(offset).ok_or((REQUEST_FAILED, "There is nothing to rename here.".to_string()))?;
+    let lines = Lines::new(&document.source);
+    let original = &document.analysis.symbols[symbol].name;
+
+    let edits = document.analysis.occurrences(symbol).into_iter()
+        .map(|(span, shorthand)| {
+            // A shorthand field keeps its name and takes the renamed variable.
+            let text = match shorthand {
+                true => format!("{}: {}", original, name),
+                false => name.to_string(),
+            };
+
+            json!({ "range": range(&lines, span), "newText": text })
+        })
+        .collect::<Vec<Json>>();
+
+    Ok(json!({ "changes": { uri: edits } }))
+}
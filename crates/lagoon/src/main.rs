@@ -1,13 +1,32 @@
-use std::fs::read_to_string;
-use clap::{Arg, App, AppSettings};
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, read_dir, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use clap::{Arg, App, AppSettings, ArgMatches};
 
-use lagoon_parser::{generate, parse};
-use lagoon_interpreter::{interpret};
+use colored::*;
+use lagoon_parser::{format, generate, parse_recovering, Program, StatementKind};
+use lagoon_parser::diagnostics::colors_enabled;
+use lagoon_interpreter::{register_global_functions, Interpreter, Engine, Level, Limits, Lint, Permissions, Access};
+
+use manifest::{Project, ProjectError};
 
 mod cmd;
+mod debug;
+mod json;
+mod lsp;
+mod manifest;
+mod repl;
 
 const VERSION: &str = "0.1-beta";
 
+// Every call made by a script also uses the Rust stack, so scripts are run on
+// a thread with enough room for the default maximum call depth.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn main() {
     let matches = App::new("Lagoon")
         .version(VERSION)
@@ -20,9 +39,39 @@ fn main() {
                 .version(VERSION)
                 .arg(
                     Arg::new("file")
-                        .about("The Lagoon file to execute.")
-                        .required(true)
+                        .about("The Lagoon file to execute. Defaults to the entry point in `lagoon.toml`.")
+                )
+                .arg(vm())
+                .args(script_options())
+        )
+        .subcommand(
+            App::new("debug")
+                .about("Debug a Lagoon file from an editor, which talks to the debugger over stdin and stdout.")
+                .version(VERSION)
+                .arg(
+                    Arg::new("file")
+                        .about("The Lagoon file to debug. Defaults to the entry point in `lagoon.toml`.")
                 )
+                .arg(
+                    Arg::new("terminal")
+                        .long("terminal")
+                        .about("Debug the file from the terminal instead of an editor.")
+                )
+                .args(script_options())
+        )
+        .subcommand(
+            App::new("test")
+                .about("Run every Lagoon file in the project's tests directory.")
+                .version(VERSION)
+                .arg(vm())
+                .args(script_options())
+        )
+        .subcommand(
+            App::new("repl")
+                .about("Start an interactive session.")
+                .version(VERSION)
+                .arg(vm())
+                .args(script_options())
         )
         .subcommand(
             App::new("js")
@@ -30,46 +79,804 @@ fn main() {
                 .version(VERSION)
                 .arg(
                     Arg::new("file")
-                        .about("The Lagoon file to transpile.")
-                        .required(true)
+                        .about("The Lagoon file to transpile. Defaults to the entry point in `lagoon.toml`.")
                 )
                 .arg(
                     Arg::new("output")
                         .about("The target destination for the transpiled file.")
-                        .required(true)
+                )
+                .arg(
+                    Arg::new("out")
+                        .about("The target destination for the transpiled file, which lets the file be left out inside of a project.")
+                        .long("out")
+                        .short('o')
+                        .takes_value(true)
+                        .conflicts_with("output")
+                )
+                .arg(locked())
+        )
+        .subcommand(
+            App::new("check")
+                .about("Look for mistakes in Lagoon files without running them.")
+                .version(VERSION)
+                .arg(
+                    Arg::new("files")
+                        .about("The files or directories to check. Defaults to every file in the project.")
+                        .multiple_values(true)
+                )
+                .args(vec![
+                    lint_level("allow", "Don't report these lints."),
+                    lint_level("warn", "Report these lints as warnings."),
+                    lint_level("deny", "Report these lints as errors."),
+                ])
+        )
+        .subcommand(
+            App::new("fmt")
+                .about("Format Lagoon files.")
+                .version(VERSION)
+                .arg(
+                    Arg::new("files")
+                        .about("The files or directories to format. Defaults to every file in the project.")
+                        .multiple_values(true)
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .about("Report the files that aren't formatted instead of changing them.")
+                )
+        )
+        .subcommand(
+            App::new("lsp")
+                .about("Start a language server that talks over stdin and stdout.")
+                .version(VERSION)
+        )
+        .subcommand(
+            App::new("init")
+                .about("Create a new Lagoon project.")
+                .version(VERSION)
+                .arg(
+                    Arg::new("directory")
+                        .about("The directory to create the project in. Defaults to the current directory.")
                 )
         )
         .get_matches();
 
-    if let Some(ref run) = matches.subcommand_matches("run") {
-        let file = run.value_of("file").unwrap();
-        let path = std::path::PathBuf::from(file);
-        let contents = read_to_string(file).unwrap();
-        let tokens = generate(contents.as_str());
-        
-        match parse(tokens) {
-            Ok(ast) => {
-                match interpret(ast, path) {
-                    Ok(_) => {},
-                    Err(e) => e.print(),
-                };
+    match matches.subcommand() {
+        Some(("run", run)) => run_script(run),
+        Some(("debug", debug)) => debug_script(debug),
+        Some(("test", test)) => run_tests(test),
+        Some(("repl", repl)) => start_repl(repl),
+        Some(("js", js)) => transpile(js),
+        Some(("check", check)) => check_files(check),
+        Some(("fmt", fmt)) => format_files(fmt),
+        Some(("lsp", _)) => lsp::start(),
+        Some(("init", init)) => create_project(init),
+        _ => unreachable!(),
+    }
+}
+
+/// The options shared by every command that runs scripts.
+fn script_options<'a>() -> Vec<Arg<'a>> {
+    vec![
+        Arg::new("fuel")
+            .long("fuel")
+            .takes_value(true)
+            .value_name("STEPS")
+            .about("Stop the script after it has taken this many steps."),
+        Arg::new("max-call-depth")
+            .long("max-call-depth")
+            .takes_value(true)
+            .value_name("CALLS")
+            .about("The maximum number of nested function calls."),
+        Arg::new("max-memory")
+            .long("max-memory")
+            .takes_value(true)
+            .value_name("BYTES")
+            .about("The approximate number of bytes the script can allocate."),
+        Arg::new("timeout")
+            .long("timeout")
+            .takes_value(true)
+            .value_name("MILLISECONDS")
+            .about("Stop the script after it has run for this long."),
+        permission("allow-read", "PATHS", "Allow the script to read files, optionally only inside of the given paths."),
+        permission("allow-write", "PATHS", "Allow the script to write files, optionally only inside of the given paths."),
+        permission("allow-env", "NAMES", "Allow the script to read environment variables, optionally only the given ones."),
+        permission("allow-run", "PROGRAMS", "Allow the script to run programs, optionally only the given ones."),
+        permission("allow-modules", "PATHS", "Allow the script to load modules from outside of its own directory."),
+        Arg::new("allow-all")
+            .long("allow-all")
+            .short('A')
+            .about("Allow the script to do anything."),
+        locked(),
+    ]
+}
+
+/// Scripts can only be debugged on the tree-walker, so `debug` doesn't offer
+/// this option.
+fn vm<'a>() -> Arg<'a> {
+    Arg::new("vm")
+        .long("vm")
+        .about("Compile the file to bytecode and run it on the virtual machine.")
+}
+
+fn locked<'a>() -> Arg<'a> {
+    Arg::new("locked")
+        .long("locked")
+        .about("Fail instead of updating `lagoon.lock` when it is out of date.")
+}
+
+fn permission<'a>(name: &'a str, value_name: &'a str, about: &'a str) -> Arg<'a> {
+    Arg::new(name)
+        .long(name)
+        .takes_value(true)
+        .min_values(0)
+        .require_equals(true)
+        .use_delimiter(true)
+        .value_name(value_name)
+        .about(about)
+}
+
+fn lint_level<'a>(name: &'a str, about: &'a str) -> Arg<'a> {
+    Arg::new(name)
+        .long(name)
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .use_delimiter(true)
+        .value_name("LINTS")
+        .about(about)
+}
+
+/// The file given on the command line, or else the entry point of the project
+/// in the current directory, along with the project that the file is part of.
+fn script_and_project(matches: &ArgMatches) -> (PathBuf, Option<Project>) {
+    let (file, project) = match matches.value_of("file") {
+        Some(file) => (PathBuf::from(file), find_project(&directory_of(Path::new(file)))),
+        None => {
+            let project = require_project();
+
+            (display_path(&project.entry()), Some(project))
+        },
+    };
+
+    if let Some(project) = &project {
+        or_exit(project.update_lockfile(matches.is_present("locked")));
+    }
+
+    (file, project)
+}
+
+fn run_script(run: &ArgMatches) {
+    let (file, project) = script_and_project(run);
+    let options = Options::new(run, project.as_ref());
+    let name = file.display().to_string();
+    let contents = read_or_exit(&file);
+    let ast = parse_or_exit(&contents, &name);
+    let path = file.canonicalize().unwrap();
+
+    let script = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        let mut interpreter = options.interpreter(ast, path);
+
+        if let Err(e) = interpreter.run() {
+            e.print(&contents, &name);
+            exit(1);
+        }
+    }).unwrap();
+
+    script.join().unwrap();
+}
+
+fn debug_script(debug: &ArgMatches) {
+    let (file, project) = script_and_project(debug);
+    let options = Options::new(debug, project.as_ref());
+    let name = file.display().to_string();
+    let contents = read_or_exit(&file);
+    let ast = parse_or_exit(&contents, &name);
+    let path = file.canonicalize().unwrap();
+    let terminal = debug.is_present("terminal");
+
+    thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        let interpreter = options.interpreter(ast, path);
+
+        match terminal {
+            true => debug::terminal::start(interpreter, &contents, &name),
+            false => debug::dap::start(interpreter, &contents, &name),
+        }
+    }).unwrap().join().unwrap();
+}
+
+fn start_repl(repl: &ArgMatches) {
+    let project = find_project(&std::env::current_dir().unwrap());
+
+    if let Some(project) = &project {
+        or_exit(project.update_lockfile(repl.is_present("locked")));
+    }
+
+    let options = Options::new(repl, project.as_ref());
+
+    thread::Builder::new().stack_size(STACK_SIZE).spawn(move || repl::start(options)).unwrap().join().unwrap();
+}
+
+/// Run every script in the tests directory, each with an interpreter of its
+/// own. A test fails when its script exits with an error.
+fn run_tests(test: &ArgMatches) {
+    let project = require_project();
+
+    or_exit(project.update_lockfile(test.is_present("locked")));
+
+    let options = Options::new(test, Some(&project));
+    let directory = project.tests();
+
+    let mut tests: Vec<PathBuf> = match read_dir(&directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().map(|e| e == "lag").unwrap_or(false))
+            .collect(),
+        Err(e) => abort(&format!("failed to read {}: {}", directory.display(), e)),
+    };
+
+    tests.sort();
+
+    println!("running {} test{}", tests.len(), if tests.len() == 1 { "" } else { "s" });
+
+    let mut failed = 0;
+
+    for test in &tests {
+        let name = manifest::slashes(&manifest::relative(&project.root, test));
+        let contents = read_or_exit(test);
+        let passed = match parse(&contents, &name) {
+            Some(ast) => {
+                let options = options.clone();
+                let path = test.clone();
+                let name = name.clone();
+
+                thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+                    let mut interpreter = options.interpreter(ast, path);
+
+                    match interpreter.run() {
+                        Ok(()) => true,
+                        Err(e) => {
+                            e.print(&contents, &name);
+                            false
+                        },
+                    }
+                }).unwrap().join().unwrap_or(false)
             },
-            Err(e) => e.print(),
+            None => false,
         };
-    } else if let Some(ref js) = matches.subcommand_matches("js") {
-        let file = js.value_of("file").unwrap();
-        let contents = read_to_string(file).unwrap();
-        let output = js.value_of("output").unwrap();
-        let tokens = generate(contents.as_str());
-
-        match parse(tokens) {
-            Ok(ast) => {
-                match cmd::js(ast, output) {
-                    Ok(_) => {},
-                    Err(e) => e.print(), 
-                };
+
+        if passed {
+            println!("test {} ... {}", name, paint(colors_enabled(), "ok", Color::Green));
+        } else {
+            println!("test {} ... {}", name, paint(colors_enabled(), "FAILED", Color::Red));
+            failed += 1;
+        }
+    }
+
+    let result = match failed {
+        0 => paint(colors_enabled(), "ok", Color::Green),
+        _ => paint(colors_enabled(), "FAILED", Color::Red),
+    };
+
+    println!();
+    println!("test result: {}. {} passed; {} failed", result, tests.len() - failed, failed);
+
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+fn transpile(js: &ArgMatches) {
+    let output = match js.value_of("output").or_else(|| js.value_of("out")) {
+        Some(output) => PathBuf::from(output),
+        None => abort("no output file was given, pass one after the file to transpile or with --out"),
+    };
+
+    // Mixing up the arguments shouldn't replace a script with JavaScript.
+    if output.extension().map(|extension| extension == "lag").unwrap_or(false) {
+        abort(&format!("refusing to write JavaScript to the Lagoon file {}", output.display()));
+    }
+
+    let (file, project) = match js.value_of("file") {
+        Some(file) => (PathBuf::from(file), find_project(&directory_of(Path::new(file)))),
+        None => {
+            let project = require_project();
+
+            (display_path(&project.entry()), Some(project))
+        },
+    };
+
+    if let Some(project) = &project {
+        or_exit(project.update_lockfile(js.is_present("locked")));
+    }
+
+    // Every module that the script imports is transpiled next to the output,
+    // keeping its position relative to the script. Packages are transpiled
+    // into a `lagoon_modules` directory next to the output.
+    let packages = directory_of(&output).join("lagoon_modules");
+    let mut queue = vec![(file, output)];
+    let mut transpiled = HashSet::new();
+
+    while let Some((file, output)) = queue.pop() {
+        let contents = read_or_exit(&file);
+
+        if ! transpiled.insert(file.canonicalize().unwrap_or_else(|_| file.clone())) {
+            continue;
+        }
+
+        let name = file.display().to_string();
+        let mut ast = parse_or_exit(&contents, &name);
+        let mut sources = HashMap::new();
+
+        // Imports of packages are pointed at the package's transpiled modules.
+        if let Some(project) = &project {
+            for statement in ast.iter_mut() {
+                if let StatementKind::Import { path, .. } = &mut statement.kind {
+                    if let Some((package, source)) = project.find_module(path) {
+                        let target = packages.join(&package.name).join(source.strip_prefix(&package.root).unwrap());
+                        let import = match manifest::slashes(&manifest::relative(&directory_of(&output), &target)) {
+                            import if import.starts_with("..") => import,
+                            import => format!("./{}", import),
+                        };
+
+                        sources.insert(import.clone(), source);
+                        *path = import;
+                    }
+                }
+            }
+        }
+
+        if let Some(parent) = output.parent() {
+            create_dir_all(parent).ok();
+        }
+
+        let imports = match cmd::js(ast, &output.display().to_string()) {
+            Ok(imports) => imports,
+            Err(e) => {
+                e.print(&contents, &name);
+                exit(1);
+            },
+        };
+
+        for import in imports {
+            let source = match sources.remove(&import) {
+                Some(source) => source,
+                None => sibling(&file, &format!("{}.lag", import.strip_suffix(".lag").unwrap_or(&import))),
+            };
+
+            queue.push((source, sibling(&output, &lagoon_js::module_path(&import))));
+        }
+    }
+}
+
+/// Create a manifest, an entry point and a test in a directory, unless there's
+/// a project there already.
+fn check_files(check: &ArgMatches) {
+    let files = files_or_project(check);
+    let project = match files.first() {
+        Some(file) => find_project(&directory_of(file)),
+        None => None,
+    };
+
+    let mut lints = project.map(|project| project.manifest.lints).unwrap_or_default();
+
+    for level in &[Level::Allow, Level::Warn, Level::Deny] {
+        for name in values(check, &level.to_string()) {
+            match name.parse() {
+                Ok(lint) => lints.set(lint, *level),
+                Err(message) => abort(&format!("{}, expected one of {}", message, Lint::ALL.iter().map(|lint| format!("`{}`", lint)).collect::<Vec<String>>().join(", "))),
+            }
+        }
+    }
+
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for file in files {
+        let name = display_path(&file).display().to_string();
+        let contents = read_or_exit(&file);
+
+        let ast = match parse(&contents, &name) {
+            Some(ast) => ast,
+            None => {
+                errors += 1;
+                continue;
             },
-            Err(e) => e.print(),
         };
+
+        let mut interpreter = Interpreter::new(ast, file);
+
+        register_global_functions(&mut interpreter);
+
+        for problem in interpreter.check(&lints) {
+            problem.print(&contents, &name);
+            eprintln!();
+
+            match problem.level {
+                Level::Deny => errors += 1,
+                _ => warnings += 1,
+            }
+        }
+    }
+
+    let plural = |count: usize, word: &str| format!("{} {}{}", count, word, if count == 1 { "" } else { "s" });
+
+    if errors > 0 {
+        let message = match warnings {
+            0 => format!("could not check due to {}", plural(errors, "previous error")),
+            _ => format!("could not check due to {}; {} emitted", plural(errors, "previous error"), plural(warnings, "warning")),
+        };
+
+        abort(&message);
+    }
+
+    if warnings > 0 {
+        let message = format!("{} emitted", plural(warnings, "warning"));
+
+        if colors_enabled() {
+            eprintln!("{}: {}", "warning".yellow().bold(), message.bold());
+        } else {
+            eprintln!("warning: {}", message);
+        }
+    }
+}
+
+fn format_files(fmt: &ArgMatches) {
+    let files = files_or_project(fmt);
+    let check = fmt.is_present("check");
+    let mut failed = false;
+
+    for file in files {
+        let name = display_path(&file).display().to_string();
+        let contents = read_or_exit(&file);
+
+        let formatted = match format(&contents) {
+            Ok(formatted) => formatted,
+            Err(_) => {
+                parse(&contents, &name);
+                failed = true;
+                continue;
+            },
+        };
+
+        if formatted == contents {
+            continue;
+        }
+
+        if check {
+            println!("{} is not formatted", name);
+            failed = true;
+        } else if let Err(e) = write(&file, formatted) {
+            abort(&format!("failed to write {}: {}", file.display(), e));
+        }
+    }
+
+    if failed {
+        exit(1);
+    }
+}
+
+/// The files named on the command line, with directories replaced by the files
+/// inside of them, or every file in the project when there aren't any.
+fn files_or_project(matches: &ArgMatches) -> Vec<PathBuf> {
+    let targets: Vec<PathBuf> = match matches.values_of("files") {
+        Some(files) => files.map(PathBuf::from).collect(),
+        None => vec![require_project().root],
+    };
+
+    let mut files = Vec::new();
+
+    for target in targets {
+        if target.is_dir() {
+            lagoon_files(&target, &mut files);
+        } else {
+            files.push(target);
+        }
+    }
+
+    files
+}
+
+/// Every Lagoon file inside of `directory`, leaving out hidden directories and
+/// the ones that packages are installed into.
+fn lagoon_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = match read_dir(directory) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+        Err(e) => abort(&format!("failed to read {}: {}", directory.display(), e)),
+    };
+
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+
+        if path.is_dir() {
+            if ! name.starts_with('.') && name != "lagoon_modules" && name != "vendor" {
+                lagoon_files(&path, files);
+            }
+        } else if path.extension().map(|e| e == "lag").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+}
+
+fn create_project(init: &ArgMatches) {
+    let directory = PathBuf::from(init.value_of("directory").unwrap_or("."));
+    let manifest = directory.join(manifest::MANIFEST);
+
+    if manifest.exists() {
+        abort(&format!("`{}` already exists", manifest.display()));
+    }
+
+    let created = create_dir_all(directory.join("src"))
+        .and_then(|_| create_dir_all(directory.join("tests")))
+        .and_then(|_| directory.canonicalize());
+
+    let root = match created {
+        Ok(root) => root,
+        Err(e) => abort(&format!("failed to create {}: {}", directory.display(), e)),
+    };
+
+    // The directory name is the best guess at a name, but it might contain
+    // characters that can't be imported.
+    let name = root.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect();
+    let name = if manifest::valid_name(&name) { name } else { "project".to_string() };
+
+    let files = [
+        (manifest::MANIFEST, format!("[package]\nname = {:?}\nversion = \"0.1.0\"\nentry = \"src/main.lag\"\n\n[dependencies]\n\n[permissions]\n", name)),
+        ("src/main.lag", "println(\"Hello, world!\")\n".to_string()),
+        ("tests/main.lag", "fn assert(condition, message) {\n    if ! condition {\n        throw error(message, \"AssertionFailed\")\n    }\n}\n\nassert(1 + 1 == 2, \"1 + 1 should equal 2\")\n".to_string()),
+    ];
+
+    for (file, contents) in files {
+        let path = root.join(file);
+
+        if path.exists() {
+            continue;
+        }
+
+        if let Err(e) = write(&path, contents) {
+            abort(&format!("failed to write {}: {}", path.display(), e));
+        }
+    }
+
+    println!("Created the project `{}` in {}.", name, directory.display());
+}
+
+/// How scripts are run, taken from the manifest and the command-line flags.
+#[derive(Clone)]
+struct Options {
+    engine: Engine,
+    limits: Limits,
+    permissions: Permissions,
+    search_paths: Vec<PathBuf>,
+    packages: Vec<(String, PathBuf)>,
+}
+
+impl Options {
+    fn new(matches: &ArgMatches, project: Option<&Project>) -> Self {
+        let engine = if matches.is_present("vm") { Engine::Bytecode } else { Engine::TreeWalker };
+
+        let mut limits = Limits::default();
+
+        if let Some(fuel) = limit(matches.value_of("fuel"), "fuel") {
+            limits = limits.with_fuel(fuel);
+        }
+
+        if let Some(depth) = limit(matches.value_of("max-call-depth"), "max-call-depth") {
+            limits = limits.with_max_call_depth(depth);
+        }
+
+        if let Some(bytes) = limit(matches.value_of("max-memory"), "max-memory") {
+            limits = limits.with_max_memory(bytes);
+        }
+
+        if let Some(milliseconds) = limit(matches.value_of("timeout"), "timeout") {
+            limits = limits.with_timeout(Duration::from_millis(milliseconds));
+        }
+
+        let base = project.map(Project::permissions).unwrap_or_default();
+        let mut permissions = permissions(matches, base);
+        let search_paths = search_paths();
+
+        // The directories in `LAGOON_PATH` are chosen by the user, so modules
+        // can always be loaded from them.
+        for path in &search_paths {
+            permissions.modules.allow(path.clone());
+        }
+
+        let packages = project
+            .map(|project| project.packages.iter().map(|package| (package.name.clone(), package.entry.clone())).collect())
+            .unwrap_or_default();
+
+        Self { engine, limits, permissions, search_paths, packages }
+    }
+
+    fn interpreter(self, ast: Program, path: PathBuf) -> Interpreter {
+        let mut interpreter = Interpreter::new(ast, path);
+
+        interpreter.set_engine(self.engine);
+        interpreter.set_limits(self.limits);
+        interpreter.set_permissions(self.permissions);
+        interpreter.set_search_paths(self.search_paths);
+
+        for (name, entry) in self.packages {
+            interpreter.add_package(name, entry);
+        }
+
+        register_global_functions(&mut interpreter);
+
+        interpreter
+    }
+}
+
+/// Add the `--allow-*` flags to the permissions from the manifest. A flag
+/// without a value grants everything, whilst paths are resolved against the
+/// current directory.
+fn permissions(run: &ArgMatches, mut permissions: Permissions) -> Permissions {
+    if run.is_present("allow-all") {
+        return Permissions::all();
+    }
+
+    let directory = std::env::current_dir().unwrap();
+
+    let paths = |name: &str, access: &mut Access<std::path::PathBuf>| if run.is_present(name) {
+        match values(run, name) {
+            values if values.is_empty() => *access = Access::All,
+            values => values.iter().for_each(|value| access.allow(directory.join(value))),
+        }
+    };
+
+    paths("allow-read", &mut permissions.read);
+    paths("allow-write", &mut permissions.write);
+    paths("allow-modules", &mut permissions.modules);
+
+    let names = |name: &str, access: &mut Access<String>| if run.is_present(name) {
+        match values(run, name) {
+            values if values.is_empty() => *access = Access::All,
+            values => values.into_iter().for_each(|value| access.allow(value)),
+        }
+    };
+
+    names("allow-env", &mut permissions.env);
+    names("allow-run", &mut permissions.run);
+
+    permissions
+}
+
+fn values(matches: &ArgMatches, name: &str) -> Vec<String> {
+    matches.values_of(name)
+        .map(|values| values.filter(|value| ! value.is_empty()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn limit<T: FromStr>(value: Option<&str>, name: &str) -> Option<T> {
+    let value = value?;
+
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("error: invalid value {:?} for --{}, expected a whole number", value, name);
+            exit(1);
+        },
+    }
+}
+
+/// The directories listed in `LAGOON_PATH`, which packages are loaded from when
+/// they can't be found in a `lagoon_modules` or `vendor` directory.
+fn search_paths() -> Vec<PathBuf> {
+    let directory = std::env::current_dir().unwrap();
+
+    match std::env::var_os("LAGOON_PATH") {
+        Some(paths) => std::env::split_paths(&paths)
+            .filter(|path| ! path.as_os_str().is_empty())
+            .map(|path| directory.join(path))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The project that `directory` belongs to, if any.
+fn find_project(directory: &Path) -> Option<Project> {
+    or_exit(Project::find(directory))
+}
+
+/// The project that the current directory belongs to, for commands that can't
+/// run without one.
+fn require_project() -> Project {
+    let directory = std::env::current_dir().unwrap();
+
+    match find_project(&directory) {
+        Some(project) => project,
+        None => or_exit(Err(ProjectError::ManifestNotFound(directory))),
+    }
+}
+
+fn or_exit<T>(result: Result<T, ProjectError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            e.print();
+            exit(1);
+        },
+    }
+}
+
+/// The directory that `file` is in, which is the current directory for a
+/// bare file name.
+fn directory_of(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(parent) if ! parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Show a path relative to the current directory where possible.
+fn display_path(path: &Path) -> PathBuf {
+    let directory = std::env::current_dir().unwrap();
+
+    path.strip_prefix(&directory).map(Path::to_path_buf).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Resolve `path` relative to the directory that `file` is in.
+fn sibling(file: &Path, path: &str) -> PathBuf {
+    file.parent().unwrap_or_else(|| Path::new("")).join(path)
+}
+
+fn read_or_exit(file: &Path) -> String {
+    match read_to_string(file) {
+        Ok(contents) => contents,
+        Err(e) => abort(&format!("failed to read {}: {}", file.display(), e)),
+    }
+}
+
+fn parse_or_exit(contents: &str, file: &str) -> Program {
+    match parse(contents, file) {
+        Some(ast) => ast,
+        None => exit(1),
+    }
+}
+
+/// Parse a script, printing any errors that it contains.
+fn parse(contents: &str, file: &str) -> Option<Program> {
+    let (ast, errors) = parse_recovering(generate(contents));
+
+    if errors.is_empty() {
+        return Some(ast);
+    }
+
+    for error in errors.iter() {
+        error.print(contents, file);
+        eprintln!();
+    }
+
+    let message = match errors.len() {
+        1 => "aborting due to previous error".to_string(),
+        n => format!("aborting due to {} previous errors", n),
+    };
+
+    if colors_enabled() {
+        eprintln!("{}: {}", "error".red().bold(), message.bold());
+    } else {
+        eprintln!("error: {}", message);
+    }
+
+    None
+}
+
+fn abort(message: &str) -> ! {
+    if colors_enabled() {
+        eprintln!("{}: {}", "error".red().bold(), message);
+    } else {
+        eprintln!("error: {}", message);
+    }
+
+    exit(1);
+}
+
+fn paint(colors: bool, text: &str, color: Color) -> String {
+    match colors {
+        true => text.color(color).to_string(),
+        false => text.to_string(),
     }
 }
@@ -0,0 +1,620 @@
+//! Projects described by a `lagoon.toml` manifest, along with the packages that
+//! they depend on and the `lagoon.lock` file that records them.
+
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use serde::Deserialize;
+use thiserror::Error;
+use toml::Spanned;
+
+use lagoon_parser::Span;
+use lagoon_parser::diagnostics::Diagnostic;
+use lagoon_interpreter::{Access, Level, Lint, Lints, Permissions};
+
+pub const MANIFEST: &str = "lagoon.toml";
+pub const LOCKFILE: &str = "lagoon.lock";
+
+/// A file that an error points into, so that it can be quoted when printed.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub path: PathBuf,
+    pub source: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ProjectError {
+    #[error("could not find `{}` in {} or any of its parents", MANIFEST, .0.display())]
+    ManifestNotFound(PathBuf),
+
+    #[error("failed to access {}: {1}", .0.display())]
+    Io(PathBuf, std::io::Error),
+
+    #[error("{message}")]
+    InvalidManifest { document: Box<Document>, span: Span, message: String, label: String },
+
+    #[error("the dependency `{name}` could not be found at {}", .path.display())]
+    DependencyNotFound { document: Box<Document>, span: Span, name: String, path: PathBuf },
+
+    #[error("the dependency `{name}` is not a git checkout")]
+    NotAGitCheckout { document: Box<Document>, span: Span, name: String },
+
+    #[error("the package `{name}` is required from two different directories")]
+    ConflictingDependency { document: Box<Document>, span: Span, name: String, first: PathBuf },
+
+    #[error("`{}` needs to be updated, but `--locked` was passed", LOCKFILE)]
+    OutdatedLockfile(PathBuf),
+}
+
+impl ProjectError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProjectError::ManifestNotFound(..) => "E0300",
+            ProjectError::Io(..) => "E0301",
+            ProjectError::InvalidManifest { .. } => "E0302",
+            ProjectError::DependencyNotFound { .. } => "E0303",
+            ProjectError::NotAGitCheckout { .. } => "E0304",
+            ProjectError::ConflictingDependency { .. } => "E0305",
+            ProjectError::OutdatedLockfile(..) => "E0306",
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.to_string()).with_code(self.code());
+
+        match self {
+            ProjectError::ManifestNotFound(..) => diagnostic
+                .with_help("run `lagoon init` to create a project"),
+            ProjectError::InvalidManifest { span, label, .. } => diagnostic
+                .with_label(*span, label.clone()),
+            ProjectError::DependencyNotFound { span, .. } => diagnostic
+                .with_label(*span, "declared here")
+                .with_help("dependency paths are relative to the directory of the manifest"),
+            ProjectError::NotAGitCheckout { span, .. } => diagnostic
+                .with_label(*span, "declared here")
+                .with_help("`git` dependencies must point at a checkout with at least one commit, use `path` for any other directory"),
+            ProjectError::ConflictingDependency { span, first, name, .. } => diagnostic
+                .with_label(*span, "this points at a different directory")
+                .with_note(format!("`{}` was already found at {}", name, first.display())),
+            ProjectError::OutdatedLockfile(..) => diagnostic
+                .with_help("run the command without `--locked` to update it"),
+            _ => diagnostic,
+        }
+    }
+
+    pub fn print(&self) {
+        let (source, path) = match self {
+            ProjectError::InvalidManifest { document, .. } |
+            ProjectError::DependencyNotFound { document, .. } |
+            ProjectError::NotAGitCheckout { document, .. } |
+            ProjectError::ConflictingDependency { document, .. } => (document.source.as_str(), document.path.as_path()),
+            ProjectError::ManifestNotFound(path) | ProjectError::Io(path, _) | ProjectError::OutdatedLockfile(path) => ("", path.as_path()),
+        };
+
+        self.diagnostic().emit(source, &path.display().to_string());
+    }
+}
+
+/// Where a dependency comes from, relative to the manifest that declares it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Path(PathBuf),
+    /// A directory that git has checked a repository out into. The commit that
+    /// is checked out gets recorded in the lockfile.
+    Git(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub source: Source,
+    pub span: Span,
+}
+
+/// The contents of a `lagoon.toml` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub version: Option<String>,
+    /// The script that `lagoon run` runs, relative to the manifest.
+    pub entry: PathBuf,
+    /// The directory that `lagoon test` runs scripts from, relative to the manifest.
+    pub tests: PathBuf,
+    pub dependencies: Vec<Dependency>,
+    /// The permissions that scripts in the project start with. The project's
+    /// own directory can always be loaded from.
+    pub permissions: Permissions,
+    /// The levels of the lints that `lagoon check` reports.
+    pub lints: Lints,
+}
+
+/// A `lagoon.toml` file as it's written, before its values are checked.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    package: Option<Spanned<RawPackage>>,
+    #[serde(default)]
+    dependencies: BTreeMap<Spanned<String>, Spanned<RawDependency>>,
+    #[serde(default)]
+    permissions: RawPermissions,
+    #[serde(default)]
+    lints: BTreeMap<Spanned<String>, Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPackage {
+    name: Option<Spanned<String>>,
+    version: Option<String>,
+    entry: Option<PathBuf>,
+    tests: Option<PathBuf>,
+}
+
+/// A dependency is declared as `name = { path = "..." }` or `name = { git = "..." }`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDependency {
+    path: Option<PathBuf>,
+    git: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RawPermissions {
+    read: Option<RawAccess>,
+    write: Option<RawAccess>,
+    env: Option<RawAccess>,
+    run: Option<RawAccess>,
+    modules: Option<RawAccess>,
+}
+
+/// A permission is either `true`, `false` or a list of the paths or names allowed.
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "expected `true`, `false` or an array of strings")]
+enum RawAccess {
+    Bool(bool),
+    Only(Vec<String>),
+}
+
+impl RawAccess {
+    fn into_access<T>(self, item: impl Fn(&str) -> T) -> Access<T> {
+        match self {
+            RawAccess::Bool(true) => Access::All,
+            RawAccess::Bool(false) => Access::Denied,
+            RawAccess::Only(items) if items.is_empty() => Access::Denied,
+            RawAccess::Only(items) => Access::Only(items.iter().map(|s| item(s)).collect()),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn parse(document: &Document) -> Result<Self, ProjectError> {
+        let root = document.path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let span = |range: Range<usize>| span(&document.source, range);
+        let invalid = |span: Span, message: String, label: &str| ProjectError::InvalidManifest {
+            document: Box::new(document.clone()), span, message, label: label.to_string(),
+        };
+
+        let raw: RawManifest = toml::from_str(&document.source)
+            .map_err(|error| invalid(error.span().map(span).unwrap_or_default(), error.message().to_string(), ""))?;
+
+        let package = raw.package.ok_or_else(|| invalid(Span::default(), "the manifest has no `[package]` table".into(), ""))?;
+        let package_span = span(package.span());
+        let package = package.into_inner();
+
+        let name = package.name.ok_or_else(|| invalid(package_span, "the package has no name".into(), "add `name = \"...\"` to this table"))?;
+
+        // Package names are what scripts import, so they can't contain a `/`.
+        if ! valid_name(name.get_ref()) {
+            return Err(invalid(span(name.span()), format!("invalid package name `{}`", name.get_ref()), "names can only contain letters, numbers, `-` and `_`"));
+        }
+
+        let mut manifest = Manifest {
+            name: name.into_inner(),
+            version: package.version,
+            entry: package.entry.unwrap_or_else(|| PathBuf::from("main.lag")),
+            tests: package.tests.unwrap_or_else(|| PathBuf::from("tests")),
+            dependencies: Vec::new(),
+            permissions: Permissions { modules: Access::Only(vec![root.clone()]), ..Permissions::none() },
+            lints: Lints::default(),
+        };
+
+        for (name, dependency) in raw.dependencies {
+            if ! valid_name(name.get_ref()) {
+                return Err(invalid(span(name.span()), format!("invalid package name `{}`", name.get_ref()), "names can only contain letters, numbers, `-` and `_`"));
+            }
+
+            let dependency_span = span(dependency.span());
+
+            let source = match dependency.into_inner() {
+                RawDependency { path: Some(path), git: None } => Source::Path(path),
+                RawDependency { path: None, git: Some(git) } => Source::Git(git),
+                _ => return Err(invalid(
+                    dependency_span,
+                    format!("the dependency `{}` needs exactly one of `path` or `git`", name.get_ref()),
+                    "expected `{ path = \"...\" }` or `{ git = \"...\" }`",
+                )),
+            };
+
+            manifest.dependencies.push(Dependency { name: name.into_inner(), source, span: dependency_span });
+        }
+
+        // Tables are read into sorted maps, so put the dependencies back in the
+        // order they were written.
+        manifest.dependencies.sort_by_key(|dependency| dependency.span.start);
+
+        let permissions = raw.permissions;
+        let path = |path: &str| root.join(path);
+        let name = |name: &str| name.to_string();
+
+        if let Some(read) = permissions.read {
+            manifest.permissions.read = read.into_access(path);
+        }
+
+        if let Some(write) = permissions.write {
+            manifest.permissions.write = write.into_access(path);
+        }
+
+        if let Some(env) = permissions.env {
+            manifest.permissions.env = env.into_access(name);
+        }
+
+        if let Some(run) = permissions.run {
+            manifest.permissions.run = run.into_access(name);
+        }
+
+        match permissions.modules.map(|modules| modules.into_access(path)) {
+            None | Some(Access::Denied) => (),
+            Some(Access::Only(paths)) => paths.into_iter().for_each(|path| manifest.permissions.modules.allow(path)),
+            Some(Access::All) => manifest.permissions.modules = Access::All,
+        }
+
+        for (lint, level) in raw.lints {
+            let lint: Lint = lint.get_ref().parse()
+                .map_err(|message| invalid(span(lint.span()), message, "not a lint"))?;
+            let level: Level = level.get_ref().parse()
+                .map_err(|message| invalid(span(level.span()), message, "expected `allow`, `warn` or `deny`"))?;
+
+            manifest.lints.set(lint, level);
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Turn a byte range in `source` into a span.
+fn span(source: &str, range: Range<usize>) -> Span {
+    let before = &source[..range.start];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or_default();
+
+    Span::new(range.start, range.end, before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+pub fn valid_name(name: &str) -> bool {
+    ! name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// A package that a project depends on, either directly or through another package.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub name: String,
+    pub version: Option<String>,
+    pub root: PathBuf,
+    /// The module that importing the package by name loads.
+    pub entry: PathBuf,
+    pub git: bool,
+    /// The commit checked out, for packages that come from git.
+    pub commit: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+    /// Every package the project depends on, sorted by name. Packages share a
+    /// single namespace, so a name can only refer to one directory.
+    pub packages: Vec<Package>,
+}
+
+impl Project {
+    /// Find the project that `directory` is part of, if there is one.
+    pub fn find(directory: &Path) -> Result<Option<Project>, ProjectError> {
+        let directory = directory.canonicalize().map_err(|e| ProjectError::Io(directory.to_path_buf(), e))?;
+
+        match directory.ancestors().find(|parent| parent.join(MANIFEST).is_file()) {
+            Some(root) => Project::load(root).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the project in `root`, resolving all of its dependencies.
+    pub fn load(root: &Path) -> Result<Project, ProjectError> {
+        let root = root.canonicalize().map_err(|e| ProjectError::Io(root.to_path_buf(), e))?;
+        let (document, manifest) = read_manifest(&root)?;
+
+        let mut packages: Vec<Package> = Vec::new();
+        let mut pending = vec![(document, manifest.dependencies.clone())];
+
+        while let Some((document, dependencies)) = pending.pop() {
+            let base = document.path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+            for dependency in dependencies {
+                let (path, git) = match &dependency.source {
+                    Source::Path(path) => (base.join(path), false),
+                    Source::Git(path) => (base.join(path), true),
+                };
+
+                let not_found = |path: PathBuf| ProjectError::DependencyNotFound {
+                    document: Box::new(document.clone()), span: dependency.span, name: dependency.name.clone(), path,
+                };
+
+                let directory = match path.canonicalize() {
+                    Ok(directory) if directory.is_dir() => directory,
+                    _ => return Err(not_found(path)),
+                };
+
+                if let Some(existing) = packages.iter().find(|package| package.name == dependency.name) {
+                    if existing.root == directory {
+                        continue;
+                    }
+
+                    return Err(ProjectError::ConflictingDependency {
+                        document: Box::new(document.clone()), span: dependency.span, name: dependency.name, first: existing.root.clone(),
+                    });
+                }
+
+                let commit = match git {
+                    true => Some(head_commit(&directory).ok_or_else(|| ProjectError::NotAGitCheckout {
+                        document: Box::new(document.clone()), span: dependency.span, name: dependency.name.clone(),
+                    })?),
+                    false => None,
+                };
+
+                // A package without a manifest of its own is loaded through its
+                // `main.lag` file, and can't have dependencies.
+                let (entry, version) = if directory.join(MANIFEST).is_file() {
+                    let (document, manifest) = read_manifest(&directory)?;
+
+                    pending.push((document, manifest.dependencies));
+
+                    (directory.join(&manifest.entry), manifest.version)
+                } else {
+                    (directory.join("main.lag"), None)
+                };
+
+                if ! entry.is_file() {
+                    return Err(not_found(entry));
+                }
+
+                packages.push(Package { name: dependency.name, version, root: directory, entry, git, commit });
+            }
+        }
+
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Project { root, manifest, packages })
+    }
+
+    pub fn entry(&self) -> PathBuf {
+        self.root.join(&self.manifest.entry)
+    }
+
+    pub fn tests(&self) -> PathBuf {
+        self.root.join(&self.manifest.tests)
+    }
+
+    /// The permissions from the manifest, which also allow modules to be
+    /// loaded from every package.
+    pub fn permissions(&self) -> Permissions {
+        let mut permissions = self.manifest.permissions.clone();
+
+        for package in &self.packages {
+            permissions.modules.allow(package.root.clone());
+        }
+
+        permissions
+    }
+
+    /// Find the file that an import of a package refers to, such as `utils` or
+    /// `utils/strings`.
+    pub fn find_module(&self, import: &str) -> Option<(&Package, PathBuf)> {
+        let (name, rest) = import.split_once('/').unwrap_or((import, ""));
+        let package = self.packages.iter().find(|package| package.name == name)?;
+
+        if rest.is_empty() {
+            return Some((package, package.entry.clone()));
+        }
+
+        let base = package.entry.parent()?.join(rest);
+        let file = match rest.ends_with(".lag") {
+            true => base.clone(),
+            false => PathBuf::from(format!("{}.lag", base.display())),
+        };
+
+        vec![file, base.join("main.lag")].into_iter()
+            .find(|file| file.is_file())
+            .map(|file| (package, file))
+    }
+
+    /// The contents of the lockfile, which records where every package was
+    /// found and, for git checkouts, which commit was used.
+    pub fn lockfile(&self) -> String {
+        let mut lockfile = format!("# This file is generated from `{}`. It should not be edited by hand.\n", MANIFEST);
+
+        for package in &self.packages {
+            let path = slashes(&relative(&self.root, &package.root));
+
+            lockfile.push_str("\n[[package]]\n");
+            lockfile.push_str(&format!("name = {:?}\n", package.name));
+
+            if let Some(version) = &package.version {
+                lockfile.push_str(&format!("version = {:?}\n", version));
+            }
+
+            match &package.commit {
+                Some(commit) => lockfile.push_str(&format!("source = \"git+{}#{}\"\n", path, commit)),
+                None => lockfile.push_str(&format!("source = \"path+{}\"\n", path)),
+            }
+        }
+
+        lockfile
+    }
+
+    /// Write the lockfile if it has changed. When the lockfile is `locked` it
+    /// is an error for it to need changing instead.
+    pub fn update_lockfile(&self, locked: bool) -> Result<(), ProjectError> {
+        let path = self.root.join(LOCKFILE);
+        let lockfile = self.lockfile();
+
+        if read_to_string(&path).ok().as_deref() == Some(lockfile.as_str()) {
+            return Ok(());
+        }
+
+        if locked {
+            return Err(ProjectError::OutdatedLockfile(path));
+        }
+
+        write(&path, lockfile).map_err(|e| ProjectError::Io(path, e))
+    }
+}
+
+fn read_manifest(root: &Path) -> Result<(Document, Manifest), ProjectError> {
+    let path = root.join(MANIFEST);
+    let source = read_to_string(&path).map_err(|e| ProjectError::Io(path.clone(), e))?;
+    let document = Document { path, source };
+    let manifest = Manifest::parse(&document)?;
+
+    Ok((document, manifest))
+}
+
+/// Read the commit that a git checkout is on, without needing git installed.
+fn head_commit(checkout: &Path) -> Option<String> {
+    let mut git = checkout.join(".git");
+
+    // Worktrees and submodules have a `.git` file pointing at the real directory.
+    if git.is_file() {
+        let pointer = read_to_string(&git).ok()?;
+
+        git = checkout.join(pointer.strip_prefix("gitdir:")?.trim());
+    }
+
+    let head = read_to_string(git.join("HEAD")).ok()?;
+
+    let reference = match head.trim().strip_prefix("ref:") {
+        Some(reference) => reference.trim().to_string(),
+        None => return Some(head.trim().to_string()),
+    };
+
+    if let Ok(commit) = read_to_string(git.join(&reference)) {
+        return Some(commit.trim().to_string());
+    }
+
+    read_to_string(git.join("packed-refs")).ok()?
+        .lines()
+        .find_map(|line| line.strip_suffix(reference.as_str())?.strip_suffix(' ').map(str::to_string))
+}
+
+/// The path to `to` from the directory `from`, e.g. `../utils`. Both paths need
+/// to be absolute, or relative to the same directory.
+pub fn relative(from: &Path, to: &Path) -> PathBuf {
+    let from = normalize(from);
+    let to = normalize(to);
+    let common = from.components().zip(to.components()).take_while(|(a, b)| a == b).count();
+
+    from.components().skip(common).map(|_| Component::ParentDir)
+        .chain(to.components().skip(common))
+        .collect()
+}
+
+/// Resolve `.` and `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(..))) => { normalized.pop(); },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Write a relative path with forward slashes, so that it reads the same on
+/// every platform.
+pub fn slashes(path: &Path) -> String {
+    match path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/") {
+        path if path.is_empty() => ".".to_string(),
+        path => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(source: &str) -> Result<Manifest, ProjectError> {
+        Manifest::parse(&Document { path: PathBuf::from("/project/lagoon.toml"), source: source.to_string() })
+    }
+
+    #[test]
+    fn it_reads_a_manifest() {
+        let manifest = manifest(r#"
+            [package]
+            name = "demo"
+            version = "0.1.0"
+            entry = "src/main.lag"
+
+            [dependencies]
+            utils = { path = "../utils" }
+            http = { git = "vendor/http" }
+
+            [permissions]
+            read = ["data"]
+            env = true
+            modules = ["../shared"]
+
+            [lints]
+            unused-variable = "allow"
+        "#).unwrap();
+
+        assert_eq!(manifest.name, "demo");
+        assert_eq!(manifest.version.as_deref(), Some("0.1.0"));
+        assert_eq!(manifest.entry, PathBuf::from("src/main.lag"));
+        assert_eq!(manifest.tests, PathBuf::from("tests"));
+        assert_eq!(manifest.dependencies.iter().map(|d| d.source.clone()).collect::<Vec<_>>(), vec![
+            Source::Path(PathBuf::from("../utils")),
+            Source::Git(PathBuf::from("vendor/http")),
+        ]);
+        assert_eq!(manifest.permissions.read, Access::Only(vec![PathBuf::from("/project/data")]));
+        assert_eq!(manifest.permissions.write, Access::Denied);
+        assert_eq!(manifest.permissions.env, Access::All);
+        assert_eq!(manifest.permissions.modules, Access::Only(vec![PathBuf::from("/project"), PathBuf::from("/project/../shared")]));
+        assert_eq!(manifest.lints.level(Lint::UnusedVariable), Level::Allow);
+        assert_eq!(manifest.lints.level(Lint::ArgumentCount), Level::Deny);
+    }
+
+    #[test]
+    fn it_rejects_invalid_manifests() {
+        let message = |source: &str| manifest(source).unwrap_err().to_string();
+
+        assert_eq!(message("[package]\nversion = \"1.0\""), "the package has no name");
+        assert_eq!(message("[package]\nname = \"a/b\""), "invalid package name `a/b`");
+        assert_eq!(message("[package]\nname = \"a\"\nauthor = \"b\""), "unknown field `author`, expected one of `name`, `version`, `entry`, `tests`");
+        assert_eq!(message("[package]\nname = true"), "invalid type: boolean `true`, expected a string");
+        assert_eq!(message("[package]\nname = \"a\"\n[dependencies]\nb = { path = \"b\", git = \"b\" }"), "the dependency `b` needs exactly one of `path` or `git`");
+        assert_eq!(message("[package]\nname = \"a\"\n[permissions]\nread = \"all\""), "expected `true`, `false` or an array of strings");
+        assert_eq!(message("[package]\nname = \"a\"\n[lints]\nunused = \"allow\""), "unknown lint `unused`");
+        assert_eq!(message("name = \"a\""), "unknown field `name`, expected one of `package`, `dependencies`, `permissions`, `lints`");
+    }
+
+    #[test]
+    fn it_finds_relative_paths() {
+        assert_eq!(relative(Path::new("/a/b"), Path::new("/a/c/d")), PathBuf::from("../c/d"));
+        assert_eq!(relative(Path::new("dist"), Path::new("dist/lagoon_modules/x")), PathBuf::from("lagoon_modules/x"));
+        assert_eq!(relative(Path::new("dist/../out"), Path::new("out")), PathBuf::from(""));
+        assert_eq!(slashes(&relative(Path::new("/a"), Path::new("/a"))), ".");
+    }
+}
@@ -0,0 +1,315 @@
+//! A small line editor for the REPL. On Unix, when stdin is a terminal it's
+//! switched to raw mode so that the cursor can be moved around the line and
+//! earlier lines can be brought back from the history. Anything else is read a
+//! line at a time.
+
+use std::fs::{read_to_string, write};
+use std::io::{self, BufRead, IsTerminal, Write};
+#[cfg(unix)]
+use std::io::Read;
+use std::path::PathBuf;
+
+/// The number of lines kept in the history file.
+const HISTORY_SIZE: usize = 1000;
+
+pub enum Line {
+    Text(String),
+    /// Ctrl-C was pressed, throwing away the line.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Interrupted,
+    /// Ctrl-D was pressed on an empty line, or the input ran out.
+    Eof,
+}
+
+pub struct Editor {
+    history: Vec<String>,
+    path: Option<PathBuf>,
+    terminal: bool,
+}
+
+impl Editor {
+    /// Create an editor that loads and saves its history at `path`.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let history = path.as_ref()
+            .and_then(|path| read_to_string(path).ok())
+            .map(|history| history.lines().map(String::from).collect())
+            .unwrap_or_default();
+
+        Self { history, path, terminal: io::stdin().is_terminal() }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.terminal
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Line> {
+        #[cfg(unix)]
+        if self.terminal {
+            let _raw = RawMode::enable()?;
+
+            return State { prompt, buffer: Vec::new(), cursor: 0, history: &self.history, index: self.history.len(), draft: Vec::new() }.edit();
+        }
+
+        // Without raw mode the terminal echoes the line itself, so only the
+        // prompt needs to be shown.
+        if self.terminal {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+        }
+
+        let mut line = String::new();
+
+        match io::stdin().lock().read_line(&mut line)? {
+            0 => Ok(Line::Eof),
+            _ => Ok(Line::Text(line.trim_end_matches(&['\r', '\n'][..]).to_string())),
+        }
+    }
+
+    /// Remember a line, skipping blank lines and repeats of the previous one.
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+
+        self.history.push(line.to_string());
+
+        if self.history.len() > HISTORY_SIZE {
+            self.history.drain(..self.history.len() - HISTORY_SIZE);
+        }
+
+        if let Some(path) = &self.path {
+            write(path, self.history.join("\n") + "\n").ok();
+        }
+    }
+}
+
+/// Puts the terminal into raw mode until it's dropped.
+#[cfg(unix)]
+struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        unsafe {
+            let mut original = std::mem::zeroed();
+
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = original;
+
+            raw.c_iflag &= ! (libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+            raw.c_lflag &= ! (libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self { original })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+        }
+    }
+}
+
+/// The line being edited.
+#[cfg(unix)]
+struct State<'a> {
+    prompt: &'a str,
+    buffer: Vec<char>,
+    cursor: usize,
+    history: &'a [String],
+    /// The history entry being shown, which is `history.len()` for a new line.
+    index: usize,
+    /// The new line, kept whilst browsing the history.
+    draft: Vec<char>,
+}
+
+#[cfg(unix)]
+impl<'a> State<'a> {
+    fn edit(mut self) -> io::Result<Line> {
+        let stdin = io::stdin();
+        let mut bytes = stdin.lock().bytes();
+
+        self.refresh()?;
+
+        while let Some(byte) = bytes.next() {
+            match byte? {
+                b'\r' | b'\n' => {
+                    print!("\r\n");
+                    io::stdout().flush()?;
+
+                    return Ok(Line::Text(self.buffer.into_iter().collect()));
+                },
+                // Ctrl-C
+                3 => {
+                    print!("^C\r\n");
+                    io::stdout().flush()?;
+
+                    return Ok(Line::Interrupted);
+                },
+                // Ctrl-D
+                4 if self.buffer.is_empty() => {
+                    print!("\r\n");
+                    io::stdout().flush()?;
+
+                    return Ok(Line::Eof);
+                },
+                4 => self.delete(),
+                // Backspace
+                8 | 127 => if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.buffer.remove(self.cursor);
+                },
+                b'\t' => self.insert("    "),
+                // Ctrl-A and Ctrl-E
+                1 => self.cursor = 0,
+                5 => self.cursor = self.buffer.len(),
+                // Ctrl-B and Ctrl-F
+                2 => self.cursor = self.cursor.saturating_sub(1),
+                6 => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+                // Ctrl-U, Ctrl-K and Ctrl-W
+                21 => {
+                    self.buffer.drain(..self.cursor);
+                    self.cursor = 0;
+                },
+                11 => self.buffer.truncate(self.cursor),
+                23 => {
+                    let mut start = self.cursor;
+
+                    while start > 0 && self.buffer[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+
+                    while start > 0 && ! self.buffer[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+
+                    self.buffer.drain(start..self.cursor);
+                    self.cursor = start;
+                },
+                // Ctrl-L
+                12 => print!("\x1b[H\x1b[2J"),
+                // Ctrl-P and Ctrl-N
+                16 => self.previous(),
+                14 => self.next(),
+                // An escape sequence, e.g. `ESC [ A` for the up arrow.
+                27 => {
+                    let kind = bytes.next().transpose()?;
+                    let code = bytes.next().transpose()?;
+
+                    match (kind, code) {
+                        (Some(b'[') | Some(b'O'), Some(b'A')) => self.previous(),
+                        (Some(b'[') | Some(b'O'), Some(b'B')) => self.next(),
+                        (Some(b'[') | Some(b'O'), Some(b'C')) => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+                        (Some(b'[') | Some(b'O'), Some(b'D')) => self.cursor = self.cursor.saturating_sub(1),
+                        (Some(b'[') | Some(b'O'), Some(b'H')) => self.cursor = 0,
+                        (Some(b'[') | Some(b'O'), Some(b'F')) => self.cursor = self.buffer.len(),
+                        // `ESC [ n ~`, used for home, end and delete.
+                        (Some(b'['), Some(digit @ b'0'..=b'9')) => match (digit, bytes.next().transpose()?) {
+                            (b'1' | b'7', Some(b'~')) => self.cursor = 0,
+                            (b'4' | b'8', Some(b'~')) => self.cursor = self.buffer.len(),
+                            (b'3', Some(b'~')) => self.delete(),
+                            _ => (),
+                        },
+                        _ => (),
+                    }
+                },
+                byte if byte < 32 => (),
+                byte => {
+                    // Read the rest of a multi-byte character.
+                    let length = match byte {
+                        0xF0..=0xFF => 4,
+                        0xE0..=0xEF => 3,
+                        0xC0..=0xDF => 2,
+                        _ => 1,
+                    };
+
+                    let mut character = vec![byte];
+
+                    for _ in 1..length {
+                        if let Some(byte) = bytes.next() {
+                            character.push(byte?);
+                        }
+                    }
+
+                    self.insert(&String::from_utf8_lossy(&character));
+                },
+            }
+
+            self.refresh()?;
+        }
+
+        Ok(Line::Eof)
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.buffer.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.buffer.len() {
+            self.buffer.remove(self.cursor);
+        }
+    }
+
+    fn previous(&mut self) {
+        if self.index == 0 {
+            return;
+        }
+
+        if self.index == self.history.len() {
+            self.draft = self.buffer.clone();
+        }
+
+        self.index -= 1;
+        self.show(self.history[self.index].chars().collect());
+    }
+
+    fn next(&mut self) {
+        if self.index == self.history.len() {
+            return;
+        }
+
+        self.index += 1;
+
+        match self.history.get(self.index) {
+            Some(line) => self.show(line.chars().collect()),
+            None => self.show(self.draft.clone()),
+        }
+    }
+
+    fn show(&mut self, buffer: Vec<char>) {
+        self.cursor = buffer.len();
+        self.buffer = buffer;
+    }
+
+    /// Redraw the line and put the cursor back where it belongs.
+    fn refresh(&self) -> io::Result<()> {
+        let line: String = self.buffer.iter().collect();
+        let column = self.prompt.chars().count() + self.cursor;
+
+        print!("\r{}{}\x1b[K\r", self.prompt, line);
+
+        if column > 0 {
+            print!("\x1b[{}C", column);
+        }
+
+        io::stdout().flush()
+    }
+}
@@ -0,0 +1,133 @@
+//! An interactive session, where every line is run in the same interpreter so
+//! that variables and functions stick around for the lines after it.
+
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+use lagoon_parser::{generate, parse_recovering, Token};
+use lagoon_interpreter::{Interpreter, Value};
+
+use crate::Options;
+use editor::{Editor, Line};
+
+mod editor;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION: &str = ".. ";
+
+const HELP: &str = "\
+:ast <code>     Show the syntax tree for some code
+:tokens <code>  Show the tokens in some code
+:env            Show the variables and functions that have been defined
+:load <file>    Run a file in the current session
+:help           Show this message
+:quit           Leave the REPL (or press Ctrl-D)";
+
+pub fn start(options: Options) {
+    let directory = std::env::current_dir().unwrap();
+
+    // Relative imports are resolved against the directory the REPL started in.
+    let mut interpreter = options.interpreter(Vec::new(), directory.join("repl.lag"));
+    let mut editor = Editor::new(history());
+
+    if editor.is_terminal() {
+        println!("Lagoon {} - type :help for help, or :quit to leave.", crate::VERSION);
+    }
+
+    while let Some(source) = read(&mut editor) {
+        let input = source.trim();
+
+        if input.is_empty() {
+            continue;
+        }
+
+        // The editor works on a single line, so code that spanned a few lines
+        // is recalled as one.
+        editor.add_history(&source.lines().map(str::trim).collect::<Vec<&str>>().join(" "));
+
+        let (command, argument) = input.split_once(char::is_whitespace)
+            .map(|(command, argument)| (command, argument.trim()))
+            .unwrap_or((input, ""));
+
+        match command {
+            ":quit" | ":exit" | ":q" => break,
+            ":help" | ":h" => println!("{}", HELP),
+            ":ast" => match parse_recovering(generate(argument)) {
+                (ast, errors) if errors.is_empty() => println!("{:#?}", ast),
+                (_, errors) => errors.iter().for_each(|error| error.print(argument, "<repl>")),
+            },
+            ":tokens" => for token in generate(argument) {
+                println!("{}:{} {:?}", token.span.line, token.span.column, token.token);
+            },
+            ":env" => for (name, value) in interpreter.variables() {
+                println!("{} = {:?}", name, value);
+            },
+            ":load" if argument.is_empty() => eprintln!("error: `:load` needs the path of a file"),
+            ":load" => match read_to_string(argument) {
+                Ok(contents) => eval(&mut interpreter, &contents, argument),
+                Err(e) => eprintln!("error: failed to read {}: {}", argument, e),
+            },
+            command if command.starts_with(':') => eprintln!("error: unknown command `{}`, type :help to see the commands", command),
+            _ => eval(&mut interpreter, &source, "<repl>"),
+        }
+    }
+}
+
+/// Read a piece of code, carrying on to the next line for as long as there
+/// are brackets that haven't been closed.
+fn read(editor: &mut Editor) -> Option<String> {
+    let mut source = String::new();
+
+    loop {
+        let prompt = if source.is_empty() { PROMPT } else { CONTINUATION };
+
+        match editor.read_line(prompt) {
+            Ok(Line::Text(line)) => {
+                if ! source.is_empty() {
+                    source.push('\n');
+                }
+
+                source.push_str(&line);
+
+                if source.trim_start().starts_with(':') || depth(&source) <= 0 {
+                    return Some(source);
+                }
+            },
+            Ok(Line::Interrupted) => source.clear(),
+            Ok(Line::Eof) | Err(_) => return None,
+        }
+    }
+}
+
+/// The number of brackets that are still open.
+fn depth(source: &str) -> isize {
+    generate(source).iter().map(|token| match token.token {
+        Token::LeftBrace | Token::LeftParen | Token::LeftBracket => 1,
+        Token::RightBrace | Token::RightParen | Token::RightBracket => -1,
+        _ => 0,
+    }).sum()
+}
+
+/// Run some code, printing the value of a trailing expression.
+fn eval(interpreter: &mut Interpreter, source: &str, path: &str) {
+    let (ast, errors) = parse_recovering(generate(source));
+
+    if ! errors.is_empty() {
+        return errors.iter().for_each(|error| error.print(source, path));
+    }
+
+    match interpreter.eval(ast) {
+        Ok(Some(Value::Null)) | Ok(None) => (),
+        Ok(Some(value)) => println!("{:?}", value),
+        Err(e) => e.print(source, path),
+    }
+}
+
+/// The file that the history is kept in, which can be moved with `LAGOON_HISTORY`.
+fn history() -> Option<PathBuf> {
+    match std::env::var_os("LAGOON_HISTORY") {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".lagoon_history")),
+    }
+}
@@ -0,0 +1,38 @@
+//! Runs `lagoon check` over scripts in a temporary directory.
+
+mod common;
+
+use std::fs::write;
+
+use common::{directory, lagoon, stderr};
+
+#[test]
+fn it_reports_problems_at_their_configured_level() {
+    let root = directory("check-levels");
+
+    write(root.join("main.lag"), "fn greet(name) {\n    let greeting = \"Hello\"\n    return name\n}\n\ngreet()\n").unwrap();
+
+    let output = lagoon(&root, &["check", "main.lag"]);
+    let report = stderr(&output);
+
+    assert!(! output.status.success());
+    assert!(report.contains("warning[unused-variable]: unused variable `greeting`"));
+    assert!(report.contains("error[argument-count]: `greet` expects 1 argument, but 0 were given"));
+    assert!(report.contains("error: could not check due to 1 previous error; 1 warning emitted"));
+
+    let output = lagoon(&root, &["check", "main.lag", "--allow", "argument-count"]);
+
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("warning: 1 warning emitted"));
+
+    // The manifest's levels apply when checking the whole project.
+    write(root.join("lagoon.toml"), "[package]\nname = \"levels\"\n\n[lints]\nunused-variable = \"allow\"\nargument-count = \"warn\"\n").unwrap();
+
+    let output = lagoon(&root, &["check"]);
+    let report = stderr(&output);
+
+    assert!(output.status.success(), "{}", report);
+    assert!(! report.contains("unused-variable"));
+    assert!(report.contains("warning[argument-count]"));
+    assert!(report.contains("`argument-count` is set to `warn`\n"));
+}
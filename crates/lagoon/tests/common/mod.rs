@@ -0,0 +1,55 @@
+//! Helpers shared by the tests that run the `lagoon` binary. Not every test
+//! uses all of them.
+#![allow(dead_code)]
+
+use std::fs::{create_dir_all, remove_dir_all};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A temporary directory that is removed when it is dropped.
+pub struct Directory(PathBuf);
+
+impl Deref for Directory {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Directory {
+    fn drop(&mut self) {
+        remove_dir_all(&self.0).ok();
+    }
+}
+
+/// Create an empty temporary directory, unique to the test binary.
+pub fn directory(name: &str) -> Directory {
+    let directory = std::env::temp_dir().join(format!("lagoon-{}-{}", name, std::process::id()));
+
+    remove_dir_all(&directory).ok();
+    create_dir_all(&directory).unwrap();
+
+    Directory(directory)
+}
+
+/// A `lagoon` command that runs in `directory` without colours.
+pub fn command(directory: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_lagoon"));
+
+    command.current_dir(directory).env("NO_COLOR", "1");
+    command
+}
+
+pub fn lagoon(directory: &Path, arguments: &[&str]) -> Output {
+    command(directory).args(arguments).output().unwrap()
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
@@ -0,0 +1,188 @@
+//! Runs `lagoon debug` over a script in a temporary directory, both from the
+//! terminal and the way an editor would drive the debug adapter.
+
+mod common;
+
+use std::fs::write;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Stdio};
+
+use common::{command, directory, Directory};
+
+const SOURCE: &str = "fn double(n) {
+    let result = n * 2
+    return result
+}
+
+let items = [1, 2]
+let a = double(2)
+println(a)
+let b = a + 1
+";
+
+/// A temporary directory with `SOURCE` in `main.lag`.
+fn script(name: &str) -> Directory {
+    let directory = directory(&format!("debug-{}", name));
+
+    write(directory.join("main.lag"), SOURCE).unwrap();
+    directory
+}
+
+fn debug(directory: &Path, arguments: &[&str]) -> Child {
+    command(directory)
+        .arg("debug")
+        .arg("main.lag")
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+#[test]
+fn it_debugs_from_the_terminal() {
+    let root = script("terminal");
+    let mut child = debug(&root, &["--terminal"]);
+
+    child.stdin.take().unwrap().write_all(b"b 5\nb 2\nw n * 10\nc\nc\nbt\nvars\np result + 1\nn\nn\nout\nc\n").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Stopped at the start of main.lag."));
+
+    // Breakpoints move to the next line that has a statement on it.
+    assert!(stdout.contains("Breakpoint set at main.lag:6."));
+    assert!(stdout.contains("Stopped at a breakpoint in main.lag:6."));
+    assert!(stdout.contains("Stopped at a breakpoint in main.lag:2.\n>    2 |     let result = n * 2\n1: n * 10 = 20"));
+    assert!(stdout.contains("#0 double at main.lag:2\n#1 <main> at main.lag:7"));
+    assert!(stdout.contains("Locals:\n    n = 2\nGlobals:\n    items = [1, 2]\n    double = <double>(n)"));
+    assert!(stdout.contains("error: Undefined variable: result."));
+    assert!(stdout.contains(">    3 |     return result\n1: n * 10 = 20"));
+    assert!(stdout.contains(">    8 | println(a)\n1: n * 10 = <error: Undefined variable: n.>"));
+    assert!(stdout.contains("(debug) 4\n"));
+}
+
+#[test]
+fn it_only_debugs_on_the_tree_walker() {
+    let root = script("vm");
+    let output = debug(&root, &["--terminal", "--vm"]).wait_with_output().unwrap();
+
+    assert!(! output.status.success());
+}
+
+#[test]
+fn it_stops_the_program_when_asked_to_quit() {
+    let root = script("quit");
+    let mut child = debug(&root, &["--terminal"]);
+
+    child.stdin.take().unwrap().write_all(b"q\n").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(! stdout.contains("4"));
+}
+
+struct Adapter {
+    child: Child,
+    output: BufReader<ChildStdout>,
+    seq: usize,
+}
+
+impl Adapter {
+    fn send(&mut self, command: &str, arguments: &str) {
+        self.seq += 1;
+
+        let message = format!(r#"{{"seq":{},"type":"request","command":"{}","arguments":{}}}"#, self.seq, command, arguments);
+
+        write!(self.child.stdin.as_mut().unwrap(), "Content-Length: {}\r\n\r\n{}", message.len(), message).unwrap();
+    }
+
+    fn read(&mut self) -> String {
+        let mut header = String::new();
+
+        self.output.read_line(&mut header).unwrap();
+        self.output.read_line(&mut String::new()).unwrap();
+
+        let length: usize = header.trim().trim_start_matches("Content-Length: ").parse().unwrap();
+        let mut body = vec![0; length];
+
+        self.output.read_exact(&mut body).unwrap();
+
+        String::from_utf8(body).unwrap()
+    }
+
+    /// Read messages until one of them contains `text`, returning that one.
+    fn until(&mut self, text: &str) -> String {
+        loop {
+            match self.read() {
+                message if message.contains(text) => return message,
+                _ => {},
+            }
+        }
+    }
+
+    fn request(&mut self, command: &str, arguments: &str) -> String {
+        self.send(command, arguments);
+        self.until(&format!(r#""request_seq":{},"#, self.seq))
+    }
+}
+
+#[test]
+fn it_debugs_from_an_editor() {
+    let root = script("adapter");
+    let path = root.join("main.lag").canonicalize().unwrap().display().to_string();
+    let mut child = debug(&root, &[]);
+    let output = BufReader::new(child.stdout.take().unwrap());
+    let mut adapter = Adapter { child, output, seq: 0 };
+
+    assert!(adapter.request("initialize", r#"{"adapterID":"lagoon"}"#).contains(r#""supportsFunctionBreakpoints":true"#));
+    adapter.until(r#""event":"initialized""#);
+
+    adapter.request("launch", r#"{"stopOnEntry":false}"#);
+
+    let breakpoints = adapter.request("setBreakpoints", &format!(r#"{{"source":{{"path":"{}"}},"breakpoints":[{{"line":3}},{{"line":20}}]}}"#, path));
+    assert!(breakpoints.contains(r#"{"verified":true,"line":3}"#));
+    assert!(breakpoints.contains(r#"{"verified":false,"line":20"#));
+
+    adapter.request("configurationDone", "{}");
+    assert!(adapter.until(r#""event":"stopped""#).contains(r#""reason":"breakpoint""#));
+
+    let trace = adapter.request("stackTrace", r#"{"threadId":1}"#);
+    assert!(trace.contains(r#""id":1,"name":"double","source":{"name":"main.lag","#));
+    assert!(trace.contains(r#""line":3,"column":5"#));
+    assert!(trace.contains(r#""name":"<main>""#));
+
+    let scopes = adapter.request("scopes", r#"{"frameId":1}"#);
+    assert!(scopes.contains(r#""name":"Locals","variablesReference":1"#));
+    assert!(scopes.contains(r#""name":"Globals","variablesReference":2"#));
+
+    let locals = adapter.request("variables", r#"{"variablesReference":1}"#);
+    assert!(locals.contains(r#"{"name":"n","value":"2","type":"number","variablesReference":0}"#));
+    assert!(locals.contains(r#"{"name":"result","value":"4","type":"number","variablesReference":0}"#));
+
+    // Lists can be expanded to see their items.
+    let globals = adapter.request("variables", r#"{"variablesReference":2}"#);
+    assert!(globals.contains(r#"{"name":"items","value":"[1, 2]","type":"list","variablesReference":3}"#));
+    assert!(adapter.request("variables", r#"{"variablesReference":3}"#).contains(r#"{"name":"1","value":"2","type":"number","variablesReference":0}"#));
+
+    assert!(adapter.request("evaluate", r#"{"expression":"result * 10","frameId":1}"#).contains(r#""result":"40""#));
+    assert!(adapter.request("evaluate", r#"{"expression":"missing","frameId":1}"#).contains(r#""success":false"#));
+
+    adapter.request("stepOut", r#"{"threadId":1}"#);
+    adapter.until(r#""event":"stopped""#);
+    assert!(adapter.request("stackTrace", r#"{"threadId":1}"#).contains(r#""name":"<main>","source":{"name":"main.lag","path":""#));
+
+    // Output from the program is sent as events instead of being printed.
+    adapter.request("continue", r#"{"threadId":1}"#);
+    assert!(adapter.until(r#""event":"output""#).contains(r#""category":"stdout","output":"4\n""#));
+    assert!(adapter.until(r#""event":"exited""#).contains(r#""exitCode":0"#));
+    adapter.until(r#""event":"terminated""#);
+
+    adapter.request("disconnect", "{}");
+    assert!(adapter.child.wait().unwrap().success());
+}
@@ -0,0 +1,56 @@
+//! Runs every example and test script with both the tree-walker and the bytecode
+//! virtual machine, making sure that the two engines produce identical output.
+
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+mod common;
+
+use common::{lagoon, stderr, stdout};
+
+fn scripts(directory: &Path) -> Vec<PathBuf> {
+    let mut scripts: Vec<PathBuf> = read_dir(directory).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map(|e| e == "lag").unwrap_or(false))
+        .collect();
+
+    scripts.sort();
+    scripts
+}
+
+/// Run a script from its own directory, with the virtual machine when `vm` is set.
+fn run(script: &Path, vm: bool) -> Output {
+    let directory = script.parent().unwrap();
+    let script = script.to_str().unwrap();
+
+    match vm {
+        true => lagoon(directory, &["run", "--vm", script]),
+        false => lagoon(directory, &["run", script]),
+    }
+}
+
+fn assert_engines_agree(directory: &Path) {
+    let scripts = scripts(directory);
+
+    assert!(! scripts.is_empty(), "no scripts found in {}", directory.display());
+
+    for script in scripts {
+        let expected = run(&script, false);
+        let actual = run(&script, true);
+
+        assert_eq!(stdout(&expected), stdout(&actual), "stdout differs for {}", script.display());
+        assert_eq!(stderr(&expected), stderr(&actual), "stderr differs for {}", script.display());
+        assert_eq!(expected.status.code(), actual.status.code(), "exit code differs for {}", script.display());
+    }
+}
+
+#[test]
+fn the_vm_matches_the_tree_walker_for_examples() {
+    assert_engines_agree(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples"));
+}
+
+#[test]
+fn the_vm_matches_the_tree_walker_for_edge_cases() {
+    assert_engines_agree(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts"));
+}
@@ -0,0 +1,32 @@
+//! Runs `lagoon fmt` over files in a temporary directory.
+
+mod common;
+
+use std::fs::{read_to_string, write};
+
+use common::{directory, lagoon, stderr, stdout};
+
+#[test]
+fn it_formats_files_and_checks_them() {
+    let root = directory("fmt-files");
+
+    write(root.join("a.lag"), "let a=[1,2,]  -- A list\n").unwrap();
+    write(root.join("b.lag"), "let b = 1\n").unwrap();
+
+    let output = lagoon(&root, &["fmt", "--check", "a.lag", "b.lag"]);
+
+    assert!(! output.status.success());
+    assert_eq!(stdout(&output), "a.lag is not formatted\n");
+    assert_eq!(read_to_string(root.join("a.lag")).unwrap(), "let a=[1,2,]  -- A list\n");
+
+    assert!(lagoon(&root, &["fmt", "."]).status.success());
+    assert_eq!(read_to_string(root.join("a.lag")).unwrap(), "let a = [1, 2] -- A list\n");
+    assert!(lagoon(&root, &["fmt", "--check", "."]).status.success());
+
+    write(root.join("c.lag"), "let = 1").unwrap();
+
+    let output = lagoon(&root, &["fmt", "c.lag"]);
+
+    assert!(! output.status.success());
+    assert!(stderr(&output).contains("error[E0001]"));
+}
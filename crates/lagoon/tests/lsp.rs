@@ -0,0 +1,133 @@
+//! Talks to `lagoon lsp` the way an editor would, by writing framed JSON-RPC
+//! messages to its stdin and reading the replies from its stdout.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const URI: &str = "file:///tmp/lagoon-lsp-test/main.lag";
+
+const SOURCE: &str = r#"struct Point {
+    x,
+    y
+}
+
+fn distance(point) {
+    return point.x + point.y
+}
+
+let name = "Lagoon"
+let x = 1
+let origin = Point { x, y: 2 }
+println(distance(origin))
+name."#;
+
+fn frame(message: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+}
+
+fn request(id: usize, method: &str, params: &str) -> String {
+    frame(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#, id, method, params))
+}
+
+fn notification(method: &str, params: &str) -> String {
+    frame(&format!(r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#, method, params))
+}
+
+fn at(line: usize, character: usize) -> String {
+    format!(r#"{{"textDocument":{{"uri":"{}"}},"position":{{"line":{},"character":{}}}}}"#, URI, line, character)
+}
+
+fn text(source: &str) -> String {
+    source.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Send every message and return the bodies of the messages that came back.
+fn session(messages: &[String]) -> Vec<String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lagoon"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(messages.concat().as_bytes()).unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+
+    let mut replies = Vec::new();
+    let mut rest = stdout.as_str();
+
+    while let Some((header, body)) = rest.split_once("\r\n\r\n") {
+        let length: usize = header.trim_start_matches("Content-Length: ").parse().unwrap();
+
+        replies.push(body[..length].to_string());
+        rest = &body[length..];
+    }
+
+    replies
+}
+
+fn reply(replies: &[String], id: usize) -> &str {
+    replies.iter().find(|reply| reply.contains(&format!(r#""id":{},"#, id))).unwrap()
+}
+
+#[test]
+fn it_answers_an_editor() {
+    let replies = session(&[
+        request(1, "initialize", r#"{"capabilities":{}}"#),
+        notification("initialized", "{}"),
+        notification("textDocument/didOpen", &format!(r#"{{"textDocument":{{"uri":"{}","languageId":"lagoon","version":1,"text":"println(missing)"}}}}"#, URI)),
+        notification("textDocument/didChange", &format!(r#"{{"textDocument":{{"uri":"{}","version":2}},"contentChanges":[{{"text":"{}"}}]}}"#, URI, text(SOURCE))),
+        request(2, "textDocument/definition", &at(12, 9)),
+        request(3, "textDocument/hover", &at(11, 14)),
+        request(4, "textDocument/hover", &at(12, 10)),
+        request(5, "textDocument/completion", &at(13, 5)),
+        request(6, "textDocument/completion", &at(6, 4)),
+        request(7, "textDocument/documentSymbol", &format!(r#"{{"textDocument":{{"uri":"{}"}}}}"#, URI)),
+        request(8, "textDocument/rename", &at(10, 4).replace("}}", r#"},"newName":"left"}"#)),
+        request(9, "textDocument/rename", &at(10, 4).replace("}}", r#"},"newName":"fn"}"#)),
+        request(10, "textDocument/unknown", "{}"),
+        request(11, "shutdown", "null"),
+        notification("exit", "null"),
+    ]);
+
+    assert!(reply(&replies, 1).contains(r#""completionProvider":{"triggerCharacters":["."]}"#));
+    assert!(reply(&replies, 1).contains(r#""renameProvider":true"#));
+
+    // Diagnostics are published whenever the document changes.
+    assert!(replies[1].contains("textDocument/publishDiagnostics"));
+    assert!(replies[1].contains(r#""code":"undefined-variable""#));
+    assert!(replies[1].contains("Undefined variable: missing."));
+    assert!(replies[2].contains(r#""range":{"start":{"line":13,"character":5}"#));
+    assert!(! replies[2].contains("undefined-variable"));
+
+    assert_eq!(reply(&replies, 2), format!(r#"{{"jsonrpc":"2.0","id":2,"result":{{"uri":"{}","range":{{"start":{{"line":5,"character":3}},"end":{{"line":5,"character":11}}}}}}}}"#, URI));
+    assert!(reply(&replies, 3).contains(r#"```lagoon\nstruct Point {\n    x,\n    y\n}\n```"#));
+    assert!(reply(&replies, 4).contains("fn distance(point)"));
+
+    // Only the methods of strings come after `name.`.
+    assert!(reply(&replies, 5).contains(r#""label":"toUpper","kind":2"#));
+    assert!(! reply(&replies, 5).contains("isEmpty"));
+
+    assert!(reply(&replies, 6).contains(r#""label":"point","kind":6"#));
+    assert!(reply(&replies, 6).contains(r#""label":"distance","kind":3"#));
+    assert!(reply(&replies, 6).contains(r#""label":"println""#));
+    assert!(! reply(&replies, 6).contains("toUpper"));
+
+    assert!(reply(&replies, 7).contains(r#""name":"Point","detail":"struct Point {","kind":23"#));
+    assert!(reply(&replies, 7).contains(r#""name":"x","detail":"","kind":8"#));
+    assert!(reply(&replies, 7).contains(r#""name":"origin","detail":"let origin","kind":13"#));
+
+    // Renaming a variable keeps the field name of shorthand struct fields.
+    let rename = reply(&replies, 8);
+    assert!(rename.contains(r#""range":{"start":{"line":10,"character":4},"end":{"line":10,"character":5}},"newText":"left""#));
+    assert!(rename.contains(r#""range":{"start":{"line":11,"character":21},"end":{"line":11,"character":22}},"newText":"x: left""#));
+    assert_eq!(rename.matches("newText").count(), 2);
+
+    assert!(reply(&replies, 9).contains(r#""error":{"code":-32803,"message":"`fn` is not a valid name."}"#));
+    assert!(reply(&replies, 10).contains(r#""code":-32601"#));
+    assert!(reply(&replies, 11).contains(r#""result":null"#));
+}
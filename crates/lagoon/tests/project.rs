@@ -0,0 +1,130 @@
+//! Drives the `init`, `run` and `test` commands against projects with a
+//! `lagoon.toml` manifest.
+
+mod common;
+
+use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+
+use common::{directory, lagoon, stderr, stdout};
+
+#[test]
+fn it_creates_runs_and_tests_a_project() {
+    let root = directory("project-init");
+
+    assert!(lagoon(&root, &["init", "hello"]).status.success());
+
+    let project = root.join("hello");
+    let output = lagoon(&project, &["run"]);
+
+    assert_eq!(stdout(&output), "Hello, world!\n");
+
+    for engine in [&["test"][..], &["test", "--vm"][..]] {
+        let output = lagoon(&project, engine);
+
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).contains("test tests/main.lag ... ok"));
+    }
+
+    write(project.join("tests/broken.lag"), "throw \"broken\"").unwrap();
+
+    let output = lagoon(&project, &["test"]);
+
+    assert!(! output.status.success());
+    assert!(stdout(&output).contains("test tests/broken.lag ... FAILED"));
+    assert!(stdout(&output).contains("test result: FAILED. 1 passed; 1 failed"));
+
+    let output = lagoon(&project, &["init"]);
+
+    assert!(! output.status.success());
+    assert!(stderr(&output).contains("`./lagoon.toml` already exists"));
+}
+
+#[test]
+fn it_transpiles_the_entry_point() {
+    let root = directory("project-js");
+
+    assert!(lagoon(&root, &["init", "app"]).status.success());
+
+    let project = root.join("app");
+    let source = read_to_string(project.join("src/main.lag")).unwrap();
+
+    for arguments in [&["js", "src/main.lag"][..], &["js", "src/other.lag", "src/main.lag"][..]] {
+        let output = lagoon(&project, arguments);
+
+        assert!(! output.status.success());
+        assert_eq!(read_to_string(project.join("src/main.lag")).unwrap(), source);
+    }
+
+    assert!(stderr(&lagoon(&project, &["js", "src/other.lag", "src/main.lag"])).contains("refusing to write JavaScript to the Lagoon file src/main.lag"));
+
+    for arguments in [&["js", "--out", "dist/main.mjs"][..], &["js", "src/main.lag", "dist/main.mjs"][..]] {
+        remove_dir_all(project.join("dist")).ok();
+
+        let output = lagoon(&project, arguments);
+
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(read_to_string(project.join("dist/main.mjs")).unwrap().contains("Hello, world!"));
+    }
+}
+
+#[test]
+fn it_resolves_dependencies_and_locks_them() {
+    let root = directory("project-dependencies");
+
+    create_dir_all(root.join("app/src")).unwrap();
+    create_dir_all(root.join("greet/lib")).unwrap();
+    create_dir_all(root.join("shared")).unwrap();
+
+    write(root.join("app/lagoon.toml"), r#"
+        [package]
+        name = "app"
+        entry = "src/main.lag"
+
+        [dependencies]
+        greet = { path = "../greet" }
+
+        [permissions]
+        modules = ["../shared"]
+    "#).unwrap();
+
+    write(root.join("app/src/main.lag"), r#"
+        import { greet } from "greet"
+        import { punctuate } from "greet/punctuation"
+        import { NAME } from "../../shared/name"
+
+        println(punctuate(greet(NAME)))
+    "#).unwrap();
+
+    write(root.join("greet/lagoon.toml"), "[package]\nname = \"greet\"\nversion = \"1.2.0\"\nentry = \"lib/main.lag\"\n").unwrap();
+    write(root.join("greet/lib/main.lag"), "export fn greet(name) { return \"Hello, \" + name }").unwrap();
+    write(root.join("greet/lib/punctuation.lag"), "export fn punctuate(s) { return s + \"!\" }").unwrap();
+    write(root.join("shared/name.lag"), "export const NAME = \"Lagoon\"").unwrap();
+
+    let app = root.join("app");
+    let output = lagoon(&app, &["run"]);
+
+    assert_eq!(stdout(&output), "Hello, Lagoon!\n", "{}", stderr(&output));
+
+    let lockfile = read_to_string(app.join("lagoon.lock")).unwrap();
+
+    assert!(lockfile.contains("[[package]]\nname = \"greet\"\nversion = \"1.2.0\"\nsource = \"path+../greet\"\n"));
+
+    // Running a file inside of the project still uses its manifest.
+    assert_eq!(stdout(&lagoon(&app.join("src"), &["run", "main.lag"])), "Hello, Lagoon!\n");
+
+    write(root.join("greet/lagoon.toml"), "[package]\nname = \"greet\"\nversion = \"1.3.0\"\nentry = \"lib/main.lag\"\n").unwrap();
+
+    let output = lagoon(&app, &["run", "--locked"]);
+
+    assert!(! output.status.success());
+    assert!(stderr(&output).contains("error[E0306]"));
+    assert_eq!(read_to_string(app.join("lagoon.lock")).unwrap(), lockfile);
+
+    write(app.join("lagoon.toml"), "[package]\nname = \"app\"\n\n[dependencies]\ngreet = { path = \"../missing\" }\n").unwrap();
+
+    let output = lagoon(&app, &["run", "src/main.lag"]);
+
+    assert!(! output.status.success());
+    assert!(stderr(&output).contains("error[E0303]: the dependency `greet` could not be found"));
+    assert!(stderr(&output).contains("greet = { path = \"../missing\" }"));
+}
@@ -0,0 +1,74 @@
+//! Feeds lines to `lagoon repl` through a pipe, which skips the prompts and
+//! line editing but otherwise behaves like an interactive session.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+
+use common::directory;
+
+fn repl(input: &str, arguments: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lagoon"))
+        .arg("repl")
+        .args(arguments)
+        .env("NO_COLOR", "1")
+        .env("LAGOON_HISTORY", "")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn it_keeps_state_between_lines_and_prints_results() {
+    let input = "let a = 1\na + 1\nfn double(n) {\n    return n * 2\n}\ndouble(a + 1)\nprintln(\"printed\")\n[a,\n 2]\n";
+
+    for arguments in [&[][..], &["--vm"][..]] {
+        let output = repl(input, arguments);
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n4\nprinted\n[1, 2]\n");
+        assert!(output.status.success());
+    }
+}
+
+#[test]
+fn it_reports_errors_and_carries_on() {
+    let output = repl("missing\n:nope\nlet b = 3\nb\n", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    assert!(stderr.contains("error[E0101]: Undefined variable: missing."));
+    assert!(stderr.contains("--> <repl>:1:1"));
+    assert!(stderr.contains("unknown command `:nope`"));
+}
+
+#[test]
+fn it_keeps_state_after_a_runtime_error() {
+    for arguments in [&[][..], &["--vm"][..]] {
+        let output = repl("let a = 1\n1 - \"a\"\na + 1\n", arguments);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+        assert!(stderr.contains("Cannot apply `-` to number and string."), "{}", stderr);
+        assert!(output.status.success());
+    }
+}
+
+#[test]
+fn it_has_meta_commands() {
+    let root = directory("repl-load");
+    let script = root.join("load.lag");
+
+    std::fs::write(&script, "let loaded = 40 + 2").unwrap();
+
+    let output = repl(&format!(":load {}\nloaded\n:env\n:tokens let x\n:ast null\n:quit\n1\n", script.display()), &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(stdout.starts_with("42\nloaded = 42\n1:1 Let\n1:5 Identifier(\"x\")\n[\n    Statement {\n"), "{}", stdout);
+    assert!(! stdout.ends_with("1\n"));
+}